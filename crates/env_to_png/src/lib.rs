@@ -6,11 +6,15 @@
 use std::num::NonZeroU32;
 
 // use magics::config::Environment;
-use gbp_environment::{Environment, PlaceableShape, RegularPolygon};
+use gbp_environment::{Environment, PlaceableShape, RegionKind, RegularPolygon, WorldRegion};
 use gbp_geometry::RelativePoint;
 use glam::{Vec2, Vec3Swizzles};
 use image::RgbImage;
 
+mod tiled;
+
+pub use tiled::{SdfSampler, TiledSdf};
+
 /// Custom resolution type, as pixels per tile.
 #[derive(Clone, Copy, Debug)]
 pub struct PixelsPerTile(NonZeroU32);
@@ -153,7 +157,7 @@ pub fn env_to_sdf_image(
     blur_percent: Percentage,
 ) -> anyhow::Result<RgbImage> {
     let image = env_to_image(env, resolution, expansion).unwrap();
-    let blur_pixels = blur_percent.0 * resolution.get() as f32;
+    let blur_pixels = blur_pixels(resolution, blur_percent);
     // println!("Blur pixels: {}", blur_pixels);
     if blur_pixels < 1.0 {
         return Ok(image);
@@ -163,24 +167,74 @@ pub fn env_to_sdf_image(
     Ok(sdf)
 }
 
+/// The standard deviation in pixels of the gaussian blur applied to the SDF
+fn blur_pixels(resolution: PixelsPerTile, blur_percent: Percentage) -> f32 {
+    blur_percent.0 * resolution.get() as f32
+}
+
 /// Convert [`Environment`] to an image.
 pub fn env_to_image(
     env: &Environment,
     resolution: PixelsPerTile,
     expansion: Percentage,
 ) -> anyhow::Result<RgbImage> {
-    let tile_size = env.tile_size();
-    let (ncols, nrows) = (env.tiles.grid.ncols(), env.tiles.grid.nrows());
+    let (width, height) = image_dimensions(env, resolution);
+    env_region_to_image(
+        env,
+        resolution,
+        expansion,
+        PixelCoords { x: 0, y: 0 },
+        PixelCoords {
+            x: width,
+            y: height,
+        },
+    )
+}
 
-    let mut image = RgbImage::new(
+/// Dimensions in pixels of the image covering the whole [`Environment`].
+pub fn image_dimensions(env: &Environment, resolution: PixelsPerTile) -> (u32, u32) {
+    let (ncols, nrows) = (env.tiles.grid.ncols(), env.tiles.grid.nrows());
+    (
         ncols as u32 * resolution.get(),
         nrows as u32 * resolution.get(),
-    );
+    )
+}
+
+/// Convert the rectangular region of the [`Environment`] image starting at
+/// `origin` with the given `size` to an image.
+/// Pixel `(x, y)` of the returned image is pixel `(origin.x + x, origin.y + y)`
+/// of the image returned by [`env_to_image`].
+pub fn env_region_to_image(
+    env: &Environment,
+    resolution: PixelsPerTile,
+    expansion: Percentage,
+    origin: PixelCoords,
+    size: PixelCoords,
+) -> anyhow::Result<RgbImage> {
+    let tile_size = env.tile_size();
+    let world_width = env.tiles.grid.ncols() as f32 * tile_size;
+    let world_height = env.tiles.grid.nrows() as f32 * tile_size;
+
+    // Keep-out regions are drawn in gray, darker the higher their cost, so the
+    // obstacle factor pushes robots out of them like it does for obstacles.
+    let keep_out_regions: Vec<(WorldRegion, f32)> = env
+        .world_regions()
+        .into_iter()
+        .filter_map(|region| match region.kind {
+            RegionKind::KeepOut { cost } => Some((region, cost)),
+            _ => None,
+        })
+        .collect();
+
+    let mut image = RgbImage::new(size.x, size.y);
 
     // Start by making the whole image white.
     for y in 0..image.height() {
         for x in 0..image.width() {
-            let pixel_coords = PixelCoords { x, y };
+            let pixel_coords = PixelCoords {
+                x: origin.x + x,
+                y: origin.y + y,
+            };
             let tile_coords = image_to_tile_coords(pixel_coords, resolution);
             let tile_dimensions = image_to_tile_units(pixel_coords, resolution, tile_size);
             let percentage_coords = tile_units_to_percentage(tile_dimensions, tile_size);
@@ -195,7 +249,17 @@ pub fn env_to_image(
                 {
                     image.put_pixel(x, y, image::Rgb([0, 0, 0]));
                 } else {
-                    image.put_pixel(x, y, image::Rgb([255, 255, 255]));
+                    let world_position = Vec2::new(
+                        tile_dimensions.x - world_width / 2.0,
+                        world_height / 2.0 - tile_dimensions.y,
+                    );
+                    let cost = keep_out_regions
+                        .iter()
+                        .filter(|(region, _)| region.contains(world_position))
+                        .map(|&(_, cost)| cost)
+                        .fold(0.0, f32::max);
+                    let value = (255.0 * (1.0 - cost)).round() as u8;
+                    image.put_pixel(x, y, image::Rgb([value, value, value]));
                 }
             } else {
                 Err(anyhow::anyhow!("Tile not found"))?;
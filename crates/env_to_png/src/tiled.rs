@@ -0,0 +1,390 @@
+//! Chunked SDF representation, for environments where rendering the whole SDF
+//! as one image would use too much memory. Chunks are rendered on first
+//! access and kept in a least-recently-used cache bounded by a memory budget.
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    num::NonZeroU32,
+    sync::{Arc, Mutex},
+};
+
+use gbp_environment::Environment;
+use image::{Rgb, RgbImage};
+
+use crate::{
+    Percentage, PixelCoords, PixelsPerTile, blur_pixels, env_region_to_image, env_to_sdf_image,
+    image_dimensions,
+};
+
+/// Longest side in pixels of the image returned by [`TiledSdf::preview`]
+const PREVIEW_MAX_SIDE: u32 = 2048;
+
+/// Bytes used by a single pixel of an [`RgbImage`]
+const BYTES_PER_PIXEL: usize = 3;
+
+/// Common interface for sampling an SDF, regardless of whether it is stored as
+/// a single image, or generated lazily in chunks.
+pub trait SdfSampler: std::fmt::Debug + Send + Sync {
+    /// Width of the whole SDF in pixels
+    fn width(&self) -> u32;
+    /// Height of the whole SDF in pixels
+    fn height(&self) -> u32;
+    /// Get the pixel at `(x, y)`, or `None` if it lies outside the SDF
+    fn get_pixel_checked(&self, x: u32, y: u32) -> Option<Rgb<u8>>;
+    /// An image of the whole SDF, used for visualisation.
+    /// Implementations are free to return a downscaled version.
+    fn preview(&self) -> Cow<'_, RgbImage>;
+}
+
+impl SdfSampler for RgbImage {
+    #[inline]
+    fn width(&self) -> u32 {
+        image::ImageBuffer::width(self)
+    }
+
+    #[inline]
+    fn height(&self) -> u32 {
+        image::ImageBuffer::height(self)
+    }
+
+    #[inline]
+    fn get_pixel_checked(&self, x: u32, y: u32) -> Option<Rgb<u8>> {
+        image::ImageBuffer::get_pixel_checked(self, x, y).copied()
+    }
+
+    fn preview(&self) -> Cow<'_, RgbImage> {
+        Cow::Borrowed(self)
+    }
+}
+
+/// Index of a chunk, as (column, row)
+type ChunkIndex = (u32, u32);
+
+struct CachedChunk {
+    image: Arc<RgbImage>,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct ChunkCache {
+    chunks: HashMap<ChunkIndex, CachedChunk>,
+    /// Monotonic counter used to find the least recently used chunk
+    tick: u64,
+}
+
+/// An SDF of an [`Environment`] split into square chunks of `chunk_size`
+/// pixels. Sampling it gives the exact same values as sampling the image
+/// returned by [`env_to_sdf_image`] with the same settings.
+pub struct TiledSdf {
+    environment: Environment,
+    resolution: PixelsPerTile,
+    expansion: Percentage,
+    blur: Percentage,
+    chunk_size: NonZeroU32,
+    width: u32,
+    height: u32,
+    max_cached_chunks: usize,
+    cache: Mutex<ChunkCache>,
+}
+
+#[allow(clippy::missing_fields_in_debug)]
+impl std::fmt::Debug for TiledSdf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Use custom impl instead of `derive(Debug)`, to not print the cached chunks
+        f.debug_struct("TiledSdf")
+            .field("resolution", &self.resolution)
+            .field("chunk_size", &self.chunk_size)
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("max_cached_chunks", &self.max_cached_chunks)
+            .finish()
+    }
+}
+
+impl TiledSdf {
+    /// Create a new [`TiledSdf`]. No chunks are rendered until they are
+    /// sampled. At most `memory_budget` bytes worth of chunks are kept in
+    /// memory at a time, but at least one chunk is always cached.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0
+    #[must_use]
+    pub fn new(
+        environment: Environment,
+        resolution: PixelsPerTile,
+        expansion: Percentage,
+        blur: Percentage,
+        chunk_size: u32,
+        memory_budget: usize,
+    ) -> Self {
+        let chunk_size = NonZeroU32::new(chunk_size).expect("chunk size must be non-zero");
+        let (width, height) = image_dimensions(&environment, resolution);
+        let bytes_per_chunk =
+            chunk_size.get() as usize * chunk_size.get() as usize * BYTES_PER_PIXEL;
+        let max_cached_chunks = (memory_budget / bytes_per_chunk).max(1);
+
+        Self {
+            environment,
+            resolution,
+            expansion,
+            blur,
+            chunk_size,
+            width,
+            height,
+            max_cached_chunks,
+            cache: Mutex::new(ChunkCache::default()),
+        }
+    }
+
+    /// The number of chunks currently held in the cache
+    pub fn cached_chunks(&self) -> usize {
+        self.cache
+            .lock()
+            .expect("chunk cache lock is not poisoned")
+            .chunks
+            .len()
+    }
+
+    /// The maximum number of chunks the memory budget allows to be cached
+    #[inline]
+    pub const fn max_cached_chunks(&self) -> usize {
+        self.max_cached_chunks
+    }
+
+    /// Get the chunk at `index`, rendering it if it is not in the cache
+    fn chunk(&self, index: ChunkIndex) -> Arc<RgbImage> {
+        {
+            let mut cache = self.cache.lock().expect("chunk cache lock is not poisoned");
+            cache.tick += 1;
+            let tick = cache.tick;
+            if let Some(chunk) = cache.chunks.get_mut(&index) {
+                chunk.last_used = tick;
+                return Arc::clone(&chunk.image);
+            }
+        }
+
+        // Render without holding the lock, so other samplers are not blocked
+        let image = Arc::new(self.render_chunk(index));
+
+        let mut cache = self.cache.lock().expect("chunk cache lock is not poisoned");
+        cache.tick += 1;
+        let tick = cache.tick;
+        if let Some(chunk) = cache.chunks.get_mut(&index) {
+            // Another thread rendered the same chunk in the meantime
+            chunk.last_used = tick;
+            return Arc::clone(&chunk.image);
+        }
+
+        while cache.chunks.len() >= self.max_cached_chunks {
+            let least_recently_used = cache
+                .chunks
+                .iter()
+                .min_by_key(|(_, chunk)| chunk.last_used)
+                .map(|(&index, _)| index)
+                .expect("cache is not empty");
+            cache.chunks.remove(&least_recently_used);
+        }
+
+        cache.chunks.insert(
+            index,
+            CachedChunk {
+                image: Arc::clone(&image),
+                last_used: tick,
+            },
+        );
+
+        image
+    }
+
+    /// Render the chunk at `index`.
+    /// The gaussian blur only reads pixels a few standard deviations away, so
+    /// by rendering a margin around the chunk before blurring, and cropping it
+    /// away afterwards, the chunk is identical to the same region of the
+    /// monolithic SDF image.
+    fn render_chunk(&self, (column, row): ChunkIndex) -> RgbImage {
+        let size = self.chunk_size.get();
+        let (x0, y0) = (column * size, row * size);
+        let (x1, y1) = ((x0 + size).min(self.width), (y0 + size).min(self.height));
+
+        let sigma = blur_pixels(self.resolution, self.blur);
+        let margin = if sigma < 1.0 {
+            0
+        } else {
+            (4.0 * sigma).ceil() as u32 + 2
+        };
+
+        let (region_x0, region_y0) = (x0.saturating_sub(margin), y0.saturating_sub(margin));
+        let (region_x1, region_y1) = (
+            (x1 + margin).min(self.width),
+            (y1 + margin).min(self.height),
+        );
+
+        let region = env_region_to_image(
+            &self.environment,
+            self.resolution,
+            self.expansion,
+            PixelCoords {
+                x: region_x0,
+                y: region_y0,
+            },
+            PixelCoords {
+                x: region_x1 - region_x0,
+                y: region_y1 - region_y0,
+            },
+        )
+        .expect("chunk lies within the environment");
+
+        if margin == 0 {
+            return region;
+        }
+
+        let blurred = image::imageops::blur(&region, sigma);
+        image::imageops::crop_imm(&blurred, x0 - region_x0, y0 - region_y0, x1 - x0, y1 - y0)
+            .to_image()
+    }
+}
+
+impl SdfSampler for TiledSdf {
+    #[inline]
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[inline]
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn get_pixel_checked(&self, x: u32, y: u32) -> Option<Rgb<u8>> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        let size = self.chunk_size.get();
+        let chunk = self.chunk((x / size, y / size));
+        Some(*chunk.get_pixel(x % size, y % size))
+    }
+
+    /// Renders the whole SDF at a reduced resolution, such that the longest
+    /// side is at most [`PREVIEW_MAX_SIDE`] pixels.
+    fn preview(&self) -> Cow<'_, RgbImage> {
+        let downscale = self
+            .width
+            .max(self.height)
+            .div_ceil(PREVIEW_MAX_SIDE)
+            .max(1);
+        let resolution = PixelsPerTile::new((self.resolution.get() / downscale).max(1));
+        let image = env_to_sdf_image(&self.environment, resolution, self.expansion, self.blur)
+            .expect("environment can be rendered");
+        Cow::Owned(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_identical_to_monolithic(
+        resolution: PixelsPerTile,
+        blur: Percentage,
+        chunk_size: u32,
+        memory_budget: usize,
+    ) {
+        let environment = Environment::complex();
+        let expansion = Percentage::new(0.1);
+        let monolithic = env_to_sdf_image(&environment, resolution, expansion, blur).unwrap();
+        let tiled = TiledSdf::new(
+            environment,
+            resolution,
+            expansion,
+            blur,
+            chunk_size,
+            memory_budget,
+        );
+
+        assert_eq!(SdfSampler::width(&tiled), monolithic.width());
+        assert_eq!(SdfSampler::height(&tiled), monolithic.height());
+
+        for (x, y, pixel) in monolithic.enumerate_pixels() {
+            assert_eq!(
+                tiled.get_pixel_checked(x, y),
+                Some(*pixel),
+                "pixel ({x}, {y}) differs"
+            );
+        }
+    }
+
+    #[test]
+    fn identical_to_monolithic_without_blur() {
+        assert_identical_to_monolithic(
+            PixelsPerTile::new(20),
+            Percentage::new(0.0),
+            32,
+            usize::MAX,
+        );
+    }
+
+    #[test]
+    fn identical_to_monolithic_with_blur() {
+        assert_identical_to_monolithic(
+            PixelsPerTile::new(20),
+            Percentage::new(0.1),
+            32,
+            usize::MAX,
+        );
+    }
+
+    #[test]
+    fn identical_to_monolithic_when_evicting() {
+        // Budget for only two chunks, so most lookups have to re-render
+        let chunk_size = 24;
+        let memory_budget = 2 * chunk_size * chunk_size * BYTES_PER_PIXEL;
+        assert_identical_to_monolithic(
+            PixelsPerTile::new(20),
+            Percentage::new(0.1),
+            chunk_size as u32,
+            memory_budget,
+        );
+    }
+
+    #[test]
+    fn cache_respects_memory_budget() {
+        let chunk_size = 16;
+        let memory_budget = 3 * chunk_size * chunk_size * BYTES_PER_PIXEL;
+        let tiled = TiledSdf::new(
+            Environment::complex(),
+            PixelsPerTile::new(20),
+            Percentage::new(0.1),
+            Percentage::new(0.1),
+            chunk_size as u32,
+            memory_budget,
+        );
+        assert_eq!(tiled.max_cached_chunks(), 3);
+        assert_eq!(tiled.cached_chunks(), 0);
+
+        for y in (0..SdfSampler::height(&tiled)).step_by(chunk_size) {
+            for x in (0..SdfSampler::width(&tiled)).step_by(chunk_size) {
+                let _ = tiled.get_pixel_checked(x, y);
+                assert!(tiled.cached_chunks() <= 3);
+            }
+        }
+        assert_eq!(tiled.cached_chunks(), 3);
+    }
+
+    #[test]
+    fn outside_is_none() {
+        let tiled = TiledSdf::new(
+            Environment::complex(),
+            PixelsPerTile::new(20),
+            Percentage::new(0.1),
+            Percentage::new(0.1),
+            32,
+            usize::MAX,
+        );
+        assert_eq!(tiled.get_pixel_checked(SdfSampler::width(&tiled), 0), None);
+        assert_eq!(tiled.get_pixel_checked(0, SdfSampler::height(&tiled)), None);
+        assert_eq!(tiled.cached_chunks(), 0);
+    }
+}
@@ -1,6 +1,12 @@
 //! A module for working with robot formations declaratively.
 
-use std::{f32::consts::PI, num::NonZeroUsize, path::Path, time::Duration};
+use std::{
+    collections::BTreeMap,
+    f32::consts::{PI, TAU},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use bevy::{
     ecs::{component::Component, system::Resource},
@@ -13,24 +19,33 @@ use serde::{Deserialize, Serialize};
 use typed_floats::StrictlyPositiveFinite;
 
 use super::geometry::{Point, Shape};
-use crate::line;
+use crate::{RobotClass, RobotSection, line};
 
 /// Strategy to use for the starting point of a formation
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum InitialPlacementStrategy {
-    /// Place robots with equal distance between them
+    /// Place robots with equal distance between them. Inside a polygon the
+    /// robots are placed on the widest grid they all fit on.
     Equal,
     /// Place robots with a random distance between them, with the
     /// constraint that surface of area of none of the robots overlap.
+    /// Inside a polygon this is Poisson-disk sampling, with the radii of the
+    /// robots as the minimum distance.
     Random {
         /// How many attempts to use before returning an error saying it was not
         /// possible to place the robots Along the shape.
         /// A high number > 1000, is a good idea, since it is not very intensive
         /// to run the algorithm determining the random placements.
-        /// TODO: use
         attempts: NonZeroUsize,
     },
+    /// Place robots `spacing` meters apart, from the start of a line, or on a
+    /// grid inside a polygon. Grid points where a robot would not fit are
+    /// skipped.
+    Grid {
+        /// Distance between the centers of neighbouring robots
+        spacing: StrictlyPositiveFinite<f32>,
+    },
 }
 
 // TODO: extend with a generalised idea of Local and Global planning
@@ -79,6 +94,10 @@ pub struct Waypoint {
     pub shape: Shape,
     // pub placement_strategy: PlacementStrategy,
     pub projection_strategy: ProjectionStrategy,
+    /// The floor of the environment the waypoint is on, `0` being the ground
+    /// floor
+    #[serde(default)]
+    pub floor: usize,
 }
 
 impl Waypoint {
@@ -88,6 +107,7 @@ impl Waypoint {
         Self {
             shape,
             projection_strategy,
+            floor: 0,
         }
     }
 }
@@ -100,6 +120,9 @@ pub struct InitialPosition {
     pub shape: Shape,
     /// Strategy for how to place the robots
     pub placement_strategy: InitialPlacementStrategy,
+    /// The floor of the environment to spawn on, `0` being the ground floor
+    #[serde(default)]
+    pub floor: usize,
 }
 
 /// Enum representing the number of times a formation should repeat.
@@ -151,6 +174,97 @@ impl Repeat {
     }
 }
 
+/// Random arrivals of a formation, in place of spawning it every
+/// `repeat.every`. Every arrival spawns the robots of the formation once.
+/// Arrivals that find the entry of the formation occupied by other robots wait
+/// in a queue until it is free.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Arrivals {
+    /// Arrivals of a Poisson process, i.e. with exponentially distributed
+    /// times between them
+    Poisson {
+        /// Mean number of arrivals per second
+        rate: StrictlyPositiveFinite<f32>,
+        /// Number of arrivals, arrivals keep coming if not set
+        #[serde(default)]
+        times: RepeatTimes,
+    },
+    /// Arrivals at the times listed in a trace file, see [`parse_trace`]. The
+    /// path is relative to the directory of the scenario.
+    Trace { file: PathBuf },
+}
+
+impl Arrivals {
+    /// Number of arrivals of a Poisson process, `None` if arrivals keep
+    /// coming or they are listed in a trace file
+    #[must_use]
+    pub const fn poisson_times(&self) -> Option<usize> {
+        match self {
+            Self::Poisson {
+                times: RepeatTimes::Finite(times),
+                ..
+            } => Some(*times),
+            _ => None,
+        }
+    }
+}
+
+/// Time until the next arrival of a Poisson process with `rate` arrivals per
+/// second. A time too long to be represented by a [`Duration`], which a tiny
+/// `rate` can give, is clamped to [`Duration::MAX`].
+pub fn poisson_interarrival_time(rate: f32, rng: &mut impl Rng) -> Duration {
+    // Inverse transform sampling of the exponential distribution
+    let u: f32 = rng.gen_range(0.0..1.0);
+    Duration::try_from_secs_f32(-(-u).ln_1p() / rate).unwrap_or(Duration::MAX)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TraceError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("line {line}: '{value}' is not a time in seconds")]
+    InvalidTime { line: usize, value: String },
+}
+
+/// Parse the arrival times of a trace file, with one time in seconds per line,
+/// measured from when the formation starts spawning after its `delay`. Empty
+/// lines and lines starting with `#` are skipped. The times are returned in
+/// order.
+///
+/// # Errors
+///
+/// Will return `Err` if a line is not a non-negative number of seconds
+pub fn parse_trace(contents: &str) -> Result<Vec<Duration>, TraceError> {
+    let mut times = contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line, value)| {
+            value
+                .parse::<f64>()
+                .ok()
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                .ok_or_else(|| TraceError::InvalidTime {
+                    line,
+                    value: value.to_string(),
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    times.sort();
+    Ok(times)
+}
+
+/// Read and parse the trace file at `path`, see [`parse_trace`]
+///
+/// # Errors
+///
+/// Will return `Err` if the file can not be read, or is not a valid trace
+pub fn read_trace<P: AsRef<Path>>(path: P) -> Result<Vec<Duration>, TraceError> {
+    parse_trace(&std::fs::read_to_string(path)?)
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
 #[serde(rename_all = "kebab-case")]
 pub enum IntersectionDistance {
@@ -198,31 +312,358 @@ pub enum CheckIntersectionWith {
     Variable(NonZeroUsize),
 }
 
+/// A point a robot of a formation starts at or has to reach
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TaskPoint {
+    /// Position relative to the size of the environment, like the points of a
+    /// [`Shape`]
+    pub position: Point,
+    /// The floor of the environment the point is on, `0` being the ground
+    /// floor
+    #[serde(default)]
+    pub floor: usize,
+}
+
+/// The start and ordered goals of a single robot in a formation, optionally
+/// overriding the settings it would otherwise get from the config and the
+/// formation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RobotTasks {
+    /// Where the robot spawns
+    pub start: TaskPoint,
+    /// The goals the robot has to reach, in order
+    pub goals: OneOrMore<TaskPoint>,
+    /// Radius of the robot, instead of a random radius in
+    /// `config.robot.radius`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radius: Option<StrictlyPositiveFinite<f32>>,
+    /// Target speed of the robot, instead of `config.robot.target-speed`
+    /// SI unit: m/s
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed: Option<StrictlyPositiveFinite<f32>>,
+    /// Planning strategy of the robot, instead of the one of the formation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub planning_strategy: Option<PlanningStrategy>,
+}
+
+/// Where robots in lifelong operation head for, every time they reach their
+/// last goal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GoalGenerator {
+    /// A random position in the free space of the floor the robot is on
+    Random,
+    /// The next of the stations, starting over after the last one. Robots
+    /// start at different stations, spread out by the order they spawn in.
+    Stations(OneOrMore<Point>),
+    /// The goals in order. A robot is finished once it has reached the last
+    /// of them.
+    Script(OneOrMore<Point>),
+}
+
+/// Keeping the robots of a formation in a shape relative to each other while
+/// they travel. Every robot but the first is tied to a teammate spawned before
+/// it, at an offset in the frame of the teammate, where `x` points in the
+/// direction the teammate is heading and `y` to its left. SI unit: m
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FormationKeeping {
+    /// The shape to keep
+    pub shape: KeepingShape,
+    /// How strictly the shape is kept
+    #[serde(default)]
+    pub mode: KeepingMode,
+    /// The sigma of the relative pose factors is
+    /// `gbp.sigma-factor-relative-pose` divided by the stiffness
+    #[serde(default = "FormationKeeping::default_stiffness")]
+    pub stiffness: StrictlyPositiveFinite<f32>,
+}
+
+impl FormationKeeping {
+    fn default_stiffness() -> StrictlyPositiveFinite<f32> {
+        1.0.try_into().expect("1.0 is positive and finite")
+    }
+
+    /// The teammate robot number `robot` keeps its place relative to, and the
+    /// offset from the teammate. `None` for the first robot, or if the shape
+    /// has no place for the robot.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn teammate_of(&self, robot: usize) -> Option<(usize, Vec2)> {
+        if robot == 0 {
+            return None;
+        }
+        match &self.shape {
+            KeepingShape::Convoy { spacing } => Some((robot - 1, Vec2::new(-spacing.get(), 0.0))),
+            KeepingShape::Wedge {
+                spacing,
+                half_angle,
+            } => {
+                // Robots 1 and 2 are in the first row, 3 and 4 in the second, ...
+                let row = ((robot + 1) / 2) as f32;
+                let side = if robot % 2 == 1 { 1.0 } else { -1.0 };
+                let (sin, cos) = half_angle.to_radians().sin_cos();
+                Some((0, row * spacing.get() * Vec2::new(-cos, side * sin)))
+            }
+            KeepingShape::Offsets(offsets) => {
+                offsets.get(robot - 1).map(|&[x, y]| (0, Vec2::new(x, y)))
+            }
+        }
+    }
+}
+
+/// The shape a formation keeps while travelling
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeepingShape {
+    /// Every robot follows `spacing` meters behind the robot spawned before it
+    Convoy {
+        spacing: StrictlyPositiveFinite<f32>,
+    },
+    /// The robots fan out behind the first robot, alternating between its
+    /// left and right side, `spacing` meters apart along each side.
+    /// `half-angle` is the angle between each side and the heading of the
+    /// first robot, in degrees.
+    #[serde(rename_all = "kebab-case")]
+    Wedge {
+        spacing: StrictlyPositiveFinite<f32>,
+        half_angle: f32,
+    },
+    /// The offset of every robot but the first from the first robot
+    Offsets(Vec<[f32; 2]>),
+}
+
+/// How strictly a formation keeps its shape
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeepingMode {
+    /// The robots are pulled towards their place in the shape at all times
+    #[default]
+    Rigid,
+    /// The robots can drift up to `slack` meters from their place in the
+    /// shape before they are pulled back, so the shape can stretch around
+    /// obstacles and other robots
+    Elastic { slack: StrictlyPositiveFinite<f32> },
+}
+
+/// The robots a formation spawns every iteration
+#[derive(Debug, Clone)]
+pub enum FormationRobots {
+    /// A number of robots placed along the shape of the initial position, and
+    /// projected onto the shape of each waypoint
+    Projected {
+        /// Number of robots to spawn every iteration
+        robots: usize,
+        /// Where to spawn the formation
+        initial_position: InitialPosition,
+        /// List of waypoints.
+        waypoints: OneOrMore<Waypoint>,
+    },
+    /// Robots with an explicit start and list of goals each
+    Tasks(OneOrMore<RobotTasks>),
+}
+
+impl FormationRobots {
+    /// Number of robots spawned every iteration
+    #[must_use]
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Projected { robots, .. } => *robots,
+            Self::Tasks(tasks) => tasks.len(),
+        }
+    }
+
+    /// Returns true if no robots are spawned
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The tasks of robot number `robot`, if the robots are listed explicitly
+    #[must_use]
+    pub fn tasks_of(&self, robot: usize) -> Option<&RobotTasks> {
+        match self {
+            Self::Projected { .. } => None,
+            Self::Tasks(tasks) => tasks.as_slice().get(robot),
+        }
+    }
+}
+
+/// The start and goals of a single robot of a formation, in world coordinates
+#[derive(Debug, Clone)]
+pub struct RobotRoute {
+    /// Position the robot spawns at
+    pub start: Vec2,
+    /// Positions the robot has to reach, in order
+    pub goals: Vec<Vec2>,
+    /// The floor of the start, followed by the floor of each goal
+    pub floors: Vec<usize>,
+}
+
+/// Error returned when a formation neither places its robots along shapes,
+/// nor lists them explicitly
+#[derive(Debug, thiserror::Error)]
+pub enum FormationError {
+    #[error("`tasks` can not be combined with `robots`, `initial-position` or `waypoints`")]
+    TasksCombinedWithShapes,
+    #[error("expected either `robots`, `initial-position` and `waypoints`, or `tasks`")]
+    MissingRobots,
+    #[error("`arrivals` can not be combined with `repeat`")]
+    ArrivalsCombinedWithRepeat,
+    #[error("robots starting on a {start} can not be projected onto a waypoint of a {waypoint}")]
+    UnsupportedWaypointShape {
+        start: &'static str,
+        waypoint: &'static str,
+    },
+    #[error("the grid `spacing` of {spacing} is less than the robot radius of {radius}")]
+    GridSpacingBelowRadius { spacing: f32, radius: f32 },
+}
+
 /// A description of a formation of robots in the simulation.
 /// It describes how/where the robots are to be spawned, how many will be
 /// spawned, how often and where they should move to.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
+#[serde(try_from = "FormationFile", into = "FormationFile")]
 pub struct Formation {
     /// Optionally spawn this formation again repeatedly with the given
     /// duration.
     pub repeat: Option<Repeat>,
+    /// Optionally spawn this formation at random arrivals, instead of
+    /// repeatedly
+    pub arrivals: Option<Arrivals>,
     // pub repeat_every: bool,
     /// The delay from the start of the simulation after which the formation
     /// should spawn.
     pub delay: Duration,
-    /// Number of robots to spawn every iteration
-    // pub robots: NonZeroUsize,
-    pub robots: usize,
     /// Planning strategy
     pub planning_strategy: PlanningStrategy,
-    /// Where to spawn the formation
-    pub initial_position: InitialPosition,
-    /// List of waypoints.
-    pub waypoints: OneOrMore<Waypoint>,
+    /// The robots to spawn every iteration, and where they should move to
+    pub robots: FormationRobots,
     pub waypoint_reached_when_intersects: ReachedWhen,
-    #[serde(default = "Formation::default_finished_when_intersects")]
     pub finished_when_intersects: ReachedWhen,
+    /// Keep the robots going after their last goal, with new goals from the
+    /// generator, instead of finishing them
+    pub lifelong: Option<GoalGenerator>,
+    /// Keep the robots in a shape relative to each other while they travel
+    pub formation_keeping: Option<FormationKeeping>,
+    /// The robot class of the robots, one of the classes in the robot section
+    /// of the config. The robots use the global settings if not set.
+    pub class: Option<String>,
+    /// The priority of the robots, in place of the priority of their class.
+    /// Robots of lower priority yield more to robots of higher priority.
+    pub priority: Option<u8>,
+}
+
+/// How a [`Formation`] is written in `formation.yaml`. The robots are either
+/// given by `robots`, `initial-position` and `waypoints`, or by `tasks`.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct FormationFile {
+    repeat: Option<Repeat>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    arrivals: Option<Arrivals>,
+    delay: Duration,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    robots: Option<usize>,
+    planning_strategy: PlanningStrategy,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    initial_position: Option<InitialPosition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    waypoints: Option<OneOrMore<Waypoint>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tasks: Option<OneOrMore<RobotTasks>>,
+    waypoint_reached_when_intersects: ReachedWhen,
+    #[serde(default = "Formation::default_finished_when_intersects")]
+    finished_when_intersects: ReachedWhen,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lifelong: Option<GoalGenerator>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    formation_keeping: Option<FormationKeeping>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    class: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    priority: Option<u8>,
+}
+
+impl TryFrom<FormationFile> for Formation {
+    type Error = FormationError;
+
+    fn try_from(file: FormationFile) -> Result<Self, Self::Error> {
+        let robots = match (
+            file.tasks,
+            file.robots,
+            file.initial_position,
+            file.waypoints,
+        ) {
+            (Some(tasks), None, None, None) => FormationRobots::Tasks(tasks),
+            (Some(_), ..) => return Err(FormationError::TasksCombinedWithShapes),
+            (None, Some(robots), Some(initial_position), Some(waypoints)) => {
+                if let Some(waypoint) = waypoints
+                    .iter()
+                    .find(|waypoint| !can_project_onto(&initial_position.shape, &waypoint.shape))
+                {
+                    return Err(FormationError::UnsupportedWaypointShape {
+                        start: initial_position.shape.name(),
+                        waypoint: waypoint.shape.name(),
+                    });
+                }
+                FormationRobots::Projected {
+                    robots,
+                    initial_position,
+                    waypoints,
+                }
+            }
+            (None, ..) => return Err(FormationError::MissingRobots),
+        };
+        if file.arrivals.is_some() && file.repeat.is_some() {
+            return Err(FormationError::ArrivalsCombinedWithRepeat);
+        }
+
+        Ok(Self {
+            repeat: file.repeat,
+            arrivals: file.arrivals,
+            delay: file.delay,
+            planning_strategy: file.planning_strategy,
+            robots,
+            waypoint_reached_when_intersects: file.waypoint_reached_when_intersects,
+            finished_when_intersects: file.finished_when_intersects,
+            lifelong: file.lifelong,
+            formation_keeping: file.formation_keeping,
+            class: file.class,
+            priority: file.priority,
+        })
+    }
+}
+
+impl From<Formation> for FormationFile {
+    fn from(formation: Formation) -> Self {
+        let (robots, initial_position, waypoints, tasks) = match formation.robots {
+            FormationRobots::Projected {
+                robots,
+                initial_position,
+                waypoints,
+            } => (Some(robots), Some(initial_position), Some(waypoints), None),
+            FormationRobots::Tasks(tasks) => (None, None, None, Some(tasks)),
+        };
+
+        Self {
+            repeat: formation.repeat,
+            arrivals: formation.arrivals,
+            delay: formation.delay,
+            robots,
+            planning_strategy: formation.planning_strategy,
+            initial_position,
+            waypoints,
+            tasks,
+            waypoint_reached_when_intersects: formation.waypoint_reached_when_intersects,
+            finished_when_intersects: formation.finished_when_intersects,
+            lifelong: formation.lifelong,
+            formation_keeping: formation.formation_keeping,
+            class: formation.class,
+            priority: formation.priority,
+        }
+    }
 }
 
 impl Default for Formation {
@@ -261,7 +702,154 @@ impl Formation {
             RepeatTimes::Finite(times) => times,
         });
         // self.robots.get().saturating_mul(times)
-        self.robots.saturating_mul(times)
+        self.robots.len().saturating_mul(times)
+    }
+
+    /// Planning strategy of robot number `robot`, which can be overridden per
+    /// robot when the robots are listed explicitly
+    #[must_use]
+    pub fn planning_strategy_of(&self, robot: usize) -> PlanningStrategy {
+        self.robots
+            .tasks_of(robot)
+            .and_then(|tasks| tasks.planning_strategy)
+            .unwrap_or(self.planning_strategy)
+    }
+
+    /// Priority of the robots, given by the formation or else by the class of
+    /// the robots in `classes`. Robots without a priority have the lowest
+    /// priority of 0.
+    #[must_use]
+    pub fn priority(&self, classes: &BTreeMap<String, RobotClass>) -> u8 {
+        self.priority
+            .or_else(|| {
+                self.class
+                    .as_ref()
+                    .and_then(|class| classes.get(class))
+                    .and_then(|class| class.priority)
+            })
+            .unwrap_or(0)
+    }
+
+    /// Check the formation against the robots of the `robot` section, which
+    /// are not known until the config is loaded
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the robots are placed on a grid with a spacing less
+    /// than their smallest radius. They can not be placed closer than that,
+    /// and the number of grid points grows without bound as the spacing
+    /// shrinks.
+    pub fn validate(&self, robot: &RobotSection) -> Result<(), FormationError> {
+        let FormationRobots::Projected {
+            initial_position, ..
+        } = &self.robots
+        else {
+            return Ok(());
+        };
+        let InitialPlacementStrategy::Grid { spacing } = initial_position.placement_strategy else {
+            return Ok(());
+        };
+        let radius = self
+            .class
+            .as_ref()
+            .and_then(|class| robot.classes.get(class))
+            .and_then(|class| class.radius.as_ref())
+            .unwrap_or(&robot.radius)
+            .min
+            .get();
+        if spacing.get() < radius {
+            return Err(FormationError::GridSpacingBelowRadius {
+                spacing: spacing.get(),
+                radius,
+            });
+        }
+        Ok(())
+    }
+
+    /// Distance from `position` to where the formation spawns its robots on
+    /// `floor`, in world coordinates. Used to tell if other robots occupy the
+    /// entry of the formation.
+    ///
+    /// Returns `None` if the formation spawns no robots on `floor`.
+    #[must_use]
+    pub fn distance_to_entry(
+        &self,
+        world_dims: WorldDimensions,
+        position: Vec2,
+        floor: usize,
+    ) -> Option<f32> {
+        match &self.robots {
+            FormationRobots::Projected {
+                initial_position, ..
+            } => (initial_position.floor == floor)
+                .then(|| distance_to_shape(&initial_position.shape, world_dims, position)),
+            FormationRobots::Tasks(tasks) => tasks
+                .iter()
+                .filter(|tasks| tasks.start.floor == floor)
+                .map(|tasks| {
+                    world_dims
+                        .point_to_world_position(tasks.start.position)
+                        .distance(position)
+                })
+                .reduce(f32::min),
+        }
+    }
+
+    /// The start, goals and floors of each robot the formation spawns, in
+    /// world coordinates. `robot_radii` is the radius of each robot, used to
+    /// place robots along shapes without overlapping.
+    ///
+    /// Returns `None` if the robots could not be placed.
+    pub fn robot_routes(
+        &self,
+        world_dims: WorldDimensions,
+        robot_radii: &[f32],
+        rng: &mut impl Rng,
+    ) -> Option<Vec<RobotRoute>> {
+        match &self.robots {
+            FormationRobots::Projected {
+                initial_position,
+                waypoints,
+                ..
+            } => {
+                let (initial_positions, waypoint_positions) =
+                    self.as_positions(world_dims, robot_radii, rng)?;
+                let floors: Vec<usize> = std::iter::once(initial_position.floor)
+                    .chain(waypoints.iter().map(|waypoint| waypoint.floor))
+                    .collect();
+
+                Some(
+                    initial_positions
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, start)| RobotRoute {
+                            start,
+                            goals: waypoint_positions
+                                .iter()
+                                .map(|positions| positions[i])
+                                .collect(),
+                            floors: floors.clone(),
+                        })
+                        .collect(),
+                )
+            }
+            FormationRobots::Tasks(tasks) => Some(
+                tasks
+                    .iter()
+                    .map(|tasks| RobotRoute {
+                        start: world_dims.point_to_world_position(tasks.start.position),
+                        goals: tasks
+                            .goals
+                            .iter()
+                            .map(|goal| world_dims.point_to_world_position(goal.position))
+                            .collect(),
+                        floors: std::iter::once(tasks.start.floor)
+                            .chain(tasks.goals.iter().map(|goal| goal.floor))
+                            .collect(),
+                    })
+                    .collect(),
+            ),
+        }
     }
 
     /// Return a new `Formation` matching the used in the **gbpplanner** paper
@@ -275,21 +863,32 @@ impl Formation {
         Self {
             // repeat: None,
             repeat: Some(Repeat::new(Duration::from_secs(10), RepeatTimes::Finite(1))),
+            arrivals: None,
             delay: Duration::from_secs(1),
-            robots: 3.try_into().expect("3 > 0"),
             planning_strategy: PlanningStrategy::OnlyLocal,
-            initial_position: InitialPosition {
-                shape: circle.clone(),
-                placement_strategy: InitialPlacementStrategy::Equal,
+            robots: FormationRobots::Projected {
+                robots: 3.try_into().expect("3 > 0"),
+                initial_position: InitialPosition {
+                    shape: circle.clone(),
+                    placement_strategy: InitialPlacementStrategy::Equal,
+                    floor: 0,
+                },
+                waypoints: one_or_more![Waypoint::new(circle, ProjectionStrategy::Cross)],
             },
-            waypoints: one_or_more![Waypoint::new(circle, ProjectionStrategy::Cross)],
             waypoint_reached_when_intersects: ReachedWhen::same_as_paper(),
             finished_when_intersects: ReachedWhen::same_as_paper(),
+            lifelong: None,
+            formation_keeping: None,
+            class: None,
+            priority: None,
         }
     }
 
     /// Convert a `Formation` description into the waypoints the robot has to
     /// follow
+    ///
+    /// Returns `None` if the robots are listed explicitly, see
+    /// [`Formation::robot_routes`] for the routes of any formation.
     #[allow(
         clippy::missing_panics_doc,
         clippy::too_many_lines,
@@ -302,12 +901,22 @@ impl Formation {
         robot_radii: &[f32],
         rng: &mut impl Rng,
     ) -> Option<(Vec<Vec2>, Vec<Vec<Vec2>>)> {
-        match self.initial_position.shape {
+        let FormationRobots::Projected {
+            robots,
+            initial_position,
+            waypoints,
+        } = &self.robots
+        else {
+            return None;
+        };
+        let robots = *robots;
+
+        match initial_position.shape {
             Shape::LineSegment((ls_start, ls_end)) => {
                 let ls_start = world_dims.point_to_world_position(ls_start);
                 let ls_end = world_dims.point_to_world_position(ls_end);
 
-                let lerp_amounts = match &self.initial_position.placement_strategy {
+                let lerp_amounts = match &initial_position.placement_strategy {
                     InitialPlacementStrategy::Random { attempts } => {
                         randomly_place_nonoverlapping_circles_along_line_segment(
                             ls_start,
@@ -319,6 +928,16 @@ impl Formation {
                             rng,
                         )
                     }
+                    InitialPlacementStrategy::Grid { .. } => {
+                        let length = ls_start.distance(ls_end);
+                        place_along_polyline(
+                            &[ls_start, ls_end],
+                            robot_radii,
+                            initial_position.placement_strategy,
+                            rng,
+                        )
+                        .map(|distances| distances.iter().map(|d| d / length).collect())
+                    }
                     InitialPlacementStrategy::Equal => {
                         evenly_place_nonoverlapping_circles_along_line_segment(
                             ls_start,
@@ -338,41 +957,16 @@ impl Formation {
                 }?;
 
                 // assert_eq!(lerp_amounts.len(), self.robots.get());
-                assert_eq!(lerp_amounts.len(), self.robots);
+                assert_eq!(lerp_amounts.len(), robots);
 
                 let initial_positions: Vec<_> = lerp_amounts
                     .iter()
                     .map(|by| ls_start.lerp(ls_end, *by))
                     .collect();
 
-                let waypoints_of_each_robot: Vec<Vec<Vec2>> = self
-                    .waypoints
+                let waypoints_of_each_robot: Vec<Vec<Vec2>> = waypoints
                     .iter()
-                    .map(|wp| {
-                        let Shape::LineSegment((ls_start, ls_end)) = wp.shape else {
-                            unimplemented!("no time for the other combinations sadly :(");
-                        };
-                        let ls_start = world_dims.point_to_world_position(ls_start);
-                        let ls_end = world_dims.point_to_world_position(ls_end);
-                        let positions: Vec<Vec2> = match wp.projection_strategy {
-                            ProjectionStrategy::Identity => lerp_amounts
-                                .iter()
-                                .map(|by| ls_start.lerp(ls_end, *by))
-                                .collect(),
-                            ProjectionStrategy::Cross => lerp_amounts
-                                .iter()
-                                .rev()
-                                .map(|by| ls_start.lerp(ls_end, *by))
-                                .collect(),
-                        };
-                        // }
-                        //
-                        // let positions: Vec<Vec2> = lerp_amounts
-                        //     .iter()
-                        //     .map(|by| ls_start.lerp(ls_end, *by))
-                        //     .collect();
-                        positions
-                    })
+                    .map(|wp| project_along_line(wp, &lerp_amounts, world_dims))
                     .collect();
 
                 assert!(
@@ -388,28 +982,35 @@ impl Formation {
                 let perimeter_radius: f32 = radius.get();
                 let center = world_dims.point_to_world_position(center);
                 // dbg!(&center);
-                let angles: Vec<f32> = match self.initial_position.placement_strategy {
+                let angles: Vec<f32> = match initial_position.placement_strategy {
                     InitialPlacementStrategy::Equal => {
-                        let angle = 2.0 * PI / self.robots as f32;
-                        let angles = (0..self.robots).map(|i| i as f32 * angle).collect();
+                        let angle = 2.0 * PI / robots as f32;
+                        let angles = (0..robots).map(|i| i as f32 * angle).collect();
                         Some(angles)
                     }
-                    InitialPlacementStrategy::Random { attempts: _ } => {
-                        // TODO: check if it even makes sense to iterate
-                        // if B * n > 2 * math.pi * A:
-                        // let mut rng = thread_rng();
-                        todo!()
-
-                        // randomly_place_nonoverlapping_circles_along_circle_perimeter(
-                        //     self.robots,
-                        //     robot_radius,
-                        //     radius,
-                        //     attempts,
-                        //     &mut rng,
-                        // )
+                    InitialPlacementStrategy::Random { attempts } => {
+                        randomly_place_nonoverlapping_circles(
+                            robot_radii,
+                            attempts,
+                            rng,
+                            |rng, _| {
+                                let angle = rng.gen_range(0.0..TAU);
+                                Some((angle, polar(angle, perimeter_radius)))
+                            },
+                        )
+                    }
+                    InitialPlacementStrategy::Grid { spacing } => {
+                        let angle = spacing.get() / perimeter_radius;
+                        let angles: Vec<f32> = (0..robots).map(|i| i as f32 * angle).collect();
+                        let positions: Vec<Vec2> = angles
+                            .iter()
+                            .map(|angle| polar(*angle, perimeter_radius))
+                            .collect();
+                        (angle * robots as f32 <= TAU && nonoverlapping(&positions, robot_radii))
+                            .then_some(angles)
                     }
                 }?;
-                assert_eq!(angles.len(), self.robots);
+                assert_eq!(angles.len(), robots);
 
                 let initial_positions: Vec<Vec2> = angles
                     .iter()
@@ -417,12 +1018,11 @@ impl Formation {
                     .map(|polar| center + polar)
                     .collect();
 
-                let waypoints_of_each_robots: Vec<Vec<Vec2>> = self
-                    .waypoints
+                let waypoints_of_each_robots: Vec<Vec<Vec2>> = waypoints
                     .iter()
                     .map(|wp| {
                         let Shape::Circle { radius, center } = wp.shape else {
-                            unimplemented!("no time for the other combinations sadly :(");
+                            unreachable!("rejected when the formation is parsed");
                         };
                         match wp.projection_strategy {
                             ProjectionStrategy::Identity => {
@@ -445,8 +1045,103 @@ impl Formation {
 
                 Some((initial_positions, waypoints_of_each_robots))
             }
-            Shape::Polygon(_) => todo!(),
+            Shape::Polyline(ref vertices) => {
+                let vertices = world_vertices(vertices.as_slice(), world_dims);
+                let length = polyline_length(&vertices);
+                let distances = place_along_polyline(
+                    &vertices,
+                    robot_radii,
+                    initial_position.placement_strategy,
+                    rng,
+                )?;
+                assert_eq!(distances.len(), robots);
+
+                let initial_positions: Vec<Vec2> = distances
+                    .iter()
+                    .map(|distance| point_along_polyline(&vertices, *distance))
+                    .collect();
+                let fractions: Vec<f32> = distances.iter().map(|d| d / length).collect();
+
+                // Each robot keeps its relative distance along the line
+                let waypoints_of_each_robot = waypoints
+                    .iter()
+                    .map(|wp| project_along_line(wp, &fractions, world_dims))
+                    .collect();
+
+                Some((initial_positions, waypoints_of_each_robot))
+            }
+            Shape::Polygon(ref vertices) => {
+                let area = Area::new(world_vertices(vertices.as_slice(), world_dims))?;
+                let initial_positions =
+                    area.place(robot_radii, initial_position.placement_strategy, rng)?;
+                assert_eq!(initial_positions.len(), robots);
+
+                // Each robot keeps its position relative to the bounding box of the polygon
+                let relative: Vec<Vec2> = initial_positions
+                    .iter()
+                    .map(|position| area.relative(*position))
+                    .collect();
+                let waypoints_of_each_robot = waypoints
+                    .iter()
+                    .map(|wp| {
+                        let Shape::Polygon(vertices) = &wp.shape else {
+                            unreachable!("rejected when the formation is parsed");
+                        };
+                        let area = Area::new(world_vertices(vertices.as_slice(), world_dims))?;
+                        Some(
+                            relative
+                                .iter()
+                                .map(|relative| match wp.projection_strategy {
+                                    ProjectionStrategy::Identity => area.absolute(*relative),
+                                    ProjectionStrategy::Cross => {
+                                        area.absolute(Vec2::ONE - *relative)
+                                    }
+                                })
+                                .collect(),
+                        )
+                    })
+                    .collect::<Option<Vec<Vec<Vec2>>>>()?;
+
+                Some((initial_positions, waypoints_of_each_robot))
+            }
+        }
+    }
+}
+
+/// Whether robots starting on `start` can be projected onto a waypoint of
+/// `waypoint`. Robots along a line segment or polyline keep their relative
+/// distance along any line segment or polyline, while robots on a circle or
+/// inside a polygon can only move to another circle or polygon.
+const fn can_project_onto(start: &Shape, waypoint: &Shape) -> bool {
+    matches!(
+        (start, waypoint),
+        (
+            Shape::LineSegment(_) | Shape::Polyline(_),
+            Shape::LineSegment(_) | Shape::Polyline(_)
+        ) | (Shape::Circle { .. }, Shape::Circle { .. })
+            | (Shape::Polygon(_), Shape::Polygon(_))
+    )
+}
+
+/// The positions on the line segment or polyline of `waypoint` at `fractions`
+/// of its length, reversed if the waypoint crosses the robots over
+fn project_along_line(
+    waypoint: &Waypoint,
+    fractions: &[f32],
+    world_dims: WorldDimensions,
+) -> Vec<Vec2> {
+    let vertices = match &waypoint.shape {
+        Shape::LineSegment((start, end)) => world_vertices(&[*start, *end], world_dims),
+        Shape::Polyline(vertices) => world_vertices(vertices.as_slice(), world_dims),
+        Shape::Circle { .. } | Shape::Polygon(_) => {
+            unreachable!("rejected when the formation is parsed")
         }
+    };
+    let length = polyline_length(&vertices);
+    let at = |fraction: &f32| point_along_polyline(&vertices, fraction * length);
+    match waypoint.projection_strategy {
+        ProjectionStrategy::Identity => fractions.iter().map(at).collect(),
+        ProjectionStrategy::Cross => fractions.iter().rev().map(at).collect(),
     }
 }
 
@@ -641,6 +1336,316 @@ fn evenly_place_nonoverlapping_circles_along_line_segment(
     Some(placed)
 }
 
+/// The points of `points` in world coordinates
+fn world_vertices(points: &[Point], world_dims: WorldDimensions) -> Vec<Vec2> {
+    points
+        .iter()
+        .map(|point| world_dims.point_to_world_position(*point))
+        .collect()
+}
+
+/// Length of the polyline through `vertices`
+fn polyline_length(vertices: &[Vec2]) -> f32 {
+    vertices
+        .iter()
+        .tuple_windows()
+        .map(|(from, to)| from.distance(*to))
+        .sum()
+}
+
+/// The point `distance` along the polyline through `vertices`, clamped to the
+/// ends of the polyline
+fn point_along_polyline(vertices: &[Vec2], distance: f32) -> Vec2 {
+    let mut remaining = distance.max(0.0);
+    for (&from, &to) in vertices.iter().tuple_windows() {
+        let length = from.distance(to);
+        if remaining <= length {
+            return if length > 0.0 {
+                from.lerp(to, remaining / length)
+            } else {
+                from
+            };
+        }
+        remaining -= length;
+    }
+    *vertices
+        .last()
+        .expect("a polyline has at least two vertices")
+}
+
+/// Distance from `point` to the line segment from `a` to `b`
+fn distance_to_segment(point: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    if ab == Vec2::ZERO {
+        return point.distance(a);
+    }
+    let t = ((point - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0);
+    point.distance(a + t * ab)
+}
+
+/// Distance from `point` to the outline of `shape`, or 0 if `point` lies
+/// inside a polygon
+fn distance_to_shape(shape: &Shape, world_dims: WorldDimensions, point: Vec2) -> f32 {
+    match shape {
+        Shape::LineSegment((start, end)) => distance_to_segment(
+            point,
+            world_dims.point_to_world_position(*start),
+            world_dims.point_to_world_position(*end),
+        ),
+        Shape::Circle { radius, center } => {
+            (point.distance(world_dims.point_to_world_position(*center)) - radius.get()).abs()
+        }
+        Shape::Polyline(vertices) => world_vertices(vertices.as_slice(), world_dims)
+            .into_iter()
+            .tuple_windows()
+            .map(|(a, b)| distance_to_segment(point, a, b))
+            .fold(f32::INFINITY, f32::min),
+        Shape::Polygon(vertices) => {
+            let vertices = world_vertices(vertices.as_slice(), world_dims);
+            if let [vertex] = vertices.as_slice() {
+                return point.distance(*vertex);
+            }
+            if Area::new(vertices.clone()).is_some_and(|area| area.contains(point)) {
+                return 0.0;
+            }
+            vertices
+                .into_iter()
+                .circular_tuple_windows()
+                .map(|(a, b)| distance_to_segment(point, a, b))
+                .fold(f32::INFINITY, f32::min)
+        }
+    }
+}
+
+/// Whether a circle at `position` with `radius` overlaps any of the circles
+/// in `placed`
+fn overlaps(placed: &[(Vec2, f32)], position: Vec2, radius: f32) -> bool {
+    placed
+        .iter()
+        .any(|(other, other_radius)| position.distance(*other) < radius + other_radius)
+}
+
+/// Whether none of the circles at `positions` with `radii` overlap each other
+fn nonoverlapping(positions: &[Vec2], radii: &[f32]) -> bool {
+    positions
+        .iter()
+        .zip(radii)
+        .tuple_combinations()
+        .all(|((p1, r1), (p2, r2))| p1.distance(*p2) >= r1 + r2)
+}
+
+/// Place circles with `radii` one at a time, at positions drawn with
+/// `sample`, which returns `None` if a circle of the given radius does not fit
+/// at the drawn position. Positions where a circle would overlap the circles
+/// placed so far are drawn again. Returns the value `sample` returned with
+/// each position, or `None` if not all circles were placed after `attempts`
+/// draws in total.
+fn randomly_place_nonoverlapping_circles<T, R: Rng>(
+    radii: &[f32],
+    attempts: NonZeroUsize,
+    rng: &mut R,
+    mut sample: impl FnMut(&mut R, f32) -> Option<(T, Vec2)>,
+) -> Option<Vec<T>> {
+    let mut attempts = attempts.get();
+    let mut placed: Vec<(Vec2, f32)> = Vec::with_capacity(radii.len());
+    let mut values = Vec::with_capacity(radii.len());
+
+    for &radius in radii {
+        loop {
+            if attempts == 0 {
+                return None;
+            }
+            attempts -= 1;
+
+            let Some((value, position)) = sample(rng, radius) else {
+                continue;
+            };
+            if !overlaps(&placed, position, radius) {
+                placed.push((position, radius));
+                values.push(value);
+                break;
+            }
+        }
+    }
+
+    Some(values)
+}
+
+/// Distances along the polyline through `vertices` to place circles with
+/// `radii` at, such that none of them overlap, as the polyline might bend
+/// back on itself
+#[allow(clippy::cast_precision_loss)]
+fn place_along_polyline(
+    vertices: &[Vec2],
+    radii: &[f32],
+    strategy: InitialPlacementStrategy,
+    rng: &mut impl Rng,
+) -> Option<Vec<f32>> {
+    let length = polyline_length(vertices);
+    let (first, last) = (*radii.first()?, *radii.last()?);
+
+    let distances: Vec<f32> = match strategy {
+        InitialPlacementStrategy::Equal if radii.len() == 1 => vec![length / 2.0],
+        InitialPlacementStrategy::Equal => {
+            let spacing = (length - first - last) / (radii.len() - 1) as f32;
+            (0..radii.len())
+                .map(|i| (i as f32).mul_add(spacing, first))
+                .collect()
+        }
+        InitialPlacementStrategy::Grid { spacing } => (0..radii.len())
+            .map(|i| (i as f32).mul_add(spacing.get(), first))
+            .collect(),
+        InitialPlacementStrategy::Random { attempts } => {
+            return randomly_place_nonoverlapping_circles(radii, attempts, rng, |rng, _| {
+                let distance = rng.gen_range(0.0..=length);
+                Some((distance, point_along_polyline(vertices, distance)))
+            });
+        }
+    };
+
+    let positions: Vec<Vec2> = distances
+        .iter()
+        .map(|distance| point_along_polyline(vertices, *distance))
+        .collect();
+    let within = distances
+        .iter()
+        .all(|distance| (0.0..=length).contains(distance));
+
+    (within && nonoverlapping(&positions, radii)).then_some(distances)
+}
+
+/// A polygon in world coordinates, to place robots inside
+#[derive(Debug)]
+struct Area {
+    vertices: Vec<Vec2>,
+    /// Corner of the bounding box with the lowest coordinates
+    min: Vec2,
+    /// Corner of the bounding box with the highest coordinates
+    max: Vec2,
+}
+
+impl Area {
+    /// Returns `None` if the polygon through `vertices` has no area
+    fn new(vertices: Vec<Vec2>) -> Option<Self> {
+        if vertices.len() < 3 {
+            return None;
+        }
+        let min = vertices.iter().copied().reduce(Vec2::min)?;
+        let max = vertices.iter().copied().reduce(Vec2::max)?;
+        (min.x < max.x && min.y < max.y).then_some(Self { vertices, min, max })
+    }
+
+    fn edges(&self) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+        self.vertices.iter().copied().circular_tuple_windows()
+    }
+
+    /// Whether `point` lies inside the polygon, by casting a ray from it and
+    /// counting the edges it crosses
+    fn contains(&self, point: Vec2) -> bool {
+        self.edges().fold(false, |inside, (a, b)| {
+            let crosses = (a.y > point.y) != (b.y > point.y)
+                && point.x < (b.x - a.x) * (point.y - a.y) / (b.y - a.y) + a.x;
+            inside != crosses
+        })
+    }
+
+    /// Whether a circle at `point` with `radius` lies entirely inside the
+    /// polygon
+    fn fits(&self, point: Vec2, radius: f32) -> bool {
+        self.contains(point)
+            && self
+                .edges()
+                .all(|(a, b)| distance_to_segment(point, a, b) >= radius)
+    }
+
+    /// Position of `point` relative to the bounding box, `(0, 0)` being the
+    /// corner with the lowest coordinates
+    fn relative(&self, point: Vec2) -> Vec2 {
+        (point - self.min) / (self.max - self.min)
+    }
+
+    /// Inverse of [`Area::relative`]
+    fn absolute(&self, relative: Vec2) -> Vec2 {
+        self.min + relative * (self.max - self.min)
+    }
+
+    /// Points of a grid with `spacing` between neighbours, centered in the
+    /// bounding box, row by row from the top left
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    fn grid(&self, spacing: f32) -> Vec<Vec2> {
+        let size = self.max - self.min;
+        let cols = (size.x / spacing) as usize + 1;
+        let rows = (size.y / spacing) as usize + 1;
+        let margin = (size - Vec2::new((cols - 1) as f32, (rows - 1) as f32) * spacing) / 2.0;
+
+        (0..rows)
+            .flat_map(|row| {
+                (0..cols).map(move |col| {
+                    Vec2::new(
+                        (col as f32).mul_add(spacing, self.min.x + margin.x),
+                        (row as f32).mul_add(-spacing, self.max.y - margin.y),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Place circles with `radii` on the points of a grid with `spacing`,
+    /// skipping the points where a circle would not fit
+    fn place_on_grid(&self, radii: &[f32], spacing: f32) -> Option<Vec<Vec2>> {
+        let mut points = self.grid(spacing).into_iter();
+        let mut placed: Vec<(Vec2, f32)> = Vec::with_capacity(radii.len());
+        for &radius in radii {
+            let point = points
+                .find(|point| self.fits(*point, radius) && !overlaps(&placed, *point, radius))?;
+            placed.push((point, radius));
+        }
+
+        Some(placed.into_iter().map(|(point, _)| point).collect())
+    }
+
+    /// Place circles with `radii` inside the polygon, such that none of them
+    /// overlap each other or the edges of the polygon
+    #[allow(clippy::cast_precision_loss)]
+    fn place(
+        &self,
+        radii: &[f32],
+        strategy: InitialPlacementStrategy,
+        rng: &mut impl Rng,
+    ) -> Option<Vec<Vec2>> {
+        match strategy {
+            InitialPlacementStrategy::Grid { spacing } => self.place_on_grid(radii, spacing.get()),
+            InitialPlacementStrategy::Equal => {
+                // Shrink the grid until every circle fits, to spread them out as much as
+                // possible
+                let smallest = 2.0 * radii.iter().copied().reduce(f32::min)?;
+                let size = self.max - self.min;
+                let mut spacing = (size.x * size.y / radii.len() as f32).sqrt();
+                while spacing >= smallest {
+                    if let Some(placed) = self.place_on_grid(radii, spacing) {
+                        return Some(placed);
+                    }
+                    spacing *= 0.95;
+                }
+                None
+            }
+            InitialPlacementStrategy::Random { attempts } => {
+                randomly_place_nonoverlapping_circles(radii, attempts, rng, |rng, radius| {
+                    let point = Vec2::new(
+                        rng.gen_range(self.min.x..=self.max.x),
+                        rng.gen_range(self.min.y..=self.max.y),
+                    );
+                    self.fits(point, radius).then_some((point, point))
+                })
+            }
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum ParseError {
     #[error("IO error: {0}")]
@@ -691,6 +1696,18 @@ impl FormationGroup {
             .fold(0usize, usize::saturating_add)
     }
 
+    /// Check every formation in the group against the robots of the `robot`
+    /// section. See [`Formation::validate`].
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` with the error of the first invalid formation
+    pub fn validate(&self, robot: &RobotSection) -> Result<(), FormationError> {
+        self.formations
+            .iter()
+            .try_for_each(|formation| formation.validate(robot))
+    }
+
     pub fn circle_from_paper() -> Self {
         Self {
             formations: one_or_more![Formation::circle_from_paper()],
@@ -707,24 +1724,31 @@ impl FormationGroup {
                         every: Duration::from_secs(4),
                         times: RepeatTimes::Finite(2),
                     }),
+                    arrivals: None,
                     delay: Duration::from_secs(2),
-                    robots: 1.try_into().expect("1 > 0"),
                     planning_strategy: PlanningStrategy::OnlyLocal,
-                    initial_position: InitialPosition {
-                        shape: line![(0.45, 0.0), (0.55, 0.0)],
-                        placement_strategy: InitialPlacementStrategy::Equal,
+                    robots: FormationRobots::Projected {
+                        robots: 1.try_into().expect("1 > 0"),
+                        initial_position: InitialPosition {
+                            shape: line![(0.45, 0.0), (0.55, 0.0)],
+                            placement_strategy: InitialPlacementStrategy::Equal,
+                            floor: 0,
+                        },
+                        waypoints: one_or_more![Waypoint::new(
+                            line![(0.45, 1.25), (0.55, 1.25)],
+                            ProjectionStrategy::Identity
+                        )],
                     },
 
-                    waypoints: one_or_more![Waypoint::new(
-                        line![(0.45, 1.25), (0.55, 1.25)],
-                        ProjectionStrategy::Identity
-                    ),],
-
                     waypoint_reached_when_intersects: ReachedWhen::same_as_paper(),
                     finished_when_intersects: ReachedWhen {
                         distance: IntersectionDistance::RobotRadius,
                         intersects_with: CheckIntersectionWith::Current,
                     },
+                    lifelong: None,
+                    formation_keeping: None,
+                    class: None,
+                    priority: None,
                 },
                 Formation {
                     // repeat: Some(Duration::from_secs(4)),
@@ -732,24 +1756,31 @@ impl FormationGroup {
                         every: Duration::from_secs(4),
                         times: RepeatTimes::Finite(2),
                     }),
+                    arrivals: None,
                     delay: Duration::from_secs(2),
-                    robots: 1.try_into().expect("1 > 0"),
                     planning_strategy: PlanningStrategy::OnlyLocal,
-                    initial_position: InitialPosition {
-                        shape: line![(0.0, 0.45), (0.0, 0.55)],
-                        placement_strategy: InitialPlacementStrategy::Equal,
+                    robots: FormationRobots::Projected {
+                        robots: 1.try_into().expect("1 > 0"),
+                        initial_position: InitialPosition {
+                            shape: line![(0.0, 0.45), (0.0, 0.55)],
+                            placement_strategy: InitialPlacementStrategy::Equal,
+                            floor: 0,
+                        },
+                        waypoints: one_or_more![Waypoint::new(
+                            line![(1.25, 0.45), (1.25, 0.55)],
+                            ProjectionStrategy::Identity
+                        )],
                     },
 
-                    waypoints: one_or_more![Waypoint::new(
-                        line![(1.25, 0.45), (1.25, 0.55)],
-                        ProjectionStrategy::Identity,
-                    ),],
-
                     waypoint_reached_when_intersects: ReachedWhen::same_as_paper(),
                     finished_when_intersects: ReachedWhen {
                         distance: IntersectionDistance::RobotRadius,
                         intersects_with: CheckIntersectionWith::Current,
                     },
+                    lifelong: None,
+                    formation_keeping: None,
+                    class: None,
+                    priority: None,
                 },
             ],
         }
@@ -770,6 +1801,631 @@ mod tests {
     mod formation {
         use super::*;
 
+        /// A formation of a single robot moving along the bottom of the
+        /// environment, spawned once, followed by the lines of `extra`
+        fn formation_yaml(extra: &str) -> String {
+            format!(
+                r"
+formations:
+- delay:
+    secs: 0
+    nanos: 0
+  planning-strategy: only-local
+  tasks:
+  - start:
+      position: {{ x: 0.1, y: 0.1 }}
+    goals:
+    - position: {{ x: 0.9, y: 0.1 }}
+  waypoint-reached-when-intersects:
+    intersects-with: current
+{extra}
+"
+            )
+        }
+
+        mod lifelong {
+            use super::*;
+
+            #[test]
+            fn not_lifelong_by_default() {
+                let group =
+                    FormationGroup::parse_from_yaml(&formation_yaml("")).expect("valid formation");
+                assert!(group.formations.first().lifelong.is_none());
+            }
+
+            #[test]
+            fn parses_goal_generators() {
+                let random = FormationGroup::parse_from_yaml(&formation_yaml("  lifelong: random"))
+                    .expect("valid formation");
+                assert!(matches!(
+                    random.formations.first().lifelong,
+                    Some(GoalGenerator::Random)
+                ));
+
+                let stations = FormationGroup::parse_from_yaml(&formation_yaml(
+                    "  lifelong: !stations\n  - { x: 0.2, y: 0.2 }\n  - { x: 0.8, y: 0.8 }",
+                ))
+                .expect("valid formation");
+                assert!(matches!(
+                    &stations.formations.first().lifelong,
+                    Some(GoalGenerator::Stations(stations)) if stations.len() == 2
+                ));
+            }
+
+            #[test]
+            fn round_trip() {
+                let group = FormationGroup::parse_from_yaml(&formation_yaml(
+                    "  lifelong: !script\n  - { x: 0.2, y: 0.2 }",
+                ))
+                .expect("valid formation");
+                let yaml = serde_yaml::to_string(&group).expect("serializable");
+                let parsed = FormationGroup::parse_from_yaml(&yaml).expect("valid formation");
+                assert!(matches!(
+                    parsed.formations.first().lifelong,
+                    Some(GoalGenerator::Script(_))
+                ));
+            }
+        }
+
+        mod formation_keeping {
+            use super::*;
+
+            #[test]
+            fn not_kept_by_default() {
+                let group =
+                    FormationGroup::parse_from_yaml(&formation_yaml("")).expect("valid formation");
+                assert!(group.formations.first().formation_keeping.is_none());
+            }
+
+            #[test]
+            fn convoy_follows_the_robot_before() {
+                let group = FormationGroup::parse_from_yaml(&formation_yaml(
+                    "  formation-keeping:\n    shape: !convoy { spacing: 2.0 }",
+                ))
+                .expect("valid formation");
+                let keeping = group
+                    .formations
+                    .first()
+                    .formation_keeping
+                    .clone()
+                    .expect("formation keeping is set");
+                assert!(matches!(keeping.mode, KeepingMode::Rigid));
+                assert!(keeping.teammate_of(0).is_none());
+                assert_eq!(keeping.teammate_of(3), Some((2, Vec2::new(-2.0, 0.0))));
+            }
+
+            #[test]
+            fn wedge_alternates_sides_behind_the_first_robot() {
+                let group = FormationGroup::parse_from_yaml(&formation_yaml(
+                    "  formation-keeping:\n    shape: !wedge { spacing: 1.0, half-angle: 60.0 \
+                         }\n    mode: !elastic { slack: 0.5 }\n    stiffness: 2.0",
+                ))
+                .expect("valid formation");
+                let keeping = group
+                    .formations
+                    .first()
+                    .formation_keeping
+                    .clone()
+                    .expect("formation keeping is set");
+                assert!(matches!(keeping.mode, KeepingMode::Elastic { .. }));
+
+                let (teammate, left) = keeping.teammate_of(1).expect("robot 1 has a teammate");
+                let (_, right) = keeping.teammate_of(2).expect("robot 2 has a teammate");
+                let (_, second_row) = keeping.teammate_of(3).expect("robot 3 has a teammate");
+                assert_eq!(teammate, 0);
+                let (sin, cos) = 60f32.to_radians().sin_cos();
+                assert!(left.abs_diff_eq(Vec2::new(-cos, sin), 1e-6));
+                assert!(right.abs_diff_eq(Vec2::new(-cos, -sin), 1e-6));
+                assert!(second_row.abs_diff_eq(Vec2::new(-2.0 * cos, 2.0 * sin), 1e-6));
+            }
+
+            #[test]
+            fn offsets_beyond_the_list_have_no_teammate() {
+                let group = FormationGroup::parse_from_yaml(&formation_yaml(
+                    "  formation-keeping:\n    shape: !offsets\n    - [-1.0, 1.0]",
+                ))
+                .expect("valid formation");
+                let keeping = group
+                    .formations
+                    .first()
+                    .formation_keeping
+                    .clone()
+                    .expect("formation keeping is set");
+                assert_eq!(keeping.teammate_of(1), Some((0, Vec2::new(-1.0, 1.0))));
+                assert!(keeping.teammate_of(2).is_none());
+            }
+        }
+
+        mod priority {
+            use super::*;
+
+            fn with_priority(priority: &str) -> FormationGroup {
+                FormationGroup::parse_from_yaml(&formation_yaml(&format!(
+                    "  class: ambulance\n{priority}"
+                )))
+                .expect("valid formation")
+            }
+
+            fn classes() -> BTreeMap<String, RobotClass> {
+                BTreeMap::from([(
+                    "ambulance".to_string(),
+                    RobotClass {
+                        priority: Some(3),
+                        ..Default::default()
+                    },
+                )])
+            }
+
+            #[test]
+            fn lowest_priority_without_a_class() {
+                let group = with_priority("");
+                assert_eq!(group.formations.first().priority(&BTreeMap::new()), 0);
+            }
+
+            #[test]
+            fn priority_of_the_class() {
+                let group = with_priority("");
+                assert_eq!(group.formations.first().priority(&classes()), 3);
+            }
+
+            #[test]
+            fn formation_overrides_the_class() {
+                let group = with_priority("  priority: 1");
+                assert_eq!(group.formations.first().priority(&classes()), 1);
+            }
+        }
+
+        mod arrivals {
+            use rand::{SeedableRng, rngs::StdRng};
+
+            use super::*;
+
+            fn with_arrivals(arrivals: &str) -> Result<FormationGroup, ParseError> {
+                FormationGroup::parse_from_yaml(&formation_yaml(arrivals))
+            }
+
+            #[test]
+            fn poisson_arrivals_are_parsed() {
+                let group =
+                    with_arrivals("  arrivals: !poisson\n    rate: 0.5\n    times: !finite 20")
+                        .expect("valid formation");
+                let arrivals = group
+                    .formations
+                    .first()
+                    .arrivals
+                    .clone()
+                    .expect("arrivals are set");
+                assert!(matches!(arrivals, Arrivals::Poisson { .. }));
+                assert_eq!(arrivals.poisson_times(), Some(20));
+            }
+
+            #[test]
+            fn arrivals_can_not_be_combined_with_repeat() {
+                let repeat = "  repeat:\n    every:\n      secs: 1\n      nanos: 0\n    times: \
+                              infinite\n  arrivals: !trace\n    file: arrivals.txt";
+                assert!(with_arrivals(repeat).is_err());
+            }
+
+            #[test]
+            fn trace_is_sorted_and_skips_comments() {
+                let trace =
+                    parse_trace("# arrival times\n2.5\n\n0.5\n  1  \n").expect("valid trace");
+                assert_eq!(
+                    trace,
+                    vec![
+                        Duration::from_secs_f64(0.5),
+                        Duration::from_secs(1),
+                        Duration::from_secs_f64(2.5),
+                    ]
+                );
+            }
+
+            #[test]
+            fn trace_with_invalid_time_is_rejected() {
+                assert!(matches!(
+                    parse_trace("1.0\n-2.0\n"),
+                    Err(TraceError::InvalidTime { line: 2, .. })
+                ));
+                assert!(parse_trace("soon\n").is_err());
+            }
+
+            #[test]
+            #[allow(clippy::cast_precision_loss)]
+            fn poisson_interarrival_times_have_the_mean_of_the_rate() {
+                const SAMPLES: usize = 10_000;
+                let mut rng = StdRng::seed_from_u64(0);
+                let rate = 4.0;
+                let mean = (0..SAMPLES)
+                    .map(|_| poisson_interarrival_time(rate, &mut rng).as_secs_f32())
+                    .sum::<f32>()
+                    / SAMPLES as f32;
+                assert!((mean - 1.0 / rate).abs() < 0.01, "mean is {mean}");
+            }
+
+            #[test]
+            fn poisson_interarrival_times_of_a_tiny_rate_are_clamped() {
+                let mut rng = StdRng::seed_from_u64(0);
+                for _ in 0..100 {
+                    let time = poisson_interarrival_time(f32::MIN_POSITIVE, &mut rng);
+                    assert!(time > Duration::from_secs(u64::from(u32::MAX)));
+                }
+            }
+
+            #[test]
+            fn distance_to_entry_of_tasks_is_to_the_nearest_start() {
+                let group = with_arrivals("").expect("valid formation");
+                let world_dims = WorldDimensions::new(100.0, 100.0);
+                let formation = group.formations.first();
+                let distance = formation
+                    .distance_to_entry(world_dims, Vec2::new(-40.0, -37.0), 0)
+                    .expect("spawns on the ground floor");
+                assert!((distance - 3.0).abs() < 1e-4);
+                assert!(
+                    formation
+                        .distance_to_entry(world_dims, Vec2::ZERO, 1)
+                        .is_none()
+                );
+            }
+        }
+
+        mod placement {
+            use min_len_vec::TwoOrMore;
+            use rand::{SeedableRng, rngs::StdRng};
+
+            use super::*;
+            use crate::{RobotRadiusSection, polygon};
+
+            const RADIUS: f32 = 1.0;
+
+            fn world_dims() -> WorldDimensions {
+                WorldDimensions::new(100.0, 100.0)
+            }
+
+            fn projected(
+                robots: usize,
+                shape: Shape,
+                placement_strategy: InitialPlacementStrategy,
+            ) -> Formation {
+                Formation {
+                    robots: FormationRobots::Projected {
+                        robots,
+                        initial_position: InitialPosition {
+                            shape: shape.clone(),
+                            placement_strategy,
+                            floor: 0,
+                        },
+                        waypoints: one_or_more![Waypoint::new(shape, ProjectionStrategy::Identity)],
+                    },
+                    ..Formation::circle_from_paper()
+                }
+            }
+
+            fn initial_positions(formation: &Formation, radii: &[f32]) -> Option<Vec<Vec2>> {
+                let mut rng = StdRng::seed_from_u64(0);
+                formation
+                    .as_positions(world_dims(), radii, &mut rng)
+                    .map(|(initial_positions, _)| initial_positions)
+            }
+
+            /// An L shaped polyline, 80 meters long in a 100x100 world
+            fn polyline() -> Shape {
+                Shape::Polyline(
+                    TwoOrMore::new(vec![
+                        Point::new(0.1, 0.1),
+                        Point::new(0.5, 0.1),
+                        Point::new(0.5, 0.5),
+                    ])
+                    .expect("three points"),
+                )
+            }
+
+            fn assert_close(actual: &[Vec2], expected: &[Vec2]) {
+                assert_eq!(actual.len(), expected.len());
+                for (a, e) in actual.iter().zip(expected) {
+                    assert!(a.distance(*e) < 1e-3, "{a} is not close to {e}");
+                }
+            }
+
+            #[test]
+            fn equal_along_polyline_spans_the_whole_line() {
+                let formation = projected(3, polyline(), InitialPlacementStrategy::Equal);
+                let positions =
+                    initial_positions(&formation, &[RADIUS; 3]).expect("robots fit the line");
+                assert_close(
+                    &positions,
+                    &[
+                        Vec2::new(-39.0, -40.0),
+                        Vec2::new(0.0, -40.0),
+                        Vec2::new(0.0, -1.0),
+                    ],
+                );
+            }
+
+            #[test]
+            fn grid_along_polyline_is_spaced_from_the_start() {
+                let formation = projected(
+                    3,
+                    polyline(),
+                    InitialPlacementStrategy::Grid {
+                        spacing: 5.0.try_into().expect("positive and finite"),
+                    },
+                );
+                let positions =
+                    initial_positions(&formation, &[RADIUS; 3]).expect("robots fit the line");
+                assert_close(
+                    &positions,
+                    &[
+                        Vec2::new(-39.0, -40.0),
+                        Vec2::new(-34.0, -40.0),
+                        Vec2::new(-29.0, -40.0),
+                    ],
+                );
+            }
+
+            #[test]
+            fn grid_longer_than_polyline_is_rejected() {
+                let formation = projected(
+                    3,
+                    polyline(),
+                    InitialPlacementStrategy::Grid {
+                        spacing: 50.0.try_into().expect("positive and finite"),
+                    },
+                );
+                assert!(initial_positions(&formation, &[RADIUS; 3]).is_none());
+            }
+
+            #[test]
+            fn grid_spacing_below_the_radius_is_rejected() {
+                let mut formation = projected(
+                    3,
+                    polyline(),
+                    InitialPlacementStrategy::Grid {
+                        spacing: 0.5.try_into().expect("positive and finite"),
+                    },
+                );
+                let mut robot = RobotSection::default();
+                assert!(matches!(
+                    formation.validate(&robot),
+                    Err(FormationError::GridSpacingBelowRadius { .. })
+                ));
+
+                // Robots of a smaller class fit the grid
+                robot.classes.insert(
+                    "scooter".to_string(),
+                    RobotClass {
+                        radius: Some(RobotRadiusSection {
+                            min: 0.25.try_into().expect("positive and finite"),
+                            max: 0.5.try_into().expect("positive and finite"),
+                        }),
+                        ..Default::default()
+                    },
+                );
+                formation.class = Some("scooter".to_string());
+                assert!(formation.validate(&robot).is_ok());
+            }
+
+            #[test]
+            fn line_segment_is_projected_onto_polyline_waypoint() {
+                let mut formation = projected(
+                    3,
+                    line![(0.1, 0.9), (0.9, 0.9)],
+                    InitialPlacementStrategy::Equal,
+                );
+                let FormationRobots::Projected { waypoints, .. } = &mut formation.robots else {
+                    unreachable!("projected formation");
+                };
+                *waypoints = one_or_more![Waypoint::new(polyline(), ProjectionStrategy::Identity)];
+
+                let mut rng = StdRng::seed_from_u64(0);
+                let (initial_positions, waypoints) = formation
+                    .as_positions(world_dims(), &[RADIUS; 3], &mut rng)
+                    .expect("robots fit the line");
+                // Every robot keeps its distance from the start of the line,
+                // which is 80 meters long, the same as the polyline
+                let expected: Vec<Vec2> = initial_positions
+                    .iter()
+                    .map(|position| {
+                        let distance = position.x + 40.0;
+                        if distance <= 40.0 {
+                            Vec2::new(-40.0 + distance, -40.0)
+                        } else {
+                            Vec2::new(0.0, distance - 80.0)
+                        }
+                    })
+                    .collect();
+                assert_close(&waypoints[0], &expected);
+            }
+
+            #[test]
+            fn unsupported_waypoint_shape_is_rejected() {
+                let mut formation = projected(
+                    3,
+                    Shape::Circle {
+                        radius: 0.4.try_into().expect("positive and finite"),
+                        center: Point::new(0.5, 0.5),
+                    },
+                    InitialPlacementStrategy::Equal,
+                );
+                let FormationRobots::Projected { waypoints, .. } = &mut formation.robots else {
+                    unreachable!("projected formation");
+                };
+                *waypoints = one_or_more![Waypoint::new(
+                    line![(0.1, 0.1), (0.9, 0.1)],
+                    ProjectionStrategy::Identity
+                )];
+
+                assert!(matches!(
+                    Formation::try_from(FormationFile::from(formation)),
+                    Err(FormationError::UnsupportedWaypointShape {
+                        start: "circle",
+                        waypoint: "line-segment",
+                    })
+                ));
+            }
+
+            #[test]
+            fn random_inside_polygon_fits_without_overlap() {
+                let square = polygon![(0.2, 0.2), (0.8, 0.2), (0.8, 0.8), (0.2, 0.8)];
+                let formation = projected(
+                    20,
+                    square,
+                    InitialPlacementStrategy::Random {
+                        attempts: NonZeroUsize::new(10_000).expect("not zero"),
+                    },
+                );
+                let radii = [2.0; 20];
+                let positions =
+                    initial_positions(&formation, &radii).expect("robots fit the polygon");
+                assert!(nonoverlapping(&positions, &radii));
+                assert!(
+                    positions
+                        .iter()
+                        .all(|p| p.x.abs() <= 28.0 && p.y.abs() <= 28.0)
+                );
+            }
+
+            #[test]
+            fn equal_inside_polygon_fits_without_overlap() {
+                let triangle = polygon![(0.1, 0.1), (0.9, 0.1), (0.5, 0.9)];
+                let formation = projected(10, triangle, InitialPlacementStrategy::Equal);
+                let radii = [2.0; 10];
+                let positions =
+                    initial_positions(&formation, &radii).expect("robots fit the polygon");
+                let area = Area::new(world_vertices(
+                    &[
+                        Point::new(0.1, 0.1),
+                        Point::new(0.9, 0.1),
+                        Point::new(0.5, 0.9),
+                    ],
+                    world_dims(),
+                ))
+                .expect("triangle has an area");
+                assert!(nonoverlapping(&positions, &radii));
+                assert!(positions.iter().all(|p| area.fits(*p, 2.0)));
+            }
+
+            #[test]
+            fn too_many_robots_for_polygon_are_rejected() {
+                let square = polygon![(0.45, 0.45), (0.55, 0.45), (0.55, 0.55), (0.45, 0.55)];
+                let formation = projected(100, square, InitialPlacementStrategy::Equal);
+                assert!(initial_positions(&formation, &[2.0; 100]).is_none());
+            }
+
+            #[test]
+            fn random_along_circle_does_not_overlap() {
+                let circle = Shape::Circle {
+                    radius: 25.0.try_into().expect("positive and finite"),
+                    center: Point::new(0.5, 0.5),
+                };
+                let mut formation = projected(
+                    8,
+                    circle.clone(),
+                    InitialPlacementStrategy::Random {
+                        attempts: NonZeroUsize::new(10_000).expect("not zero"),
+                    },
+                );
+                if let FormationRobots::Projected { waypoints, .. } = &mut formation.robots {
+                    *waypoints = one_or_more![Waypoint::new(circle, ProjectionStrategy::Cross)];
+                }
+                let radii = [3.0; 8];
+                let positions =
+                    initial_positions(&formation, &radii).expect("robots fit the circle");
+                assert!(nonoverlapping(&positions, &radii));
+            }
+        }
+
+        mod tasks {
+            use pretty_assertions::assert_eq;
+
+            use super::*;
+
+            const TASKS: &str = r"
+formations:
+- repeat: null
+  delay:
+    secs: 0
+    nanos: 0
+  planning-strategy: only-local
+  tasks:
+  - start:
+      position: { x: 0.1, y: 0.1 }
+    goals:
+    - position: { x: 0.9, y: 0.1 }
+    - position: { x: 0.9, y: 0.9 }
+      floor: 1
+    radius: 2.0
+    speed: 5.0
+  - start:
+      position: { x: 0.1, y: 0.9 }
+    goals:
+    - position: { x: 0.5, y: 0.5 }
+    planning-strategy: rrt-star
+  waypoint-reached-when-intersects:
+    intersects-with: current
+";
+
+            #[test]
+            fn parses_explicit_tasks() {
+                let group = FormationGroup::parse_from_yaml(TASKS).expect("valid formation");
+                let formation = group.formations.first();
+                assert_eq!(formation.robots.len(), 2);
+                assert_eq!(group.robots_to_spawn(), 2);
+
+                let first = formation.robots.tasks_of(0).expect("robots are listed");
+                assert_eq!(first.goals.len(), 2);
+                assert_eq!(first.goals.last().floor, 1);
+                assert_eq!(first.radius.map(|radius| radius.get()), Some(2.0));
+                assert!(matches!(
+                    formation.planning_strategy_of(0),
+                    PlanningStrategy::OnlyLocal
+                ));
+                assert!(matches!(
+                    formation.planning_strategy_of(1),
+                    PlanningStrategy::RrtStar
+                ));
+            }
+
+            #[test]
+            fn routes_of_explicit_tasks() {
+                let group = FormationGroup::parse_from_yaml(TASKS).expect("valid formation");
+                let routes = group
+                    .formations
+                    .first()
+                    .robot_routes(
+                        WorldDimensions::new(100.0, 100.0),
+                        &[2.0, 1.0],
+                        &mut rand::thread_rng(),
+                    )
+                    .expect("explicit tasks can always be placed");
+
+                assert_eq!(routes.len(), 2);
+                assert_eq!(routes[0].start, Vec2::new(-40.0, -40.0));
+                assert_eq!(
+                    routes[0].goals,
+                    vec![Vec2::new(40.0, -40.0), Vec2::new(40.0, 40.0)]
+                );
+                assert_eq!(routes[0].floors, vec![0, 0, 1]);
+                assert_eq!(routes[1].goals, vec![Vec2::ZERO]);
+            }
+
+            #[test]
+            fn shapes_and_tasks_can_not_be_combined() {
+                let contents = TASKS.replace("  tasks:", "  robots: 2\n  tasks:");
+                assert!(FormationGroup::parse_from_yaml(&contents).is_err());
+            }
+
+            #[test]
+            fn shape_based_formation_round_trips() {
+                let contents = serde_yaml::to_string(&FormationGroup::intersection_from_paper())
+                    .expect("serializable");
+                let group = FormationGroup::parse_from_yaml(&contents).expect("valid formation");
+                assert!(matches!(
+                    group.formations.first().robots,
+                    FormationRobots::Projected { robots: 1, .. }
+                ));
+            }
+        }
+
         // #[test]
         // fn default_is_valid() {
         //     let default = Formation::default();
@@ -1,4 +1,4 @@
-use min_len_vec::OneOrMore;
+use min_len_vec::{OneOrMore, TwoOrMore};
 use serde::{Deserialize, Serialize};
 use typed_floats::StrictlyPositiveFinite;
 use unit_interval::UnitInterval;
@@ -94,8 +94,11 @@ impl From<RelativePoint> for bevy::math::Vec2 {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, derive_more::IsVariant)]
+#[derive(
+    Debug, Clone, Serialize, Deserialize, derive_more::IsVariant, strum_macros::IntoStaticStr,
+)]
 #[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
 pub enum Shape {
     Circle {
         radius: StrictlyPositiveFinite<f32>,
@@ -103,9 +106,17 @@ pub enum Shape {
     },
     Polygon(OneOrMore<Point>),
     LineSegment((Point, Point)),
+    /// Line segments joined end to end, through two or more points
+    Polyline(TwoOrMore<Point>),
 }
 
 impl Shape {
+    /// The name of the shape, as written in `formation.yaml`
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        self.into()
+    }
+
     pub const fn as_polygon(&self) -> Option<&OneOrMore<Point>> {
         if let Self::Polygon(v) = self {
             Some(v)
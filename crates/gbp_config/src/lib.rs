@@ -2,8 +2,11 @@
 pub mod formation;
 pub mod geometry;
 pub mod reader;
+pub mod scenario;
+pub mod tasks;
+pub mod timeline;
 
-use std::{num::NonZeroUsize, ops::RangeInclusive};
+use std::{collections::BTreeMap, num::NonZeroUsize, ops::RangeInclusive};
 
 use bevy::{
     ecs::system::Resource,
@@ -45,18 +48,52 @@ pub struct GraphvizInterrobotSection {
     // pub edge: GraphvizEdgeAttributes,
 }
 
+/// A format the factorgraphs of the robots can be exported in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FactorGraphFormat {
+    /// Graphviz DOT, compiled to a PNG with `dot` if it is installed
+    Dot,
+    /// GraphML, readable by e.g. Gephi, yEd and NetworkX
+    Graphml,
+    /// JSON with the belief of every variable and the last message along
+    /// every edge
+    Json,
+    /// A plain text summary of what every node is connected to
+    Adjacency,
+}
+
+impl FactorGraphFormat {
+    /// The file extension of factorgraphs exported in this format
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Dot => "dot",
+            Self::Graphml => "graphml",
+            Self::Json => "json",
+            Self::Adjacency => "txt",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct GraphvizSection {
     pub interrobot: GraphvizInterrobotSection,
     #[serde(default = "GraphvizSection::default_export_location")]
     pub export_location: String,
+    /// The formats the factorgraphs are exported in
+    #[serde(default = "GraphvizSection::default_formats")]
+    pub formats: Vec<FactorGraphFormat>,
 }
 
 impl GraphvizSection {
     pub fn default_export_location() -> String {
         "./assets/export".to_string()
     }
+
+    pub fn default_formats() -> Vec<FactorGraphFormat> {
+        vec![FactorGraphFormat::Dot]
+    }
 }
 
 impl Default for GraphvizSection {
@@ -75,6 +112,7 @@ impl Default for GraphvizSection {
                 },
             },
             export_location: "./assets/".to_string(),
+            formats: Self::default_formats(),
         }
     }
 }
@@ -125,6 +163,278 @@ impl Default for ManualSection {
     }
 }
 
+/// A format the data of a simulation can be exported in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExportFormat {
+    /// A single JSON document with all the data
+    Json,
+    /// One CSV file per table, with one row per observation
+    Csv,
+    /// One Parquet file per table, with the same tables as the CSV files
+    Parquet,
+}
+
+/// A format telemetry can be streamed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TelemetryFormat {
+    /// One JSON object per line
+    Ndjson,
+    /// One CSV row per line, with a header
+    Csv,
+}
+
+impl TelemetryFormat {
+    /// The file extension of files in this format
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Ndjson => "ndjson",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+/// Settings for streaming the state of the robots to a file while the
+/// simulation runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TelemetrySection {
+    /// Whether to stream telemetry. When enabled, the export at the end of
+    /// a run only contains a summary of every robot, and not their samples
+    pub enabled: bool,
+    /// The format of the telemetry file
+    pub format: TelemetryFormat,
+    /// Number of fixed timesteps between each sample of the robots. The file
+    /// is flushed after every sample, so it is complete up to the last sample
+    /// if the application crashes
+    pub every: NonZeroUsize,
+}
+
+impl Default for TelemetrySection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            format: TelemetryFormat::Ndjson,
+            every: 10.try_into().expect("10 > 0"),
+        }
+    }
+}
+
+/// Settings for recording the planned horizon of the robots while the
+/// simulation runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HorizonSection {
+    /// Whether to record the belief of every variable along the horizon, and
+    /// the last measurement of the obstacle and tracking factors
+    pub enabled: bool,
+    /// Number of fixed timesteps between each sample of the horizons
+    pub every: NonZeroUsize,
+    /// Number of samples in each part of the recording. A part can only be
+    /// read once it is completed, so at most this many samples are lost if
+    /// the application crashes
+    #[serde(default = "HorizonSection::default_samples_per_part")]
+    pub samples_per_part: NonZeroUsize,
+}
+
+impl HorizonSection {
+    fn default_samples_per_part() -> NonZeroUsize {
+        20.try_into().expect("20 > 0")
+    }
+}
+
+impl Default for HorizonSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            every: 50.try_into().expect("50 > 0"),
+            samples_per_part: Self::default_samples_per_part(),
+        }
+    }
+}
+
+/// Settings for measuring the connectivity of the communication graph of the
+/// robots while the simulation runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ConnectivitySection {
+    /// Number of fixed timesteps between each measurement. Every measurement
+    /// computes the eigenvalues of the Laplacian of the communication graph,
+    /// which is cubic in the number of robots
+    pub every: NonZeroUsize,
+}
+
+impl Default for ConnectivitySection {
+    fn default() -> Self {
+        Self {
+            every: 10.try_into().expect("10 > 0"),
+        }
+    }
+}
+
+/// A simulation event that can be written to the event log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LoggedEvent {
+    /// A robot was spawned
+    RobotSpawned,
+    /// A robot was despawned
+    RobotDespawned,
+    /// A robot reached a waypoint of its route
+    RobotReachedWaypoint,
+    /// A robot reached the last waypoint of its route
+    RobotFinishedRoute,
+    /// Two robots collided
+    RobotRobotCollision,
+    /// A robot reached a goal area for the first time
+    GoalAreaReached,
+    /// A waypoint was created for a robot
+    WaypointCreated,
+    /// Every robot of every formation finished its route
+    AllFormationsFinished,
+}
+
+impl LoggedEvent {
+    /// Every event that can be logged
+    pub const ALL: [Self; 8] = [
+        Self::RobotSpawned,
+        Self::RobotDespawned,
+        Self::RobotReachedWaypoint,
+        Self::RobotFinishedRoute,
+        Self::RobotRobotCollision,
+        Self::GoalAreaReached,
+        Self::WaypointCreated,
+        Self::AllFormationsFinished,
+    ];
+}
+
+/// Settings for writing the events of the simulation to a log while it runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EventLogSection {
+    /// Whether to write the event log
+    pub enabled: bool,
+    /// The events to write, others are left out. Every event if not given
+    #[serde(default = "EventLogSection::default_include")]
+    pub include: Vec<LoggedEvent>,
+}
+
+impl EventLogSection {
+    fn default_include() -> Vec<LoggedEvent> {
+        LoggedEvent::ALL.to_vec()
+    }
+}
+
+impl Default for EventLogSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            include: Self::default_include(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExportSection {
+    /// The formats to export in, every export writes the files of each format
+    #[serde(default = "ExportSection::default_formats")]
+    pub formats: Vec<ExportFormat>,
+    /// Streaming of telemetry during the run
+    #[serde(default)]
+    pub telemetry: TelemetrySection,
+    /// Recording of the planned horizon during the run
+    #[serde(default)]
+    pub horizon: HorizonSection,
+    /// Log of the events of the simulation during the run
+    #[serde(default)]
+    pub events: EventLogSection,
+    /// Measurement of the connectivity of the communication graph during
+    /// the run
+    #[serde(default)]
+    pub connectivity: ConnectivitySection,
+}
+
+impl ExportSection {
+    fn default_formats() -> Vec<ExportFormat> {
+        vec![ExportFormat::Json]
+    }
+}
+
+impl Default for ExportSection {
+    fn default() -> Self {
+        Self {
+            formats: Self::default_formats(),
+            telemetry: TelemetrySection::default(),
+            horizon: HorizonSection::default(),
+            events: EventLogSection::default(),
+            connectivity: ConnectivitySection::default(),
+        }
+    }
+}
+
+/// What a recording of the simulation is encoded to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RecordingOutput {
+    /// A directory of PNG frames, with an `ffconcat` manifest giving the
+    /// duration of every frame, that can be encoded to a video with `ffmpeg`
+    ImageSequence,
+    /// An animated GIF
+    Gif,
+    /// An animated PNG
+    Apng,
+}
+
+/// Text drawn in the corner of the recorded frames
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RecordingOverlay {
+    /// The simulated time
+    pub time: bool,
+    /// The name of the scenario
+    pub scenario: bool,
+    /// The number of robots and collisions, and the goals reached per minute
+    /// in lifelong operation
+    pub metrics: bool,
+}
+
+impl RecordingOverlay {
+    /// Whether any overlay is enabled
+    pub const fn any(&self) -> bool {
+        self.time || self.scenario || self.metrics
+    }
+}
+
+/// Settings for recording the simulation, when started with `--record`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RecordingSection {
+    /// Frames per second of simulated time. Frames are captured at this rate
+    /// regardless of how fast the application renders, and a frame rendered
+    /// after several frame intervals is shown for all of them
+    pub fps: NonZeroUsize,
+    /// What the recording is encoded to
+    pub output: RecordingOutput,
+    /// Text drawn on top of the recorded frames
+    #[serde(default)]
+    pub overlay: RecordingOverlay,
+    /// Whether to stop recording when every formation has finished
+    pub stop_on_finish: bool,
+}
+
+impl Default for RecordingSection {
+    fn default() -> Self {
+        Self {
+            fps: 30.try_into().expect("30 > 0"),
+            output: RecordingOutput::ImageSequence,
+            overlay: RecordingOverlay::default(),
+            stop_on_finish: true,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct VisualisationSection {
@@ -159,6 +469,7 @@ pub enum DrawSetting {
     RobotRobotCollisions,
     EnvironmentColliders,
     RobotEnvironmentCollisions,
+    Regions,
     // InfiniteGrid,
 }
 
@@ -185,6 +496,8 @@ pub struct DrawSection {
     pub environment_colliders: bool,
     pub robot_robot_collisions: bool,
     pub robot_environment_collisions: bool,
+    #[serde(default)]
+    pub regions: bool,
     // pub infinite_grid: bool,
 }
 
@@ -209,6 +522,7 @@ impl Default for DrawSection {
             environment_colliders: false,
             robot_robot_collisions: false,
             robot_environment_collisions: false,
+            regions: false,
             // infinite_grid: true,
         }
     }
@@ -235,6 +549,7 @@ impl DrawSection {
             "environment_colliders" => "Environment Colliders",
             "robot_robot_collisions" => "Robot-Robot Collisions",
             "robot_environment_collisions" => "Robot-Environment Collisions",
+            "regions" => "Regions",
             // "infinite_grid" => "Infinite Grid",
             _ => "Unknown",
         }
@@ -450,6 +765,20 @@ pub struct FactorsEnabledSection {
     pub interrobot: bool,
     pub obstacle: bool,
     pub tracking: bool,
+    #[serde(default = "FactorsEnabledSection::default_region")]
+    pub region: bool,
+    #[serde(default = "FactorsEnabledSection::default_relative_pose")]
+    pub relative_pose: bool,
+}
+
+impl FactorsEnabledSection {
+    const fn default_region() -> bool {
+        true
+    }
+
+    const fn default_relative_pose() -> bool {
+        true
+    }
 }
 
 impl Default for FactorsEnabledSection {
@@ -460,6 +789,8 @@ impl Default for FactorsEnabledSection {
             interrobot: true,
             obstacle: true,
             tracking: false,
+            region: Self::default_region(),
+            relative_pose: Self::default_relative_pose(),
         }
     }
 }
@@ -503,6 +834,18 @@ pub struct GbpSection {
     pub sigma_factor_obstacle: f32,
     /// Sigma for Tracking factors
     pub sigma_factor_tracking: f32,
+    /// Sigma for Region factors
+    #[serde(default = "GbpSection::default_sigma_factor_region")]
+    pub sigma_factor_region: f32,
+    /// Sigma for Relative Pose factors, between robots keeping a formation
+    #[serde(default = "GbpSection::default_sigma_factor_relative_pose")]
+    pub sigma_factor_relative_pose: f32,
+    /// The sigma of an Interrobot factor is divided by this, for each level of
+    /// priority the other robot is above the robot the factor belongs to.
+    /// Robots of lower priority yield more, as the factors in their
+    /// factorgraphs are stronger.
+    #[serde(default = "GbpSection::default_priority_scaling")]
+    pub priority_scaling: StrictlyPositiveFinite<f32>,
     /// Parameter affecting how planned path is spaced out in time
     pub lookahead_multiple: usize,
     /// Tracking section
@@ -518,6 +861,20 @@ pub struct GbpSection {
     pub variables: usize,
 }
 
+impl GbpSection {
+    const fn default_sigma_factor_region() -> f32 {
+        0.1
+    }
+
+    const fn default_sigma_factor_relative_pose() -> f32 {
+        0.5
+    }
+
+    fn default_priority_scaling() -> StrictlyPositiveFinite<f32> {
+        StrictlyPositiveFinite::<f32>::new(2.0).expect("2.0 > 0.0")
+    }
+}
+
 impl Default for GbpSection {
     fn default() -> Self {
         Self {
@@ -526,6 +883,9 @@ impl Default for GbpSection {
             sigma_factor_interrobot: 0.01,
             sigma_factor_obstacle: 0.01,
             sigma_factor_tracking: 0.1,
+            sigma_factor_region: Self::default_sigma_factor_region(),
+            sigma_factor_relative_pose: Self::default_sigma_factor_relative_pose(),
+            priority_scaling: Self::default_priority_scaling(),
             lookahead_multiple: 3,
             tracking: TrackingSection::default(),
             // iterations_per_timestep: 10,
@@ -604,6 +964,10 @@ pub struct RobotSection {
     /// Communication parameters
     pub communication: CommunicationSection,
     pub inter_robot_safety_distance_multiplier: StrictlyPositiveFinite<f32>,
+    /// Named classes of robots, that formations can spawn instead of robots
+    /// with the settings above
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub classes: BTreeMap<String, RobotClass>,
 }
 
 impl Default for RobotSection {
@@ -618,10 +982,88 @@ impl Default for RobotSection {
             // **gbpplanner** effectively uses 2.2 * radius with the way they calculate it
             inter_robot_safety_distance_multiplier: StrictlyPositiveFinite::<f32>::new(2.2)
                 .expect("2.2 > 0.0"),
+            classes: BTreeMap::new(),
         }
     }
 }
 
+/// A named class of robots, with settings of its own in place of the ones in
+/// the robot and gbp sections. Settings that are left out are taken from those
+/// sections.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RobotClass {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radius: Option<RobotRadiusSection>,
+    /// SI unit: m/s
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_speed: Option<StrictlyPositiveFinite<f32>>,
+    /// SI unit: s
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub planning_horizon: Option<StrictlyPositiveFinite<f32>>,
+    /// SI unit: m
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub communication_radius: Option<StrictlyPositiveFinite<f32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sigma_factor_dynamics: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sigma_factor_interrobot: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sigma_factor_obstacle: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sigma_factor_tracking: Option<f32>,
+    /// One of the accent colours of the theme, e.g. `peach`. The robots are
+    /// given a random colour if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub colour: Option<String>,
+    #[serde(default)]
+    pub mesh: RobotMesh,
+    /// Robots of lower priority yield more to robots of higher priority. A
+    /// formation can set a priority of its own in place of this one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u8>,
+}
+
+impl RobotClass {
+    /// Put the settings of the class in place of the ones in `robot` and `gbp`
+    pub fn apply(&self, robot: &mut RobotSection, gbp: &mut GbpSection) {
+        if let Some(radius) = &self.radius {
+            robot.radius = radius.clone();
+        }
+        if let Some(target_speed) = self.target_speed {
+            robot.target_speed = target_speed;
+        }
+        if let Some(planning_horizon) = self.planning_horizon {
+            robot.planning_horizon = planning_horizon;
+        }
+        if let Some(communication_radius) = self.communication_radius {
+            robot.communication.radius = communication_radius;
+        }
+        if let Some(sigma) = self.sigma_factor_dynamics {
+            gbp.sigma_factor_dynamics = sigma;
+        }
+        if let Some(sigma) = self.sigma_factor_interrobot {
+            gbp.sigma_factor_interrobot = sigma;
+        }
+        if let Some(sigma) = self.sigma_factor_obstacle {
+            gbp.sigma_factor_obstacle = sigma;
+        }
+        if let Some(sigma) = self.sigma_factor_tracking {
+            gbp.sigma_factor_tracking = sigma;
+        }
+    }
+}
+
+/// The mesh robots are drawn with, sized by their radius
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RobotMesh {
+    #[default]
+    Sphere,
+    Cylinder,
+    Cuboid,
+}
+
 /// Interaction Section
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -783,6 +1225,14 @@ pub struct Config {
     /// Contains parameters for manual time-stepping
     #[serde(default)]
     pub manual: ManualSection,
+    /// **Export section:**
+    /// Contains parameters for how to export the data of a simulation
+    #[serde(default)]
+    pub export: ExportSection,
+    /// **Recording section:**
+    /// Contains parameters for how to record the simulation
+    #[serde(default)]
+    pub recording: RecordingSection,
 
     #[serde(default)]
     pub debug: DebugSection,
@@ -812,6 +1262,8 @@ impl Default for Config {
             rrt: RRTSection::default(),
             graphviz: GraphvizSection::default(),
             manual: ManualSection::default(),
+            export: ExportSection::default(),
+            recording: RecordingSection::default(),
             debug: DebugSection::default(),
         }
     }
@@ -839,4 +1291,94 @@ impl Config {
         // let config = toml::from_str(contents)?;
         // Ok(config)
     }
+
+    /// The config of a robot of the class `name`, with the settings of the
+    /// class in place of the global ones. Returns `None` if there is no such
+    /// class.
+    #[must_use]
+    pub fn for_class(&self, name: &str) -> Option<Self> {
+        let class = self.robot.classes.get(name)?;
+        let mut config = self.clone();
+        class.apply(&mut config.robot, &mut config.gbp);
+        Some(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn event_log_includes_every_event_by_default() {
+        let events: EventLogSection = toml::from_str("enabled = true").expect("valid section");
+        assert_eq!(events.include, LoggedEvent::ALL.to_vec());
+    }
+
+    fn ambulance() -> RobotClass {
+        RobotClass {
+            target_speed: Some(StrictlyPositiveFinite::<f32>::new(8.0).expect("8.0 > 0.0")),
+            communication_radius: Some(
+                StrictlyPositiveFinite::<f32>::new(50.0).expect("50.0 > 0.0"),
+            ),
+            sigma_factor_interrobot: Some(0.5),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn class_overrides_only_the_settings_it_sets() {
+        let mut robot = RobotSection::default();
+        let mut gbp = GbpSection::default();
+        ambulance().apply(&mut robot, &mut gbp);
+
+        assert_eq!(robot.target_speed.get(), 8.0);
+        assert_eq!(robot.communication.radius.get(), 50.0);
+        assert_eq!(gbp.sigma_factor_interrobot, 0.5);
+
+        let defaults = (RobotSection::default(), GbpSection::default());
+        assert_eq!(robot.planning_horizon, defaults.0.planning_horizon);
+        assert_eq!(robot.radius.range(), defaults.0.radius.range());
+        assert_eq!(gbp.sigma_factor_dynamics, defaults.1.sigma_factor_dynamics);
+        assert_eq!(gbp.sigma_factor_obstacle, defaults.1.sigma_factor_obstacle);
+        assert_eq!(gbp.sigma_factor_tracking, defaults.1.sigma_factor_tracking);
+    }
+
+    #[test]
+    fn config_of_a_class_inherits_the_rest_of_the_config() {
+        let mut config = Config::default();
+        config.robot.planning_horizon = StrictlyPositiveFinite::<f32>::new(7.0).expect("7.0 > 0.0");
+        config.gbp.sigma_factor_obstacle = 0.2;
+        config
+            .robot
+            .classes
+            .insert("ambulance".to_string(), ambulance());
+
+        let class_config = config.for_class("ambulance").expect("the class exists");
+        assert_eq!(class_config.robot.target_speed.get(), 8.0);
+        assert_eq!(class_config.gbp.sigma_factor_interrobot, 0.5);
+        assert_eq!(class_config.robot.planning_horizon.get(), 7.0);
+        assert_eq!(class_config.gbp.sigma_factor_obstacle, 0.2);
+        assert_eq!(
+            class_config.gbp.priority_scaling,
+            config.gbp.priority_scaling
+        );
+
+        // The global config is left as it was
+        assert_eq!(
+            config.robot.target_speed,
+            RobotSection::default().target_speed
+        );
+    }
+
+    #[test]
+    fn unknown_class_has_no_config() {
+        let mut config = Config::default();
+        config
+            .robot
+            .classes
+            .insert("ambulance".to_string(), ambulance());
+        assert!(config.for_class("taxi").is_none());
+    }
 }
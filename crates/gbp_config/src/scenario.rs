@@ -0,0 +1,136 @@
+//! Composition of scenarios.
+//!
+//! A scenario can extend another scenario, by setting `extends` in its
+//! `config.toml` to the directory of the other scenario, relative to its own
+//! directory, e.g. `extends = "../Showcase"`. Its files then only have to
+//! contain the settings that differ from the extended scenario, and files that
+//! are left out are taken from the extended scenario as is.
+//!
+//! Files are merged key by key. Tables and mappings are merged recursively,
+//! while every other value, including arrays and sequences, replaces the value
+//! it overrides.
+
+/// The key in `config.toml` with the directory of the scenario it extends
+pub const EXTENDS_KEY: &str = "extends";
+
+/// Remove the `extends` key from the top level table of a `config.toml`, and
+/// return the directory it refers to.
+///
+/// Returns `None` if `config` does not extend another scenario, or if the
+/// value is not a string.
+pub fn take_extends(config: &mut toml::Value) -> Option<String> {
+    match config.as_table_mut()?.remove(EXTENDS_KEY)? {
+        toml::Value::String(extends) => Some(extends),
+        _ => None,
+    }
+}
+
+/// Merge the TOML value `overrides` into `base`
+pub fn merge_toml(base: &mut toml::Value, overrides: toml::Value) {
+    match (base, overrides) {
+        (toml::Value::Table(base), toml::Value::Table(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_toml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// Merge the YAML value `overrides` into `base`
+pub fn merge_yaml(base: &mut serde_yaml::Value, overrides: serde_yaml::Value) {
+    match (base, overrides) {
+        (serde_yaml::Value::Mapping(base), serde_yaml::Value::Mapping(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_yaml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    mod toml_files {
+        use super::*;
+
+        fn parse(contents: &str) -> toml::Value {
+            toml::from_str(contents).expect("valid toml")
+        }
+
+        #[test]
+        fn extends_is_taken_out() {
+            let mut config = parse("extends = \"../Showcase\"\n[simulation]\nhz = 60.0\n");
+            assert_eq!(take_extends(&mut config).as_deref(), Some("../Showcase"));
+            assert_eq!(config, parse("[simulation]\nhz = 60.0\n"));
+            assert_eq!(take_extends(&mut config), None);
+        }
+
+        #[test]
+        fn tables_are_merged_recursively() {
+            let mut base = parse(
+                "[simulation]\nhz = 60.0\nprng-seed = 0\n[gbp]\nsigma-factor-dynamics = 0.1\n",
+            );
+            merge_toml(
+                &mut base,
+                parse("[simulation]\nprng-seed = 42\n[robot]\ntarget-speed = 7.0\n"),
+            );
+            assert_eq!(
+                base,
+                parse(
+                    "[simulation]\nhz = 60.0\nprng-seed = 42\n[gbp]\nsigma-factor-dynamics = \
+                     0.1\n[robot]\ntarget-speed = 7.0\n"
+                )
+            );
+        }
+
+        #[test]
+        fn arrays_are_replaced() {
+            let mut base = parse("values = [1, 2, 3]\n");
+            merge_toml(&mut base, parse("values = [4]\n"));
+            assert_eq!(base, parse("values = [4]\n"));
+        }
+    }
+
+    mod yaml_files {
+        use super::*;
+
+        fn parse(contents: &str) -> serde_yaml::Value {
+            serde_yaml::from_str(contents).expect("valid yaml")
+        }
+
+        #[test]
+        fn mappings_are_merged_recursively() {
+            let mut base = parse("tiles:\n  grid: [a, b]\n  settings:\n    tile-size: 50.0\n");
+            merge_yaml(
+                &mut base,
+                parse("tiles:\n  settings:\n    tile-size: 100.0\n"),
+            );
+            assert_eq!(
+                base,
+                parse("tiles:\n  grid: [a, b]\n  settings:\n    tile-size: 100.0\n")
+            );
+        }
+
+        #[test]
+        fn sequences_are_replaced() {
+            let mut base = parse("formations:\n- delay: 1\n- delay: 2\n");
+            merge_yaml(&mut base, parse("formations:\n- delay: 3\n"));
+            assert_eq!(base, parse("formations:\n- delay: 3\n"));
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! A pool of pickup and dropoff tasks, that are assigned to robots as they
+//! arrive during the simulation.
+
+use std::time::Duration;
+
+use bevy::ecs::system::Resource;
+use serde::{Deserialize, Serialize};
+use typed_floats::StrictlyPositiveFinite;
+
+use super::geometry::Point;
+
+/// Strategy used to assign arrived tasks to robots without a task
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AllocatorKind {
+    /// Repeatedly assign the robot and task closest to each other
+    #[default]
+    GreedyNearest,
+    /// Assign tasks such that the total distance to the pickups is the lowest
+    /// possible
+    Hungarian,
+    /// Let robots bid for the tasks closest to them
+    Auction {
+        /// Minimum amount a robot raises the price of a task by when bidding
+        epsilon: StrictlyPositiveFinite<f32>,
+    },
+}
+
+/// Something to pick up at one point, and drop off at another
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Task {
+    /// Position relative to the size of the environment, like the points of a
+    /// [`Shape`](crate::geometry::Shape)
+    pub pickup: Point,
+    /// Position relative to the size of the environment
+    pub dropoff: Point,
+    /// The floor of the environment both points are on, `0` being the ground
+    /// floor
+    #[serde(default)]
+    pub floor: usize,
+    /// Time from the start of the simulation at which the task arrives, and
+    /// can be assigned to a robot
+    #[serde(default)]
+    pub arrives_at: Duration,
+}
+
+/// Tasks given to robots once they have reached the goals of their formation.
+/// Read from the optional `tasks.yaml` of a simulation.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Resource)]
+#[serde(rename_all = "kebab-case")]
+pub struct TaskPool {
+    #[serde(default)]
+    pub allocator: AllocatorKind,
+    pub tasks: Vec<Task>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ParseError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("YAML error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+}
+
+impl TaskPool {
+    /// Attempt to parse a `TaskPool` from a YAML encoded string.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if `contents` is not valid YAML, or does not
+    /// represent a valid `TaskPool`.
+    pub fn parse_from_yaml(contents: &str) -> Result<Self, ParseError> {
+        Ok(serde_yaml::from_str(contents)?)
+    }
+
+    /// Indices of the tasks in order of arrival
+    pub fn by_arrival(&self) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..self.tasks.len()).collect();
+        indices.sort_by_key(|&i| self.tasks[i].arrives_at);
+        indices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn parse_task_pool() {
+        let contents = r"
+allocator: !auction
+  epsilon: 0.01
+tasks:
+- pickup: { x: 0.1, y: 0.1 }
+  dropoff: { x: 0.9, y: 0.9 }
+  arrives-at:
+    secs: 10
+    nanos: 0
+- pickup: { x: 0.5, y: 0.5 }
+  dropoff: { x: 0.2, y: 0.8 }
+  floor: 1
+";
+        let pool = TaskPool::parse_from_yaml(contents).expect("valid task pool");
+        assert!(matches!(pool.allocator, AllocatorKind::Auction { .. }));
+        assert_eq!(pool.tasks.len(), 2);
+        assert_eq!(pool.tasks[1].floor, 1);
+        assert_eq!(pool.by_arrival(), vec![1, 0]);
+    }
+
+    #[test]
+    fn greedy_nearest_by_default() {
+        let pool = TaskPool::parse_from_yaml("tasks: []").expect("valid task pool");
+        assert!(matches!(pool.allocator, AllocatorKind::GreedyNearest));
+    }
+}
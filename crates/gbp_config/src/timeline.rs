@@ -0,0 +1,239 @@
+//! Scripted events of a scenario, executed at fixed times during the
+//! simulation. Read from the optional `timeline.yaml` of a scenario.
+
+use std::time::Duration;
+
+use bevy::ecs::system::Resource;
+use serde::{Deserialize, Serialize};
+use unit_interval::UnitInterval;
+
+use crate::Config;
+
+/// The robots an [`Action`] applies to
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RobotSelection {
+    /// Every robot in the simulation
+    All,
+    /// The robots spawned by the formation with this index in
+    /// `formation.yaml`
+    Formation(usize),
+    /// A random fraction of the robots in the simulation. The robots are drawn
+    /// every time the action is executed.
+    Fraction(UnitInterval),
+}
+
+/// Something that happens at a point in time of a [`Timeline`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    /// Change a setting of `config.toml`. `key` is the path to the setting
+    /// with its sections separated by dots, e.g.
+    /// `gbp.iteration-schedule.internal`
+    SetConfig { key: String, value: toml::Value },
+    /// Make the radios of the robots fail, until restored
+    KnockOutComms { robots: RobotSelection },
+    /// Undo [`Action::KnockOutComms`]
+    RestoreComms { robots: RobotSelection },
+    /// Turn the region of `environment.yaml` named `region` into a keep-out
+    /// region as impassable as a wall, until unblocked
+    BlockRegion { region: String },
+    /// Undo [`Action::BlockRegion`]
+    UnblockRegion { region: String },
+    /// Stop the formations from spawning more robots, until resumed
+    PauseSpawning,
+    /// Undo [`Action::PauseSpawning`]
+    ResumeSpawning,
+    /// Make the robots stand still where they are, until resumed
+    StopRobots { robots: RobotSelection },
+    /// Undo [`Action::StopRobots`]
+    ResumeRobots { robots: RobotSelection },
+    /// Take a screenshot of the window
+    Screenshot,
+    /// Export the data of the simulation, as if requested from the UI
+    Export,
+}
+
+/// An [`Action`] and the time it is executed at
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TimedAction {
+    /// Time from the start of the simulation
+    pub at: Duration,
+    pub action: Action,
+}
+
+/// Actions executed at fixed times during a simulation.
+/// Read from the optional `timeline.yaml` of a simulation.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Resource)]
+#[serde(rename_all = "kebab-case")]
+pub struct Timeline {
+    pub events: Vec<TimedAction>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ParseError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("YAML error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+}
+
+impl Timeline {
+    /// Attempt to parse a `Timeline` from a YAML encoded string. The events
+    /// are sorted by time, and events at the same time keep their order.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if `contents` is not valid YAML, or does not
+    /// represent a valid `Timeline`.
+    pub fn parse_from_yaml(contents: &str) -> Result<Self, ParseError> {
+        let mut timeline: Self = serde_yaml::from_str(contents)?;
+        timeline.events.sort_by_key(|event| event.at);
+        Ok(timeline)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SetConfigError {
+    #[error("no setting named '{0}' in config.toml")]
+    UnknownKey(String),
+    #[error("invalid value for '{key}': {error}")]
+    InvalidValue { key: String, error: toml::de::Error },
+    #[error("failed to serialize the config: {0}")]
+    Serialize(#[from] toml::ser::Error),
+}
+
+/// A copy of `config` with the setting at `key` replaced by `value`. `key` is
+/// the path to the setting as written in `config.toml`, with the sections
+/// separated by dots.
+///
+/// # Errors
+///
+/// Will return `Err` if there is no setting at `key`, e.g. because it is an
+/// optional setting that is not set, or if `value` is not valid for it.
+pub fn set_config(
+    config: &Config,
+    key: &str,
+    value: toml::Value,
+) -> Result<Config, SetConfigError> {
+    let mut root = toml::Value::try_from(config)?;
+
+    let mut setting = &mut root;
+    for section in key.split('.') {
+        setting = setting
+            .as_table_mut()
+            .and_then(|table| table.get_mut(section))
+            .ok_or_else(|| SetConfigError::UnknownKey(key.to_string()))?;
+    }
+    *setting = value;
+
+    root.try_into()
+        .map_err(|error| SetConfigError::InvalidValue {
+            key: key.to_string(),
+            error,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    mod parse {
+        use super::*;
+
+        #[test]
+        fn events_are_sorted_by_time() {
+            let contents = r"
+events:
+- at:
+    secs: 20
+    nanos: 0
+  action: !restore-comms
+    robots: all
+- at:
+    secs: 10
+    nanos: 0
+  action: !knock-out-comms
+    robots: !fraction 0.5
+- at:
+    secs: 10
+    nanos: 0
+  action: pause-spawning
+- at:
+    secs: 5
+    nanos: 0
+  action: !set-config
+    key: gbp.iteration-schedule.internal
+    value: 5
+";
+            let timeline = Timeline::parse_from_yaml(contents).expect("valid timeline");
+            let at: Vec<u64> = timeline
+                .events
+                .iter()
+                .map(|event| event.at.as_secs())
+                .collect();
+            assert_eq!(at, vec![5, 10, 10, 20]);
+            assert!(matches!(
+                timeline.events[1].action,
+                Action::KnockOutComms {
+                    robots: RobotSelection::Fraction(_),
+                }
+            ));
+            assert!(matches!(timeline.events[2].action, Action::PauseSpawning));
+        }
+
+        #[test]
+        fn fraction_outside_unit_interval_is_rejected() {
+            let contents = r"
+events:
+- at:
+    secs: 0
+    nanos: 0
+  action: !stop-robots
+    robots: !fraction 1.5
+";
+            assert!(Timeline::parse_from_yaml(contents).is_err());
+        }
+    }
+
+    mod setting {
+        use super::*;
+
+        #[test]
+        fn nested_setting_is_replaced() {
+            let config = Config::default();
+            let config = set_config(
+                &config,
+                "gbp.iteration-schedule.internal",
+                toml::Value::Integer(42),
+            )
+            .expect("the setting exists");
+            assert_eq!(config.gbp.iteration_schedule.internal, 42);
+        }
+
+        #[test]
+        fn unknown_key_is_rejected() {
+            let config = Config::default();
+            assert!(matches!(
+                set_config(&config, "gbp.no-such-setting", toml::Value::Integer(1)),
+                Err(SetConfigError::UnknownKey(_))
+            ));
+        }
+
+        #[test]
+        fn invalid_value_is_rejected() {
+            let config = Config::default();
+            assert!(matches!(
+                set_config(
+                    &config,
+                    "gbp.iteration-schedule.internal",
+                    toml::Value::String("many".into())
+                ),
+                Err(SetConfigError::InvalidValue { .. })
+            ));
+        }
+    }
+}
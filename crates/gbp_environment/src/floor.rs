@@ -0,0 +1,275 @@
+//! Environments with several floors stacked on top of each other, connected
+//! by transfer points such as elevators and ramps.
+//!
+//! The [`Environment`] itself is the ground floor, i.e. floor `0`. Every
+//! [`Floor`] in [`Environment::floors`] is another layer, with floor `i` being
+//! `floors[i - 1]`.
+
+use std::num::NonZeroUsize;
+
+use bevy::math::Vec2;
+use gbp_geometry::RelativePoint;
+use serde::{Deserialize, Serialize};
+use typed_floats::StrictlyPositiveFinite;
+
+use crate::{Environment, EnvironmentError, Obstacles, Regions, Tiles};
+
+/// An additional floor of an [`Environment`], above the ground floor.
+/// All floors share the dimensions of the ground floor, so that positions
+/// are comparable across floors.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Floor {
+    pub name: String,
+    /// Height of the floor above the ground floor. Only used for
+    /// visualisation, as the simulation itself is 2D.
+    pub elevation: f32,
+    pub tiles: Tiles,
+    /// Obstacles on the floor. Their schedules are ignored, only obstacles
+    /// on the ground floor appear and disappear.
+    #[serde(default = "Obstacles::empty")]
+    pub obstacles: Obstacles,
+}
+
+/// The kind of a [`Transfer`]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, strum_macros::IntoStaticStr,
+)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum TransferKind {
+    Elevator,
+    Ramp,
+}
+
+/// One end of a [`Transfer`]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TransferEnd {
+    /// Index of the floor, `0` being the ground floor
+    pub floor: usize,
+    /// Position relative to the whole floor. `(0, 0)` is the bottom left
+    /// corner and `(1, 1)` the top right corner, the same as for shapes in
+    /// `formation.yaml`.
+    pub position: RelativePoint,
+}
+
+impl TransferEnd {
+    /// Resolve the position to world coordinates
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    pub fn to_world(&self, env: &Environment) -> Vec2 {
+        let tile_size = env.tile_size();
+        let width = f64::from(env.tiles.grid.ncols() as f32 * tile_size);
+        let height = f64::from(env.tiles.grid.nrows() as f32 * tile_size);
+        Vec2::new(
+            ((self.position.x.get() - 0.5) * width) as f32,
+            ((self.position.y.get() - 0.5) * height) as f32,
+        )
+    }
+}
+
+/// A connection between two floors, that robots can use in both directions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Transfer {
+    pub name: String,
+    pub kind: TransferKind,
+    pub ends: [TransferEnd; 2],
+    /// Number of robots that can be in transit at the same time
+    pub capacity: NonZeroUsize,
+    /// Time in seconds it takes to get from one end to the other
+    pub transit_time: StrictlyPositiveFinite<f32>,
+}
+
+impl Transfer {
+    /// Ensure that the transfer connects two different floors of `env`
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if:
+    /// 1. One of the ends is on a floor that does not exist
+    /// 2. Both ends are on the same floor
+    pub fn validate(&self, env: &Environment) -> Result<(), EnvironmentError> {
+        if let Some(end) = self.ends.iter().find(|end| end.floor >= env.floor_count()) {
+            return Err(EnvironmentError::TransferFloorOutOfBounds {
+                transfer: self.name.clone(),
+                floor: end.floor,
+            });
+        }
+
+        if self.ends[0].floor == self.ends[1].floor {
+            return Err(EnvironmentError::TransferWithinOneFloor(self.name.clone()));
+        }
+
+        Ok(())
+    }
+}
+
+impl Environment {
+    /// Number of floors, including the ground floor
+    #[inline]
+    pub fn floor_count(&self) -> usize {
+        self.floors.len() + 1
+    }
+
+    /// Whether the environment has more than one floor
+    #[inline]
+    pub fn has_floors(&self) -> bool {
+        !self.floors.is_empty()
+    }
+
+    /// The environment of floor number `floor` on its own, without any other
+    /// floors or transfers. Regions only exist on the ground floor.
+    pub fn floor(&self, floor: usize) -> Option<Self> {
+        if floor == 0 {
+            return Some(Self {
+                floors: Vec::new(),
+                transfers: Vec::new(),
+                ..self.clone()
+            });
+        }
+
+        self.floors.get(floor - 1).map(|layer| Self {
+            tiles: layer.tiles.clone(),
+            obstacles: layer.obstacles.clone(),
+            regions: Regions::empty(),
+            floors: Vec::new(),
+            transfers: Vec::new(),
+        })
+    }
+
+    /// Height of floor number `floor` above the ground floor
+    pub fn elevation(&self, floor: usize) -> f32 {
+        floor
+            .checked_sub(1)
+            .and_then(|index| self.floors.get(index))
+            .map_or(0.0, |layer| layer.elevation)
+    }
+
+    /// Ensure that the floors and transfers are valid
+    pub(crate) fn validate_floors(&self) -> Result<(), EnvironmentError> {
+        for (index, layer) in self.floors.iter().enumerate() {
+            let same_dimensions = layer.tiles.grid.shape() == self.tiles.grid.shape()
+                && (layer.tiles.settings.tile_size - self.tile_size()).abs() < f32::EPSILON;
+            if !same_dimensions {
+                return Err(EnvironmentError::FloorDimensionsMismatch(
+                    layer.name.clone(),
+                ));
+            }
+            self.floor(index + 1).expect("floor exists").validate()?;
+        }
+
+        for transfer in &self.transfers {
+            transfer.validate(self)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn environment_with_floors(transfers: &str) -> Result<Environment, crate::ParseError> {
+        let yaml = format!(
+            r"
+tiles:
+  grid:
+  - ┼
+  settings:
+    tile-size: 10.0
+    path-width: 0.5
+    obstacle-height: 1.0
+obstacles: []
+floors:
+- name: first
+  elevation: 5.0
+  tiles:
+    grid:
+    - ┼
+    settings:
+      tile-size: 10.0
+      path-width: 0.5
+      obstacle-height: 1.0
+transfers:
+{transfers}"
+        );
+        Environment::parse(&yaml)
+    }
+
+    #[test]
+    fn floors_are_parsed() {
+        let env = environment_with_floors(
+            r"
+- name: lift
+  kind: elevator
+  ends:
+  - floor: 0
+    position:
+      x: 0.5
+      y: 0.5
+  - floor: 1
+    position:
+      x: 0.75
+      y: 0.5
+  capacity: 2
+  transit-time: 10.0",
+        )
+        .expect("valid environment");
+
+        assert_eq!(env.floor_count(), 2);
+        assert!((env.elevation(1) - 5.0).abs() < f32::EPSILON);
+        assert!(env.floor(2).is_none());
+
+        let upper = env.floor(1).expect("floor 1 exists");
+        assert_eq!(
+            env.transfers[0].ends[1].to_world(&upper),
+            Vec2::new(2.5, 0.0)
+        );
+    }
+
+    #[test]
+    fn transfer_to_missing_floor_is_invalid() {
+        let env = environment_with_floors(
+            r"
+- name: lift
+  kind: elevator
+  ends:
+  - floor: 0
+    position:
+      x: 0.5
+      y: 0.5
+  - floor: 2
+    position:
+      x: 0.5
+      y: 0.5
+  capacity: 1
+  transit-time: 10.0",
+        );
+
+        assert!(env.is_err());
+    }
+
+    #[test]
+    fn transfer_within_one_floor_is_invalid() {
+        let env = environment_with_floors(
+            r"
+- name: ramp
+  kind: ramp
+  ends:
+  - floor: 1
+    position:
+      x: 0.5
+      y: 0.5
+  - floor: 1
+    position:
+      x: 0.25
+      y: 0.5
+  capacity: 1
+  transit-time: 10.0",
+        );
+
+        assert!(env.is_err());
+    }
+}
@@ -11,6 +11,14 @@ use gbp_linalg::Float;
 use serde::{Deserialize, Serialize};
 use typed_floats::StrictlyPositiveFinite;
 
+mod floor;
+mod region;
+mod schedule;
+
+pub use floor::{Floor, Transfer, TransferEnd, TransferKind};
+pub use region::{Region, RegionArea, RegionKind, Regions, WorldRegion};
+pub use schedule::{ActiveInterval, Schedule};
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, Component)]
 #[serde(rename_all = "kebab-case")]
 pub struct TileCoordinates {
@@ -552,6 +560,9 @@ pub struct Obstacle {
     pub translation: RelativePoint,
     /// Which tile in the grid the obstacle should be placed
     pub tile_coordinates: TileCoordinates,
+    /// When the obstacle is present. If `None` it is always present
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Schedule>,
 }
 
 impl Obstacle {
@@ -573,6 +584,7 @@ impl Obstacle {
             rotation: Rotation(Angle::new(rotation).expect("Invalid angle")),
             translation: RelativePoint::new(translation.0, translation.1)
                 .expect("Invalid relative point"),
+            schedule: None,
         }
     }
 }
@@ -616,6 +628,11 @@ pub struct SdfSettings {
     pub resolution: u32,
     pub expansion: f32,
     pub blur: f32,
+    /// Generate the SDF lazily in chunks, instead of as a single image.
+    /// Useful for large environments, where the single image would take up a
+    /// lot of memory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<SdfChunkSettings>,
 }
 
 impl Default for SdfSettings {
@@ -624,6 +641,25 @@ impl Default for SdfSettings {
             resolution: 200,
             expansion: 0.1,
             blur: 0.05,
+            chunks: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SdfChunkSettings {
+    /// Side length of a chunk in pixels
+    pub size: u32,
+    /// Upper bound on the memory used by cached chunks, in megabytes
+    pub memory_budget: u32,
+}
+
+impl Default for SdfChunkSettings {
+    fn default() -> Self {
+        Self {
+            size: 512,
+            memory_budget: 64,
         }
     }
 }
@@ -683,6 +719,14 @@ pub enum EnvironmentType {
 pub struct Environment {
     pub tiles: Tiles,
     pub obstacles: Obstacles,
+    #[serde(default)]
+    pub regions: Regions,
+    /// Floors above the ground floor, see [`Floor`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub floors: Vec<Floor>,
+    /// Transfers connecting the floors
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transfers: Vec<Transfer>,
 }
 
 impl Default for Environment {
@@ -709,6 +753,33 @@ pub enum EnvironmentError {
     EmptyGrid,
     #[error("Environment matrix representation has rows of different lengths")]
     DifferentLengthRows,
+    #[error("Region {region:?} references tile ({row}, {col}) outside the tile grid")]
+    RegionTileOutOfBounds {
+        region: String,
+        row: usize,
+        col: usize,
+    },
+    #[error("Region {0:?} is a polygon with fewer than 3 vertices")]
+    RegionPolygonTooFewPoints(String),
+    #[error("Region {region:?} has a cost of {cost}, which is not in [0, 1]")]
+    RegionCostOutOfRange { region: String, cost: f32 },
+    #[error("Obstacle {obstacle} has an invalid active interval from {from} to {to:?}")]
+    InvalidScheduleInterval {
+        obstacle: usize,
+        from: f32,
+        to: Option<f32>,
+    },
+    #[error(
+        "Obstacle {obstacle} has an active interval that does not end within its period of \
+         {period}"
+    )]
+    ScheduleIntervalOutsidePeriod { obstacle: usize, period: f32 },
+    #[error("Floor {0:?} does not have the same dimensions as the ground floor")]
+    FloorDimensionsMismatch(String),
+    #[error("Transfer {transfer:?} references floor {floor}, which does not exist")]
+    TransferFloorOutOfBounds { transfer: String, floor: usize },
+    #[error("Transfer {0:?} has both of its ends on the same floor")]
+    TransferWithinOneFloor(String),
 }
 
 impl Environment {
@@ -747,6 +818,11 @@ impl Environment {
     /// Will return `Err` if:
     /// 1. The matrix representation is not empty
     /// 2. All rows in the matrix representation are the same length
+    /// 3. All regions are valid, see [`Region::validate`]
+    /// 4. All obstacle schedules are valid, see [`Schedule::validate`]
+    /// 5. All floors have the dimensions of the ground floor, and are valid
+    ///    themselves
+    /// 6. All transfers are valid, see [`Transfer::validate`]
     pub fn validate(self) -> Result<Self, EnvironmentError> {
         if self.tiles.grid.is_empty() {
             Err(EnvironmentError::EmptyGrid)
@@ -758,6 +834,15 @@ impl Environment {
         {
             Err(EnvironmentError::DifferentLengthRows)
         } else {
+            for region in self.regions.iter() {
+                region.validate(&self)?;
+            }
+            for (index, obstacle) in self.obstacles.iter().enumerate() {
+                if let Some(schedule) = &obstacle.schedule {
+                    schedule.validate(index)?;
+                }
+            }
+            self.validate_floors()?;
             Ok(self)
         }
     }
@@ -780,6 +865,9 @@ impl Environment {
                 },
             },
             obstacles: Obstacles::empty(),
+            regions: Regions::empty(),
+            floors: Vec::new(),
+            transfers: Vec::new(),
         }
     }
 
@@ -796,6 +884,9 @@ impl Environment {
                 },
             },
             obstacles: Obstacles::empty(),
+            regions: Regions::empty(),
+            floors: Vec::new(),
+            transfers: Vec::new(),
         }
     }
 
@@ -817,6 +908,9 @@ impl Environment {
                 }
             },
             obstacles: Obstacles::empty(),
+            regions: Regions::empty(),
+            floors: Vec::new(),
+            transfers: Vec::new(),
         }
     }
 
@@ -840,6 +934,9 @@ impl Environment {
                 },
             },
             obstacles: Obstacles::empty(),
+            regions: Regions::empty(),
+            floors: Vec::new(),
+            transfers: Vec::new(),
         }
     }
 
@@ -875,6 +972,9 @@ impl Environment {
                 },
             },
             obstacles: Obstacles::empty(),
+            regions: Regions::empty(),
+            floors: Vec::new(),
+            transfers: Vec::new(),
         }
     }
 
@@ -897,6 +997,9 @@ impl Environment {
                 },
             },
             obstacles: Obstacles::empty(),
+            regions: Regions::empty(),
+            floors: Vec::new(),
+            transfers: Vec::new(),
         }
     }
 
@@ -957,6 +1060,9 @@ impl Environment {
                     (0.38, 0.432),
                 ),
             ]),
+            regions: Regions::empty(),
+            floors: Vec::new(),
+            transfers: Vec::new(),
         }
     }
 
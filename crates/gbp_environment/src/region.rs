@@ -0,0 +1,324 @@
+//! Semantic regions of an [`Environment`], e.g. speed zones, one-way lanes and
+//! keep-out zones. Regions do not change the geometry of the map, but how
+//! robots are expected to behave inside them.
+
+use angle::Angle;
+use bevy::math::Vec2;
+use derive_more::IntoIterator;
+use gbp_geometry::RelativePoint;
+use serde::{Deserialize, Serialize};
+use typed_floats::StrictlyPositiveFinite;
+
+use crate::{Environment, EnvironmentError, TileCoordinates};
+
+/// What a [`Region`] means for the robots moving through it
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, strum_macros::IntoStaticStr)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum RegionKind {
+    /// Robots should not move faster than `max_speed` in m/s
+    #[serde(rename_all = "kebab-case")]
+    SpeedLimit {
+        max_speed: StrictlyPositiveFinite<f32>,
+    },
+    /// Robots should only travel in `direction`, given in radians
+    /// counter-clockwise from the positive x-axis
+    #[serde(rename_all = "kebab-case")]
+    OneWay { direction: Angle },
+    /// Robots should stay out of the region. `cost` is in [0, 1], where a cost
+    /// of 1 makes the region as impassable as a wall.
+    #[serde(rename_all = "kebab-case")]
+    KeepOut { cost: f32 },
+    /// Area shared with other traffic, where robots keep a larger distance to
+    /// each other. The multiplier replaces the
+    /// `inter-robot-safety-distance-multiplier` of the config
+    #[serde(rename_all = "kebab-case")]
+    SharedSpace {
+        safety_distance_multiplier: StrictlyPositiveFinite<f32>,
+    },
+}
+
+impl RegionKind {
+    /// The name of the kind, as written in `environment.yaml`
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        self.into()
+    }
+
+    /// Unit vector of the preferred direction of travel, if the region is a
+    /// one-way lane
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn direction(&self) -> Option<Vec2> {
+        match self {
+            Self::OneWay { direction } => Some(Vec2::from_angle(direction.as_radians() as f32)),
+            _ => None,
+        }
+    }
+
+    /// Whether robots should treat the region as an obstacle when planning
+    /// a global path. This is the only way regions affect the global planner,
+    /// as the RRT* it uses has no cost function, only a feasibility check.
+    pub fn impassable(&self) -> bool {
+        matches!(self, Self::KeepOut { cost } if *cost >= 1.0)
+    }
+}
+
+/// The area covered by a [`Region`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RegionArea {
+    /// A set of whole tiles
+    Tiles(Vec<TileCoordinates>),
+    /// A polygon with vertices relative to the whole environment. `(0, 0)` is
+    /// the bottom left corner and `(1, 1)` the top right corner, the same as
+    /// for shapes in `formation.yaml`.
+    Polygon(Vec<RelativePoint>),
+}
+
+/// A labelled area of the environment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Region {
+    pub name: String,
+    pub kind: RegionKind,
+    pub area: RegionArea,
+}
+
+impl Region {
+    /// Resolve the area of the region to polygons in world coordinates
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    pub fn to_world(&self, env: &Environment) -> WorldRegion {
+        let tile_size = env.tile_size();
+        let width = env.tiles.grid.ncols() as f32 * tile_size;
+        let height = env.tiles.grid.nrows() as f32 * tile_size;
+
+        let polygons = match &self.area {
+            RegionArea::Tiles(tiles) => tiles
+                .iter()
+                .map(|&TileCoordinates { row, col }| {
+                    let left = (col as f32).mul_add(tile_size, -width / 2.0);
+                    let top = (row as f32).mul_add(-tile_size, height / 2.0);
+                    vec![
+                        Vec2::new(left, top),
+                        Vec2::new(left, top - tile_size),
+                        Vec2::new(left + tile_size, top - tile_size),
+                        Vec2::new(left + tile_size, top),
+                    ]
+                })
+                .collect(),
+            RegionArea::Polygon(points) => vec![
+                points
+                    .iter()
+                    .map(|p| {
+                        Vec2::new(
+                            ((p.x.get() - 0.5) * f64::from(width)) as f32,
+                            ((p.y.get() - 0.5) * f64::from(height)) as f32,
+                        )
+                    })
+                    .collect(),
+            ],
+        };
+
+        WorldRegion {
+            name: self.name.clone(),
+            kind: self.kind,
+            polygons,
+        }
+    }
+
+    /// Ensure that the region is valid in the given `env`
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if:
+    /// 1. The region references a tile outside the tile grid
+    /// 2. The region is a polygon with fewer than 3 vertices
+    /// 3. The region is a keep-out zone with a cost outside [0, 1]
+    pub fn validate(&self, env: &Environment) -> Result<(), EnvironmentError> {
+        match &self.area {
+            RegionArea::Tiles(tiles) => {
+                if let Some(tile) = tiles.iter().find(|tile| {
+                    tile.row >= env.tiles.grid.nrows() || tile.col >= env.tiles.grid.ncols()
+                }) {
+                    return Err(EnvironmentError::RegionTileOutOfBounds {
+                        region: self.name.clone(),
+                        row: tile.row,
+                        col: tile.col,
+                    });
+                }
+            }
+            RegionArea::Polygon(points) if points.len() < 3 => {
+                return Err(EnvironmentError::RegionPolygonTooFewPoints(
+                    self.name.clone(),
+                ));
+            }
+            RegionArea::Polygon(_) => {}
+        }
+
+        if let RegionKind::KeepOut { cost } = self.kind {
+            if !(0.0..=1.0).contains(&cost) {
+                return Err(EnvironmentError::RegionCostOutOfRange {
+                    region: self.name.clone(),
+                    cost,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Collection of [`Region`]s
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoIterator)]
+pub struct Regions(Vec<Region>);
+
+impl Regions {
+    /// Create a new empty vector of [`Region`]
+    #[must_use]
+    pub const fn empty() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<Region> {
+        self.0.iter()
+    }
+
+    /// The region named `name`, if there is one
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Region> {
+        self.0.iter_mut().find(|region| region.name == name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// A [`Region`] with its area resolved to polygons in world coordinates
+#[derive(Debug, Clone)]
+pub struct WorldRegion {
+    pub name: String,
+    pub kind: RegionKind,
+    pub polygons: Vec<Vec<Vec2>>,
+}
+
+impl WorldRegion {
+    /// Whether `point` in world coordinates lies inside the region
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.polygons
+            .iter()
+            .any(|polygon| polygon_contains(polygon, point))
+    }
+}
+
+/// Even-odd rule point in polygon test
+fn polygon_contains(polygon: &[Vec2], point: Vec2) -> bool {
+    let mut inside = false;
+    let mut j = polygon.len() - 1;
+    for i in 0..polygon.len() {
+        let (a, b) = (polygon[i], polygon[j]);
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < (b.x - a.x) * (point.y - a.y) / (b.y - a.y) + a.x
+        {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+impl Environment {
+    /// All regions of the environment, resolved to world coordinates
+    pub fn world_regions(&self) -> Vec<WorldRegion> {
+        self.regions
+            .iter()
+            .map(|region| region.to_world(self))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn environment_with_regions(regions: &str) -> Environment {
+        let yaml = format!(
+            r"
+tiles:
+  grid:
+  - ┌┐
+  - └┘
+  settings:
+    tile-size: 10.0
+    path-width: 0.5
+    obstacle-height: 1.0
+obstacles: []
+regions:
+{regions}"
+        );
+        Environment::parse(&yaml).expect("valid environment")
+    }
+
+    #[test]
+    fn tile_region_covers_the_tile() {
+        let env = environment_with_regions(
+            r"
+- name: slow
+  kind: !speed-limit
+    max-speed: 1.0
+  area: !tiles
+  - row: 0
+    col: 1",
+        );
+        let regions = env.world_regions();
+        assert_eq!(regions.len(), 1);
+        // row 0 is the top row, col 1 the right column
+        assert!(regions[0].contains(Vec2::new(5.0, 5.0)));
+        assert!(!regions[0].contains(Vec2::new(-5.0, 5.0)));
+        assert!(!regions[0].contains(Vec2::new(5.0, -5.0)));
+    }
+
+    #[test]
+    fn polygon_region_uses_relative_coordinates() {
+        let env = environment_with_regions(
+            r"
+- name: lane
+  kind: !one-way
+    direction: 0.0
+  area: !polygon
+  - x: 0.0
+    y: 0.0
+  - x: 0.5
+    y: 0.0
+  - x: 0.5
+    y: 0.5
+  - x: 0.0
+    y: 0.5",
+        );
+        let regions = env.world_regions();
+        // (0, 0) is the bottom left corner
+        assert!(regions[0].contains(Vec2::new(-5.0, -5.0)));
+        assert!(!regions[0].contains(Vec2::new(5.0, 5.0)));
+        assert_eq!(regions[0].kind.direction(), Some(Vec2::X));
+    }
+
+    #[test]
+    fn out_of_bounds_tile_is_invalid() {
+        let yaml = r"
+tiles:
+  grid:
+  - ┼
+  settings:
+    tile-size: 10.0
+    path-width: 0.5
+    obstacle-height: 1.0
+obstacles: []
+regions:
+- name: nowhere
+  kind: !keep-out
+    cost: 0.5
+  area: !tiles
+  - row: 1
+    col: 0";
+        assert!(Environment::parse(yaml).is_err());
+    }
+}
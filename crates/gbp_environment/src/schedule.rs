@@ -0,0 +1,203 @@
+//! Schedules for obstacles that are only present some of the time, e.g. doors
+//! and gates that open and close.
+
+use serde::{Deserialize, Serialize};
+use typed_floats::StrictlyPositiveFinite;
+
+use crate::{Environment, EnvironmentError, Obstacle, Obstacles};
+
+/// An interval of simulated time in seconds, during which an obstacle is
+/// present. The interval is half-open, i.e. `[from, to)`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ActiveInterval {
+    pub from: f32,
+    /// If `None`, the obstacle stays present once it appears
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<f32>,
+}
+
+impl ActiveInterval {
+    /// Whether `t` lies inside the interval
+    #[inline]
+    pub fn contains(&self, t: f32) -> bool {
+        self.from <= t && self.to.is_none_or(|to| t < to)
+    }
+}
+
+/// When an [`Obstacle`] is present in the environment.
+/// Obstacles without a schedule are always present.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Schedule {
+    /// The intervals during which the obstacle is present
+    pub active: Vec<ActiveInterval>,
+    /// If set, the schedule repeats every `period` seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub period: Option<StrictlyPositiveFinite<f32>>,
+}
+
+impl Schedule {
+    /// Whether the obstacle is present at `t` seconds after the start of the
+    /// simulation
+    pub fn is_active(&self, t: f32) -> bool {
+        let t = self.period.map_or(t, |period| t.rem_euclid(period.get()));
+        self.active.iter().any(|interval| interval.contains(t))
+    }
+
+    /// Ensure that the schedule of obstacle number `obstacle` is valid
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if:
+    /// 1. An interval starts before 0, or ends before it starts
+    /// 2. The schedule is periodic, and an interval does not end within the
+    ///    period
+    pub fn validate(&self, obstacle: usize) -> Result<(), EnvironmentError> {
+        for interval in &self.active {
+            if interval.from < 0.0 || interval.to.is_some_and(|to| to <= interval.from) {
+                return Err(EnvironmentError::InvalidScheduleInterval {
+                    obstacle,
+                    from: interval.from,
+                    to: interval.to,
+                });
+            }
+
+            if let Some(period) = self.period {
+                if interval.to.is_none_or(|to| to > period.get()) {
+                    return Err(EnvironmentError::ScheduleIntervalOutsidePeriod {
+                        obstacle,
+                        period: period.get(),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Obstacle {
+    /// Whether the obstacle is present at `t` seconds after the start of the
+    /// simulation
+    #[inline]
+    pub fn is_active(&self, t: f32) -> bool {
+        self.schedule
+            .as_ref()
+            .is_none_or(|schedule| schedule.is_active(t))
+    }
+}
+
+impl Environment {
+    /// Whether any obstacle of the environment has a schedule
+    pub fn has_scheduled_obstacles(&self) -> bool {
+        self.obstacles
+            .iter()
+            .any(|obstacle| obstacle.schedule.is_some())
+    }
+
+    /// For each obstacle, whether it is present at time `t`
+    pub fn obstacles_active_at(&self, t: f32) -> Vec<bool> {
+        self.obstacles
+            .iter()
+            .map(|obstacle| obstacle.is_active(t))
+            .collect()
+    }
+
+    /// A copy of the environment, with only the obstacles present at time `t`
+    #[must_use]
+    pub fn active_at(&self, t: f32) -> Self {
+        Self {
+            obstacles: Obstacles(
+                self.obstacles
+                    .iter()
+                    .filter(|obstacle| obstacle.is_active(t))
+                    .cloned()
+                    .collect(),
+            ),
+            ..self.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interval(from: f32, to: Option<f32>) -> ActiveInterval {
+        ActiveInterval { from, to }
+    }
+
+    #[test]
+    fn open_ended_interval() {
+        let schedule = Schedule {
+            active: vec![interval(5.0, None)],
+            period: None,
+        };
+        assert!(!schedule.is_active(4.9));
+        assert!(schedule.is_active(5.0));
+        assert!(schedule.is_active(1000.0));
+    }
+
+    #[test]
+    fn periodic_schedule_repeats() {
+        let schedule = Schedule {
+            active: vec![interval(0.0, Some(10.0))],
+            period: Some(StrictlyPositiveFinite::<f32>::new(30.0).expect("30 > 0")),
+        };
+        assert!(schedule.is_active(5.0));
+        assert!(!schedule.is_active(15.0));
+        assert!(schedule.is_active(35.0));
+        assert!(!schedule.is_active(59.0));
+    }
+
+    #[test]
+    fn scheduled_obstacles_are_parsed_and_filtered() {
+        let yaml = r"
+tiles:
+  grid:
+  - ┼
+  settings:
+    tile-size: 10.0
+    path-width: 0.5
+    obstacle-height: 1.0
+obstacles:
+- shape: !circle
+    radius: 0.1
+  rotation: 0.0
+  translation:
+    x: 0.5
+    y: 0.5
+  tile-coordinates:
+    row: 0
+    col: 0
+  schedule:
+    active:
+    - from: 0.0
+      to: 10.0
+    period: 20.0
+- shape: !circle
+    radius: 0.1
+  rotation: 0.0
+  translation:
+    x: 0.25
+    y: 0.25
+  tile-coordinates:
+    row: 0
+    col: 0";
+        let env = Environment::parse(yaml).expect("valid environment");
+        assert!(env.has_scheduled_obstacles());
+        assert_eq!(env.obstacles_active_at(5.0), vec![true, true]);
+        assert_eq!(env.obstacles_active_at(15.0), vec![false, true]);
+        assert_eq!(env.active_at(15.0).obstacles.iter().count(), 1);
+    }
+
+    #[test]
+    fn interval_outside_period_is_invalid() {
+        let schedule = Schedule {
+            active: vec![interval(0.0, Some(40.0))],
+            period: Some(StrictlyPositiveFinite::<f32>::new(30.0).expect("30 > 0")),
+        };
+        assert!(schedule.validate(0).is_err());
+    }
+}
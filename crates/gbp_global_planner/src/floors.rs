@@ -0,0 +1,247 @@
+//! Route planning across the floors of an [`Environment`].
+//!
+//! Finds the sequence of [`Transfer`](gbp_environment::Transfer)s to take to
+//! get from one floor to another. The path on each floor is planned
+//! separately, e.g. with [`rrtstar`](crate::rrtstar).
+
+use bevy::math::Vec2;
+use gbp_environment::Environment;
+
+/// A position on a given floor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FloorPosition {
+    pub position: Vec2,
+    /// Index of the floor, `0` being the ground floor
+    pub floor: usize,
+}
+
+impl FloorPosition {
+    #[must_use]
+    pub const fn new(position: Vec2, floor: usize) -> Self {
+        Self { position, floor }
+    }
+}
+
+/// A single use of a transfer on a route between floors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferLeg {
+    /// Index of the transfer in [`Environment::transfers`]
+    pub transfer: usize,
+    /// Where the transfer is entered
+    pub entry: FloorPosition,
+    /// Where the transfer is left
+    pub exit: FloorPosition,
+    /// Time in seconds it takes to get from `entry` to `exit`
+    pub transit_time: f32,
+}
+
+/// Plan which transfers to take to get from `start` to `goal`, minimising the
+/// time taken. Travel time on a floor is estimated from the straight line
+/// distance and `speed`.
+///
+/// Returns `None` if the floor of `goal` can not be reached from the floor of
+/// `start`, and an empty route if they are on the same floor.
+#[must_use]
+pub fn plan_floor_route(
+    environment: &Environment,
+    start: FloorPosition,
+    goal: FloorPosition,
+    speed: f32,
+) -> Option<Vec<TransferLeg>> {
+    if start.floor == goal.floor {
+        return Some(Vec::new());
+    }
+
+    // Node 0 is the start, node 1 the goal, and node 2 + 2 * i + j is end j of
+    // transfer i
+    let nodes: Vec<FloorPosition> = [start, goal]
+        .into_iter()
+        .chain(environment.transfers.iter().flat_map(|transfer| {
+            transfer
+                .ends
+                .iter()
+                .map(|end| FloorPosition::new(end.to_world(environment), end.floor))
+        }))
+        .collect();
+
+    let transfer_of = |node: usize| node.checked_sub(2).map(|i| i / 2);
+    let edge_cost = |from: usize, to: usize| -> Option<f32> {
+        match (transfer_of(from), transfer_of(to)) {
+            (Some(a), Some(b)) if a == b => Some(environment.transfers[a].transit_time.get()),
+            _ if nodes[from].floor == nodes[to].floor => {
+                Some(nodes[from].position.distance(nodes[to].position) / speed)
+            }
+            _ => None,
+        }
+    };
+
+    // Dijkstra, the number of transfers is small enough to not need a heap
+    let mut cost = vec![f32::INFINITY; nodes.len()];
+    let mut previous: Vec<Option<usize>> = vec![None; nodes.len()];
+    let mut visited = vec![false; nodes.len()];
+    cost[0] = 0.0;
+
+    while let Some(current) = (0..nodes.len())
+        .filter(|&node| !visited[node] && cost[node].is_finite())
+        .min_by(|&a, &b| cost[a].total_cmp(&cost[b]))
+    {
+        if current == 1 {
+            break;
+        }
+        visited[current] = true;
+
+        for next in (0..nodes.len()).filter(|&node| !visited[node]) {
+            let Some(edge) = edge_cost(current, next) else {
+                continue;
+            };
+            if cost[current] + edge < cost[next] {
+                cost[next] = cost[current] + edge;
+                previous[next] = Some(current);
+            }
+        }
+    }
+
+    if !cost[1].is_finite() {
+        return None;
+    }
+
+    let mut path = vec![1];
+    while let Some(node) = previous[path[path.len() - 1]] {
+        path.push(node);
+    }
+    path.reverse();
+
+    let legs = path
+        .windows(2)
+        .filter_map(|pair| {
+            let (entry, exit) = (pair[0], pair[1]);
+            let transfer = transfer_of(entry)?;
+            (transfer_of(exit) == Some(transfer)).then(|| TransferLeg {
+                transfer,
+                entry: nodes[entry],
+                exit: nodes[exit],
+                transit_time: environment.transfers[transfer].transit_time.get(),
+            })
+        })
+        .collect();
+
+    Some(legs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lift(name: &str, from: (usize, f32), to: (usize, f32), transit_time: f32) -> String {
+        format!(
+            r"
+- name: {name}
+  kind: elevator
+  ends:
+  - floor: {}
+    position:
+      x: {}
+      y: 0.5
+  - floor: {}
+    position:
+      x: {}
+      y: 0.5
+  capacity: 1
+  transit-time: {transit_time}",
+            from.0, from.1, to.0, to.1
+        )
+    }
+
+    fn building(transfers: &[String]) -> Environment {
+        let floor = r"
+  tiles:
+    grid:
+    - ┼
+    settings:
+      tile-size: 100.0
+      path-width: 0.5
+      obstacle-height: 1.0";
+        let yaml = format!(
+            r"
+tiles:
+  grid:
+  - ┼
+  settings:
+    tile-size: 100.0
+    path-width: 0.5
+    obstacle-height: 1.0
+obstacles: []
+floors:
+- name: first
+  elevation: 5.0{floor}
+- name: second
+  elevation: 10.0{floor}
+transfers:{}",
+            transfers.concat()
+        );
+        Environment::parse(&yaml).expect("valid environment")
+    }
+
+    #[test]
+    fn same_floor_needs_no_transfers() {
+        let env = building(&[lift("a", (0, 0.5), (1, 0.5), 10.0)]);
+        let route = plan_floor_route(
+            &env,
+            FloorPosition::new(Vec2::ZERO, 1),
+            FloorPosition::new(Vec2::ONE, 1),
+            1.0,
+        );
+        assert_eq!(route, Some(Vec::new()));
+    }
+
+    #[test]
+    fn route_through_several_floors() {
+        let env = building(&[
+            lift("a", (0, 0.5), (1, 0.5), 10.0),
+            lift("b", (1, 0.75), (2, 0.75), 10.0),
+        ]);
+        let route = plan_floor_route(
+            &env,
+            FloorPosition::new(Vec2::ZERO, 0),
+            FloorPosition::new(Vec2::ZERO, 2),
+            1.0,
+        )
+        .expect("floor 2 is reachable");
+
+        assert_eq!(route.len(), 2);
+        assert_eq!(route[0].transfer, 0);
+        assert_eq!(route[0].exit.floor, 1);
+        assert_eq!(route[1].transfer, 1);
+        assert_eq!(route[1].entry.position, Vec2::new(25.0, 0.0));
+    }
+
+    #[test]
+    fn prefers_the_faster_transfer() {
+        let env = building(&[
+            lift("far", (0, 0.1), (1, 0.1), 10.0),
+            lift("near", (0, 0.6), (1, 0.6), 10.0),
+        ]);
+        let route = plan_floor_route(
+            &env,
+            FloorPosition::new(Vec2::new(20.0, 0.0), 0),
+            FloorPosition::new(Vec2::new(20.0, 0.0), 1),
+            1.0,
+        )
+        .expect("floor 1 is reachable");
+
+        assert_eq!(route.len(), 1);
+        assert_eq!(route[0].transfer, 1);
+    }
+
+    #[test]
+    fn unreachable_floor() {
+        let env = building(&[lift("a", (0, 0.5), (1, 0.5), 10.0)]);
+        let route = plan_floor_route(
+            &env,
+            FloorPosition::new(Vec2::ZERO, 0),
+            FloorPosition::new(Vec2::ZERO, 2),
+            1.0,
+        );
+        assert!(route.is_none());
+    }
+}
@@ -1,5 +1,6 @@
 //! Global path planning module
 
+pub mod floors;
 pub mod rrtstar;
 
 use std::sync::Arc;
@@ -130,6 +131,16 @@ impl Colliders {
             shape,
         });
     }
+
+    /// Append all colliders of `other`
+    pub fn extend_from(&mut self, other: &Self) {
+        self.0.extend(other.0.iter().cloned());
+    }
+
+    /// Keep only the colliders for which `f` returns `true`
+    pub fn retain(&mut self, f: impl FnMut(&Collider) -> bool) {
+        self.0.retain(f);
+    }
 }
 
 struct CollisionProblem {
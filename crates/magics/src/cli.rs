@@ -46,6 +46,11 @@ pub struct Cli {
     #[arg(short, long, group = "dump")]
     pub list_scenarios: bool,
 
+    /// Print the fully resolved config, environment and formation group of a
+    /// scenario, with the files of the scenarios it extends merged in
+    #[arg(long, value_name = "SCENARIO", group = "dump")]
+    pub print_resolved: Option<String>,
+
     /// Initial scenario to load
     /// If not specified, the first scenario in lexiographical order is loaded
     /// from the simulations directory
@@ -83,10 +88,56 @@ pub struct Cli {
     #[arg(long)]
     pub height: Option<u32>,
 
-    /// Record image sequences of the running game, that later can be
-    /// concatenated into a video with `ffmpeg`
+    /// Record the simulation at a fixed rate of simulated time, to an image
+    /// sequence that can be encoded to a video with `ffmpeg`, or to an
+    /// animation. See the `[recording]` section of the config
     #[arg(long)]
     pub record: bool,
+
+    /// Dump the factorgraphs of every robot this many seconds of simulated
+    /// time after a scenario is loaded, in the formats of the `[graphviz]`
+    /// section of the config. Can be given multiple times
+    #[arg(long, value_name = "SECONDS")]
+    pub dump_factorgraphs_at: Vec<f32>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Commands that are run instead of the simulation
+#[derive(Debug, clap::Subcommand)]
+pub enum Command {
+    /// Generate a report comparing the JSON exports in a directory
+    Report {
+        /// Directory with the `export_*.json` files to compare
+        dir: std::path::PathBuf,
+        /// Setting to group the runs by, e.g. `simulation.prng-seed`,
+        /// `robots` or `gbp.iteration-schedule.schedule`. Can be given
+        /// multiple times
+        #[arg(short, long = "group-by", value_name = "KEY")]
+        group_by: Vec<String>,
+        /// Format of the report
+        #[arg(long, value_enum, default_value_t)]
+        format: crate::export::report::ReportFormat,
+        /// File to write the report to, instead of stdout
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Render top-down plots of the trajectories in JSON exports
+    Plot {
+        /// The `export_*.json` files to plot
+        #[arg(required = true)]
+        exports: Vec<std::path::PathBuf>,
+        /// Format of the plots
+        #[arg(long, value_enum, default_value_t)]
+        format: crate::export::plot::PlotFormat,
+        /// Width of the plots in pixels
+        #[arg(long, default_value_t = 1000)]
+        width: u32,
+        /// Directory to write the plots to, instead of next to the exports
+        #[arg(short, long)]
+        out_dir: Option<std::path::PathBuf>,
+    },
 }
 
 /// Verbosity level
@@ -7,7 +7,9 @@ use units::sample_rate::SampleRate;
 
 use crate::{
     factorgraph::prelude::FactorGraph,
-    planner::{collisions::resources::RobotRobotCollisions, RobotConnections},
+    planner::{
+        collisions::resources::RobotRobotCollisions, lifelong::LifelongLog, RobotConnections,
+    },
     simulation_loader::{LoadSimulation, ReloadSimulation},
 };
 
@@ -20,6 +22,7 @@ pub struct SampleRates {
     pub robots: Option<SampleRate>,
     pub robot_collisions: Option<SampleRate>,
     pub variables_and_factors: Option<SampleRate>,
+    pub lifelong_goals: Option<SampleRate>,
     // pub messages_sent: Option<SampleRate>,
 }
 
@@ -29,6 +32,7 @@ impl Default for SampleRates {
             robots: None,
             robot_collisions: Some(SampleRate::from_hz(5.try_into().expect("1 > 0"))),
             variables_and_factors: Some(SampleRate::from_hz(2.try_into().expect("2 > 0"))),
+            lifelong_goals: Some(SampleRate::from_hz(1.try_into().expect("1 > 0"))),
             // messages_sent: Some(SampleRate::from_hz(2.try_into().expect("2 > 0"))),
         }
     }
@@ -60,7 +64,9 @@ impl Plugin for RobotDiagnosticsPlugin {
             .register_diagnostic(Diagnostic::new(Self::MESSAGES_RECEIVED_EXTERNAL_COUNT))
             .register_diagnostic(Diagnostic::new(Self::MESSAGES_SENT_EXTERNAL_COUNT))
             .register_diagnostic(Diagnostic::new(Self::MESSAGES_SENT_INTERNAL_COUNT))
-            .register_diagnostic(Diagnostic::new(Self::ROBOT_COLLISION_COUNT));
+            .register_diagnostic(Diagnostic::new(Self::ROBOT_COLLISION_COUNT))
+            .register_diagnostic(Diagnostic::new(Self::GOALS_PER_MINUTE))
+            .register_diagnostic(Diagnostic::new(Self::GOAL_LATENCY));
 
         add_diagnostic_system!(app, self.sample_rates.robots, Self::robots);
         add_diagnostic_system!(
@@ -76,6 +82,7 @@ impl Plugin for RobotDiagnosticsPlugin {
             self.sample_rates.robot_collisions,
             Self::count_robot_collisions
         );
+        add_diagnostic_system!(app, self.sample_rates.lifelong_goals, Self::lifelong_goals);
 
         app.add_systems(
             Update,
@@ -91,6 +98,11 @@ impl RobotDiagnosticsPlugin {
     pub const EXTERNAL_MESSAGES_SENT_COUNT: DiagnosticPath =
         DiagnosticPath::const_new("external_messages_sent_count");
     pub const FACTOR_COUNT: DiagnosticPath = DiagnosticPath::const_new("factor_count");
+    /// Goals reached per minute by robots in lifelong operation
+    pub const GOALS_PER_MINUTE: DiagnosticPath = DiagnosticPath::const_new("goals_per_minute");
+    /// Mean seconds from when a robot in lifelong operation is given a goal,
+    /// until it reaches it
+    pub const GOAL_LATENCY: DiagnosticPath = DiagnosticPath::const_new("goal_latency");
     pub const MESSAGES_RECEIVED_EXTERNAL_COUNT: DiagnosticPath =
         DiagnosticPath::const_new("messages_received_internal_count");
     pub const MESSAGES_RECEIVED_INTERNAL_COUNT: DiagnosticPath =
@@ -131,6 +143,22 @@ impl RobotDiagnosticsPlugin {
         });
     }
 
+    fn lifelong_goals(
+        mut diagnostics: Diagnostics,
+        lifelong_log: Res<LifelongLog>,
+        time: Res<Time<Virtual>>,
+    ) {
+        if lifelong_log.goals().is_empty() {
+            return;
+        }
+        diagnostics.add_measurement(&Self::GOALS_PER_MINUTE, || {
+            f64::from(lifelong_log.goals_per_minute(&time))
+        });
+        if let Some(latency) = lifelong_log.mean_latency() {
+            diagnostics.add_measurement(&Self::GOAL_LATENCY, || f64::from(latency));
+        }
+    }
+
     // #[allow(clippy::cast_precision_loss)]
     // fn messages_sent(
     //     mut diagnostics: Diagnostics,
@@ -231,6 +259,8 @@ impl RobotDiagnosticsPlugin {
             Self::EXTERNAL_MESSAGES_SENT_COUNT,
             Self::ROBOT_COLLISION_COUNT,
             Self::ENVIRONMENT_COLLISION_COUNT,
+            Self::GOALS_PER_MINUTE,
+            Self::GOAL_LATENCY,
         ] {
             if let Some(diagnostic) = store.get_mut(path) {
                 diagnostic.clear_history();
@@ -0,0 +1,268 @@
+//! Obstacles that appear and disappear according to the
+//! [`Schedule`](gbp_environment::Schedule) given in the environment, e.g. doors
+//! and gates.
+//!
+//! When an obstacle changes state the obstacle is masked out of the
+//! [`Colliders`], robots using a global planner plan a new path, and the SDF
+//! is rebuilt in the background and swapped in once it is done.
+
+use bevy::{
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task, futures_lite::future},
+};
+use gbp_config::{Config, formation::PlanningStrategy};
+use gbp_environment::Environment;
+use gbp_global_planner::Colliders;
+
+use super::{floors::OnFloor, map_generator::ObstacleIndex};
+use crate::{
+    factorgraph::prelude::FactorGraph,
+    planner::robot::Mission,
+    simulation_loader::{LoadSimulation, ReloadSimulation, Sdf, SharedSdfImage, build_sdf},
+};
+
+pub struct DynamicObstaclesPlugin;
+
+impl Plugin for DynamicObstaclesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ObstacleStateChanged>()
+            .init_resource::<ObstacleStates>()
+            .init_resource::<InactiveColliders>()
+            .init_resource::<ObstacleStateLog>()
+            .init_resource::<PendingSdf>()
+            .add_systems(
+                Update,
+                reset_obstacle_states
+                    .run_if(on_event::<LoadSimulation>().or_else(on_event::<ReloadSimulation>())),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    update_obstacle_states,
+                    replan_global_paths.run_if(on_event::<ObstacleStateChanged>()),
+                )
+                    .chain()
+                    .run_if(
+                        environment_has_scheduled_obstacles.and_then(resource_exists::<Colliders>),
+                    ),
+            )
+            .add_systems(
+                FixedUpdate,
+                swap_in_rebuilt_sdf.run_if(|pending: Res<PendingSdf>| pending.0.is_some()),
+            );
+    }
+}
+
+/// **Bevy** [`Event`] sent when a scheduled obstacle appears or disappears
+#[derive(Debug, Clone, Copy, Event)]
+pub struct ObstacleStateChanged {
+    /// Index of the obstacle in [`Environment::obstacles`]
+    pub obstacle: usize,
+    /// Whether the obstacle is now present
+    pub active: bool,
+}
+
+/// **Bevy** [`Resource`] with the current state of every obstacle
+#[derive(Debug, Default, Resource)]
+pub struct ObstacleStates {
+    /// `None` until the states have been applied after loading a simulation
+    active: Option<Vec<bool>>,
+    /// Elapsed virtual time when the simulation was loaded, schedules are
+    /// relative to this
+    loaded_at: f32,
+}
+
+impl ObstacleStates {
+    /// Whether obstacle number `obstacle` is present. Obstacles are present
+    /// until their schedule has been applied.
+    pub fn is_active(&self, obstacle: usize) -> bool {
+        self.active
+            .as_ref()
+            .and_then(|active| active.get(obstacle).copied())
+            .unwrap_or(true)
+    }
+
+    /// Elapsed virtual time when the simulation was loaded
+    pub const fn loaded_at(&self) -> f32 {
+        self.loaded_at
+    }
+}
+
+/// **Bevy** [`Resource`] with the colliders of obstacles that are currently
+/// not present, and therefore masked out of [`Colliders`]
+#[derive(Default, Resource, Deref, DerefMut)]
+struct InactiveColliders(Colliders);
+
+/// A change of state of a scheduled obstacle
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct ObstacleStateChange {
+    /// Index of the obstacle in [`Environment::obstacles`]
+    pub obstacle: usize,
+    /// Whether the obstacle became present
+    pub active: bool,
+    /// Seconds since the simulation was loaded
+    pub at: f32,
+}
+
+/// **Bevy** [`Resource`] recording every state change since the simulation
+/// was loaded, included in the export
+#[derive(Debug, Default, Resource, Deref)]
+pub struct ObstacleStateLog(Vec<ObstacleStateChange>);
+
+/// **Bevy** [`Resource`] with the SDF of the ground floor being rebuilt in
+/// the background, if any. Rendering a monolithic SDF takes far longer than a
+/// fixed timestep, so the obstacle factors keep using the old SDF until the
+/// new one is done.
+#[derive(Default, Resource)]
+pub(crate) struct PendingSdf(Option<Task<SharedSdfImage>>);
+
+impl PendingSdf {
+    /// Start rebuilding the SDF of `environment`. A rebuild that is still
+    /// running is cancelled, as its environment is out of date.
+    pub(crate) fn rebuild(&mut self, environment: Environment) {
+        let task = AsyncComputeTaskPool::get().spawn(async move { build_sdf(&environment) });
+        self.0 = Some(task);
+    }
+}
+
+/// **Bevy** system that swaps in the SDF rebuilt in the background once it is
+/// done, for the obstacle factors of every robot on the ground floor
+fn swap_in_rebuilt_sdf(
+    mut pending: ResMut<PendingSdf>,
+    mut sdf: ResMut<Sdf>,
+    mut factorgraphs: Query<(&mut FactorGraph, &OnFloor)>,
+) {
+    let Some(task) = pending.0.as_mut() else {
+        return;
+    };
+    let Some(new_sdf) = future::block_on(future::poll_once(task)) else {
+        return;
+    };
+    pending.0 = None;
+
+    // Scheduled obstacles and regions only exist on the ground floor
+    for (mut factorgraph, _) in factorgraphs
+        .iter_mut()
+        .filter(|(_, on_floor)| on_floor.0 == 0)
+    {
+        factorgraph.modify_obstacle_factors(|obstacle_factor| {
+            obstacle_factor.set_sdf(new_sdf.clone());
+        });
+    }
+    *sdf = Sdf(new_sdf);
+}
+
+/// **Bevy** run condition, to only track obstacle states if any obstacle has a
+/// schedule
+fn environment_has_scheduled_obstacles(env_config: Res<Environment>) -> bool {
+    env_config.has_scheduled_obstacles()
+}
+
+fn reset_obstacle_states(
+    mut evr_load_simulation: EventReader<LoadSimulation>,
+    mut obstacle_states: ResMut<ObstacleStates>,
+    mut inactive_colliders: ResMut<InactiveColliders>,
+    mut obstacle_state_log: ResMut<ObstacleStateLog>,
+    mut pending_sdf: ResMut<PendingSdf>,
+    time: Res<Time<Virtual>>,
+) {
+    // Loading a simulation replaces all colliders, while reloading keeps them
+    if evr_load_simulation.read().count() > 0 {
+        inactive_colliders.clear();
+    }
+    // The SDF of the previous simulation must not replace the new one
+    pending_sdf.0 = None;
+
+    *obstacle_states = ObstacleStates {
+        active: None,
+        loaded_at: time.elapsed_seconds(),
+    };
+    obstacle_state_log.0.clear();
+}
+
+/// **Bevy** system that evaluates the schedule of every obstacle, and applies
+/// any changes to the colliders and the obstacle meshes, and starts rebuilding
+/// the SDF
+#[allow(clippy::too_many_arguments)]
+fn update_obstacle_states(
+    mut evw_obstacle_state_changed: EventWriter<ObstacleStateChanged>,
+    mut obstacle_states: ResMut<ObstacleStates>,
+    mut obstacle_state_log: ResMut<ObstacleStateLog>,
+    mut colliders: ResMut<Colliders>,
+    mut inactive_colliders: ResMut<InactiveColliders>,
+    mut pending_sdf: ResMut<PendingSdf>,
+    mut obstacles: Query<(&ObstacleIndex, &mut Visibility)>,
+    env_config: Res<Environment>,
+    config: Res<Config>,
+    time: Res<Time<Virtual>>,
+) {
+    let t = time.elapsed_seconds() - obstacle_states.loaded_at;
+    let active = env_config.obstacles_active_at(t);
+
+    if obstacle_states.active.as_ref() == Some(&active) {
+        return;
+    }
+
+    // The first time after loading, the states are only applied, not reported
+    if let Some(previous) = obstacle_states.active.as_deref() {
+        for (obstacle, (&was_active, &is_active)) in previous.iter().zip(&active).enumerate() {
+            if was_active != is_active {
+                info!(
+                    "obstacle {} is now {}",
+                    obstacle,
+                    if is_active { "present" } else { "absent" }
+                );
+                evw_obstacle_state_changed.send(ObstacleStateChanged {
+                    obstacle,
+                    active: is_active,
+                });
+                obstacle_state_log.0.push(ObstacleStateChange {
+                    obstacle,
+                    active: is_active,
+                    at: t,
+                });
+            }
+        }
+    }
+
+    // Map from the entity of each spawned obstacle to whether it is present
+    let is_present = |entity: Option<Entity>| {
+        entity
+            .and_then(|entity| obstacles.get(entity).ok())
+            .is_none_or(|(index, _)| active.get(**index).copied().unwrap_or(true))
+    };
+
+    let mut all_colliders = std::mem::take(&mut *colliders);
+    all_colliders.extend_from(&inactive_colliders);
+    let mut masked = all_colliders.clone();
+    all_colliders.retain(|collider| is_present(collider.associated_mesh));
+    masked.retain(|collider| !is_present(collider.associated_mesh));
+    *colliders = all_colliders;
+    inactive_colliders.0 = masked;
+
+    for (index, mut visibility) in &mut obstacles {
+        let present = active.get(**index).copied().unwrap_or(true);
+        *visibility = if present && config.visualisation.draw.generated_map {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+    }
+
+    pending_sdf.rebuild(env_config.active_at(t));
+
+    obstacle_states.active = Some(active);
+}
+
+/// **Bevy** system that makes every robot using a global planner plan a new
+/// path from where it is, as the old one might be blocked, or a shorter one
+/// might have opened up.
+pub(crate) fn replan_global_paths(
+    mut robots: Query<(&Transform, &mut Mission, &PlanningStrategy)>,
+) {
+    for (transform, mut mission, planning_strategy) in &mut robots {
+        if matches!(planning_strategy, PlanningStrategy::RrtStar) {
+            mission.replan_from(transform.translation.xz());
+        }
+    }
+}
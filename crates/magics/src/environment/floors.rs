@@ -0,0 +1,276 @@
+//! Robots moving between the floors of a multi-floor environment, see
+//! [`gbp_environment::Floor`].
+//!
+//! When a robot has waypoints on different floors, the transfers it has to
+//! take are inserted into its mission as it spawns. Once the robot reaches the
+//! entry of a transfer it waits until the transfer has capacity, disappears for
+//! the transit time, and then continues from the exit on the other floor.
+//! Robots only interact with robots on the same floor.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use gbp_config::Config;
+use gbp_environment::Environment;
+use gbp_global_planner::floors::{FloorPosition, TransferLeg, plan_floor_route};
+use gbp_linalg::Float;
+
+use crate::{
+    bevy_utils::run_conditions::time::virtual_time_is_paused,
+    factorgraph::prelude::FactorGraph,
+    planner::robot::{Mission, MissionState, StateVector, reached_waypoint},
+    simulation_loader::{FloorSdfs, Sdf},
+};
+
+/// Height robots are drawn at above the floor they are on
+const ROBOT_HEIGHT: f32 = 1.5;
+
+pub struct FloorsPlugin;
+
+impl Plugin for FloorsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RobotChangedFloor>().add_systems(
+            FixedUpdate,
+            (enter_transfers, complete_transfers)
+                .chain()
+                .after(reached_waypoint)
+                .run_if(environment_has_transfers.and_then(not(virtual_time_is_paused))),
+        );
+    }
+}
+
+/// **Bevy** [`Component`] with the floor a robot is on. Removed while the
+/// robot is in transit between two floors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component, Deref)]
+pub struct OnFloor(pub usize);
+
+/// **Bevy** [`Component`] with the transfers a robot has left to take, in
+/// order
+#[derive(Debug, Default, Component, Deref)]
+pub struct TransferPlan(VecDeque<TransferLeg>);
+
+/// **Bevy** [`Component`] attached to a robot from when it reaches the entry
+/// of a transfer, until it leaves at the exit
+#[derive(Debug, Clone, Copy, Component)]
+pub struct Transferring {
+    leg: TransferLeg,
+    /// Elapsed time at which the robot arrives at the exit, `None` while it
+    /// waits for the transfer to have capacity
+    arrives_at: Option<f32>,
+}
+
+/// **Bevy** [`Event`] sent when a robot arrives on another floor
+#[derive(Debug, Clone, Copy, Event)]
+pub struct RobotChangedFloor {
+    pub robot: Entity,
+    /// Index of the transfer in [`Environment::transfers`]
+    pub transfer: usize,
+    pub floor: usize,
+}
+
+/// **Bevy** run condition, to only move robots between floors if there are
+/// any transfers
+fn environment_has_transfers(env_config: Res<Environment>) -> bool {
+    !env_config.transfers.is_empty()
+}
+
+/// Position of a robot at `position` on floor number `floor`, in **Bevy**
+/// coordinates
+pub fn robot_translation(env_config: &Environment, position: Vec2, floor: usize) -> Vec3 {
+    Vec3::new(
+        position.x,
+        -ROBOT_HEIGHT - env_config.elevation(floor),
+        position.y,
+    )
+}
+
+/// Insert the entry and exit of every transfer needed to get between
+/// consecutive `waypoints` on different floors, where `floors` is the floor of
+/// each waypoint. The robot stops at the entry of a transfer, and heads for
+/// the next waypoint from the exit.
+///
+/// Returns `None` if a waypoint is on a floor that can not be reached.
+pub fn insert_transfers(
+    env_config: &Environment,
+    waypoints: &[StateVector],
+    floors: &[usize],
+    speed: f32,
+) -> Option<(Vec<StateVector>, TransferPlan)> {
+    let head_for = |waypoint: &mut StateVector, target: Vec2| {
+        let direction = (target - waypoint.position()).normalize_or_zero();
+        waypoint.update_velocity(direction * speed);
+    };
+
+    let mut with_transfers: Vec<StateVector> = Vec::with_capacity(waypoints.len());
+    let mut legs = VecDeque::new();
+
+    for (i, (&waypoint, &floor)) in waypoints.iter().zip(floors).enumerate() {
+        if let Some(&previous_floor) = i.checked_sub(1).and_then(|i| floors.get(i)) {
+            let route = plan_floor_route(
+                env_config,
+                FloorPosition::new(waypoints[i - 1].position(), previous_floor),
+                FloorPosition::new(waypoint.position(), floor),
+                speed,
+            )?;
+
+            for leg in route {
+                if let Some(previous) = with_transfers.last_mut() {
+                    head_for(previous, leg.entry.position);
+                }
+                with_transfers.push(StateVector::new(leg.entry.position.extend(0.0).extend(0.0)));
+                let mut exit = StateVector::new(leg.exit.position.extend(0.0).extend(0.0));
+                head_for(&mut exit, waypoint.position());
+                with_transfers.push(exit);
+                legs.push_back(leg);
+            }
+        }
+        with_transfers.push(waypoint);
+    }
+
+    Some((with_transfers, TransferPlan(legs)))
+}
+
+/// **Bevy** system that stops robots once they have reached the entry of their
+/// next transfer, and lets waiting robots into transfers with capacity
+fn enter_transfers(
+    mut commands: Commands,
+    mut robots: Query<(
+        Entity,
+        &mut Mission,
+        &TransferPlan,
+        Option<&mut Transferring>,
+        &mut Visibility,
+    )>,
+    env_config: Res<Environment>,
+    time: Res<Time>,
+) {
+    // Number of robots currently in transit through each transfer
+    let mut in_transit = vec![0_usize; env_config.transfers.len()];
+    for (_, _, _, transferring, _) in &robots {
+        if let Some(transferring) = transferring.filter(|it| it.arrives_at.is_some()) {
+            in_transit[transferring.leg.transfer] += 1;
+        }
+    }
+
+    for (entity, mut mission, plan, transferring, mut visibility) in &mut robots {
+        match transferring {
+            None => {
+                let Some(leg) = plan.front() else {
+                    continue;
+                };
+                // The entry counts as reached once the robot heads for the exit
+                let at_entry = mission
+                    .target()
+                    .is_some_and(|target| target.abs_diff_eq(leg.exit.position, 1e-3));
+                if at_entry {
+                    mission.state = MissionState::InTransit;
+                    commands.entity(entity).insert(Transferring {
+                        leg: *leg,
+                        arrives_at: None,
+                    });
+                }
+            }
+            Some(mut transferring) if transferring.arrives_at.is_none() => {
+                let transfer = transferring.leg.transfer;
+                if in_transit[transfer] < env_config.transfers[transfer].capacity.get() {
+                    in_transit[transfer] += 1;
+                    transferring.arrives_at =
+                        Some(time.elapsed_seconds() + transferring.leg.transit_time);
+                    *visibility = Visibility::Hidden;
+                    commands.entity(entity).remove::<OnFloor>();
+                    info!(
+                        "robot {:?} entered transfer '{}'",
+                        entity, env_config.transfers[transfer].name
+                    );
+                }
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+/// **Bevy** system that moves robots to the exit of their transfer once the
+/// transit time has passed
+#[allow(clippy::too_many_arguments)]
+fn complete_transfers(
+    mut commands: Commands,
+    mut evw_robot_changed_floor: EventWriter<RobotChangedFloor>,
+    mut robots: Query<(
+        Entity,
+        &Transferring,
+        &mut TransferPlan,
+        &mut Mission,
+        &mut FactorGraph,
+        &mut Transform,
+        &mut Visibility,
+    )>,
+    env_config: Res<Environment>,
+    config: Res<Config>,
+    sdf: Res<Sdf>,
+    floor_sdfs: Res<FloorSdfs>,
+    time: Res<Time>,
+) {
+    for (
+        entity,
+        transferring,
+        mut plan,
+        mut mission,
+        mut factorgraph,
+        mut transform,
+        mut visibility,
+    ) in &mut robots
+    {
+        if transferring
+            .arrives_at
+            .is_none_or(|arrives_at| time.elapsed_seconds() < arrives_at)
+        {
+            continue;
+        }
+
+        let TransferLeg { transfer, exit, .. } = transferring.leg;
+        transform.translation = robot_translation(&env_config, exit.position, exit.floor);
+        *visibility = if config.visualisation.draw.robots {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+
+        // Start over from the exit, with the obstacles of the new floor
+        let means = vec![
+            [
+                Float::from(exit.position.x),
+                Float::from(exit.position.y),
+                0.0,
+                0.0
+            ];
+            factorgraph.node_count().variables
+        ];
+        factorgraph.reset_variables(&means, 1e30, Float::INFINITY);
+        factorgraph.reset_tracking_factors();
+        if let Some(floor_sdf) = floor_sdfs.get(exit.floor, &sdf) {
+            factorgraph.modify_obstacle_factors(|obstacle_factor| {
+                obstacle_factor.set_sdf(floor_sdf.clone());
+            });
+        }
+
+        mission.complete_transfer(&time);
+        if let Some(current_waypoint_index) = mission.current_waypoint_index() {
+            factorgraph.modify_tracking_factors(|tracking| {
+                tracking.set_tracking_index(current_waypoint_index);
+            });
+        }
+
+        plan.0.pop_front();
+        commands
+            .entity(entity)
+            .remove::<Transferring>()
+            .insert(OnFloor(exit.floor));
+
+        info!("robot {:?} arrived on floor {}", entity, exit.floor);
+        evw_robot_changed_floor.send(RobotChangedFloor {
+            robot: entity,
+            transfer,
+            floor: exit.floor,
+        });
+    }
+}
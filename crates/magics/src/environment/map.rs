@@ -110,10 +110,11 @@ fn spawn_sdf_map_representation(
         info!("despawned sdf map representation");
     }
 
-    let width = sdf.0.width();
-    let height = sdf.0.height();
+    let preview = sdf.0.preview();
+    let width = preview.width();
+    let height = preview.height();
     let mut rgba_buffer = vec![255u8; width as usize * height as usize * 4];
-    let input = sdf.0.as_raw();
+    let input = preview.as_raw();
     let mut i = 0;
     for chunk in input.chunks(3) {
         rgba_buffer[i..i + 3].copy_from_slice(&chunk[0..3]);
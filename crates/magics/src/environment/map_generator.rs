@@ -12,6 +12,7 @@ use parry2d::{
     shape,
 };
 
+use super::dynamic_obstacles::ObstacleStates;
 use crate::{
     asset_loader::Materials, bevy_utils::run_conditions::event_exists, input::DrawSettingsEvent,
     simulation_loader::LoadSimulation,
@@ -30,6 +31,11 @@ impl Plugin for GenMapPlugin {
                 Update,
                 (build_tile_grid.pipe(build_obstacles.pipe(insert_colliders_resource))).chain().run_if(on_event::<LoadSimulation>()),
             )
+            .add_systems(
+                Update,
+                (insert_keep_out_colliders_resource, build_upper_floors)
+                    .run_if(on_event::<LoadSimulation>()),
+            )
             .add_systems(
                 Update,
                 show_or_hide_generated_map.run_if(event_exists::<DrawSettingsEvent>),
@@ -54,6 +60,11 @@ pub mod events {
 #[derive(Debug, Component)]
 pub struct ObstacleMarker;
 
+/// Index of the obstacle in [`Environment::obstacles`] that the entity was
+/// spawned from
+#[derive(Debug, Clone, Copy, Component, Deref)]
+pub struct ObstacleIndex(pub usize);
+
 // #[derive(Clone)]
 // pub struct Collider {
 //     pub associated_mesh: Option<Entity>,
@@ -106,6 +117,97 @@ fn insert_colliders_resource(In(colliders): In<Colliders>, mut commands: Command
     commands.insert_resource(colliders);
 }
 
+/// **Bevy** [`Resource`] with a collider for every impassable keep-out region
+/// of the environment. Kept apart from [`Colliders`], as the regions are not
+/// physical obstacles, and should only be avoided by the global planner.
+#[derive(Resource, Default, Clone, Deref)]
+pub struct KeepOutColliders(Colliders);
+
+impl KeepOutColliders {
+    /// Build the colliders of the impassable keep-out regions of
+    /// `environment`. Non-convex polygons are approximated by their convex
+    /// hull.
+    pub fn from_environment(environment: &Environment) -> Self {
+        let mut colliders = Colliders::default();
+
+        for region in environment
+            .world_regions()
+            .into_iter()
+            .filter(|region| region.kind.impassable())
+        {
+            for polygon in &region.polygons {
+                let points: Vec<_> = polygon
+                    .iter()
+                    .map(|p| parry2d::math::Point::new(p.x, p.y))
+                    .collect();
+                let Some(shape) = shape::ConvexPolygon::from_convex_hull(points.as_slice()) else {
+                    warn!("keep-out region '{}' has a degenerate polygon", region.name);
+                    continue;
+                };
+                let shape: Arc<dyn shape::Shape> = Arc::new(shape);
+                colliders.push(None, Isometry2::identity(), shape);
+            }
+        }
+
+        Self(colliders)
+    }
+}
+
+/// **Bevy** system that builds [`KeepOutColliders`] from the regions of the
+/// [`Environment`]
+fn insert_keep_out_colliders_resource(mut commands: Commands, env_config: Res<Environment>) {
+    commands.insert_resource(KeepOutColliders::from_environment(&env_config));
+}
+
+/// **Bevy** [`Resource`] with the colliders of every floor above the ground
+/// floor. The colliders of the ground floor are in [`Colliders`].
+#[derive(Resource, Default, Clone)]
+pub struct FloorColliders(Vec<Colliders>);
+
+impl FloorColliders {
+    /// The colliders of floor number `floor`, where floor `0` uses the
+    /// `ground` colliders
+    pub fn get<'a>(&'a self, floor: usize, ground: &'a Colliders) -> Option<&'a Colliders> {
+        match floor {
+            0 => Some(ground),
+            _ => self.0.get(floor - 1),
+        }
+    }
+}
+
+/// **Bevy** system that spawns the tile grid and obstacles of every floor
+/// above the ground floor, and builds [`FloorColliders`] from them
+fn build_upper_floors(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    env_config: Res<Environment>,
+    config: Res<Config>,
+    materials: Res<Materials>,
+) {
+    let mut floor_colliders = Vec::with_capacity(env_config.floors.len());
+    for floor in 1..env_config.floor_count() {
+        let mut colliders = spawn_tile_grid(
+            &mut commands,
+            &mut meshes,
+            &env_config,
+            floor,
+            &config,
+            &materials,
+        );
+        colliders.extend_from(&spawn_obstacles(
+            &mut commands,
+            &mut meshes,
+            &env_config,
+            floor,
+            &config,
+            &materials,
+        ));
+        floor_colliders.push(colliders);
+    }
+
+    commands.insert_resource(FloorColliders(floor_colliders));
+}
+
 /// **Bevy** [`Startup`] _system_.
 /// Takes the [`Environment`] configuration and generates all specified
 /// [`Obstacles`].
@@ -133,11 +235,6 @@ fn insert_colliders_resource(In(colliders): In<Colliders>, mut commands: Command
 ///
 /// Placement of all shapes is given as a `(x, y)` percentage local to a
 /// specific tile
-#[allow(
-    clippy::too_many_lines,
-    clippy::cast_precision_loss,
-    clippy::cast_possible_truncation
-)]
 fn build_obstacles(
     In(mut colliders): In<Colliders>,
     mut commands: Commands,
@@ -147,6 +244,36 @@ fn build_obstacles(
     // scene_assets: Res<SceneAssets>,
     materials: Res<Materials>,
 ) -> Colliders {
+    colliders.extend_from(&spawn_obstacles(
+        &mut commands,
+        &mut meshes,
+        &env_config,
+        0,
+        &config,
+        &materials,
+    ));
+    colliders
+}
+
+/// Spawn the obstacles of floor number `floor` of `environment`, and return
+/// their colliders
+#[allow(
+    clippy::too_many_lines,
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation
+)]
+fn spawn_obstacles(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    environment: &Environment,
+    floor: usize,
+    config: &Config,
+    materials: &Materials,
+) -> Colliders {
+    let env_config = &environment.floor(floor).expect("floor exists");
+    let elevation = environment.elevation(floor);
+    let mut colliders = Colliders::default();
+
     let tile_grid = &env_config.tiles.grid;
     let tile_size = env_config.tile_size();
     let obstacle_height = -env_config.obstacle_height();
@@ -484,14 +611,17 @@ fn build_obstacles(
     });
 
     obstacles_to_spawn
-        .flatten() // filter out None
-        .for_each(|(mesh, transform, isometry, shape)| {
+        .enumerate()
+        // filter out None
+        .filter_map(|(index, obstacle)| obstacle.map(|obstacle| (index, obstacle)))
+        .for_each(|(index, (mesh, transform, isometry, shape))| {
             // TODO: remember to get rotation of obstacle, i.e. for triangles
-            let entity = commands.spawn((
+            let mut entity = commands.spawn((
                 PbrBundle {
                     mesh,
                     material: materials.obstacle.clone(),
-                    transform,
+                    transform: transform
+                        .with_translation(transform.translation - elevation * Vec3::Y),
                     visibility: if config.visualisation.draw.generated_map {
                         Visibility::Visible
                     } else {
@@ -502,7 +632,12 @@ fn build_obstacles(
                 ObstacleMarker,
                 bevy_mod_picking::PickableBundle::default(),
                 On::<Pointer<Click>>::send_event::<events::ObstacleClickedOn>(),
-            )).id();
+            ));
+            // Only obstacles on the ground floor follow their schedule
+            if floor == 0 {
+                entity.insert(ObstacleIndex(index));
+            }
+            let entity = entity.id();
 
             colliders.push(
                 Some(entity),
@@ -538,7 +673,6 @@ fn build_obstacles(
 ///     - Such that the map is centered
 /// - Uses the `Environment.width` to determine the width of the paths,
 ///    - Otherwise, the empty space is filled with solid meshes
-#[allow(clippy::too_many_lines, clippy::cast_precision_loss)]
 fn build_tile_grid(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -553,6 +687,30 @@ fn build_tile_grid(
         info!("despawn obstacle entity: {:?}", entity);
     }
 
+    spawn_tile_grid(
+        &mut commands,
+        &mut meshes,
+        &env_config,
+        0,
+        &config,
+        &materials,
+    )
+}
+
+/// Spawn the tile grid of floor number `floor` of `environment`, and return
+/// the colliders of its walls
+#[allow(clippy::too_many_lines, clippy::cast_precision_loss)]
+fn spawn_tile_grid(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    environment: &Environment,
+    floor: usize,
+    config: &Config,
+    materials: &Materials,
+) -> Colliders {
+    let env_config = &environment.floor(floor).expect("floor exists");
+    let elevation = environment.elevation(floor);
+
     let tile_grid = &env_config.tiles.grid;
 
     let obstacle_height = env_config.obstacle_height();
@@ -1265,7 +1423,8 @@ fn build_tile_grid(
                         .spawn((
                             PbrBundle {
                                 mesh: meshes.add(*cuboid),
-                                transform: *transform,
+                                transform: transform
+                                    .with_translation(transform.translation - elevation * Vec3::Y),
                                 material: materials.obstacle.clone(),
                                 visibility: if config.visualisation.draw.generated_map {
                                     Visibility::Visible
@@ -1304,12 +1463,15 @@ fn build_tile_grid(
 ///   field
 fn show_or_hide_generated_map(
     mut evr_draw_settings: EventReader<DrawSettingsEvent>,
-    mut query: Query<&mut Visibility, With<ObstacleMarker>>,
+    mut query: Query<(&mut Visibility, Option<&ObstacleIndex>), With<ObstacleMarker>>,
+    obstacle_states: Res<ObstacleStates>,
 ) {
     for event in evr_draw_settings.read() {
         if matches!(event.setting, DrawSetting::GeneratedMap) {
-            for mut visibility in &mut query {
-                *visibility = if event.draw {
+            for (mut visibility, index) in &mut query {
+                // Obstacles that are not present according to their schedule stay hidden
+                let present = index.is_none_or(|index| obstacle_states.is_active(**index));
+                *visibility = if event.draw && present {
                     Visibility::Visible
                 } else {
                     Visibility::Hidden
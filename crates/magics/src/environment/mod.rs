@@ -1,11 +1,15 @@
 pub mod camera;
 pub mod cursor;
+pub mod dynamic_obstacles;
+pub mod floors;
 pub mod follow_cameras;
 pub mod map;
 pub mod map_generator;
 
 use camera::CameraPlugin;
 use cursor::CursorToGroundPlugin;
+use dynamic_obstacles::DynamicObstaclesPlugin;
+use floors::FloorsPlugin;
 pub use follow_cameras::FollowCameraMe;
 use follow_cameras::FollowCamerasPlugin;
 use map::MapPlugin;
@@ -25,6 +29,8 @@ impl bevy::app::Plugin for EnvironmentPlugin {
             MapPlugin,
             CursorToGroundPlugin,
             GenMapPlugin,
+            DynamicObstaclesPlugin,
+            FloorsPlugin,
         ));
     }
 }
@@ -1,4 +1,13 @@
-use std::{collections::HashMap, io::Write};
+mod backends;
+mod event_log;
+pub mod factorgraphs;
+mod horizon;
+pub mod plot;
+pub mod report;
+mod tables;
+mod telemetry;
+
+use std::collections::{BTreeMap, HashMap};
 
 use bevy::{input::common_conditions::input_just_pressed, prelude::*};
 use gbp_config::formation::PlanningStrategy;
@@ -6,18 +15,44 @@ use itertools::Itertools;
 
 use self::events::TakeSnapshotOfRobot;
 use crate::{
+    environment::dynamic_obstacles::{ObstacleStateChange, ObstacleStateLog},
     factorgraph::prelude::FactorGraph,
     goal_area,
-    planner::{self, robot::Radius},
+    planner::{
+        self,
+        connectivity::{ConnectivityLog, ConnectivitySample},
+        lifelong::{GoalRecord, LifelongLog},
+        robot::{Radius, StableRobotId},
+        spawner::{ArrivalLog, ArrivalRecord},
+        tasks::{TaskLog, TaskRecord},
+    },
     simulation_loader::{LoadSimulation, ReloadSimulation},
 };
 
 #[derive(Default)]
-pub struct ExportPlugin;
+pub struct ExportPlugin {
+    /// Seconds of simulated time to dump the factorgraphs at, set with
+    /// `--dump-factorgraphs-at`
+    dump_factorgraphs_at: Vec<f32>,
+}
+
+impl ExportPlugin {
+    pub const fn new(dump_factorgraphs_at: Vec<f32>) -> Self {
+        Self {
+            dump_factorgraphs_at,
+        }
+    }
+}
 
 impl Plugin for ExportPlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<events::Export>()
+        app.add_plugins((
+            telemetry::TelemetryPlugin,
+            horizon::HorizonPlugin,
+            event_log::EventLogPlugin,
+            factorgraphs::FactorGraphDumpPlugin::new(self.dump_factorgraphs_at.clone()),
+        ))
+            .add_event::<events::Export>()
             .add_event::<events::TakeSnapshotOfRobot>()
             .add_event::<events::OpenLatestExport>()
             .init_resource::<resources::SnapshottedRobots>()
@@ -104,6 +139,7 @@ fn open_latest_export(
 
 #[derive(serde::Serialize)]
 pub struct RobotData {
+    id: StableRobotId,
     radius: f32,
     positions: Vec<[f32; 2]>,
     // velocities: Vec<[f32; 2]>,
@@ -114,6 +150,12 @@ pub struct RobotData {
     mission: MissionData,
     planning_strategy: PlanningStrategy,
     color: String,
+    /// The robot class of the robot, if any
+    class: Option<String>,
+    priority: u8,
+    /// How much longer the mission took than driving straight between its
+    /// waypoints at the target speed of the robot
+    delay: f64,
 }
 
 #[derive(serde::Serialize)]
@@ -160,40 +202,46 @@ struct RouteData {
 //     }
 // }
 
+/// The [`StableRobotId`] of every robot in an export, by its entity
+type RobotIds = HashMap<Entity, StableRobotId>;
+
+/// Robots are `None` if they were despawned without a snapshot being taken
 #[derive(serde::Serialize)]
 struct RobotRobotCollision {
-    robot_a: Entity,
-    robot_b: Entity,
+    robot_a: Option<StableRobotId>,
+    robot_b: Option<StableRobotId>,
     aabbs: Vec<parry2d::bounding_volume::Aabb>,
 }
 
-impl std::convert::From<((Entity, Entity), &[parry2d::bounding_volume::Aabb])>
-    for RobotRobotCollision
-{
-    fn from((k, v): ((Entity, Entity), &[parry2d::bounding_volume::Aabb])) -> Self {
+impl RobotRobotCollision {
+    fn new(
+        ((a, b), aabbs): ((Entity, Entity), &[parry2d::bounding_volume::Aabb]),
+        ids: &RobotIds,
+    ) -> Self {
         Self {
-            robot_a: k.0,
-            robot_b: k.1,
-            aabbs: v.into_iter().copied().collect(),
+            robot_a: ids.get(&a).copied(),
+            robot_b: ids.get(&b).copied(),
+            aabbs: aabbs.to_vec(),
         }
     }
 }
 
 #[derive(serde::Serialize)]
 struct RobotEnvironmentCollision {
-    robot: Entity,
+    robot: Option<StableRobotId>,
     obstacle: Entity,
     aabbs: Vec<parry2d::bounding_volume::Aabb>,
 }
 
-impl std::convert::From<((Entity, Entity), &[parry2d::bounding_volume::Aabb])>
-    for RobotEnvironmentCollision
-{
-    fn from((k, v): ((Entity, Entity), &[parry2d::bounding_volume::Aabb])) -> Self {
+impl RobotEnvironmentCollision {
+    fn new(
+        ((robot, obstacle), aabbs): ((Entity, Entity), &[parry2d::bounding_volume::Aabb]),
+        ids: &RobotIds,
+    ) -> Self {
         Self {
-            robot: k.0,
-            obstacle: k.1,
-            aabbs: v.into_iter().copied().collect(),
+            robot: ids.get(&robot).copied(),
+            obstacle,
+            aabbs: aabbs.to_vec(),
         }
     }
 }
@@ -227,14 +275,18 @@ struct MessageCount {
 #[derive(serde::Serialize)]
 struct GoalAreaData {
     aabb: parry2d::bounding_volume::Aabb,
-    history: std::collections::HashMap<Entity, f32>,
+    history: BTreeMap<StableRobotId, f32>,
 }
 
-impl std::convert::From<&goal_area::components::GoalArea> for GoalAreaData {
-    fn from(ga: &goal_area::components::GoalArea) -> Self {
+impl GoalAreaData {
+    fn new(ga: &goal_area::components::GoalArea, ids: &RobotIds) -> Self {
         Self {
             aabb: ga.aabb,
-            history: ga.history().clone(),
+            history: ga
+                .history()
+                .iter()
+                .filter_map(|(robot, reached_at)| Some((*ids.get(robot)?, *reached_at)))
+                .collect(),
         }
     }
 }
@@ -245,13 +297,129 @@ struct ExportData {
     makespan: f64,
     delta_t: f64,
     gbp: GbpData,
-    robots: HashMap<Entity, RobotData>,
+    robots: BTreeMap<StableRobotId, RobotData>,
     prng_seed: u64,
     config: gbp_config::Config,
     // obstacles: Vec<Obstacle>,
     obstacles: HashMap<Entity, Obstacle>,
     collisions: CollisionData,
     goal_areas: HashMap<Entity, GoalAreaData>,
+    regions: Vec<RegionData>,
+    obstacle_state_changes: Vec<ObstacleStateChange>,
+    tasks: Vec<TaskRecord>,
+    lifelong: LifelongData,
+    priorities: BTreeMap<u8, PriorityData>,
+    arrivals: BTreeMap<usize, ArrivalData>,
+    /// The connectivity of the communication graph over time
+    connectivity: Vec<ConnectivitySample>,
+    /// The telemetry file streamed during the run, if one was opened, in which
+    /// case `positions` and `velocities` of every robot are empty
+    telemetry: Option<std::path::PathBuf>,
+}
+
+/// Delays of the robots of a priority
+#[derive(serde::Serialize)]
+struct PriorityData {
+    robots: usize,
+    mean_delay: f64,
+    max_delay: f64,
+}
+
+impl PriorityData {
+    /// Group the robots in `robots` by their priority
+    #[allow(clippy::cast_precision_loss)]
+    fn of<'a>(robots: impl IntoIterator<Item = &'a RobotData>) -> BTreeMap<u8, Self> {
+        robots
+            .into_iter()
+            .into_group_map_by(|robot| robot.priority)
+            .into_iter()
+            .map(|(priority, robots)| {
+                let delays = robots.iter().map(|robot| robot.delay);
+                let data = Self {
+                    robots: robots.len(),
+                    mean_delay: delays.clone().sum::<f64>() / robots.len() as f64,
+                    max_delay: delays.fold(0.0, f64::max),
+                };
+                (priority, data)
+            })
+            .collect()
+    }
+}
+
+/// Arrivals of a formation spawning at random arrivals, and how long they
+/// waited for the entry of the formation to be free
+#[derive(serde::Serialize)]
+struct ArrivalData {
+    spawned: usize,
+    waiting: usize,
+    max_queue_length: usize,
+    mean_wait: f64,
+    max_wait: f64,
+    arrivals: Vec<ArrivalRecord>,
+}
+
+impl ArrivalData {
+    /// Group the arrivals in `arrival_log` by their formation
+    #[allow(clippy::cast_precision_loss)]
+    fn of(arrival_log: &ArrivalLog) -> BTreeMap<usize, Self> {
+        let arrivals = arrival_log
+            .arrivals()
+            .iter()
+            .into_group_map_by(|arrival| arrival.formation);
+        arrival_log
+            .queues()
+            .iter()
+            .map(|(&formation, queue)| {
+                let arrivals: Vec<ArrivalRecord> = arrivals
+                    .get(&formation)
+                    .map(|arrivals| arrivals.iter().map(|&&arrival| arrival).collect())
+                    .unwrap_or_default();
+                let waits = arrivals.iter().map(ArrivalRecord::waited);
+                let data = Self {
+                    spawned: arrivals.len(),
+                    waiting: queue.current,
+                    max_queue_length: queue.max,
+                    mean_wait: if arrivals.is_empty() {
+                        0.0
+                    } else {
+                        waits.clone().sum::<f64>() / arrivals.len() as f64
+                    },
+                    max_wait: waits.fold(0.0, f64::max),
+                    arrivals,
+                };
+                (formation, data)
+            })
+            .collect()
+    }
+}
+
+/// Goals reached by robots in lifelong operation
+#[derive(serde::Serialize)]
+struct LifelongData {
+    goals_per_minute: f32,
+    mean_latency: Option<f32>,
+    goals: Vec<GoalRecord>,
+}
+
+#[derive(serde::Serialize)]
+struct RegionData {
+    name: String,
+    kind: gbp_environment::RegionKind,
+    polygons: Vec<Vec<[f32; 2]>>,
+}
+
+impl std::convert::From<gbp_environment::WorldRegion> for RegionData {
+    fn from(region: gbp_environment::WorldRegion) -> Self {
+        Self {
+            name: region.name,
+            kind: region.kind,
+            polygons: region
+                .polygons
+                .into_iter()
+                .map(|polygon| polygon.into_iter().map(Into::into).collect())
+                .collect(),
+        }
+    }
 }
 
 #[derive(serde::Serialize)]
@@ -272,6 +440,139 @@ struct GbpData {
     iterations: GbpIterationData,
 }
 
+/// An empty directory for the files written by the test `name`, unique to
+/// the test process
+#[cfg(test)]
+pub(crate) fn empty_test_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("magics_{name}_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("temp dir is writable");
+    dir
+}
+
+#[cfg(test)]
+impl ExportData {
+    /// A small export of two robots, where the first collides with the second
+    /// and reaches one of two goal areas
+    fn example() -> Self {
+        use parry2d::{bounding_volume::Aabb, math::Point};
+
+        let robot = |index, velocities, routes| RobotData {
+            id: StableRobotId {
+                formation: 0,
+                wave: 0,
+                index,
+            },
+            radius: 1.0,
+            positions: vec![],
+            velocities,
+            collisions: CollisionCountData {
+                robots: 1,
+                environment: 0,
+            },
+            messages: MessageData {
+                sent: MessageCount {
+                    internal: 10,
+                    external: 2,
+                },
+                received: MessageCount {
+                    internal: 10,
+                    external: 3,
+                },
+            },
+            mission: MissionData {
+                waypoints: vec![],
+                started_at: 0.0,
+                finished_at: 12.5,
+                routes,
+            },
+            planning_strategy: PlanningStrategy::OnlyLocal,
+            color: "#ff8000".to_string(),
+            class: (index == 1).then(|| "heavy".to_string()),
+            priority: 1,
+            delay: 0.5,
+        };
+        let velocities = [(0.0, [0.0, 0.0]), (0.5, [1.0, 2.0])]
+            .map(
+                |(timestamp, [x, y])| planner::tracking::VelocityMeasurement {
+                    velocity: Vec3::new(2.0, 0.0, 4.0),
+                    position: Vec3::new(x, 0.0, y),
+                    timestamp,
+                    measured_over: std::time::Duration::from_millis(500),
+                },
+            )
+            .to_vec();
+        let route = RouteData {
+            waypoints: vec![[0.0, 0.0], [10.0, 5.0]],
+            started_at: 0.0,
+            finished_at: 12.5,
+        };
+        let robots: BTreeMap<_, _> = [robot(0, velocities, vec![route]), robot(1, vec![], vec![])]
+            .into_iter()
+            .map(|robot| (robot.id, robot))
+            .collect();
+        let ids: Vec<_> = robots.keys().copied().collect();
+
+        let aabb = Aabb::new(Point::new(0.0, 0.0), Point::new(1.0, 1.0));
+        let graph = planner::connectivity::GraphConnectivity {
+            edges: 1,
+            components: 1,
+            average_degree: 1.0,
+            algebraic_connectivity: 2.0,
+        };
+        let goal_area = |history| GoalAreaData { aabb, history };
+
+        Self {
+            scenario: "test".to_string(),
+            makespan: 12.5,
+            delta_t: 0.1,
+            gbp: GbpData {
+                iterations: GbpIterationData {
+                    internal: 10,
+                    external: 10,
+                },
+            },
+            robots,
+            prng_seed: 0,
+            config: gbp_config::Config::default(),
+            obstacles: HashMap::new(),
+            collisions: CollisionData {
+                robots: vec![RobotRobotCollision {
+                    robot_a: Some(ids[0]),
+                    robot_b: Some(ids[1]),
+                    aabbs: vec![aabb],
+                }],
+                environment: vec![],
+            },
+            goal_areas: HashMap::from([
+                (
+                    Entity::from_raw(0),
+                    goal_area(BTreeMap::from([(ids[0], 8.0)])),
+                ),
+                (Entity::from_raw(1), goal_area(BTreeMap::new())),
+            ]),
+            regions: vec![],
+            obstacle_state_changes: vec![],
+            tasks: vec![],
+            lifelong: LifelongData {
+                goals_per_minute: 0.0,
+                mean_latency: None,
+                goals: vec![],
+            },
+            priorities: BTreeMap::new(),
+            arrivals: BTreeMap::new(),
+            connectivity: vec![ConnectivitySample {
+                t: 0.5,
+                robots: 2,
+                connected: graph,
+                in_range: graph,
+                antennas_off: 0,
+            }],
+            telemetry: None,
+        }
+    }
+}
+
 fn export(
     mut evr_export: EventReader<events::Export>,
     mut evw_toast: EventWriter<bevy_notify::ToastEvent>,
@@ -287,6 +588,10 @@ fn export(
         &planner::robot::Mission,
         &PlanningStrategy,
         &crate::theme::ColorAssociation,
+        Option<&planner::robot::OfClass>,
+        &planner::robot::Priority,
+        Option<&planner::robot::TargetSpeed>,
+        &StableRobotId,
         // &ColorAssociation,
         // &ColorAssociation,
     )>,
@@ -299,6 +604,16 @@ fn export(
     time_fixed: Res<Time<Fixed>>,
     catppuccin: Res<crate::theme::CatppuccinTheme>,
     obstacles: Res<gbp_global_planner::Colliders>,
+    env_config: Res<gbp_environment::Environment>,
+    // grouped, as a system can take at most 16 parameters
+    (obstacle_state_log, task_log, lifelong_log, arrival_log, connectivity_log, telemetry_stream): (
+        Res<ObstacleStateLog>,
+        Res<TaskLog>,
+        Res<LifelongLog>,
+        Res<ArrivalLog>,
+        Res<ConnectivityLog>,
+        Res<telemetry::TelemetryStream>,
+    ),
 ) {
     // schema:
     //
@@ -359,23 +674,24 @@ fn export(
             mission,
             planning_strategy,
             color_assoc,
+            of_class,
+            priority,
+            target_speed,
+            &id,
         ) in q_robots.iter()
         {
             if robot_snapshots.contains_key(&robot_entity) {
                 continue;
             }
-            let positions: Vec<[f32; 2]> = positions.positions().map(Into::into).collect();
-            // let velocities: Vec<[f32; 2]> =
-            // velocities.velocities().map(Into::into).collect();
-            let velocities: Vec<_> = velocities.measurements().collect();
+            let (positions, velocities) = samples(positions, velocities, &telemetry_stream);
             let robot_collisions = robot_collisions.get(robot_entity).unwrap_or(0);
             let environment_collisions = environment_collisions.get(robot_entity).unwrap_or(0);
 
             let catppuccin::Colour(r, g, b) = catppuccin.get_display_colour(&color_assoc.name);
-            let color: String = format!("#{:2x}{:2x}{:2x}", r, g, b);
+            let color: String = format!("#{:02x}{:02x}{:02x}", r, g, b);
 
-            // let id = format!("{:?}", robot_entity);
             let robot_data = RobotData {
+                id,
                 radius: radius.0,
                 positions,
                 velocities,
@@ -434,6 +750,12 @@ fn export(
                 },
                 planning_strategy: *planning_strategy,
                 color,
+                class: of_class.map(|of_class| of_class.0.clone()),
+                priority: priority.0,
+                delay: mission.delay(
+                    planner::robot::TargetSpeed::of(target_speed, &config),
+                    time_fixed.elapsed_seconds_f64(),
+                ),
             };
 
             robot_snapshots.insert(robot_entity, robot_data);
@@ -541,68 +863,97 @@ fn export(
             })
             .collect();
 
+        let ids: RobotIds = robot_snapshots
+            .iter()
+            .map(|(entity, robot)| (*entity, robot.id))
+            .collect();
+
         let collisions = CollisionData {
-            robots: robot_collisions.collisions().map_into().collect(),
-            environment: environment_collisions.collisions().map_into().collect(),
+            robots: robot_collisions
+                .collisions()
+                .map(|collision| RobotRobotCollision::new(collision, &ids))
+                .collect(),
+            environment: environment_collisions
+                .collisions()
+                .map(|collision| RobotEnvironmentCollision::new(collision, &ids))
+                .collect(),
         };
 
         let goal_areas = q_goal_areas
             .iter()
-            .map(|(entity, area)| (entity, area.into()))
+            .map(|(entity, area)| (entity, GoalAreaData::new(area, &ids)))
             .collect();
 
+        let regions = env_config
+            .world_regions()
+            .into_iter()
+            .map(Into::into)
+            .collect();
+
+        let priorities = PriorityData::of(robot_snapshots.values());
+
         let export_data = ExportData {
             scenario: environment.to_string(),
             makespan,
             delta_t: time_fixed.delta_seconds_f64(),
             gbp,
-            robots: robot_snapshots.drain().collect(),
+            robots: robot_snapshots
+                .drain()
+                .map(|(_, robot)| (robot.id, robot))
+                .collect(),
             prng_seed: config.simulation.prng_seed,
             config: config.clone(),
             obstacles,
             collisions,
             goal_areas,
+            regions,
+            obstacle_state_changes: obstacle_state_log.to_vec(),
+            tasks: task_log.records().to_vec(),
+            lifelong: LifelongData {
+                goals_per_minute: lifelong_log.goals_per_minute(&time_virtual),
+                mean_latency: lifelong_log.mean_latency(),
+                goals: lifelong_log.goals().to_vec(),
+            },
+            priorities,
+            arrivals: ArrivalData::of(&arrival_log),
+            connectivity: connectivity_log.samples().to_vec(),
+            telemetry: telemetry_stream.path().map(std::path::Path::to_path_buf),
         };
 
-        let json = serde_json::to_string_pretty(&export_data).unwrap();
-
         let prefix = format!("export_{}_", environment.to_lowercase());
         let basename_postfix = match event.postfix {
-            ExportSavePostfix::Number => {
-                let glob_pattern = format!("{}*.json", prefix.as_str());
-                let existing_files = glob::glob(glob_pattern.as_str()).expect("valid glob pattern");
-                let latest_id = existing_files
-                    .filter_map(std::result::Result::ok)
-                    .filter_map(|path| {
-                        path.file_name().and_then(|file_name| {
-                            file_name.to_str().map(std::string::ToString::to_string)
-                        })
-                    })
-                    .filter_map(|basename| {
-                        basename[prefix.len()..basename.len() - 5]
-                            .parse::<usize>()
-                            .ok()
-                    })
-                    .max();
-
-                let id = latest_id.map_or(0, |id| id + 1);
-                id.to_string()
-            } // ExportSavePostfix::UnixTimestamp => chrono::Utc::now().timestamp().to_string(),
+            ExportSavePostfix::Number => next_export_number(&prefix).to_string(),
+            // ExportSavePostfix::UnixTimestamp => chrono::Utc::now().timestamp().to_string(),
         };
+        let basename = format!("{prefix}{basename_postfix}");
 
         let dirname = match event.save_at_location {
             ExportSaveLocation::Cwd => std::env::current_dir().expect("current directory exists"),
             ExportSaveLocation::At(ref path) => path.clone(),
         };
 
-        let output_filepath = dirname.join(format!("{}{}.json", prefix, basename_postfix));
+        let mut written = Vec::new();
+        for &format in &config.export.formats {
+            match backends::backend_for(format).write(&export_data, &dirname, &basename) {
+                Ok(paths) => written.extend(paths),
+                Err(err) => {
+                    let err_msg = format!("Failed to export data as {format:?}: {err}");
+                    error!(err_msg);
+                    evw_toast.send(bevy_notify::ToastEvent::error(err_msg));
+                }
+            }
+        }
 
-        let mut file = std::fs::File::create(output_filepath.clone()).unwrap();
-        file.write_all(json.as_bytes()).unwrap();
+        let Some(output_filepath) = written.first().cloned() else {
+            continue;
+        };
 
         let message = format!(
             "Data exported successfully to '{}'",
-            output_filepath.to_string_lossy().to_string()
+            written
+                .iter()
+                .map(|path| path.to_string_lossy())
+                .join("', '")
         );
         info!(message);
 
@@ -614,6 +965,28 @@ fn export(
     }
 }
 
+/// The number after the latest export with a basename starting with `prefix`
+/// in the current directory, regardless of the formats it was exported in
+pub(crate) fn next_export_number(prefix: &str) -> usize {
+    let glob_pattern = format!("{prefix}*");
+    let existing_files = glob::glob(glob_pattern.as_str()).expect("valid glob pattern");
+    existing_files
+        .filter_map(std::result::Result::ok)
+        .filter_map(|path| {
+            path.file_name()
+                .and_then(|file_name| file_name.to_str().map(std::string::ToString::to_string))
+        })
+        .filter_map(|basename| {
+            let number: String = basename[prefix.len()..]
+                .chars()
+                .take_while(char::is_ascii_digit)
+                .collect();
+            number.parse::<usize>().ok()
+        })
+        .max()
+        .map_or(0, |id| id + 1)
+}
+
 #[derive(Debug, Clone, Default, Copy, PartialEq, Eq)]
 pub enum ExportSavePostfix {
     #[default]
@@ -621,6 +994,22 @@ pub enum ExportSavePostfix {
     // UnixTimestamp,
 }
 
+/// The tracked positions and velocities of a robot, or none if they are
+/// streamed to the telemetry file instead
+fn samples(
+    positions: &planner::tracking::PositionTracker,
+    velocities: &planner::tracking::VelocityTracker,
+    telemetry_stream: &telemetry::TelemetryStream,
+) -> (Vec<[f32; 2]>, Vec<planner::tracking::VelocityMeasurement>) {
+    if telemetry_stream.is_open() {
+        return (Vec::new(), Vec::new());
+    }
+    (
+        positions.positions().map(Into::into).collect(),
+        velocities.measurements().collect(),
+    )
+}
+
 fn take_snapshot_of_robot(
     robot_entity: Entity,
     // q_robots: &Query<(
@@ -639,6 +1028,10 @@ fn take_snapshot_of_robot(
         &planner::robot::Mission,
         &PlanningStrategy,
         &crate::theme::ColorAssociation,
+        Option<&planner::robot::OfClass>,
+        &planner::robot::Priority,
+        Option<&planner::robot::TargetSpeed>,
+        &StableRobotId,
     )>,
 
     robot_collisions: &crate::planner::collisions::resources::RobotRobotCollisions,
@@ -646,9 +1039,22 @@ fn take_snapshot_of_robot(
     // time_virtual: &Time<Virtual>,
     time_fixed: &Time<Fixed>,
     catppuccin: &crate::theme::CatppuccinTheme,
+    config: &gbp_config::Config,
+    telemetry_stream: &telemetry::TelemetryStream,
 ) -> anyhow::Result<RobotData> {
-    let Ok((fgraph, positions, velocities, radius, mission, planning_strategy, color_assoc)) =
-        q_robots.get(robot_entity)
+    let Ok((
+        fgraph,
+        positions,
+        velocities,
+        radius,
+        mission,
+        planning_strategy,
+        color_assoc,
+        of_class,
+        priority,
+        target_speed,
+        &id,
+    )) = q_robots.get(robot_entity)
     else {
         anyhow::bail!(
             "cannot take snapshot of non-existing robot {:?}",
@@ -656,17 +1062,15 @@ fn take_snapshot_of_robot(
         );
     };
 
-    let positions: Vec<[f32; 2]> = positions.positions().map(Into::into).collect();
-    // let velocities: Vec<[f32; 2]> =
-    // velocities.velocities().map(Into::into).collect();
-    let velocities: Vec<_> = velocities.measurements().collect();
+    let (positions, velocities) = samples(positions, velocities, telemetry_stream);
     let robot_collisions = robot_collisions.get(robot_entity).unwrap_or(0);
     let environment_collisions = environment_collisions.get(robot_entity).unwrap_or(0);
 
     let catppuccin::Colour(r, g, b) = catppuccin.get_display_colour(&color_assoc.name);
-    let color: String = format!("#{:2x}{:2x}{:2x}", r, g, b);
+    let color: String = format!("#{:02x}{:02x}{:02x}", r, g, b);
 
     let robot_data = RobotData {
+        id,
         radius: radius.0,
         positions,
         velocities,
@@ -697,6 +1101,12 @@ fn take_snapshot_of_robot(
         },
         planning_strategy: *planning_strategy,
         color,
+        class: of_class.map(|of_class| of_class.0.clone()),
+        priority: priority.0,
+        delay: mission.delay(
+            planner::robot::TargetSpeed::of(target_speed, config),
+            time_fixed.elapsed_seconds_f64(),
+        ),
         mission: MissionData {
             started_at: mission.started_at(),
             finished_at: mission
@@ -749,6 +1159,10 @@ fn await_robot_snapshot_request(
         &planner::robot::Mission,
         &PlanningStrategy,
         &crate::theme::ColorAssociation,
+        Option<&planner::robot::OfClass>,
+        &planner::robot::Priority,
+        Option<&planner::robot::TargetSpeed>,
+        &StableRobotId,
     )>,
 
     robot_collisions: Res<crate::planner::collisions::resources::RobotRobotCollisions>,
@@ -756,6 +1170,8 @@ fn await_robot_snapshot_request(
     // time_virtual: Res<Time<Virtual>>,
     catppuccin: Res<crate::theme::CatppuccinTheme>,
     time_fixed: Res<Time<Fixed>>,
+    config: Res<gbp_config::Config>,
+    telemetry_stream: Res<telemetry::TelemetryStream>,
 ) {
     for TakeSnapshotOfRobot(robot_id) in evr_submit_robot_data.read() {
         // ignore if the robot has already been submitted
@@ -769,6 +1185,8 @@ fn await_robot_snapshot_request(
             &environment_collisions,
             &time_fixed,
             &catppuccin,
+            &config,
+            &telemetry_stream,
         ) else {
             error!(
                 "failed to take snapshot of robot {:?}, reason entity does not exist",
@@ -0,0 +1,186 @@
+//! Backends writing the data of an export to files, one for every
+//! [`ExportFormat`].
+
+use std::{
+    io::Write,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+use gbp_config::ExportFormat;
+use serde::{Serialize, de::DeserializeOwned};
+
+use super::{
+    ExportData,
+    tables::{TableWriter, Tables},
+};
+
+/// Writes the data of an export to one or more files
+pub(super) trait ExportBackend {
+    /// Write `data` to files in `dir`, with names starting with `basename`,
+    /// and return the paths of the written files
+    fn write(&self, data: &ExportData, dir: &Path, basename: &str) -> anyhow::Result<Vec<PathBuf>>;
+}
+
+/// The backend writing exports in `format`
+pub(super) fn backend_for(format: ExportFormat) -> Box<dyn ExportBackend> {
+    match format {
+        ExportFormat::Json => Box::new(JsonBackend),
+        ExportFormat::Csv => Box::new(TablesBackend::<CsvWriter>(PhantomData)),
+        ExportFormat::Parquet => Box::new(TablesBackend::<ParquetWriter>(PhantomData)),
+    }
+}
+
+/// Writes the whole export as a single pretty-printed JSON document
+struct JsonBackend;
+
+impl ExportBackend for JsonBackend {
+    fn write(&self, data: &ExportData, dir: &Path, basename: &str) -> anyhow::Result<Vec<PathBuf>> {
+        let json = serde_json::to_string_pretty(data)?;
+        let path = dir.join(format!("{basename}.json"));
+        let mut file = std::fs::File::create(&path)?;
+        file.write_all(json.as_bytes())?;
+        Ok(vec![path])
+    }
+}
+
+/// Splits the export into [`Tables`], and writes each of them with `W`.
+/// The basename of the export is used as the run id of every row.
+struct TablesBackend<W>(PhantomData<W>);
+
+/// A [`TableWriter`] writing to files in a directory
+trait FileTableWriter: TableWriter {
+    fn new(dir: &Path, basename: &str) -> Self;
+}
+
+impl<W: FileTableWriter> ExportBackend for TablesBackend<W> {
+    fn write(&self, data: &ExportData, dir: &Path, basename: &str) -> anyhow::Result<Vec<PathBuf>> {
+        Tables::new(basename, data).write_with(&W::new(dir, basename))
+    }
+}
+
+/// Writes every table to `<basename>_<table>.csv`
+struct CsvWriter {
+    dir: PathBuf,
+    basename: String,
+}
+
+impl FileTableWriter for CsvWriter {
+    fn new(dir: &Path, basename: &str) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            basename: basename.to_string(),
+        }
+    }
+}
+
+impl TableWriter for CsvWriter {
+    fn write_table<T: Serialize + DeserializeOwned>(
+        &self,
+        name: &str,
+        rows: &[T],
+    ) -> anyhow::Result<PathBuf> {
+        let path = self.dir.join(format!("{}_{name}.csv", self.basename));
+        let mut writer = csv::Writer::from_path(&path)?;
+        for row in rows {
+            writer.serialize(row)?;
+        }
+        writer.flush()?;
+        Ok(path)
+    }
+}
+
+/// Writes every table to `<basename>_<table>.parquet`
+struct ParquetWriter {
+    dir: PathBuf,
+    basename: String,
+}
+
+impl FileTableWriter for ParquetWriter {
+    fn new(dir: &Path, basename: &str) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            basename: basename.to_string(),
+        }
+    }
+}
+
+impl TableWriter for ParquetWriter {
+    fn write_table<T: Serialize + DeserializeOwned>(
+        &self,
+        name: &str,
+        rows: &[T],
+    ) -> anyhow::Result<PathBuf> {
+        use serde_arrow::schema::{SchemaLike, TracingOptions};
+
+        // The schema is traced from the type rather than the rows, so empty
+        // tables still get their columns
+        let fields = Vec::<arrow::datatypes::FieldRef>::from_type::<T>(
+            TracingOptions::default().allow_null_fields(true),
+        )?;
+        let batch = serde_arrow::to_record_batch(&fields, &rows)?;
+
+        let path = self.dir.join(format!("{}_{name}.parquet", self.basename));
+        let file = std::fs::File::create(&path)?;
+        let mut writer = parquet::arrow::ArrowWriter::try_new(file, batch.schema(), None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{
+        super::{empty_test_dir, tables::RobotRow},
+        *,
+    };
+
+    fn robots() -> Vec<RobotRow> {
+        Tables::new("run-1", &ExportData::example()).robots
+    }
+
+    #[test]
+    fn csv_table_round_trips() {
+        let dir = empty_test_dir("csv_table");
+        let rows = robots();
+        let path = CsvWriter::new(&dir, "run-1")
+            .write_table("robots", &rows)
+            .expect("table is written");
+        assert_eq!(path, dir.join("run-1_robots.csv"));
+
+        let read: Vec<RobotRow> = csv::Reader::from_path(&path)
+            .expect("table exists")
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .expect("rows are valid");
+        assert_eq!(read, rows);
+    }
+
+    #[test]
+    fn parquet_table_round_trips() {
+        let dir = empty_test_dir("parquet_table");
+        let rows = robots();
+        let path = ParquetWriter::new(&dir, "run-1")
+            .write_table("robots", &rows)
+            .expect("table is written");
+        assert_eq!(path, dir.join("run-1_robots.parquet"));
+
+        let file = std::fs::File::open(&path).expect("table exists");
+        let batches = parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(file)
+            .expect("valid parquet")
+            .build()
+            .expect("valid parquet")
+            .collect::<Result<Vec<_>, _>>()
+            .expect("rows are valid");
+        let read: Vec<RobotRow> = batches
+            .iter()
+            .flat_map(|batch| {
+                serde_arrow::from_record_batch::<Vec<RobotRow>>(batch).expect("rows are valid")
+            })
+            .collect();
+        assert_eq!(read, rows);
+    }
+}
@@ -0,0 +1,396 @@
+//! Log of the events of the simulation while it runs.
+//!
+//! Every event of a kind in
+//! [`EventLogSection::include`](gbp_config::EventLogSection::include) is
+//! appended to `events_<scenario>_<n>.ndjson` as a JSON object on its own
+//! line, with the seconds of simulated time since the simulation was loaded,
+//! the stable id of the robot it concerns, if any, and its payload, e.g.
+//!
+//! ```json
+//! {"t":12.5,"robot_id":"f0-w0-r3","event":"robot-reached-waypoint","waypoint_index":1}
+//! ```
+//!
+//! The file is flushed after every update, so the timeline of a run can be
+//! reconstructed from it, even if the application crashes.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use bevy::prelude::*;
+use gbp_config::{Config, LoggedEvent};
+use serde::Serialize;
+
+use crate::{
+    goal_area::{components::GoalArea, events::GoalAreaReached},
+    planner::{
+        collisions::events::RobotRobotCollision,
+        robot::{
+            RobotDespawned, RobotFinishedRoute, RobotReachedWaypoint, RobotSpawned, StableRobotId,
+        },
+        spawner::{AllFormationsFinished, WaypointCreated},
+    },
+    simulation_loader::{LoadSimulation, ReloadSimulation, SimulationManager},
+};
+
+pub struct EventLogPlugin;
+
+impl Plugin for EventLogPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EventLog>()
+            .add_systems(
+                Update,
+                open_event_log
+                    .run_if(on_event::<LoadSimulation>().or_else(on_event::<ReloadSimulation>())),
+            )
+            .add_systems(
+                PostUpdate,
+                log_events.run_if(|log: Res<EventLog>| log.is_open()),
+            );
+    }
+}
+
+/// An event of the simulation, and its payload
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+enum Payload {
+    RobotSpawned,
+    RobotDespawned,
+    RobotReachedWaypoint {
+        waypoint_index: usize,
+    },
+    RobotFinishedRoute,
+    RobotRobotCollision {
+        /// The robot the logged robot collided with
+        other_robot_id: Option<StableRobotId>,
+        /// The intersection of the two robots
+        mins: [f32; 2],
+        maxs: [f32; 2],
+    },
+    GoalAreaReached {
+        /// The goal area that was reached
+        mins: [f32; 2],
+        maxs: [f32; 2],
+    },
+    WaypointCreated {
+        x: f32,
+        y: f32,
+    },
+    AllFormationsFinished,
+}
+
+impl Payload {
+    const fn kind(&self) -> LoggedEvent {
+        match self {
+            Self::RobotSpawned => LoggedEvent::RobotSpawned,
+            Self::RobotDespawned => LoggedEvent::RobotDespawned,
+            Self::RobotReachedWaypoint { .. } => LoggedEvent::RobotReachedWaypoint,
+            Self::RobotFinishedRoute => LoggedEvent::RobotFinishedRoute,
+            Self::RobotRobotCollision { .. } => LoggedEvent::RobotRobotCollision,
+            Self::GoalAreaReached { .. } => LoggedEvent::GoalAreaReached,
+            Self::WaypointCreated { .. } => LoggedEvent::WaypointCreated,
+            Self::AllFormationsFinished => LoggedEvent::AllFormationsFinished,
+        }
+    }
+}
+
+/// A line of the event log
+#[derive(Debug, Serialize)]
+struct EventRow {
+    t: f64,
+    /// The robot the event concerns. `None` for events that concern no
+    /// robot, or robots that were spawned before the log was opened
+    #[serde(skip_serializing_if = "Option::is_none")]
+    robot_id: Option<StableRobotId>,
+    #[serde(flatten)]
+    payload: Payload,
+}
+
+/// **Bevy** [`Resource`] with the event log of the running simulation, if
+/// the event log is enabled
+#[derive(Resource, Default)]
+struct EventLog {
+    file: Option<(PathBuf, BufWriter<File>)>,
+    /// Elapsed virtual time when the simulation was loaded
+    loaded_at: f64,
+    /// The stable id of every robot spawned since the log was opened. Robots
+    /// are kept after they are despawned, so the events read after their
+    /// entity is gone can still name them
+    robot_ids: HashMap<Entity, StableRobotId>,
+}
+
+impl EventLog {
+    const fn is_open(&self) -> bool {
+        self.file.is_some()
+    }
+}
+
+/// **Bevy** system that starts a new event log whenever a simulation is
+/// loaded or reloaded, if the event log is enabled
+fn open_event_log(
+    mut log: ResMut<EventLog>,
+    mut evw_toast: EventWriter<bevy_notify::ToastEvent>,
+    sim_manager: Res<SimulationManager>,
+    config: Res<Config>,
+    time: Res<Time<Virtual>>,
+) {
+    *log = EventLog::default();
+    if !config.export.events.enabled {
+        return;
+    }
+
+    let environment = sim_manager.active_name().unwrap_or_default();
+    let prefix = format!("events_{}_", environment.to_lowercase());
+    let dirname = std::env::current_dir().expect("current directory exists");
+    let path = dirname.join(format!(
+        "{prefix}{}.ndjson",
+        super::next_export_number(&prefix)
+    ));
+
+    match File::create(&path) {
+        Ok(file) => {
+            info!("logging events to '{}'", path.display());
+            log.file = Some((path, BufWriter::new(file)));
+            log.loaded_at = time.elapsed_seconds_f64();
+        }
+        Err(err) => {
+            let err_msg = format!("Failed to create {}: {}", path.display(), err);
+            error!(err_msg);
+            evw_toast.send(bevy_notify::ToastEvent::error(err_msg));
+        }
+    }
+}
+
+/// **Bevy** system that appends the events sent during this update to the
+/// event log
+#[allow(clippy::too_many_arguments)]
+fn log_events(
+    mut log: ResMut<EventLog>,
+    mut evw_toast: EventWriter<bevy_notify::ToastEvent>,
+    q_new_robots: Query<(Entity, &StableRobotId), Added<StableRobotId>>,
+    q_goal_areas: Query<&GoalArea>,
+    mut evr_robot_spawned: EventReader<RobotSpawned>,
+    mut evr_waypoint_created: EventReader<WaypointCreated>,
+    mut evr_robot_reached_waypoint: EventReader<RobotReachedWaypoint>,
+    mut evr_robot_robot_collision: EventReader<RobotRobotCollision>,
+    mut evr_goal_area_reached: EventReader<GoalAreaReached>,
+    mut evr_robot_finished_route: EventReader<RobotFinishedRoute>,
+    mut evr_robot_despawned: EventReader<RobotDespawned>,
+    mut evr_all_formations_finished: EventReader<AllFormationsFinished>,
+    config: Res<Config>,
+    time: Res<Time<Virtual>>,
+) {
+    let EventLog {
+        file: Some((path, writer)),
+        loaded_at,
+        robot_ids,
+    } = &mut *log
+    else {
+        return;
+    };
+    robot_ids.extend(q_new_robots.iter().map(|(entity, &id)| (entity, id)));
+
+    // Events sent in the same update are logged in the order they happen in
+    // the lifetime of a robot
+    let mut events: Vec<(Option<Entity>, Payload)> = Vec::new();
+    events.extend(
+        evr_robot_spawned
+            .read()
+            .map(|&RobotSpawned(robot)| (Some(robot), Payload::RobotSpawned)),
+    );
+    events.extend(evr_waypoint_created.read().map(|waypoint| {
+        (
+            Some(waypoint.for_robot),
+            Payload::WaypointCreated {
+                x: waypoint.position.x,
+                y: waypoint.position.y,
+            },
+        )
+    }));
+    events.extend(evr_robot_reached_waypoint.read().map(|reached| {
+        (
+            Some(reached.robot_id),
+            Payload::RobotReachedWaypoint {
+                waypoint_index: reached.waypoint_index,
+            },
+        )
+    }));
+    for collision in evr_robot_robot_collision.read() {
+        let (mins, maxs) = (collision.intersection.mins, collision.intersection.maxs);
+        for (robot, other) in [
+            (collision.robot_a, collision.robot_b),
+            (collision.robot_b, collision.robot_a),
+        ] {
+            events.push((
+                Some(robot),
+                Payload::RobotRobotCollision {
+                    other_robot_id: robot_ids.get(&other).copied(),
+                    mins: [mins.x, mins.y],
+                    maxs: [maxs.x, maxs.y],
+                },
+            ));
+        }
+    }
+    events.extend(evr_goal_area_reached.read().filter_map(|reached| {
+        let aabb = q_goal_areas.get(reached.area).ok()?.aabb;
+        Some((
+            Some(reached.reached_by),
+            Payload::GoalAreaReached {
+                mins: [aabb.mins.x, aabb.mins.y],
+                maxs: [aabb.maxs.x, aabb.maxs.y],
+            },
+        ))
+    }));
+    events.extend(
+        evr_robot_finished_route
+            .read()
+            .map(|&RobotFinishedRoute(robot)| (Some(robot), Payload::RobotFinishedRoute)),
+    );
+    events.extend(
+        evr_robot_despawned
+            .read()
+            .map(|&RobotDespawned(robot)| (Some(robot), Payload::RobotDespawned)),
+    );
+    events.extend(
+        evr_all_formations_finished
+            .read()
+            .map(|_| (None, Payload::AllFormationsFinished)),
+    );
+
+    let include = &config.export.events.include;
+    let t = time.elapsed_seconds_f64() - *loaded_at;
+    let written = events
+        .into_iter()
+        .filter(|(_, payload)| include.contains(&payload.kind()))
+        .try_for_each(|(robot, payload)| {
+            let row = EventRow {
+                t,
+                robot_id: robot.and_then(|robot| robot_ids.get(&robot).copied()),
+                payload,
+            };
+            serde_json::to_writer(&mut *writer, &row)?;
+            writer.write_all(b"\n")?;
+            anyhow::Ok(())
+        })
+        .and_then(|()| Ok(writer.flush()?));
+    if let Err(err) = written {
+        let err_msg = format!(
+            "Failed to write events to {}, stopping the event log: {}",
+            path.display(),
+            err
+        );
+        error!(err_msg);
+        evw_toast.send(bevy_notify::ToastEvent::error(err_msg));
+        log.file = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn rows_are_flat_json_objects() {
+        let robot_id = StableRobotId {
+            formation: 0,
+            wave: 1,
+            index: 3,
+        };
+        let row = EventRow {
+            t: 12.5,
+            robot_id: Some(robot_id),
+            payload: Payload::RobotReachedWaypoint { waypoint_index: 1 },
+        };
+        assert_eq!(
+            serde_json::to_string(&row).unwrap(),
+            r#"{"t":12.5,"robot_id":"f0-w1-r3","event":"robot-reached-waypoint","waypoint_index":1}"#
+        );
+
+        let row = EventRow {
+            t: 60.0,
+            robot_id: None,
+            payload: Payload::AllFormationsFinished,
+        };
+        assert_eq!(
+            serde_json::to_string(&row).unwrap(),
+            r#"{"t":60.0,"event":"all-formations-finished"}"#
+        );
+    }
+
+    #[test]
+    fn payloads_match_their_config_name() {
+        let payload = Payload::WaypointCreated { x: 1.0, y: 2.0 };
+        let name = serde_json::to_value(&payload).unwrap()["event"].clone();
+        assert_eq!(name, serde_json::to_value(payload.kind()).unwrap());
+    }
+
+    #[test]
+    fn events_of_an_update_are_logged_in_lifetime_order() {
+        let path = super::super::empty_test_dir("event_log").join("events.ndjson");
+        let mut config = Config::default();
+        config
+            .export
+            .events
+            .include
+            .retain(|&event| event != LoggedEvent::WaypointCreated);
+
+        let mut app = App::new();
+        app.add_event::<bevy_notify::ToastEvent>()
+            .add_event::<RobotSpawned>()
+            .add_event::<WaypointCreated>()
+            .add_event::<RobotReachedWaypoint>()
+            .add_event::<RobotRobotCollision>()
+            .add_event::<GoalAreaReached>()
+            .add_event::<RobotFinishedRoute>()
+            .add_event::<RobotDespawned>()
+            .add_event::<AllFormationsFinished>()
+            .insert_resource(config)
+            .init_resource::<Time<Virtual>>()
+            .insert_resource(EventLog {
+                file: Some((
+                    path.clone(),
+                    BufWriter::new(File::create(&path).expect("log is created")),
+                )),
+                ..default()
+            })
+            .add_systems(Update, log_events);
+
+        let robot = app
+            .world
+            .spawn(StableRobotId {
+                formation: 0,
+                wave: 0,
+                index: 2,
+            })
+            .id();
+        // Sent in the reverse order of the lifetime of the robot
+        app.world.send_event(AllFormationsFinished);
+        app.world.send_event(RobotDespawned(robot));
+        app.world.send_event(RobotReachedWaypoint {
+            robot_id: robot,
+            waypoint_index: 1,
+        });
+        app.world.send_event(WaypointCreated {
+            for_robot: robot,
+            position: Vec2::ZERO,
+        });
+        app.world.send_event(RobotSpawned(robot));
+        app.update();
+
+        let contents = std::fs::read_to_string(&path).expect("log exists");
+        assert_eq!(
+            contents.lines().collect::<Vec<_>>(),
+            vec![
+                r#"{"t":0.0,"robot_id":"f0-w0-r2","event":"robot-spawned"}"#,
+                r#"{"t":0.0,"robot_id":"f0-w0-r2","event":"robot-reached-waypoint","waypoint_index":1}"#,
+                r#"{"t":0.0,"robot_id":"f0-w0-r2","event":"robot-despawned"}"#,
+                r#"{"t":0.0,"event":"all-formations-finished"}"#,
+            ]
+        );
+    }
+}
@@ -0,0 +1,749 @@
+//! Export of the factorgraphs of every robot in the formats of
+//! [`FactorGraphFormat`], with the `Export Graph` action, or at given times of
+//! simulated time with `--dump-factorgraphs-at`.
+//!
+//! Every node is named `<robot>_<index>`, where `<robot>` is the
+//! [`StableRobotId`] of the robot and `<index>` the index of the node in its
+//! factorgraph, in every format.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use gbp_config::{Config, FactorGraphFormat, GraphvizSection};
+use serde::Serialize;
+
+use crate::{
+    factorgraph::{
+        graphviz::{Edge, ExportGraph, LastMessage, Node, NodeKind},
+        prelude::FactorGraph,
+    },
+    planner::{
+        RobotConnections,
+        robot::{RadioAntenna, StableRobotId},
+    },
+    simulation_loader::{LoadSimulation, ReloadSimulation, SimulationManager},
+};
+
+pub struct FactorGraphDumpPlugin {
+    /// Seconds of simulated time after a simulation is loaded to dump the
+    /// factorgraphs at, set with `--dump-factorgraphs-at`
+    at: Vec<f32>,
+}
+
+impl FactorGraphDumpPlugin {
+    pub const fn new(at: Vec<f32>) -> Self {
+        Self { at }
+    }
+}
+
+impl Plugin for FactorGraphDumpPlugin {
+    fn build(&self, app: &mut App) {
+        if self.at.is_empty() {
+            return;
+        }
+
+        let mut at = self.at.clone();
+        at.sort_by(f32::total_cmp);
+        app.insert_resource(FactorGraphDumps {
+            at,
+            pending: Vec::new(),
+            loaded_at: 0.0,
+        })
+        .add_systems(
+            Update,
+            (
+                schedule_factorgraph_dumps
+                    .run_if(on_event::<LoadSimulation>().or_else(on_event::<ReloadSimulation>())),
+                dump_factorgraphs.run_if(|dumps: Res<FactorGraphDumps>| !dumps.pending.is_empty()),
+            )
+                .chain(),
+        );
+    }
+}
+
+/// The components of a robot its factorgraph is collected from
+type RobotItem<'a> = (Entity, &'a FactorGraph, &'a RadioAntenna, &'a StableRobotId);
+
+/// A node in another robots factorgraph
+type ExternalNode = (StableRobotId, usize);
+
+/// The factorgraph of a robot
+struct RobotGraph {
+    robot: StableRobotId,
+    antenna_active: bool,
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+}
+
+/// An edge between an interrobot or relative pose factor and a variable in
+/// the factorgraph of another robot
+struct ExternalEdge {
+    from: ExternalNode,
+    to: ExternalNode,
+    /// Whether the antenna of the robot with the factor is active
+    active: bool,
+    /// The last message sent from the factor to the variable, if any
+    message_from: Option<LastMessage>,
+    /// The last message sent from the variable to the factor, if any
+    message_to: Option<LastMessage>,
+}
+
+/// The factorgraphs of every robot at a point in time
+pub(crate) struct FactorGraphs {
+    /// Seconds of simulated time the factorgraphs were collected at
+    t: f64,
+    robots: Vec<RobotGraph>,
+    external_edges: Vec<ExternalEdge>,
+}
+
+impl FactorGraphs {
+    /// Collect the factorgraphs of `robots`, ordered by their stable ids
+    pub(crate) fn collect<'a>(robots: impl IntoIterator<Item = RobotItem<'a>>, t: f64) -> Self {
+        let mut robots: Vec<_> = robots.into_iter().collect();
+        robots.sort_by_key(|(_, _, _, robot_id)| **robot_id);
+
+        // Robots are named by their stable id rather than their entity, so exports of
+        // different runs of the same scenario can be compared
+        let stable_ids: HashMap<Entity, StableRobotId> = robots
+            .iter()
+            .map(|(entity, _, _, robot_id)| (*entity, **robot_id))
+            .collect();
+        let factorgraphs: HashMap<Entity, &FactorGraph> = robots
+            .iter()
+            .map(|(entity, factorgraph, ..)| (*entity, *factorgraph))
+            .collect();
+
+        let mut external_edges = Vec::new();
+        let robots = robots
+            .into_iter()
+            .map(|(_, factorgraph, antenna, &robot)| {
+                let (nodes, edges) = factorgraph.export_graph();
+                external_edges.extend(nodes.iter().filter_map(|node| match node.kind {
+                    NodeKind::InterRobotFactor {
+                        external_variable_id,
+                        ..
+                    }
+                    | NodeKind::RelativePoseFactor {
+                        external_variable_id,
+                    } => {
+                        let other = external_variable_id.factorgraph_id;
+                        let variable = external_variable_id.variable_index.index();
+                        // The message to the variable is in the inbox of the variable in the
+                        // factorgraph of the other robot
+                        Some(ExternalEdge {
+                            from: (robot, node.index),
+                            to: (*stable_ids.get(&other)?, variable),
+                            active: antenna.active,
+                            message_from: factorgraphs.get(&other).and_then(|graph| {
+                                graph.last_message(factorgraph.id(), node.index, variable)
+                            }),
+                            message_to: factorgraph.last_message(other, variable, node.index),
+                        })
+                    }
+                    _ => None,
+                }));
+
+                RobotGraph {
+                    robot,
+                    antenna_active: antenna.active,
+                    nodes,
+                    edges,
+                }
+            })
+            .collect();
+
+        Self {
+            t,
+            robots,
+            external_edges,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.robots.is_empty()
+    }
+
+    /// The factorgraphs in `format`
+    pub(crate) fn to_format(&self, format: FactorGraphFormat, config: &GraphvizSection) -> String {
+        match format {
+            FactorGraphFormat::Dot => self.to_dot(config),
+            FactorGraphFormat::Graphml => self.to_graphml(),
+            FactorGraphFormat::Json => self.to_json(),
+            FactorGraphFormat::Adjacency => self.to_adjacency(),
+        }
+    }
+
+    /// Write the factorgraphs in every format of `config` to `path` with the
+    /// extension of the format appended, and return the paths written to
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if one of the files cannot be written
+    pub(crate) fn write(
+        &self,
+        path: &Path,
+        config: &GraphvizSection,
+    ) -> std::io::Result<Vec<PathBuf>> {
+        config
+            .formats
+            .iter()
+            .map(|&format| {
+                // Appended rather than set with `Path::with_extension`, which would replace
+                // everything after the last dot, e.g. of a fractional dump time
+                let mut file = path.as_os_str().to_owned();
+                file.push(".");
+                file.push(format.extension());
+                let path = PathBuf::from(file);
+                std::fs::write(&path, self.to_format(format, config))?;
+                Ok(path)
+            })
+            .collect()
+    }
+
+    fn to_dot(&self, config: &GraphvizSection) -> String {
+        let cluster_margin = 16;
+
+        let mut buf = String::with_capacity(4 * 1024); // 4 kB
+        let _ = writeln!(buf, "graph {{");
+        let _ = writeln!(buf, "  dpi=96;");
+        let _ = writeln!(buf, r#"  label="factorgraph""#);
+        let _ = writeln!(buf, "  node [style=filled];");
+        let _ = writeln!(buf, "  layout=neato;");
+
+        for graph in &self.robots {
+            let robot = graph.robot;
+            let _ = writeln!(buf, r#"  subgraph "{robot}" {{"#);
+            let _ = writeln!(buf, "  margin={cluster_margin}");
+            let _ = writeln!(buf, r#"  label="{robot}""#);
+            for node in &graph.nodes {
+                let label = match node.kind {
+                    NodeKind::Variable { .. } => format!("v{}", node.index),
+                    NodeKind::InterRobotFactor { .. } => "fr".to_string(),
+                    NodeKind::DynamicFactor => "fd".to_string(),
+                    NodeKind::ObstacleFactor => "fo".to_string(),
+                    NodeKind::TrackingFactor => "ft".to_string(),
+                    NodeKind::RegionFactor => "fa".to_string(),
+                    NodeKind::RelativePoseFactor { .. } => "fk".to_string(),
+                };
+                let _ = writeln!(
+                    buf,
+                    r#""{robot}_{}" [label="{label}", fillcolor="{}", shape={}, width="{}"]"#,
+                    node.index,
+                    node.color(),
+                    node.shape(),
+                    node.width()
+                );
+            }
+            let _ = writeln!(buf, "}}");
+
+            let _ = writeln!(buf);
+            for edge in &graph.edges {
+                let _ = writeln!(buf, r#""{robot}_{}" -- "{robot}_{}""#, edge.from, edge.to);
+            }
+        }
+
+        // Add edges between interrobot factors and the variable they are connected to
+        // in another robots graph
+        for edge in &self.external_edges {
+            let attributes = if edge.active {
+                &config.interrobot.active
+            } else {
+                &config.interrobot.inactive
+            };
+            let _ = writeln!(
+                buf,
+                r#" "{}_{}" -- "{}_{}" [len={}, style={}, color="{}", penwidth=3.0]"#,
+                edge.from.0,
+                edge.from.1,
+                edge.to.0,
+                edge.to.1,
+                attributes.len,
+                attributes.style,
+                attributes.color
+            );
+        }
+
+        let _ = writeln!(buf, "}}"); // closing '}' for starting "graph {"
+        buf
+    }
+
+    fn to_graphml(&self) -> String {
+        let mut buf = String::with_capacity(4 * 1024);
+        let _ = writeln!(buf, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        let _ = writeln!(
+            buf,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        );
+        for (id, domain, ty) in [
+            ("kind", "node", "string"),
+            ("robot", "node", "string"),
+            ("horizon-index", "node", "int"),
+            ("x", "node", "double"),
+            ("y", "node", "double"),
+            ("mean", "node", "string"),
+            ("covariance", "node", "string"),
+            ("external", "edge", "boolean"),
+            ("active", "edge", "boolean"),
+            ("message-from-mean", "edge", "string"),
+            ("message-from-precision", "edge", "string"),
+            ("message-to-mean", "edge", "string"),
+            ("message-to-precision", "edge", "string"),
+        ] {
+            let _ = writeln!(
+                buf,
+                r#"  <key id="{id}" for="{domain}" attr.name="{id}" attr.type="{ty}"/>"#
+            );
+        }
+        let _ = writeln!(
+            buf,
+            r#"  <graph id="factorgraphs" edgedefault="undirected">"#
+        );
+
+        let data = |buf: &mut String, key: &str, value: &dyn std::fmt::Display| {
+            let _ = writeln!(buf, r#"      <data key="{key}">{value}</data>"#);
+        };
+        let joined = |values: &[f64]| {
+            values
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let messages = |buf: &mut String,
+                        message_from: Option<&LastMessage>,
+                        message_to: Option<&LastMessage>| {
+            for (key, message) in [("from", message_from), ("to", message_to)] {
+                if let Some(message) = message {
+                    data(buf, &format!("message-{key}-mean"), &joined(&message.mean));
+                    data(
+                        buf,
+                        &format!("message-{key}-precision"),
+                        &joined(&message.precision),
+                    );
+                }
+            }
+        };
+
+        for graph in &self.robots {
+            let robot = graph.robot;
+            for node in &graph.nodes {
+                let _ = writeln!(buf, r#"    <node id="{robot}_{}">"#, node.index);
+                data(&mut buf, "kind", &kind_name(&node.kind));
+                data(&mut buf, "robot", &robot);
+                if let NodeKind::Variable {
+                    x,
+                    y,
+                    horizon_index,
+                    ref mean,
+                    ref covariance,
+                } = node.kind
+                {
+                    data(&mut buf, "horizon-index", &horizon_index);
+                    data(&mut buf, "x", &x);
+                    data(&mut buf, "y", &y);
+                    data(&mut buf, "mean", &joined(mean));
+                    data(&mut buf, "covariance", &joined(covariance));
+                }
+                let _ = writeln!(buf, "    </node>");
+            }
+
+            for edge in &graph.edges {
+                let _ = writeln!(
+                    buf,
+                    r#"    <edge source="{robot}_{}" target="{robot}_{}">"#,
+                    edge.from, edge.to
+                );
+                data(&mut buf, "external", &false);
+                messages(
+                    &mut buf,
+                    edge.message_from.as_ref(),
+                    edge.message_to.as_ref(),
+                );
+                let _ = writeln!(buf, "    </edge>");
+            }
+        }
+
+        for edge in &self.external_edges {
+            let _ = writeln!(
+                buf,
+                r#"    <edge source="{}_{}" target="{}_{}">"#,
+                edge.from.0, edge.from.1, edge.to.0, edge.to.1
+            );
+            data(&mut buf, "external", &true);
+            data(&mut buf, "active", &edge.active);
+            messages(
+                &mut buf,
+                edge.message_from.as_ref(),
+                edge.message_to.as_ref(),
+            );
+            let _ = writeln!(buf, "    </edge>");
+        }
+
+        let _ = writeln!(buf, "  </graph>");
+        let _ = writeln!(buf, "</graphml>");
+        buf
+    }
+
+    fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct JsonGraphs<'a> {
+            t: f64,
+            robots: Vec<JsonRobot>,
+            nodes: Vec<JsonNode<'a>>,
+            edges: Vec<JsonEdge<'a>>,
+        }
+
+        #[derive(Serialize)]
+        struct JsonRobot {
+            id: StableRobotId,
+            antenna_active: bool,
+        }
+
+        #[derive(Serialize)]
+        struct JsonNode<'a> {
+            id: String,
+            robot: StableRobotId,
+            index: usize,
+            kind: &'static str,
+            /// Only set for variables
+            horizon_index: Option<usize>,
+            mean: Option<&'a [f64]>,
+            covariance: Option<&'a [f64]>,
+        }
+
+        #[derive(Serialize)]
+        struct JsonMessage<'a> {
+            mean: &'a [f64],
+            precision: &'a [f64],
+        }
+
+        #[derive(Serialize)]
+        struct JsonEdge<'a> {
+            from: String,
+            to: String,
+            external: bool,
+            /// Only set for external edges
+            active: Option<bool>,
+            message_from: Option<JsonMessage<'a>>,
+            message_to: Option<JsonMessage<'a>>,
+        }
+
+        fn message(message: Option<&LastMessage>) -> Option<JsonMessage<'_>> {
+            message.map(|message| JsonMessage {
+                mean: &message.mean,
+                precision: &message.precision,
+            })
+        }
+
+        let mut json = JsonGraphs {
+            t: self.t,
+            robots: Vec::with_capacity(self.robots.len()),
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        };
+        for graph in &self.robots {
+            let robot = graph.robot;
+            json.robots.push(JsonRobot {
+                id: robot,
+                antenna_active: graph.antenna_active,
+            });
+            json.nodes.extend(graph.nodes.iter().map(|node| {
+                let (horizon_index, mean, covariance) = match node.kind {
+                    NodeKind::Variable {
+                        horizon_index,
+                        ref mean,
+                        ref covariance,
+                        ..
+                    } => (
+                        Some(horizon_index),
+                        Some(mean.as_slice()),
+                        Some(covariance.as_slice()),
+                    ),
+                    _ => (None, None, None),
+                };
+                JsonNode {
+                    id: format!("{robot}_{}", node.index),
+                    robot,
+                    index: node.index,
+                    kind: kind_name(&node.kind),
+                    horizon_index,
+                    mean,
+                    covariance,
+                }
+            }));
+            json.edges.extend(graph.edges.iter().map(|edge| JsonEdge {
+                from: format!("{robot}_{}", edge.from),
+                to: format!("{robot}_{}", edge.to),
+                external: false,
+                active: None,
+                message_from: message(edge.message_from.as_ref()),
+                message_to: message(edge.message_to.as_ref()),
+            }));
+        }
+        json.edges
+            .extend(self.external_edges.iter().map(|edge| JsonEdge {
+                from: format!("{}_{}", edge.from.0, edge.from.1),
+                to: format!("{}_{}", edge.to.0, edge.to.1),
+                external: true,
+                active: Some(edge.active),
+                message_from: message(edge.message_from.as_ref()),
+                message_to: message(edge.message_to.as_ref()),
+            }));
+
+        serde_json::to_string_pretty(&json).expect("the factorgraphs serialize to JSON")
+    }
+
+    /// Every node of every factorgraph, with the nodes it is connected to.
+    /// Nodes in the same factorgraph are named by their index alone
+    fn to_adjacency(&self) -> String {
+        let mut external: BTreeMap<ExternalNode, Vec<ExternalNode>> = BTreeMap::new();
+        for edge in &self.external_edges {
+            external.entry(edge.from).or_default().push(edge.to);
+            external.entry(edge.to).or_default().push(edge.from);
+        }
+
+        let mut buf = String::with_capacity(4 * 1024);
+        let _ = writeln!(buf, "# factorgraphs at t = {:.3} s", self.t);
+        for graph in &self.robots {
+            let robot = graph.robot;
+            let _ = writeln!(
+                buf,
+                "{robot} (antenna {})",
+                if graph.antenna_active {
+                    "active"
+                } else {
+                    "inactive"
+                }
+            );
+
+            let mut neighbours: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+            for edge in &graph.edges {
+                neighbours
+                    .entry(edge.from)
+                    .or_default()
+                    .push(edge.to.to_string());
+                neighbours
+                    .entry(edge.to)
+                    .or_default()
+                    .push(edge.from.to_string());
+            }
+            for node in &graph.nodes {
+                let mut connected = neighbours.remove(&node.index).unwrap_or_default();
+                connected.extend(
+                    external
+                        .get(&(robot, node.index))
+                        .into_iter()
+                        .flatten()
+                        .map(|(other, index)| format!("{other}_{index}")),
+                );
+                let label = match node.kind {
+                    NodeKind::Variable { horizon_index, .. } => format!("variable {horizon_index}"),
+                    ref kind => format!("{} factor", kind_name(kind)),
+                };
+                let _ = writeln!(buf, "  {} {label}: {}", node.index, connected.join(", "));
+            }
+        }
+        buf
+    }
+}
+
+/// The name of the kind of a node in the JSON and GraphML exports
+const fn kind_name(kind: &NodeKind) -> &'static str {
+    match kind {
+        NodeKind::Variable { .. } => "variable",
+        NodeKind::InterRobotFactor { .. } => "interrobot",
+        NodeKind::DynamicFactor => "dynamic",
+        NodeKind::ObstacleFactor => "obstacle",
+        NodeKind::TrackingFactor => "tracking",
+        NodeKind::RegionFactor => "region",
+        NodeKind::RelativePoseFactor { .. } => "relative-pose",
+    }
+}
+
+/// **Bevy** [`Resource`] with the times the factorgraphs are dumped at
+#[derive(Resource)]
+struct FactorGraphDumps {
+    /// Seconds of simulated time after loading, in ascending order
+    at: Vec<f32>,
+    /// The times not yet dumped at in the running simulation, in descending
+    /// order
+    pending: Vec<f32>,
+    /// Elapsed virtual time when the simulation was loaded
+    loaded_at: f32,
+}
+
+/// **Bevy** system that schedules the dumps again whenever a simulation is
+/// loaded or reloaded
+fn schedule_factorgraph_dumps(mut dumps: ResMut<FactorGraphDumps>, time: Res<Time<Virtual>>) {
+    dumps.pending = dumps.at.iter().rev().copied().collect();
+    dumps.loaded_at = time.elapsed_seconds();
+}
+
+/// **Bevy** system that writes the factorgraphs of every robot in every
+/// format of [`GraphvizSection::formats`] when a dump is due
+fn dump_factorgraphs(
+    mut dumps: ResMut<FactorGraphDumps>,
+    mut evw_toast: EventWriter<bevy_notify::ToastEvent>,
+    q_robots: Query<(Entity, &FactorGraph, &RadioAntenna, &StableRobotId), With<RobotConnections>>,
+    sim_manager: Res<SimulationManager>,
+    config: Res<Config>,
+    time: Res<Time<Virtual>>,
+) {
+    let elapsed = time.elapsed_seconds() - dumps.loaded_at;
+    let Some(&at) = dumps.pending.last() else {
+        return;
+    };
+    if elapsed < at {
+        return;
+    }
+    // Dumps that are overdue, because the simulation was paused or
+    // stepped, are written once
+    dumps.pending.retain(|&t| t > elapsed);
+
+    let graphs = FactorGraphs::collect(&q_robots, f64::from(elapsed));
+    if graphs.is_empty() {
+        warn!("there are no factorgraphs to dump at {at} s");
+        return;
+    }
+
+    let environment = sim_manager.active_name().unwrap_or_default();
+    let path = std::env::current_dir()
+        .expect("current directory exists")
+        .join(format!("factorgraphs_{}_{at}s", environment.to_lowercase()));
+    match graphs.write(&path, &config.graphviz) {
+        Ok(paths) => {
+            for path in paths {
+                info!("dumped factorgraphs to '{}'", path.display());
+            }
+        }
+        Err(err) => {
+            let err_msg = format!("Failed to dump factorgraphs at {at} s: {err}");
+            error!(err_msg);
+            evw_toast.send(bevy_notify::ToastEvent::error(err_msg));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn robot(index: usize) -> StableRobotId {
+        StableRobotId {
+            formation: 0,
+            wave: 0,
+            index,
+        }
+    }
+
+    fn variable(index: usize, horizon_index: usize) -> Node {
+        Node {
+            index,
+            kind: NodeKind::Variable {
+                x: 0.0,
+                y: 0.0,
+                horizon_index,
+                mean: vec![0.0; 4],
+                covariance: vec![0.0; 16],
+            },
+        }
+    }
+
+    fn factor(index: usize) -> Node {
+        Node {
+            index,
+            kind: NodeKind::DynamicFactor,
+        }
+    }
+
+    fn edge(from: usize, to: usize) -> Edge {
+        Edge {
+            from,
+            to,
+            message_from: None,
+            message_to: None,
+        }
+    }
+
+    fn graphs() -> FactorGraphs {
+        FactorGraphs {
+            t: 1.5,
+            robots: vec![
+                RobotGraph {
+                    robot: robot(0),
+                    antenna_active: true,
+                    nodes: vec![variable(0, 0), variable(1, 1), factor(2)],
+                    edges: vec![edge(0, 2), edge(1, 2)],
+                },
+                RobotGraph {
+                    robot: robot(1),
+                    antenna_active: false,
+                    nodes: vec![variable(0, 0)],
+                    edges: vec![],
+                },
+            ],
+            external_edges: vec![ExternalEdge {
+                from: (robot(0), 2),
+                to: (robot(1), 0),
+                active: true,
+                message_from: Some(LastMessage {
+                    mean: vec![1.0, 2.0, 0.0, 0.0],
+                    precision: vec![0.0; 16],
+                }),
+                message_to: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn adjacency_lists_internal_and_external_neighbours() {
+        let adjacency = graphs().to_adjacency();
+        assert_eq!(
+            adjacency,
+            "# factorgraphs at t = 1.500 s\nf0-w0-r0 (antenna active)\n  0 variable 0: 2\n  1 \
+             variable 1: 2\n  2 dynamic factor: 0, 1, f0-w0-r1_0\nf0-w0-r1 (antenna inactive)\n  \
+             0 variable 0: f0-w0-r0_2\n"
+        );
+    }
+
+    #[test]
+    fn json_has_every_node_and_edge() {
+        let json: serde_json::Value = serde_json::from_str(&graphs().to_json()).unwrap();
+        assert_eq!(json["nodes"].as_array().unwrap().len(), 4);
+        assert_eq!(json["edges"].as_array().unwrap().len(), 3);
+        assert_eq!(json["nodes"][0]["id"], "f0-w0-r0_0");
+        assert_eq!(json["nodes"][2]["kind"], "dynamic");
+        assert_eq!(json["edges"][2]["external"], true);
+        assert_eq!(
+            json["edges"][2]["message_from"]["mean"],
+            serde_json::json!([1.0, 2.0, 0.0, 0.0])
+        );
+        assert!(json["edges"][2]["message_to"].is_null());
+    }
+
+    #[test]
+    fn fractional_dump_times_are_kept_in_the_file_names() {
+        let dir = super::super::empty_test_dir("factorgraphs_dump");
+        let config = GraphvizSection {
+            formats: vec![FactorGraphFormat::Json, FactorGraphFormat::Adjacency],
+            ..Default::default()
+        };
+        let paths = graphs()
+            .write(&dir.join("factorgraphs_junction_1.5s"), &config)
+            .expect("files are written");
+        assert_eq!(
+            paths,
+            vec![
+                dir.join("factorgraphs_junction_1.5s.json"),
+                dir.join("factorgraphs_junction_1.5s.txt"),
+            ]
+        );
+        assert!(paths.iter().all(|path| path.is_file()));
+    }
+}
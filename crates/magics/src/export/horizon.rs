@@ -0,0 +1,431 @@
+//! Recording of what the robots planned while the simulation runs.
+//!
+//! Every [`HorizonSection::every`](gbp_config::HorizonSection::every) fixed
+//! timesteps, the belief of every variable along the horizon of every robot,
+//! and the last measurement of its obstacle and tracking factors, are
+//! appended as a row group to two Parquet files,
+//! `horizon_<scenario>_<n>_variables_<part>.parquet` and
+//! `horizon_<scenario>_<n>_factors_<part>.parquet`.
+//!
+//! A Parquet file cannot be read before its footer is written, so the
+//! recording is split into parts of
+//! [`HorizonSection::samples_per_part`](gbp_config::HorizonSection::samples_per_part)
+//! samples each. A part is completed when it is full, when the simulation is
+//! loaded or reloaded, or when the application exits, so a crash only loses
+//! the samples of the last part.
+
+use std::{
+    fs::File,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use arrow::datatypes::{FieldRef, Schema};
+use bevy::{app::AppExit, prelude::*};
+use gbp_config::Config;
+use parquet::arrow::ArrowWriter;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_arrow::schema::{SchemaLike, TracingOptions};
+
+use crate::{
+    factorgraph::prelude::FactorGraph,
+    planner::robot::StableRobotId,
+    simulation_loader::{LoadSimulation, ReloadSimulation, SimulationManager},
+};
+
+pub struct HorizonPlugin;
+
+impl Plugin for HorizonPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HorizonRecording>()
+            .add_systems(
+                Update,
+                start_horizon_recording
+                    .run_if(on_event::<LoadSimulation>().or_else(on_event::<ReloadSimulation>())),
+            )
+            .add_systems(
+                FixedUpdate,
+                record_horizons.run_if(|recording: Res<HorizonRecording>| recording.is_open()),
+            )
+            .add_systems(Last, finish_horizon_recording.run_if(on_event::<AppExit>()));
+    }
+}
+
+/// The belief of a variable along the horizon of a robot
+#[derive(Serialize, Deserialize)]
+struct VariableRow {
+    run_id: String,
+    robot_id: String,
+    t: f64,
+    /// Index of the variable along the horizon, the current state is 0
+    variable: u32,
+    x: f32,
+    y: f32,
+    vx: f32,
+    vy: f32,
+    /// The 4x4 covariance of the belief in row-major order
+    covariance: Vec<f32>,
+    /// Whether the covariance is finite
+    valid: bool,
+}
+
+/// The last measurement of an obstacle or tracking factor
+#[derive(Serialize, Deserialize)]
+struct FactorRow {
+    run_id: String,
+    robot_id: String,
+    t: f64,
+    /// Index of the variable along the horizon the factor is connected to
+    variable: u32,
+    /// Either `obstacle` or `tracking`
+    kind: String,
+    /// Where the factor was last measured
+    x: f32,
+    y: f32,
+    value: f32,
+}
+
+/// A Parquet file rows of `T` are appended to, one row group at a time
+struct ParquetStream<T> {
+    fields: Vec<FieldRef>,
+    writer: ArrowWriter<File>,
+    _row: PhantomData<T>,
+}
+
+impl<T: Serialize + DeserializeOwned> ParquetStream<T> {
+    fn create(path: &Path) -> anyhow::Result<Self> {
+        let fields = Vec::<FieldRef>::from_type::<T>(TracingOptions::default())?;
+        let schema = Arc::new(Schema::new(fields.clone()));
+        let writer = ArrowWriter::try_new(File::create(path)?, schema, None)?;
+        Ok(Self {
+            fields,
+            writer,
+            _row: PhantomData,
+        })
+    }
+
+    /// Append `rows` as a row group
+    fn append(&mut self, rows: &[T]) -> anyhow::Result<()> {
+        let batch = serde_arrow::to_record_batch(&self.fields, &rows)?;
+        self.writer.write(&batch)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the footer of the file, without which it cannot be read
+    fn finish(self) -> anyhow::Result<()> {
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+/// The files a part of a horizon recording is written to
+struct HorizonFiles {
+    variables: ParquetStream<VariableRow>,
+    factors: ParquetStream<FactorRow>,
+    /// Samples appended to the files
+    samples: usize,
+}
+
+impl HorizonFiles {
+    fn create(dir: &Path, run_id: &str, part: usize) -> anyhow::Result<Self> {
+        let (variables_path, factors_path) = files_of(dir, run_id, part);
+        Ok(Self {
+            variables: ParquetStream::create(&variables_path)?,
+            factors: ParquetStream::create(&factors_path)?,
+            samples: 0,
+        })
+    }
+
+    /// Append a sample of the horizons of every robot
+    fn append(&mut self, variables: &[VariableRow], factors: &[FactorRow]) -> anyhow::Result<()> {
+        self.variables.append(variables)?;
+        self.factors.append(factors)?;
+        self.samples += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        self.variables.finish()?;
+        self.factors.finish()
+    }
+}
+
+/// **Bevy** [`Resource`] with the files the horizons of the running
+/// simulation are recorded to, if recording is enabled
+#[derive(Resource, Default)]
+struct HorizonRecording {
+    /// The files of the current part
+    files: Option<HorizonFiles>,
+    dir: PathBuf,
+    run_id: String,
+    part: usize,
+    /// Fixed timesteps since the last sample
+    steps: usize,
+}
+
+impl HorizonRecording {
+    const fn is_open(&self) -> bool {
+        self.files.is_some()
+    }
+
+    /// Complete the files of the current part of the recording, if any
+    fn finish(&mut self) {
+        let Some(files) = self.files.take() else {
+            return;
+        };
+        if let Err(err) = files.finish() {
+            error!("failed to complete the horizon recording: {err}");
+        }
+    }
+
+    /// Complete the current part of the recording, and start the next one
+    fn next_part(&mut self) -> anyhow::Result<()> {
+        if let Some(files) = self.files.take() {
+            files.finish()?;
+        }
+        self.part += 1;
+        self.files = Some(HorizonFiles::create(&self.dir, &self.run_id, self.part)?);
+        Ok(())
+    }
+}
+
+fn files_of(dir: &Path, run_id: &str, part: usize) -> (PathBuf, PathBuf) {
+    (
+        dir.join(format!("{run_id}_variables_{part}.parquet")),
+        dir.join(format!("{run_id}_factors_{part}.parquet")),
+    )
+}
+
+/// **Bevy** system that completes the previous recording, and starts a new
+/// one if enabled, whenever a simulation is loaded or reloaded
+fn start_horizon_recording(
+    mut recording: ResMut<HorizonRecording>,
+    mut evw_toast: EventWriter<bevy_notify::ToastEvent>,
+    sim_manager: Res<SimulationManager>,
+    config: Res<Config>,
+) {
+    recording.finish();
+    *recording = HorizonRecording::default();
+    if !config.export.horizon.enabled {
+        return;
+    }
+
+    let environment = sim_manager.active_name().unwrap_or_default();
+    let prefix = format!("horizon_{}_", environment.to_lowercase());
+    let run_id = format!("{prefix}{}", super::next_export_number(&prefix));
+    let dirname = std::env::current_dir().expect("current directory exists");
+
+    match HorizonFiles::create(&dirname, &run_id, 0) {
+        Ok(files) => {
+            info!(
+                "recording horizons to '{}'",
+                dirname.join(format!("{run_id}_*.parquet")).display()
+            );
+            recording.files = Some(files);
+            recording.dir = dirname;
+            recording.run_id = run_id;
+        }
+        Err(err) => {
+            let err_msg = format!("Failed to start recording horizons: {err}");
+            error!(err_msg);
+            evw_toast.send(bevy_notify::ToastEvent::error(err_msg));
+        }
+    }
+}
+
+/// **Bevy** system that appends the horizon of every robot to the recording
+/// every [`HorizonSection::every`](gbp_config::HorizonSection::every) fixed
+/// timesteps
+#[allow(clippy::cast_possible_truncation)]
+fn record_horizons(
+    mut recording: ResMut<HorizonRecording>,
+    mut evw_toast: EventWriter<bevy_notify::ToastEvent>,
+    q_robots: Query<(&StableRobotId, &FactorGraph)>,
+    config: Res<Config>,
+    time_fixed: Res<Time<Fixed>>,
+) {
+    recording.steps += 1;
+    if recording.steps < config.export.horizon.every.get() {
+        return;
+    }
+    recording.steps = 0;
+
+    let HorizonRecording {
+        files: Some(files),
+        run_id,
+        ..
+    } = &mut *recording
+    else {
+        return;
+    };
+
+    let t = time_fixed.elapsed_seconds_f64();
+    let mut variables = Vec::new();
+    let mut factors = Vec::new();
+    let mut robots: Vec<_> = q_robots.iter().collect();
+    robots.sort_by_key(|(robot_id, _)| **robot_id);
+
+    for (robot_id, graph) in robots {
+        let robot_id = robot_id.to_string();
+        variables.extend((0..).zip(graph.variables()).map(|(variable, (_, node))| {
+            VariableRow {
+                run_id: run_id.clone(),
+                robot_id: robot_id.clone(),
+                t,
+                variable,
+                x: node.belief.mean[0] as f32,
+                y: node.belief.mean[1] as f32,
+                vx: node.belief.mean[2] as f32,
+                vy: node.belief.mean[3] as f32,
+                covariance: node
+                    .belief
+                    .covariance_matrix
+                    .iter()
+                    .map(|&c| c as f32)
+                    .collect(),
+                valid: node.belief.covariance_matrix.iter().all(|c| c.is_finite()),
+            }
+        }));
+
+        // Obstacle and tracking factors are connected to the variables after
+        // the current one, in order
+        let factor_row = |variable, kind: &str, pos: Vec2, value: f64| FactorRow {
+            run_id: run_id.clone(),
+            robot_id: robot_id.clone(),
+            t,
+            variable,
+            kind: kind.to_string(),
+            x: pos.x,
+            y: pos.y,
+            value: value as f32,
+        };
+        factors.extend((1..).zip(graph.variable_and_their_obstacle_factors()).map(
+            |(variable, (_, factor))| {
+                let measurement = factor.last_measurement();
+                factor_row(variable, "obstacle", measurement.pos, measurement.value)
+            },
+        ));
+        factors.extend((1..).zip(graph.variable_and_their_tracking_factors()).map(
+            |(variable, (_, factor))| {
+                let measurement = factor.last_measurement();
+                factor_row(variable, "tracking", measurement.pos, measurement.value)
+            },
+        ));
+    }
+
+    let appended = files.append(&variables, &factors);
+    let part_is_full = files.samples >= config.export.horizon.samples_per_part.get();
+    let appended = appended.and_then(|()| {
+        if part_is_full {
+            recording.next_part()
+        } else {
+            Ok(())
+        }
+    });
+    if let Err(err) = appended {
+        let err_msg = format!("Failed to record horizons, stopping the recording: {err}");
+        error!(err_msg);
+        evw_toast.send(bevy_notify::ToastEvent::error(err_msg));
+        recording.finish();
+    }
+}
+
+/// **Bevy** system that completes the recording when the application exits
+fn finish_horizon_recording(mut recording: ResMut<HorizonRecording>) {
+    recording.finish();
+}
+
+#[cfg(test)]
+mod tests {
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use pretty_assertions::assert_eq;
+
+    use super::{super::empty_test_dir, *};
+
+    fn variable_row(t: f64) -> VariableRow {
+        VariableRow {
+            run_id: "horizon_test_0".to_string(),
+            robot_id: "f0-w0-r0".to_string(),
+            t,
+            variable: 0,
+            x: 1.0,
+            y: 2.0,
+            vx: 0.5,
+            vy: 0.0,
+            covariance: vec![0.0; 16],
+            valid: true,
+        }
+    }
+
+    fn factor_row(t: f64) -> FactorRow {
+        FactorRow {
+            run_id: "horizon_test_0".to_string(),
+            robot_id: "f0-w0-r0".to_string(),
+            t,
+            variable: 1,
+            kind: "obstacle".to_string(),
+            x: 1.0,
+            y: 2.0,
+            value: 0.1,
+        }
+    }
+
+    /// Number of rows in the Parquet file at `path`, if it can be read
+    fn rows_in(path: &Path) -> anyhow::Result<usize> {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?.build()?;
+        reader.map(|batch| anyhow::Ok(batch?.num_rows())).sum()
+    }
+
+    fn recording(name: &str) -> HorizonRecording {
+        let dir = empty_test_dir(name);
+        HorizonRecording {
+            files: Some(
+                HorizonFiles::create(&dir, "horizon_test_0", 0).expect("files are created"),
+            ),
+            dir,
+            run_id: "horizon_test_0".to_string(),
+            ..default()
+        }
+    }
+
+    #[test]
+    fn part_is_unreadable_until_it_is_completed() {
+        let mut recording = recording("horizon_unreadable");
+        let files = recording.files.as_mut().expect("recording is open");
+        files
+            .append(&[variable_row(0.5)], &[factor_row(0.5)])
+            .expect("sample is appended");
+
+        let (variables, factors) = files_of(&recording.dir, &recording.run_id, 0);
+        assert!(rows_in(&variables).is_err());
+
+        recording.finish();
+        assert_eq!(rows_in(&variables).expect("footer is written"), 1);
+        assert_eq!(rows_in(&factors).expect("footer is written"), 1);
+    }
+
+    #[test]
+    fn next_part_completes_the_current_one() {
+        let mut recording = recording("horizon_next_part");
+        for t in [0.5, 1.0] {
+            recording
+                .files
+                .as_mut()
+                .expect("recording is open")
+                .append(&[variable_row(t)], &[factor_row(t)])
+                .expect("sample is appended");
+        }
+        recording.next_part().expect("next part is started");
+
+        let (variables, _) = files_of(&recording.dir, &recording.run_id, 0);
+        assert_eq!(rows_in(&variables).expect("part 0 is completed"), 2);
+        assert_eq!(recording.part, 1);
+        assert_eq!(recording.files.as_ref().map(|files| files.samples), Some(0));
+
+        recording.finish();
+        let (variables, _) = files_of(&recording.dir, &recording.run_id, 1);
+        assert_eq!(rows_in(&variables).expect("part 1 is completed"), 0);
+    }
+}
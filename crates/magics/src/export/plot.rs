@@ -0,0 +1,427 @@
+//! Top-down plots of the JSON export of a run, rendered with `magics plot`
+//! without a GPU or a window.
+//!
+//! A plot shows the walls of the environment of the scenario, rasterised
+//! with [`env_to_png`], the obstacles, the trajectory of every robot in its
+//! exported color, the goal areas, and a marker at every collision. Plots are
+//! written as SVG, or rasterised to PNG on the CPU.
+//!
+//! The trajectories are drawn from the sampled positions in the export, so
+//! runs with telemetry enabled, where the samples are streamed to a separate
+//! file, are plotted without them.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use base64::Engine;
+use gbp_environment::Environment;
+use serde::Deserialize;
+
+use crate::simulation_loader::ResolvedScenario;
+
+/// The format of a plot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum PlotFormat {
+    /// A vector image
+    #[default]
+    Svg,
+    /// A raster image, rasterised on the CPU
+    Png,
+}
+
+impl PlotFormat {
+    /// The file extension of plots in this format
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Svg => "svg",
+            Self::Png => "png",
+        }
+    }
+}
+
+/// The parts of an export that are plotted
+#[derive(Deserialize)]
+struct Export {
+    scenario: String,
+    robots: BTreeMap<String, Robot>,
+    obstacles: BTreeMap<String, Obstacle>,
+    collisions: Collisions,
+    goal_areas: BTreeMap<String, GoalArea>,
+}
+
+#[derive(Deserialize)]
+struct Robot {
+    positions: Vec<[f32; 2]>,
+    color: String,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum Obstacle {
+    Circle { center: [f32; 2], radius: f32 },
+    Polygon { vertices: Vec<[f32; 2]> },
+}
+
+#[derive(Deserialize)]
+struct Collisions {
+    robots: Vec<Collision>,
+    environment: Vec<Collision>,
+}
+
+#[derive(Deserialize)]
+struct Collision {
+    aabbs: Vec<Aabb>,
+}
+
+#[derive(Deserialize)]
+struct Aabb {
+    mins: [f32; 2],
+    maxs: [f32; 2],
+}
+
+#[derive(Deserialize)]
+struct GoalArea {
+    aabb: Aabb,
+}
+
+/// Longest side in pixels of the rasterised environment embedded in a plot
+const MAX_ENVIRONMENT_SIDE: u32 = 2048;
+
+/// The region of the world shown in a plot
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bounds {
+    min: [f32; 2],
+    max: [f32; 2],
+}
+
+impl Bounds {
+    /// The whole environment, centered at the origin
+    #[allow(clippy::cast_precision_loss)]
+    fn of_environment(env: &Environment) -> Self {
+        let tile_size = env.tiles.settings.tile_size;
+        let half_width = tile_size * env.tiles.grid.ncols() as f32 / 2.0;
+        let half_height = tile_size * env.tiles.grid.nrows() as f32 / 2.0;
+        Self {
+            min: [-half_width, -half_height],
+            max: [half_width, half_height],
+        }
+    }
+
+    /// The smallest region containing every position and obstacle in
+    /// `export`, with a margin, or `None` if there is nothing to plot
+    fn of_export(export: &Export) -> Option<Self> {
+        let obstacle_points = export
+            .obstacles
+            .values()
+            .flat_map(|obstacle| match obstacle {
+                Obstacle::Circle { center, radius } => vec![
+                    [center[0] - radius, center[1] - radius],
+                    [center[0] + radius, center[1] + radius],
+                ],
+                Obstacle::Polygon { vertices } => vertices.clone(),
+            });
+        let points: Vec<[f32; 2]> = export
+            .robots
+            .values()
+            .flat_map(|robot| robot.positions.iter().copied())
+            .chain(obstacle_points)
+            .collect();
+
+        let first = points.first()?;
+        let (mut min, mut max) = (*first, *first);
+        for [x, y] in &points {
+            min = [min[0].min(*x), min[1].min(*y)];
+            max = [max[0].max(*x), max[1].max(*y)];
+        }
+        let margin = 0.05 * (max[0] - min[0]).max(max[1] - min[1]).max(1.0);
+        Some(Self {
+            min: [min[0] - margin, min[1] - margin],
+            max: [max[0] + margin, max[1] + margin],
+        })
+    }
+
+    fn width(&self) -> f32 {
+        self.max[0] - self.min[0]
+    }
+
+    fn height(&self) -> f32 {
+        self.max[1] - self.min[1]
+    }
+
+    /// The position of the world point `p` in the plot, where the y axis
+    /// points down
+    fn to_plot(self, [x, y]: [f32; 2]) -> [f32; 2] {
+        [x - self.min[0], self.max[1] - y]
+    }
+}
+
+/// Read the export at `path`, and render it as an SVG plot `width` pixels
+/// wide. The environment is looked up by the name of the scenario of the
+/// export in `simulations_dir`, and left out if it cannot be loaded.
+///
+/// # Errors
+///
+/// Will return `Err` if the export cannot be read or parsed, or has nothing
+/// to plot
+pub fn render(path: &Path, simulations_dir: &Path, width: u32) -> anyhow::Result<String> {
+    let contents = std::fs::read_to_string(path)?;
+    let export: Export = serde_json::from_str(&contents)
+        .map_err(|err| anyhow::anyhow!("{} is not a JSON export: {err}", path.display()))?;
+
+    let environment = match ResolvedScenario::load(&simulations_dir.join(&export.scenario)) {
+        Ok(resolved) => Some(resolved.environment),
+        Err(err) => {
+            eprintln!(
+                "warning: plotting {} without its environment: {err}",
+                path.display()
+            );
+            None
+        }
+    };
+
+    render_export(&export, environment.as_ref(), width)
+}
+
+/// Render `export` as an SVG plot `width` pixels wide, on top of
+/// `environment` if given
+fn render_export(
+    export: &Export,
+    environment: Option<&Environment>,
+    width: u32,
+) -> anyhow::Result<String> {
+    let bounds = environment
+        .map(Bounds::of_environment)
+        .or_else(|| Bounds::of_export(export))
+        .ok_or_else(|| anyhow::anyhow!("the export has no robot positions or obstacles"))?;
+    let (w, h) = (bounds.width(), bounds.height());
+    // Stroke widths and marker sizes, relative to the size of the world
+    let unit = w.max(h) / 500.0;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{:.0}" viewBox="0 0 {w} {h}">"#,
+        width as f32 * h / w
+    );
+    let _ = writeln!(svg, r#"<rect width="{w}" height="{h}" fill="white"/>"#);
+
+    if let Some(environment) = environment {
+        let png = environment_png(environment)?;
+        let _ = writeln!(
+            svg,
+            r#"<image width="{w}" height="{h}" preserveAspectRatio="none" href="data:image/png;base64,{}"/>"#,
+            base64::engine::general_purpose::STANDARD.encode(png)
+        );
+    }
+
+    for obstacle in export.obstacles.values() {
+        match obstacle {
+            Obstacle::Circle { center, radius } => {
+                let [cx, cy] = bounds.to_plot(*center);
+                let _ = writeln!(
+                    svg,
+                    r##"<circle cx="{cx}" cy="{cy}" r="{radius}" fill="#9ca0b0" stroke="#4c4f69" stroke-width="{unit}"/>"##
+                );
+            }
+            Obstacle::Polygon { vertices } => {
+                let _ = writeln!(
+                    svg,
+                    r##"<polygon points="{}" fill="#9ca0b0" stroke="#4c4f69" stroke-width="{unit}"/>"##,
+                    points(bounds, vertices)
+                );
+            }
+        }
+    }
+
+    for area in export.goal_areas.values() {
+        let [x, y] = bounds.to_plot([area.aabb.mins[0], area.aabb.maxs[1]]);
+        let _ = writeln!(
+            svg,
+            r##"<rect x="{x}" y="{y}" width="{}" height="{}" fill="#40a02b" fill-opacity="0.2" stroke="#40a02b" stroke-width="{unit}"/>"##,
+            area.aabb.maxs[0] - area.aabb.mins[0],
+            area.aabb.maxs[1] - area.aabb.mins[1],
+        );
+    }
+
+    for robot in export.robots.values() {
+        let (Some(first), Some(last)) = (robot.positions.first(), robot.positions.last()) else {
+            continue;
+        };
+        let color = &robot.color;
+        let _ = writeln!(
+            svg,
+            r#"<polyline points="{}" fill="none" stroke="{color}" stroke-width="{}" stroke-linejoin="round"/>"#,
+            points(bounds, &robot.positions),
+            2.0 * unit
+        );
+        let [sx, sy] = bounds.to_plot(*first);
+        let [ex, ey] = bounds.to_plot(*last);
+        let _ = writeln!(
+            svg,
+            r#"<circle cx="{sx}" cy="{sy}" r="{}" fill="{color}"/>"#,
+            3.0 * unit
+        );
+        let _ = writeln!(
+            svg,
+            r#"<circle cx="{ex}" cy="{ey}" r="{}" fill="white" stroke="{color}" stroke-width="{unit}"/>"#,
+            3.0 * unit
+        );
+    }
+
+    let collisions = export
+        .collisions
+        .robots
+        .iter()
+        .chain(&export.collisions.environment)
+        .flat_map(|collision| &collision.aabbs);
+    for aabb in collisions {
+        let center = [
+            (aabb.mins[0] + aabb.maxs[0]) / 2.0,
+            (aabb.mins[1] + aabb.maxs[1]) / 2.0,
+        ];
+        let [cx, cy] = bounds.to_plot(center);
+        let r = 4.0 * unit;
+        let _ = writeln!(
+            svg,
+            r##"<path d="M {} {} L {} {} M {} {} L {} {}" stroke="#d20f39" stroke-width="{}"/>"##,
+            cx - r,
+            cy - r,
+            cx + r,
+            cy + r,
+            cx - r,
+            cy + r,
+            cx + r,
+            cy - r,
+            1.5 * unit
+        );
+    }
+
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+/// `vertices` as the `points` of an SVG polyline or polygon
+fn points(bounds: Bounds, vertices: &[[f32; 2]]) -> String {
+    vertices
+        .iter()
+        .map(|&vertex| {
+            let [x, y] = bounds.to_plot(vertex);
+            format!("{x},{y}")
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The walls of `environment` as a PNG, in grey on white
+#[allow(clippy::cast_possible_truncation)]
+fn environment_png(environment: &Environment) -> anyhow::Result<Vec<u8>> {
+    let tiles = environment
+        .tiles
+        .grid
+        .ncols()
+        .max(environment.tiles.grid.nrows()) as u32;
+    let pixels_per_tile = environment
+        .tiles
+        .settings
+        .sdf
+        .resolution
+        .min(MAX_ENVIRONMENT_SIDE / tiles.max(1))
+        .max(1);
+
+    let mut image = env_to_png::env_to_image(
+        environment,
+        env_to_png::PixelsPerTile::new(pixels_per_tile),
+        env_to_png::Percentage::new(0.0),
+    )?;
+    // Free space is white and walls are black, which is lightened to grey
+    for pixel in image.pixels_mut() {
+        for channel in &mut pixel.0 {
+            *channel = (96 + u32::from(*channel) * 159 / 255) as u8;
+        }
+    }
+
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)?;
+    Ok(png)
+}
+
+/// Rasterise the SVG plot `svg` to a PNG
+///
+/// # Errors
+///
+/// Will return `Err` if `svg` cannot be parsed
+pub fn rasterise(svg: &str) -> anyhow::Result<Vec<u8>> {
+    use resvg::{
+        tiny_skia,
+        usvg::{self, TreeParsing},
+    };
+
+    let tree = usvg::Tree::from_str(svg, &usvg::Options::default())?;
+    let tree = resvg::Tree::from_usvg(&tree);
+    let size = tree.size.to_int_size();
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+        .ok_or_else(|| anyhow::anyhow!("the plot has no area"))?;
+    tree.render(tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    Ok(pixmap.encode_png()?)
+}
+
+/// The path of the plot of the export at `export`, written to `out_dir` if
+/// given, and else next to the export
+pub fn output_path(export: &Path, out_dir: Option<&Path>, format: PlotFormat) -> PathBuf {
+    let path = export.with_extension(format.extension());
+    match (out_dir, path.file_name()) {
+        (Some(dir), Some(file_name)) => dir.join(file_name),
+        _ => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn export() -> Export {
+        serde_json::from_value(serde_json::json!({
+            "scenario": "test",
+            "robots": {
+                "f0-w0-r0": { "positions": [[0.0, 0.0], [8.0, 5.0]], "color": "#ff0000" },
+            },
+            "obstacles": {
+                "4v0": { "type": "Circle", "center": [-10.0, 0.0], "radius": 2.0 },
+            },
+            "collisions": { "robots": [], "environment": [] },
+            "goal_areas": {},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn bounds_contain_positions_and_obstacles() {
+        let bounds = Bounds::of_export(&export()).unwrap();
+        // with a margin of 5% of the longest side
+        assert_eq!(bounds.min, [-13.0, -3.0]);
+        assert_eq!(bounds.max, [9.0, 6.0]);
+    }
+
+    #[test]
+    fn y_axis_points_down_in_plot() {
+        let bounds = Bounds {
+            min: [-5.0, -5.0],
+            max: [5.0, 5.0],
+        };
+        assert_eq!(bounds.to_plot([-5.0, 5.0]), [0.0, 0.0]);
+        assert_eq!(bounds.to_plot([5.0, -5.0]), [10.0, 10.0]);
+    }
+
+    #[test]
+    fn trajectories_are_drawn_in_their_color() {
+        let svg = render_export(&export(), None, 800).unwrap();
+        assert!(svg.contains(r##"stroke="#ff0000""##));
+        assert!(svg.contains("<polyline"));
+    }
+}
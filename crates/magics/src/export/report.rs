@@ -0,0 +1,658 @@
+//! Comparison report of the JSON exports of several runs, generated with
+//! `magics report`.
+//!
+//! Runs are grouped by the values of chosen settings, and the metrics of the
+//! runs of every group are aggregated to their mean, standard deviation and
+//! 95% confidence interval of the mean. The report is a single Markdown or
+//! HTML document, with a table and a plot of every metric.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+/// The format of a report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// Markdown, with the plots embedded as data URIs
+    #[default]
+    Markdown,
+    /// A standalone HTML page, with the plots inlined
+    Html,
+}
+
+/// A metric of a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, strum_macros::EnumIter)]
+enum Metric {
+    Makespan,
+    Ldj,
+    Distance,
+    RobotCollisions,
+    EnvironmentCollisions,
+    Messages,
+}
+
+impl Metric {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Makespan => "Makespan [s]",
+            Self::Ldj => "Mean LDJ",
+            Self::Distance => "Mean distance travelled [m]",
+            Self::RobotCollisions => "Robot collisions",
+            Self::EnvironmentCollisions => "Environment collisions",
+            Self::Messages => "Messages sent",
+        }
+    }
+}
+
+/// An export of a run, and the value of its metrics. Metrics that cannot be
+/// computed from the export, e.g. because its samples were streamed as
+/// telemetry, are left out.
+struct Run {
+    path: PathBuf,
+    export: Value,
+    metrics: BTreeMap<Metric, f64>,
+}
+
+/// Aggregate of the values of a metric over the runs of a group
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Summary {
+    n: usize,
+    mean: f64,
+    std: f64,
+    /// Half the width of the 95% confidence interval of the mean
+    ci95: f64,
+}
+
+/// The two-sided 97.5th percentile of the Student t distribution with
+/// `df` degrees of freedom
+fn t_critical(df: usize) -> f64 {
+    const TABLE: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+        2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+    TABLE.get(df.wrapping_sub(1)).copied().unwrap_or(1.960)
+}
+
+impl Summary {
+    /// Summarise `values`, `None` if there are none
+    #[allow(clippy::cast_precision_loss)]
+    fn of(values: &[f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        let n = values.len();
+        let mean = values.iter().sum::<f64>() / n as f64;
+        if n == 1 {
+            return Some(Self {
+                n,
+                mean,
+                std: 0.0,
+                ci95: 0.0,
+            });
+        }
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+        let std = variance.sqrt();
+        Some(Self {
+            n,
+            mean,
+            std,
+            ci95: t_critical(n - 1) * std / (n as f64).sqrt(),
+        })
+    }
+}
+
+/// `[x, y]` of a sample serialised as an array, ignoring the height of a
+/// `Vec3`, which is the middle component
+fn xy(value: &Value) -> Option<[f64; 2]> {
+    let components: Vec<f64> = value.as_array()?.iter().filter_map(Value::as_f64).collect();
+    match components.as_slice() {
+        &[x, y] => Some([x, y]),
+        &[x, _, z] => Some([x, z]),
+        _ => None,
+    }
+}
+
+/// Total distance between consecutive `positions`
+fn distance_travelled(positions: &[[f64; 2]]) -> f64 {
+    positions
+        .windows(2)
+        .map(|pair| (pair[1][0] - pair[0][0]).hypot(pair[1][1] - pair[0][1]))
+        .sum()
+}
+
+/// Gradient of `ys` sampled every `dt`, with central differences in the
+/// interior and one-sided differences at the ends, like `numpy.gradient`
+fn gradient(ys: &[f64], dt: f64) -> Vec<f64> {
+    let n = ys.len();
+    (0..n)
+        .map(|i| match i {
+            0 => (ys[1] - ys[0]) / dt,
+            i if i == n - 1 => (ys[i] - ys[i - 1]) / dt,
+            i => (ys[i + 1] - ys[i - 1]) / (2.0 * dt),
+        })
+        .collect()
+}
+
+/// Integral of `ys` sampled every `dt` with the composite Simpson's rule,
+/// with the trapezoidal rule for the last interval if there is an odd number
+/// of intervals
+fn simpson(ys: &[f64], dt: f64) -> f64 {
+    let intervals = ys.len() - 1;
+    let even = intervals - intervals % 2;
+    let simpson = (0..even)
+        .step_by(2)
+        .map(|i| dt / 3.0 * 4.0f64.mul_add(ys[i + 1], ys[i] + ys[i + 2]))
+        .sum::<f64>();
+    if even == intervals {
+        simpson
+    } else {
+        simpson + dt / 2.0 * (ys[intervals - 1] + ys[intervals])
+    }
+}
+
+/// The Log Dimensionless Jerk of a robot driving with `velocities` at
+/// `timestamps`, `None` if there are too few samples to compute it
+#[allow(clippy::cast_precision_loss)]
+fn ldj(velocities: &[[f64; 2]], timestamps: &[f64]) -> Option<f64> {
+    if velocities.len() < 3 || velocities.len() != timestamps.len() {
+        return None;
+    }
+    let duration = timestamps[timestamps.len() - 1] - timestamps[0];
+    let dt = duration / (timestamps.len() - 1) as f64;
+    if dt <= 0.0 {
+        return None;
+    }
+
+    let (vx, vy): (Vec<f64>, Vec<f64>) = velocities.iter().map(|&[x, y]| (x, y)).unzip();
+    let jx = gradient(&gradient(&vx, dt), dt);
+    let jy = gradient(&gradient(&vy, dt), dt);
+    let squared_jerk: Vec<f64> = jx.iter().zip(&jy).map(|(x, y)| x * x + y * y).collect();
+
+    let v_max = vx
+        .iter()
+        .zip(&vy)
+        .map(|(x, y)| x.hypot(*y))
+        .fold(0.0, f64::max);
+    if v_max == 0.0 {
+        return None;
+    }
+
+    Some(-(duration.powi(3) / v_max.powi(2) * simpson(&squared_jerk, dt)).ln())
+}
+
+/// Mean of `values`, `None` if there are none
+#[allow(clippy::cast_precision_loss)]
+fn mean(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn metrics_of(export: &Value) -> BTreeMap<Metric, f64> {
+    let mut metrics = BTreeMap::new();
+    if let Some(makespan) = export["makespan"].as_f64() {
+        metrics.insert(Metric::Makespan, makespan);
+    }
+
+    let robots: Vec<&Value> = export["robots"]
+        .as_object()
+        .map(|robots| robots.values().collect())
+        .unwrap_or_default();
+
+    let distances: Vec<f64> = robots
+        .iter()
+        .filter_map(|robot| {
+            let positions: Vec<[f64; 2]> = robot["positions"]
+                .as_array()?
+                .iter()
+                .filter_map(xy)
+                .collect();
+            (positions.len() > 1).then(|| distance_travelled(&positions))
+        })
+        .collect();
+    if let Some(distance) = mean(&distances) {
+        metrics.insert(Metric::Distance, distance);
+    }
+
+    let ldjs: Vec<f64> = robots
+        .iter()
+        .filter_map(|robot| {
+            let samples = robot["velocities"].as_array()?;
+            let velocities: Vec<[f64; 2]> =
+                samples.iter().filter_map(|s| xy(&s["velocity"])).collect();
+            let timestamps: Vec<f64> = samples
+                .iter()
+                .filter_map(|s| s["timestamp"].as_f64())
+                .collect();
+            ldj(&velocities, &timestamps)
+        })
+        .filter(|ldj| ldj.is_finite())
+        .collect();
+    if let Some(ldj) = mean(&ldjs) {
+        metrics.insert(Metric::Ldj, ldj);
+    }
+
+    let count = |key: &str| export["collisions"][key].as_array().map(Vec::len);
+    if let Some(robot_collisions) = count("robots") {
+        metrics.insert(Metric::RobotCollisions, robot_collisions as f64);
+    }
+    if let Some(environment_collisions) = count("environment") {
+        metrics.insert(Metric::EnvironmentCollisions, environment_collisions as f64);
+    }
+
+    let messages: f64 = robots
+        .iter()
+        .filter_map(|robot| {
+            let sent = &robot["messages"]["sent"];
+            Some(sent["internal"].as_f64()? + sent["external"].as_f64()?)
+        })
+        .sum();
+    if !robots.is_empty() {
+        metrics.insert(Metric::Messages, messages);
+    }
+
+    metrics
+}
+
+/// The value of the setting at `key` of an export, as a label. `key` is a
+/// path with the keys separated by dots, which is looked up in the export
+/// itself, e.g. `scenario`, and else in its config, e.g.
+/// `gbp.iteration-schedule.schedule`. Objects and arrays are labelled by
+/// their number of entries, so `robots` is the number of robots.
+fn group_label(export: &Value, key: &str) -> String {
+    let lookup = |root: &Value| {
+        key.split('.')
+            .try_fold(root, |value, key| value.get(key))
+            .cloned()
+    };
+    match lookup(export).or_else(|| lookup(&export["config"])) {
+        Some(Value::String(string)) => string,
+        Some(Value::Object(object)) => object.len().to_string(),
+        Some(Value::Array(array)) => array.len().to_string(),
+        Some(value) => value.to_string(),
+        None => "-".to_string(),
+    }
+}
+
+/// Read every JSON export in `dir`
+fn read_runs(dir: &Path) -> anyhow::Result<Vec<Run>> {
+    let pattern = dir.join("export_*.json");
+    let mut paths: Vec<PathBuf> = glob::glob(&pattern.to_string_lossy())?
+        .filter_map(Result::ok)
+        .collect();
+    paths.sort();
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let contents = std::fs::read_to_string(&path)?;
+            let export: Value = serde_json::from_str(&contents)
+                .map_err(|err| anyhow::anyhow!("failed to parse {}: {err}", path.display()))?;
+            let metrics = metrics_of(&export);
+            Ok(Run {
+                path,
+                export,
+                metrics,
+            })
+        })
+        .collect()
+}
+
+/// The label of every group and the summary of every metric of its runs
+type Groups = BTreeMap<Vec<String>, BTreeMap<Metric, Summary>>;
+
+fn summarise(runs: &[Run], group_by: &[String]) -> Groups {
+    let mut grouped: BTreeMap<Vec<String>, Vec<&Run>> = BTreeMap::new();
+    for run in runs {
+        let label = group_by
+            .iter()
+            .map(|key| group_label(&run.export, key))
+            .collect();
+        grouped.entry(label).or_default().push(run);
+    }
+
+    grouped
+        .into_iter()
+        .map(|(label, runs)| {
+            let summaries = <Metric as strum::IntoEnumIterator>::iter()
+                .filter_map(|metric| {
+                    let values: Vec<f64> = runs
+                        .iter()
+                        .filter_map(|run| run.metrics.get(&metric).copied())
+                        .collect();
+                    Summary::of(&values).map(|summary| (metric, summary))
+                })
+                .collect();
+            (label, summaries)
+        })
+        .collect()
+}
+
+/// A bar chart of the mean of `metric` of every group, with the 95%
+/// confidence interval as error bars
+#[allow(clippy::cast_precision_loss)]
+fn plot(groups: &Groups, metric: Metric) -> String {
+    const WIDTH: f64 = 640.0;
+    const HEIGHT: f64 = 320.0;
+    const MARGIN: f64 = 48.0;
+
+    let bars: Vec<(String, Summary)> = groups
+        .iter()
+        .filter_map(|(label, summaries)| Some((label.join(", "), *summaries.get(&metric)?)))
+        .collect();
+    let top = bars
+        .iter()
+        .map(|(_, summary)| summary.mean + summary.ci95)
+        .fold(0.0, f64::max);
+    let bottom = bars
+        .iter()
+        .map(|(_, summary)| summary.mean - summary.ci95)
+        .fold(0.0, f64::min);
+    let range = if top - bottom > 0.0 {
+        top - bottom
+    } else {
+        1.0
+    };
+    let y = |value: f64| MARGIN + (top - value) / range * (HEIGHT - 2.0 * MARGIN);
+
+    let slot = (WIDTH - 2.0 * MARGIN) / bars.len().max(1) as f64;
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" font-family="sans-serif" font-size="11">"#
+    );
+    let _ = write!(
+        svg,
+        r#"<text x="{}" y="20" text-anchor="middle" font-size="14">{}</text>"#,
+        WIDTH / 2.0,
+        metric.name()
+    );
+    let _ = write!(
+        svg,
+        r#"<line x1="{MARGIN}" y1="{0}" x2="{1}" y2="{0}" stroke="black"/>"#,
+        y(0.0),
+        WIDTH - MARGIN
+    );
+    let _ = write!(
+        svg,
+        r#"<text x="{}" y="{}" text-anchor="end">{top:.3}</text><text x="{}" y="{}" text-anchor="end">{bottom:.3}</text>"#,
+        MARGIN - 4.0,
+        y(top) + 4.0,
+        MARGIN - 4.0,
+        y(bottom) + 4.0,
+    );
+    for (i, (label, summary)) in bars.iter().enumerate() {
+        let center = (i as f64 + 0.5).mul_add(slot, MARGIN);
+        let bar_top = y(summary.mean.max(0.0));
+        let bar_height = (y(summary.mean.min(0.0)) - bar_top).abs();
+        let _ = write!(
+            svg,
+            "<rect x=\"{}\" y=\"{bar_top}\" width=\"{}\" height=\"{bar_height}\" \
+             fill=\"#8aadf4\"/>",
+            center - slot * 0.35,
+            slot * 0.7,
+        );
+        let _ = write!(
+            svg,
+            r#"<line x1="{center}" y1="{}" x2="{center}" y2="{}" stroke="black"/>"#,
+            y(summary.mean + summary.ci95),
+            y(summary.mean - summary.ci95),
+        );
+        let _ = write!(
+            svg,
+            r#"<text x="{center}" y="{}" text-anchor="middle">{}</text>"#,
+            HEIGHT - MARGIN + 16.0,
+            escape_html(label)
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// `svg` as a data URI, percent-encoding the characters that are not allowed
+/// in one
+fn data_uri(svg: &str) -> String {
+    let mut uri = String::from("data:image/svg+xml;utf8,");
+    for c in svg.chars() {
+        match c {
+            '%' | '#' | '<' | '>' | '"' | ' ' | '(' | ')' => {
+                let _ = write!(uri, "%{:02X}", c as u32);
+            }
+            c => uri.push(c),
+        }
+    }
+    uri
+}
+
+/// Rows of the table of `metric`, with a column for every key in `group_by`
+fn table_rows(groups: &Groups, metric: Metric) -> Vec<Vec<String>> {
+    groups
+        .iter()
+        .filter_map(|(label, summaries)| {
+            let summary = summaries.get(&metric)?;
+            let mut row = label.clone();
+            row.extend([
+                summary.n.to_string(),
+                format!("{:.3}", summary.mean),
+                format!("{:.3}", summary.std),
+                format!("±{:.3}", summary.ci95),
+            ]);
+            Some(row)
+        })
+        .collect()
+}
+
+/// Generate a report of the exports in `dir`, with the runs grouped by the
+/// settings at the keys in `group_by`. See [`group_label`] for how settings
+/// are looked up.
+///
+/// # Errors
+///
+/// If `dir` cannot be read, contains no exports, or an export is not valid
+/// JSON
+pub fn generate(dir: &Path, group_by: &[String], format: ReportFormat) -> anyhow::Result<String> {
+    let runs = read_runs(dir)?;
+    if runs.is_empty() {
+        anyhow::bail!("no exports named export_*.json in {}", dir.display());
+    }
+    let groups = summarise(&runs, group_by);
+
+    let mut header: Vec<String> = if group_by.is_empty() {
+        vec!["group".to_string()]
+    } else {
+        group_by.to_vec()
+    };
+    header.extend(["n", "mean", "std", "95% CI"].map(String::from));
+    let groups: Groups = if group_by.is_empty() {
+        groups
+            .into_iter()
+            .map(|(_, summaries)| (vec!["all".to_string()], summaries))
+            .collect()
+    } else {
+        groups
+    };
+
+    let title = format!("Comparison of {} runs in {}", runs.len(), dir.display());
+    let mut report = String::new();
+    match format {
+        ReportFormat::Markdown => {
+            let _ = writeln!(report, "# {title}\n");
+            for metric in <Metric as strum::IntoEnumIterator>::iter() {
+                let rows = table_rows(&groups, metric);
+                if rows.is_empty() {
+                    continue;
+                }
+                let _ = writeln!(report, "## {}\n", metric.name());
+                let _ = writeln!(report, "| {} |", header.join(" | "));
+                let _ = writeln!(report, "|{}", "---|".repeat(header.len()));
+                for row in rows {
+                    let _ = writeln!(report, "| {} |", row.join(" | "));
+                }
+                let _ = writeln!(
+                    report,
+                    "\n![{}]({})\n",
+                    metric.name(),
+                    data_uri(&plot(&groups, metric))
+                );
+            }
+            let _ = writeln!(report, "## Runs\n");
+            for run in &runs {
+                let _ = writeln!(report, "- `{}`", run.path.display());
+            }
+        }
+        ReportFormat::Html => {
+            let _ = writeln!(
+                report,
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n\
+                 <style>body {{ font-family: sans-serif; }} table {{ border-collapse: collapse; }} \
+                 td, th {{ border: 1px solid #ccc; padding: 4px 8px; text-align: right; \
+                 }}</style>\n</head>\n<body>\n<h1>{0}</h1>",
+                escape_html(&title)
+            );
+            for metric in <Metric as strum::IntoEnumIterator>::iter() {
+                let rows = table_rows(&groups, metric);
+                if rows.is_empty() {
+                    continue;
+                }
+                let _ = writeln!(report, "<h2>{}</h2>\n<table>", escape_html(metric.name()));
+                let cells = |row: &[String], tag: &str| {
+                    row.iter()
+                        .map(|cell| format!("<{tag}>{}</{tag}>", escape_html(cell)))
+                        .collect::<String>()
+                };
+                let _ = writeln!(report, "<tr>{}</tr>", cells(&header, "th"));
+                for row in rows {
+                    let _ = writeln!(report, "<tr>{}</tr>", cells(&row, "td"));
+                }
+                let _ = writeln!(report, "</table>\n{}", plot(&groups, metric));
+            }
+            let _ = writeln!(report, "<h2>Runs</h2>\n<ul>");
+            for run in &runs {
+                let _ = writeln!(
+                    report,
+                    "<li><code>{}</code></li>",
+                    escape_html(&run.path.to_string_lossy())
+                );
+            }
+            let _ = writeln!(report, "</ul>\n</body>\n</html>");
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{super::empty_test_dir, *};
+
+    #[test]
+    fn summary_of_values() {
+        let summary = Summary::of(&[1.0, 2.0, 3.0]).expect("there are values");
+        assert_eq!(summary.n, 3);
+        approx::assert_relative_eq!(summary.mean, 2.0);
+        approx::assert_relative_eq!(summary.std, 1.0);
+        approx::assert_relative_eq!(summary.ci95, 4.303 / 3.0f64.sqrt());
+        assert!(Summary::of(&[]).is_none());
+    }
+
+    #[test]
+    fn distance_travelled_along_positions() {
+        let positions = [[0.0, 0.0], [3.0, 4.0], [3.0, 0.0]];
+        approx::assert_relative_eq!(distance_travelled(&positions), 9.0);
+    }
+
+    #[test]
+    fn constant_velocity_has_no_jerk() {
+        let velocities = [[1.0, 0.0]; 5];
+        let timestamps = [0.0, 1.0, 2.0, 3.0, 4.0];
+        let ldj = ldj(&velocities, &timestamps).expect("enough samples");
+        assert!(ldj.is_infinite() && ldj.is_sign_positive());
+    }
+
+    #[test]
+    fn groups_are_labelled_by_settings() {
+        let export = serde_json::json!({
+            "scenario": "Circle",
+            "robots": { "1v0": {}, "2v0": {} },
+            "config": { "simulation": { "prng-seed": 31 } },
+        });
+        assert_eq!(group_label(&export, "scenario"), "Circle");
+        assert_eq!(group_label(&export, "robots"), "2");
+        assert_eq!(group_label(&export, "simulation.prng-seed"), "31");
+        assert_eq!(group_label(&export, "gbp.missing"), "-");
+    }
+
+    /// A directory for the test `name` with two runs of the `Circle` scenario
+    /// and one of the `Junction` scenario
+    fn exports(name: &str) -> PathBuf {
+        let dir = empty_test_dir(name);
+        for (name, scenario, makespan) in [
+            ("export_circle_0.json", "Circle", 10.0),
+            ("export_circle_1.json", "Circle", 20.0),
+            ("export_junction_0.json", "Junction", 5.0),
+        ] {
+            let export = serde_json::json!({
+                "scenario": scenario,
+                "makespan": makespan,
+                "collisions": { "robots": [], "environment": [] },
+            });
+            std::fs::write(dir.join(name), export.to_string()).expect("temp dir is writable");
+        }
+        std::fs::write(dir.join("notes.json"), "not an export").expect("temp dir is writable");
+        dir
+    }
+
+    #[test]
+    fn markdown_report_has_a_row_for_every_group() {
+        let dir = exports("report_markdown");
+        let report = generate(&dir, &["scenario".to_string()], ReportFormat::Markdown)
+            .expect("the exports are valid");
+
+        assert!(report.starts_with("# Comparison of 3 runs in "));
+        let makespan = [
+            "## Makespan [s]",
+            "",
+            "| scenario | n | mean | std | 95% CI |",
+            "|---|---|---|---|---|",
+            "| Circle | 2 | 15.000 | 7.071 | ±63.530 |",
+            "| Junction | 1 | 5.000 | 0.000 | ±0.000 |",
+        ]
+        .join("\n");
+        assert!(report.contains(&makespan), "{report}");
+        assert!(report.contains("| Circle | 2 | 0.000 | 0.000 | ±0.000 |"));
+        // Metrics that none of the exports have are left out
+        assert!(!report.contains("Mean LDJ"));
+        assert!(!report.contains("notes.json"));
+    }
+
+    #[test]
+    fn runs_are_one_group_without_settings_to_group_by() {
+        let dir = exports("report_html");
+        let report = generate(&dir, &[], ReportFormat::Html).expect("the exports are valid");
+
+        assert!(
+            report.contains(
+                "<tr><th>group</th><th>n</th><th>mean</th><th>std</th><th>95% CI</th></tr>"
+            )
+        );
+        assert!(report.contains("<tr><td>all</td><td>3</td><td>11.667</td>"));
+        assert!(!report.contains("<td>Circle</td>"));
+    }
+
+    #[test]
+    fn directory_without_exports_is_an_error() {
+        let dir = empty_test_dir("report_empty");
+        assert!(generate(&dir, &[], ReportFormat::Markdown).is_err());
+    }
+}
@@ -0,0 +1,372 @@
+//! The data of an export as tidy tables, with one row per observation, for the
+//! columnar export formats.
+//!
+//! Every row carries the id of the run it is from, and robots are identified
+//! by their [`StableRobotId`] instead of by their [`Entity`], so the tables of
+//! several runs can be concatenated and compared.
+//!
+//! [`StableRobotId`]: crate::planner::robot::StableRobotId
+//! [`Entity`]: bevy::ecs::entity::Entity
+
+use std::path::PathBuf;
+
+use heck::ToKebabCase;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use super::ExportData;
+
+/// A robot, with its summary statistics
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub(super) struct RobotRow {
+    run_id: String,
+    robot_id: String,
+    formation: u32,
+    wave: u32,
+    index: u32,
+    radius: f32,
+    color: String,
+    class: Option<String>,
+    priority: u8,
+    planning_strategy: String,
+    started_at: f64,
+    finished_at: f64,
+    delay: f64,
+    robot_collisions: u64,
+    environment_collisions: u64,
+    messages_sent_internal: u64,
+    messages_sent_external: u64,
+    messages_received_internal: u64,
+    messages_received_external: u64,
+}
+
+/// The state of a robot at a point in time
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub(super) struct SampleRow {
+    run_id: String,
+    robot_id: String,
+    t: f64,
+    x: f32,
+    y: f32,
+    vx: f32,
+    vy: f32,
+}
+
+/// An overlap between the bounding boxes of a robot and another robot or an
+/// obstacle
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub(super) struct CollisionRow {
+    run_id: String,
+    /// Either `robot` or `environment`
+    kind: String,
+    robot_id: Option<String>,
+    other_robot_id: Option<String>,
+    /// Index of the entity of the obstacle, for collisions with the
+    /// environment
+    obstacle: Option<u32>,
+    min_x: f32,
+    min_y: f32,
+    max_x: f32,
+    max_y: f32,
+}
+
+/// A waypoint of a route of a robot
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub(super) struct RouteRow {
+    run_id: String,
+    robot_id: String,
+    route: u32,
+    waypoint: u32,
+    x: f32,
+    y: f32,
+    started_at: f64,
+    finished_at: f64,
+}
+
+/// A goal area, and a robot that reached it. Areas no robot has reached have
+/// a single row without a robot.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub(super) struct GoalAreaRow {
+    run_id: String,
+    goal_area: u32,
+    min_x: f32,
+    min_y: f32,
+    max_x: f32,
+    max_y: f32,
+    robot_id: Option<String>,
+    reached_at: Option<f32>,
+}
+
+/// The connectivity of the communication graph, and of the graph of the
+/// robots within communication range of each other, at a point in time
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub(super) struct ConnectivityRow {
+    run_id: String,
+    t: f64,
+    robots: u32,
+    edges: u32,
+    components: u32,
+    average_degree: f64,
+    algebraic_connectivity: f64,
+    in_range_edges: u32,
+    in_range_components: u32,
+    in_range_average_degree: f64,
+    in_range_algebraic_connectivity: f64,
+    antennas_off: u32,
+}
+
+/// Writes a table to a file in some format
+pub(super) trait TableWriter {
+    /// Write the rows of the table `name`, and return the path of the written
+    /// file
+    fn write_table<T: Serialize + DeserializeOwned>(
+        &self,
+        name: &str,
+        rows: &[T],
+    ) -> anyhow::Result<PathBuf>;
+}
+
+/// The tables of an export
+pub(super) struct Tables {
+    pub(super) robots: Vec<RobotRow>,
+    pub(super) samples: Vec<SampleRow>,
+    pub(super) collisions: Vec<CollisionRow>,
+    pub(super) routes: Vec<RouteRow>,
+    pub(super) goal_areas: Vec<GoalAreaRow>,
+    pub(super) connectivity: Vec<ConnectivityRow>,
+}
+
+impl Tables {
+    /// Split `data` into tables, with `run_id` in every row
+    #[allow(clippy::too_many_lines, clippy::cast_possible_truncation)]
+    pub(super) fn new(run_id: &str, data: &ExportData) -> Self {
+        let run_id = run_id.to_string();
+
+        let robots = data
+            .robots
+            .iter()
+            .map(|(id, robot)| RobotRow {
+                run_id: run_id.clone(),
+                robot_id: id.to_string(),
+                formation: id.formation as u32,
+                wave: id.wave as u32,
+                index: id.index as u32,
+                radius: robot.radius,
+                color: robot.color.clone(),
+                class: robot.class.clone(),
+                priority: robot.priority,
+                planning_strategy: <&str>::from(robot.planning_strategy).to_kebab_case(),
+                started_at: robot.mission.started_at,
+                finished_at: robot.mission.finished_at,
+                delay: robot.delay,
+                robot_collisions: robot.collisions.robots as u64,
+                environment_collisions: robot.collisions.environment as u64,
+                messages_sent_internal: robot.messages.sent.internal as u64,
+                messages_sent_external: robot.messages.sent.external as u64,
+                messages_received_internal: robot.messages.received.internal as u64,
+                messages_received_external: robot.messages.received.external as u64,
+            })
+            .collect();
+
+        let samples = data
+            .robots
+            .iter()
+            .flat_map(|(id, robot)| {
+                let robot_id = id.to_string();
+                let run_id = &run_id;
+                robot.velocities.iter().map(move |sample| SampleRow {
+                    run_id: run_id.clone(),
+                    robot_id: robot_id.clone(),
+                    t: sample.timestamp,
+                    x: sample.position.x,
+                    y: sample.position.z,
+                    vx: sample.velocity.x,
+                    vy: sample.velocity.z,
+                })
+            })
+            .collect();
+
+        let robot_collisions = data.collisions.robots.iter().flat_map(|collision| {
+            collision.aabbs.iter().map(|aabb| CollisionRow {
+                run_id: run_id.clone(),
+                kind: "robot".to_string(),
+                robot_id: collision.robot_a.map(|id| id.to_string()),
+                other_robot_id: collision.robot_b.map(|id| id.to_string()),
+                obstacle: None,
+                min_x: aabb.mins.x,
+                min_y: aabb.mins.y,
+                max_x: aabb.maxs.x,
+                max_y: aabb.maxs.y,
+            })
+        });
+        let environment_collisions = data.collisions.environment.iter().flat_map(|collision| {
+            collision.aabbs.iter().map(|aabb| CollisionRow {
+                run_id: run_id.clone(),
+                kind: "environment".to_string(),
+                robot_id: collision.robot.map(|id| id.to_string()),
+                other_robot_id: None,
+                obstacle: Some(collision.obstacle.index()),
+                min_x: aabb.mins.x,
+                min_y: aabb.mins.y,
+                max_x: aabb.maxs.x,
+                max_y: aabb.maxs.y,
+            })
+        });
+        let collisions = robot_collisions.chain(environment_collisions).collect();
+
+        let routes = data
+            .robots
+            .iter()
+            .flat_map(|(id, robot)| {
+                let robot_id = id.to_string();
+                let run_id = &run_id;
+                (0..)
+                    .zip(&robot.mission.routes)
+                    .flat_map(move |(route_index, route)| {
+                        (0..)
+                            .zip(&route.waypoints)
+                            .map(move |(waypoint, [x, y])| RouteRow {
+                                run_id: run_id.clone(),
+                                robot_id: robot_id.clone(),
+                                route: route_index,
+                                waypoint,
+                                x: *x,
+                                y: *y,
+                                started_at: route.started_at,
+                                finished_at: route.finished_at,
+                            })
+                    })
+            })
+            .collect();
+
+        let mut areas: Vec<_> = data.goal_areas.iter().collect();
+        areas.sort_by_key(|(entity, _)| **entity);
+        let goal_areas = (0..)
+            .zip(areas)
+            .flat_map(|(goal_area, (_, area))| {
+                let row = |robot_id, reached_at| GoalAreaRow {
+                    run_id: run_id.clone(),
+                    goal_area,
+                    min_x: area.aabb.mins.x,
+                    min_y: area.aabb.mins.y,
+                    max_x: area.aabb.maxs.x,
+                    max_y: area.aabb.maxs.y,
+                    robot_id,
+                    reached_at,
+                };
+                if area.history.is_empty() {
+                    return vec![row(None, None)];
+                }
+                let mut reached: Vec<_> = area
+                    .history
+                    .iter()
+                    .map(|(robot, reached_at)| (robot.to_string(), *reached_at))
+                    .collect();
+                reached.sort_by(|a, b| a.1.total_cmp(&b.1));
+                reached
+                    .into_iter()
+                    .map(|(robot_id, reached_at)| row(Some(robot_id), Some(reached_at)))
+                    .collect()
+            })
+            .collect();
+
+        let connectivity = data
+            .connectivity
+            .iter()
+            .map(|sample| ConnectivityRow {
+                run_id: run_id.clone(),
+                t: sample.t,
+                robots: sample.robots as u32,
+                edges: sample.connected.edges as u32,
+                components: sample.connected.components as u32,
+                average_degree: sample.connected.average_degree,
+                algebraic_connectivity: sample.connected.algebraic_connectivity,
+                in_range_edges: sample.in_range.edges as u32,
+                in_range_components: sample.in_range.components as u32,
+                in_range_average_degree: sample.in_range.average_degree,
+                in_range_algebraic_connectivity: sample.in_range.algebraic_connectivity,
+                antennas_off: sample.antennas_off as u32,
+            })
+            .collect();
+
+        Self {
+            robots,
+            samples,
+            collisions,
+            routes,
+            goal_areas,
+            connectivity,
+        }
+    }
+
+    /// Write every table with `writer`, and return the paths of the written
+    /// files
+    pub(super) fn write_with(&self, writer: &impl TableWriter) -> anyhow::Result<Vec<PathBuf>> {
+        Ok(vec![
+            writer.write_table("robots", &self.robots)?,
+            writer.write_table("samples", &self.samples)?,
+            writer.write_table("collisions", &self.collisions)?,
+            writer.write_table("routes", &self.routes)?,
+            writer.write_table("goal_areas", &self.goal_areas)?,
+            writer.write_table("connectivity", &self.connectivity)?,
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn every_row_carries_the_run_and_stable_robot_ids() {
+        let tables = Tables::new("run-1", &ExportData::example());
+
+        assert_eq!(tables.robots.len(), 2);
+        assert!(tables.robots.iter().all(|row| row.run_id == "run-1"));
+        assert_eq!(tables.robots[0].robot_id, "f0-w0-r0");
+        assert_eq!(tables.robots[0].planning_strategy, "only-local");
+        assert_eq!(tables.robots[1].class.as_deref(), Some("heavy"));
+
+        assert_eq!(tables.collisions.len(), 1);
+        assert_eq!(tables.collisions[0].kind, "robot");
+        assert_eq!(tables.collisions[0].robot_id.as_deref(), Some("f0-w0-r0"));
+        assert_eq!(
+            tables.collisions[0].other_robot_id.as_deref(),
+            Some("f0-w0-r1")
+        );
+        assert_eq!(tables.connectivity.len(), 1);
+    }
+
+    #[test]
+    fn samples_and_routes_have_one_row_per_observation() {
+        let tables = Tables::new("run-1", &ExportData::example());
+
+        // The y-axis of the plane is the z-axis of the world
+        let ys: Vec<f32> = tables.samples.iter().map(|row| row.y).collect();
+        assert_eq!(ys, vec![0.0, 2.0]);
+        approx::assert_relative_eq!(tables.samples[1].vy, 4.0);
+
+        let waypoints: Vec<(u32, u32)> = tables
+            .routes
+            .iter()
+            .map(|row| (row.route, row.waypoint))
+            .collect();
+        assert_eq!(waypoints, vec![(0, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn unreached_goal_areas_have_a_row_without_a_robot() {
+        let tables = Tables::new("run-1", &ExportData::example());
+
+        let reached: Vec<_> = tables
+            .goal_areas
+            .iter()
+            .map(|row| (row.goal_area, row.robot_id.as_deref(), row.reached_at))
+            .collect();
+        assert_eq!(
+            reached,
+            vec![(0, Some("f0-w0-r0"), Some(8.0)), (1, None, None)]
+        );
+    }
+}
@@ -0,0 +1,343 @@
+//! Streaming of the state of the robots to a telemetry file while the
+//! simulation runs.
+//!
+//! Unlike the export, which is written once from the bounded
+//! [`PositionTracker`](crate::planner::tracking::PositionTracker) and
+//! [`VelocityTracker`](crate::planner::tracking::VelocityTracker) buffers,
+//! the telemetry file is appended to every
+//! [`TelemetrySection::every`](gbp_config::TelemetrySection::every) fixed
+//! timesteps, and flushed after every sample, so no samples are lost in long
+//! runs, and the file survives a crash of the application.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use bevy::prelude::*;
+use gbp_config::{Config, TelemetryFormat};
+use gbp_linalg::Float;
+
+use crate::{
+    factorgraph::prelude::FactorGraph,
+    planner::{
+        RobotConnections,
+        robot::{RadioAntenna, StableRobotId},
+    },
+    simulation_loader::{LoadSimulation, ReloadSimulation, SimulationManager},
+};
+
+pub struct TelemetryPlugin;
+
+impl Plugin for TelemetryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TelemetryStream>()
+            .add_systems(
+                Update,
+                open_telemetry_stream
+                    .run_if(on_event::<LoadSimulation>().or_else(on_event::<ReloadSimulation>())),
+            )
+            .add_systems(
+                FixedUpdate,
+                stream_telemetry.run_if(|stream: Res<TelemetryStream>| stream.is_open()),
+            );
+    }
+}
+
+/// The state of a robot at a fixed timestep
+#[derive(serde::Serialize)]
+struct TelemetryRow {
+    run_id: String,
+    robot_id: StableRobotId,
+    t: f64,
+    x: f32,
+    y: f32,
+    vx: f32,
+    vy: f32,
+    /// Covariance of the position of the belief of the current state
+    cov_xx: f32,
+    cov_xy: f32,
+    cov_yy: f32,
+    /// Mean of the trace of the position covariance of the beliefs of every
+    /// variable along the horizon
+    horizon_cov_trace: f32,
+    messages_sent_internal: u64,
+    messages_sent_external: u64,
+    messages_received_internal: u64,
+    messages_received_external: u64,
+    antenna_active: bool,
+    robots_within_comms_range: u32,
+    robots_connected_with: u32,
+}
+
+enum TelemetryWriter {
+    Ndjson(BufWriter<File>),
+    Csv(csv::Writer<File>),
+}
+
+impl TelemetryWriter {
+    fn create(path: &std::path::Path, format: TelemetryFormat) -> std::io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(match format {
+            TelemetryFormat::Ndjson => Self::Ndjson(BufWriter::new(file)),
+            TelemetryFormat::Csv => Self::Csv(csv::Writer::from_writer(file)),
+        })
+    }
+
+    fn write(&mut self, row: &TelemetryRow) -> anyhow::Result<()> {
+        match self {
+            Self::Ndjson(writer) => {
+                serde_json::to_writer(&mut *writer, row)?;
+                writer.write_all(b"\n")?;
+            }
+            Self::Csv(writer) => writer.serialize(row)?,
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Ndjson(writer) => writer.flush(),
+            Self::Csv(writer) => writer.flush(),
+        }
+    }
+}
+
+/// **Bevy** [`Resource`] with the telemetry file of the running simulation,
+/// if telemetry is enabled
+#[derive(Resource, Default)]
+pub(super) struct TelemetryStream {
+    file: Option<(PathBuf, TelemetryWriter)>,
+    run_id: String,
+    /// Fixed timesteps since the last sample
+    steps: usize,
+}
+
+impl TelemetryStream {
+    /// Whether a telemetry file is being streamed, which it is not if
+    /// telemetry is disabled or the file could not be created
+    pub(super) const fn is_open(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// The path of the telemetry file, if telemetry is being streamed
+    pub(super) fn path(&self) -> Option<&std::path::Path> {
+        self.file.as_ref().map(|(path, _)| path.as_path())
+    }
+}
+
+/// **Bevy** system that starts a new telemetry file whenever a simulation is
+/// loaded or reloaded, if telemetry is enabled
+fn open_telemetry_stream(
+    mut stream: ResMut<TelemetryStream>,
+    mut evw_toast: EventWriter<bevy_notify::ToastEvent>,
+    sim_manager: Res<SimulationManager>,
+    config: Res<Config>,
+) {
+    *stream = TelemetryStream::default();
+    let telemetry = &config.export.telemetry;
+    if !telemetry.enabled {
+        return;
+    }
+
+    let environment = sim_manager.active_name().unwrap_or_default();
+    let prefix = format!("telemetry_{}_", environment.to_lowercase());
+    let run_id = format!("{prefix}{}", super::next_export_number(&prefix));
+    let dirname = std::env::current_dir().expect("current directory exists");
+    let path = dirname.join(format!("{run_id}.{}", telemetry.format.extension()));
+
+    match TelemetryWriter::create(&path, telemetry.format) {
+        Ok(writer) => {
+            info!("streaming telemetry to '{}'", path.display());
+            stream.file = Some((path, writer));
+            stream.run_id = run_id;
+        }
+        Err(err) => {
+            let err_msg = format!("Failed to create {}: {}", path.display(), err);
+            error!(err_msg);
+            evw_toast.send(bevy_notify::ToastEvent::error(err_msg));
+        }
+    }
+}
+
+/// Covariance of the position `[xx, xy, yy]` of a belief
+#[allow(clippy::cast_possible_truncation)]
+fn position_covariance(covariance: &gbp_linalg::Matrix<Float>) -> [f32; 3] {
+    [
+        covariance[(0, 0)] as f32,
+        covariance[(0, 1)] as f32,
+        covariance[(1, 1)] as f32,
+    ]
+}
+
+/// **Bevy** system that appends the state of every robot to the telemetry
+/// file every [`TelemetrySection::every`](gbp_config::TelemetrySection::every)
+/// fixed timesteps
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+fn stream_telemetry(
+    mut stream: ResMut<TelemetryStream>,
+    mut evw_toast: EventWriter<bevy_notify::ToastEvent>,
+    q_robots: Query<(
+        &StableRobotId,
+        &Transform,
+        &FactorGraph,
+        &RobotConnections,
+        &RadioAntenna,
+    )>,
+    config: Res<Config>,
+    time_fixed: Res<Time<Fixed>>,
+) {
+    stream.steps += 1;
+    if stream.steps < config.export.telemetry.every.get() {
+        return;
+    }
+    stream.steps = 0;
+
+    let TelemetryStream {
+        file: Some((path, writer)),
+        run_id,
+        ..
+    } = &mut *stream
+    else {
+        return;
+    };
+
+    let t = time_fixed.elapsed_seconds_f64();
+    let mut rows: Vec<TelemetryRow> = q_robots
+        .iter()
+        .filter_map(|(&robot_id, transform, graph, connections, antenna)| {
+            let (_, current) = graph.first_variable()?;
+            let [cov_xx, cov_xy, cov_yy] = position_covariance(&current.belief.covariance_matrix);
+            let traces: Vec<f32> = graph
+                .variables()
+                .map(|(_, variable)| {
+                    let [xx, _, yy] = position_covariance(&variable.belief.covariance_matrix);
+                    xx + yy
+                })
+                .collect();
+            let sent = graph.messages_sent();
+            let received = graph.messages_received();
+
+            Some(TelemetryRow {
+                run_id: run_id.clone(),
+                robot_id,
+                t,
+                x: transform.translation.x,
+                y: transform.translation.z,
+                vx: current.belief.mean[2] as f32,
+                vy: current.belief.mean[3] as f32,
+                cov_xx,
+                cov_xy,
+                cov_yy,
+                horizon_cov_trace: traces.iter().sum::<f32>() / traces.len() as f32,
+                messages_sent_internal: sent.internal as u64,
+                messages_sent_external: sent.external as u64,
+                messages_received_internal: received.internal as u64,
+                messages_received_external: received.external as u64,
+                antenna_active: antenna.active,
+                robots_within_comms_range: connections.robots_within_comms_range.len() as u32,
+                robots_connected_with: connections.robots_connected_with.len() as u32,
+            })
+        })
+        .collect();
+    rows.sort_by_key(|row| row.robot_id);
+
+    let written = rows
+        .iter()
+        .try_for_each(|row| writer.write(row))
+        .and_then(|()| Ok(writer.flush()?));
+    if let Err(err) = written {
+        let err_msg = format!(
+            "Failed to write telemetry to {}, stopping telemetry: {}",
+            path.display(),
+            err
+        );
+        error!(err_msg);
+        evw_toast.send(bevy_notify::ToastEvent::error(err_msg));
+        stream.file = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{super::empty_test_dir, *};
+
+    fn row(index: usize, t: f64) -> TelemetryRow {
+        TelemetryRow {
+            run_id: "telemetry_test_0".to_string(),
+            robot_id: StableRobotId {
+                formation: 0,
+                wave: 0,
+                index,
+            },
+            t,
+            x: 1.0,
+            y: 2.0,
+            vx: 0.5,
+            vy: 0.0,
+            cov_xx: 0.25,
+            cov_xy: 0.0,
+            cov_yy: 0.25,
+            horizon_cov_trace: 1.0,
+            messages_sent_internal: 4,
+            messages_sent_external: 2,
+            messages_received_internal: 4,
+            messages_received_external: 1,
+            antenna_active: true,
+            robots_within_comms_range: 2,
+            robots_connected_with: 1,
+        }
+    }
+
+    #[test]
+    fn ndjson_rows_are_readable_after_every_flush() {
+        let path = empty_test_dir("telemetry_ndjson").join("telemetry.ndjson");
+        let mut writer =
+            TelemetryWriter::create(&path, TelemetryFormat::Ndjson).expect("file is created");
+
+        writer.write(&row(0, 0.5)).expect("row is written");
+        writer.flush().expect("file is flushed");
+        // The writer is still open, as it would be if the application crashed
+        let contents = std::fs::read_to_string(&path).expect("file exists");
+        assert_eq!(contents.lines().count(), 1);
+        let line: serde_json::Value =
+            serde_json::from_str(contents.trim_end()).expect("line is a JSON object");
+        assert_eq!(line["robot_id"], "f0-w0-r0");
+        assert_eq!(line["t"], 0.5);
+        assert_eq!(line["robots_connected_with"], 1);
+    }
+
+    #[test]
+    fn csv_has_a_header_and_a_line_per_row() {
+        let path = empty_test_dir("telemetry_csv").join("telemetry.csv");
+        let mut writer =
+            TelemetryWriter::create(&path, TelemetryFormat::Csv).expect("file is created");
+
+        for index in 0..2 {
+            writer.write(&row(index, 0.5)).expect("row is written");
+        }
+        writer.flush().expect("file is flushed");
+        let contents = std::fs::read_to_string(&path).expect("file exists");
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("run_id,robot_id,t,x,y,"));
+        assert!(lines[2].starts_with("telemetry_test_0,f0-w0-r1,0.5,"));
+    }
+
+    #[test]
+    fn position_covariance_is_the_upper_left_block() {
+        let covariance = ndarray::array![
+            [1.0, 2.0, 9.0, 9.0],
+            [2.0, 3.0, 9.0, 9.0],
+            [9.0, 9.0, 9.0, 9.0],
+            [9.0, 9.0, 9.0, 9.0],
+        ];
+        let [xx, xy, yy] = position_covariance(&covariance);
+        approx::assert_relative_eq!(xx, 1.0);
+        approx::assert_relative_eq!(xy, 2.0);
+        approx::assert_relative_eq!(yy, 3.0);
+    }
+}
@@ -1,6 +1,7 @@
-use std::{borrow::Cow, num::NonZeroUsize, ops::Sub};
+use std::{borrow::Cow, num::NonZeroUsize, ops::Sub, sync::Arc};
 
-use bevy::log::info;
+use bevy::{log::info, math::Vec2};
+use gbp_environment::{RegionKind, WorldRegion};
 use gbp_linalg::prelude::*;
 use ndarray::s;
 use typed_floats::StrictlyPositiveFinite;
@@ -36,13 +37,23 @@ impl ExternalVariableId {
 /// be in the same position at the same timestep (collision). This factor is
 /// created between variables of two robots. The factor has 0 energy if the
 /// variables are further away than the safety distance.
+/// Inside a shared space region of the environment the safety distance is
+/// given by the multiplier of the region instead.
+/// Between robots of different priority, the factor in the factorgraph of the
+/// robot with the lower priority is stronger, so that robot yields more.
 #[derive(Debug, Clone)]
 pub struct InterRobotFactor {
     safety_distance: Float,
+    /// The mean of the radii of the two robots, as robots of different
+    /// classes can have different radii
     robot_radius: Float,
     skip: bool,
     pub external_variable: ExternalVariableId,
     tiny_offset: Float,
+    shared_spaces: Arc<[WorldRegion]>,
+    /// The priority of the other robot minus the priority of the robot this
+    /// factor belongs to
+    priority_difference: i32,
     // all_zeros_jacobian: Matrix<Float>,
 }
 
@@ -73,9 +84,38 @@ impl InterRobotFactor {
             skip: false,
             external_variable,
             tiny_offset: Float::from(Self::TINY_OFFSET_SCALE) * robot_number.get() as f64,
+            shared_spaces: Arc::from([]),
+            priority_difference: 0,
         }
     }
 
+    /// Set the priority of the other robot minus the priority of the robot
+    /// this factor belongs to
+    #[must_use]
+    pub const fn with_priority_difference(mut self, priority_difference: i32) -> Self {
+        self.priority_difference = priority_difference;
+        self
+    }
+
+    /// Get the priority of the other robot minus the priority of the robot
+    /// this factor belongs to
+    #[inline(always)]
+    pub const fn priority_difference(&self) -> i32 {
+        self.priority_difference
+    }
+
+    /// Use the shared space regions among `regions` to change the safety
+    /// distance, while the variable is inside them
+    #[must_use]
+    pub fn with_shared_spaces(mut self, regions: &[WorldRegion]) -> Self {
+        self.shared_spaces = regions
+            .iter()
+            .filter(|region| matches!(region.kind, RegionKind::SharedSpace { .. }))
+            .cloned()
+            .collect();
+        self
+    }
+
     /// Get the safety distance
     #[inline(always)]
     pub const fn safety_distance(&self) -> Float {
@@ -88,6 +128,31 @@ impl InterRobotFactor {
         self.safety_distance = multiplier.get() * self.robot_radius
     }
 
+    /// The safety distance at the estimated position of the variable in
+    /// `linearisation_point`. If the position is inside one or more shared
+    /// spaces, the largest of their multipliers is used.
+    #[allow(clippy::cast_possible_truncation)]
+    fn safety_distance_at(&self, linearisation_point: &Vector<Float>) -> Float {
+        if self.shared_spaces.is_empty() {
+            return self.safety_distance;
+        }
+
+        let position = Vec2::new(linearisation_point[0] as f32, linearisation_point[1] as f32);
+        self.shared_spaces
+            .iter()
+            .filter(|region| region.contains(position))
+            .filter_map(|region| match region.kind {
+                RegionKind::SharedSpace {
+                    safety_distance_multiplier,
+                } => Some(Float::from(safety_distance_multiplier.get())),
+                _ => None,
+            })
+            .reduce(Float::max)
+            .map_or(self.safety_distance, |multiplier| {
+                multiplier * self.robot_radius
+            })
+    }
+
     fn diff_between_estimated_positions(
         &self,
         linearisation_point: &Vector<Float>,
@@ -146,16 +211,17 @@ impl Factor for InterRobotFactor {
         // };
 
         let radius = x_diff.euclidean_norm();
-        if radius <= self.safety_distance {
+        let safety_distance = self.safety_distance_at(lineraisation_point);
+        if radius <= safety_distance {
             // J(0, seqN(0, n_dofs_ / 2)) = -1.f / safety_distance_ / r * X_diff;
             jacobian
                 .slice_mut(s![0, ..DOFS / 2])
-                .assign(&(-1.0 / self.safety_distance / radius * &x_diff));
+                .assign(&(-1.0 / safety_distance / radius * &x_diff));
 
             // J(0, seqN(n_dofs_, n_dofs_ / 2)) = 1.f / safety_distance_ / r * X_diff;
             jacobian
                 .slice_mut(s![0, DOFS..DOFS + (DOFS / 2)])
-                .assign(&(1.0 / self.safety_distance / radius * &x_diff));
+                .assign(&(1.0 / safety_distance / radius * &x_diff));
         }
         Cow::Owned(jacobian)
     }
@@ -185,7 +251,8 @@ impl Factor for InterRobotFactor {
         // let squared_distance = x_diff.mapv(|x| x * x).sum();
 
         let radius = x_diff.euclidean_norm();
-        if radius <= self.safety_distance {
+        let safety_distance = self.safety_distance_at(lineraisation_point);
+        if radius <= safety_distance {
             if self.skip {
                 info!(
                     "within safety distance, radius = {}, setting self.skip to false",
@@ -197,7 +264,7 @@ impl Factor for InterRobotFactor {
             // NOTE: in Eigen, indexing a matrix with a single index corresponds to indexing
             // the matrix as a flattened array in column-major order.
             // h[(0, 0)] = 1.0 * (1.0 - radius / self.safety_distance);
-            measurement[0] = 1.0 * (1.0 - radius / self.safety_distance);
+            measurement[0] = 1.0 * (1.0 - radius / safety_distance);
         }
 
         Measurement::new(measurement)
@@ -222,7 +289,7 @@ impl Factor for InterRobotFactor {
             .mapv(|x| x.powi(2))
             .sum();
 
-        squared_distance >= self.safety_distance.powi(2)
+        squared_distance >= self.safety_distance_at(&state.linearisation_point).powi(2)
     }
 
     #[inline(always)]
@@ -238,7 +305,8 @@ impl Factor for InterRobotFactor {
 
 impl std::fmt::Display for InterRobotFactor {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "safety_distance: {}", self.safety_distance)
+        writeln!(f, "safety_distance: {}", self.safety_distance)?;
+        writeln!(f, "priority_difference: {}", self.priority_difference)
         // TODO: write more
     }
 }
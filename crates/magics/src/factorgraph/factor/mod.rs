@@ -7,7 +7,7 @@ use typed_floats::StrictlyPositiveFinite;
 
 use self::{
     dynamic::DynamicFactor, interrobot::InterRobotFactor, obstacle::ObstacleFactor,
-    tracking::TrackingFactor,
+    region::RegionFactor, relative_pose::RelativePoseFactor, tracking::TrackingFactor,
 };
 use super::{
     DOFS, MessageCount, MessagesReceived, MessagesSent,
@@ -24,6 +24,8 @@ pub(in crate::factorgraph) mod interrobot;
 mod marginalise_factor_distance;
 pub(crate) mod obstacle;
 pub(in crate::factorgraph) mod pose;
+pub(crate) mod region;
+pub(crate) mod relative_pose;
 pub(in crate::factorgraph) mod tracking;
 // mod velocity;
 // pub(in crate::factorgraph) mod velocity;
@@ -217,6 +219,9 @@ impl FactorNode {
         safety_distance_multiplier: StrictlyPositiveFinite<Float>,
         external_variable: ExternalVariableId,
         robot_number: NonZeroUsize,
+        regions: &[gbp_environment::WorldRegion],
+        priority_difference: i32,
+        priority_scaling: StrictlyPositiveFinite<Float>,
         enabled: bool,
     ) -> Self {
         let interrobot_factor = InterRobotFactor::new(
@@ -224,7 +229,11 @@ impl FactorNode {
             external_variable,
             Some(safety_distance_multiplier),
             robot_number,
-        );
+        )
+        .with_shared_spaces(regions)
+        .with_priority_difference(priority_difference);
+        // The factor is stronger, the higher the priority of the other robot is
+        let strength = strength / priority_scaling.get().powi(priority_difference);
         let kind = FactorKind::InterRobot(interrobot_factor);
         let state = FactorState::new(measurement, strength, InterRobotFactor::NEIGHBORS);
 
@@ -252,6 +261,36 @@ impl FactorNode {
         Self::new(factorgraph_id, state, kind, enabled)
     }
 
+    /// Create a new region factor
+    pub fn new_region_factor(
+        factorgraph_id: FactorGraphId,
+        strength: Float,
+        measurement: Vector<Float>,
+        regions: std::sync::Arc<[gbp_environment::WorldRegion]>,
+        enabled: bool,
+    ) -> Self {
+        let state = FactorState::new(measurement, strength, RegionFactor::NEIGHBORS);
+        let region_factor = RegionFactor::new(regions);
+        let kind = FactorKind::Region(region_factor);
+        Self::new(factorgraph_id, state, kind, enabled)
+    }
+
+    /// Create a new relative pose factor
+    pub fn new_relative_pose_factor(
+        factorgraph_id: FactorGraphId,
+        strength: Float,
+        measurement: Vector<Float>,
+        external_variable: ExternalVariableId,
+        slack: f32,
+        stiffness: f32,
+        enabled: bool,
+    ) -> Self {
+        let state = FactorState::new(measurement, strength, RelativePoseFactor::NEIGHBORS);
+        let relative_pose_factor = RelativePoseFactor::new(external_variable, slack, stiffness);
+        let kind = FactorKind::RelativePose(relative_pose_factor);
+        Self::new(factorgraph_id, state, kind, enabled)
+    }
+
     /// Create a new tracking factor
     pub fn new_tracking_factor(
         factorgraph_id: FactorGraphId,
@@ -477,6 +516,18 @@ impl FactorNode {
         self.kind.is_tracking()
     }
 
+    /// Check if the factor is a [`RegionFactor`]
+    #[inline(always)]
+    pub fn is_region(&self) -> bool {
+        self.kind.is_region()
+    }
+
+    /// Check if the factor is a [`RelativePoseFactor`]
+    #[inline(always)]
+    pub fn is_relative_pose(&self) -> bool {
+        self.kind.is_relative_pose()
+    }
+
     pub fn empty_inbox(&mut self) {
         // empty_inbox
         self.inbox.values_mut().for_each(|m| *m = Message::empty());
@@ -496,6 +547,10 @@ pub enum FactorKind {
     Obstacle(ObstacleFactor),
     /// `TrackingFactor`
     Tracking(TrackingFactor),
+    /// `RegionFactor`
+    Region(RegionFactor),
+    /// `RelativePoseFactor`
+    RelativePose(RelativePoseFactor),
 }
 
 impl std::fmt::Display for FactorKind {
@@ -505,6 +560,8 @@ impl std::fmt::Display for FactorKind {
             Self::Dynamic(f) => f.fmt(formatter),
             Self::Obstacle(f) => f.fmt(formatter),
             Self::Tracking(f) => f.fmt(formatter),
+            Self::Region(f) => f.fmt(formatter),
+            Self::RelativePose(f) => f.fmt(formatter),
         }
     }
 }
@@ -516,6 +573,8 @@ impl Factor for FactorKind {
             Self::Dynamic(f) => f.name(),
             Self::Obstacle(f) => f.name(),
             Self::Tracking(f) => f.name(),
+            Self::Region(f) => f.name(),
+            Self::RelativePose(f) => f.name(),
         }
     }
 
@@ -525,6 +584,8 @@ impl Factor for FactorKind {
             Self::Dynamic(f) => f.color(),
             Self::Obstacle(f) => f.color(),
             Self::Tracking(f) => f.color(),
+            Self::Region(f) => f.color(),
+            Self::RelativePose(f) => f.color(),
         }
     }
 
@@ -538,6 +599,8 @@ impl Factor for FactorKind {
             Self::InterRobot(f) => f.jacobian(state, linearisation_point),
             Self::Obstacle(f) => f.jacobian(state, linearisation_point),
             Self::Tracking(f) => f.jacobian(state, linearisation_point),
+            Self::Region(f) => f.jacobian(state, linearisation_point),
+            Self::RelativePose(f) => f.jacobian(state, linearisation_point),
         }
     }
 
@@ -549,6 +612,8 @@ impl Factor for FactorKind {
             Self::InterRobot(f) => f.measure(state, linearisation_point),
             Self::Obstacle(f) => f.measure(state, linearisation_point),
             Self::Tracking(f) => f.measure(state, linearisation_point),
+            Self::Region(f) => f.measure(state, linearisation_point),
+            Self::RelativePose(f) => f.measure(state, linearisation_point),
         }
     }
 
@@ -558,6 +623,8 @@ impl Factor for FactorKind {
             Self::InterRobot(f) => f.skip(state),
             Self::Obstacle(f) => f.skip(state),
             Self::Tracking(f) => f.skip(state),
+            Self::Region(f) => f.skip(state),
+            Self::RelativePose(f) => f.skip(state),
         }
     }
 
@@ -567,6 +634,8 @@ impl Factor for FactorKind {
             Self::InterRobot(f) => f.jacobian_delta(),
             Self::Obstacle(f) => f.jacobian_delta(),
             Self::Tracking(f) => f.jacobian_delta(),
+            Self::Region(f) => f.jacobian_delta(),
+            Self::RelativePose(f) => f.jacobian_delta(),
         }
     }
 
@@ -577,6 +646,8 @@ impl Factor for FactorKind {
             Self::InterRobot(f) => f.linear(),
             Self::Obstacle(f) => f.linear(),
             Self::Tracking(f) => f.linear(),
+            Self::Region(f) => f.linear(),
+            Self::RelativePose(f) => f.linear(),
         }
     }
 
@@ -586,6 +657,8 @@ impl Factor for FactorKind {
             FactorKind::Dynamic(f) => f.neighbours(),
             FactorKind::Obstacle(f) => f.neighbours(),
             FactorKind::Tracking(f) => f.neighbours(),
+            FactorKind::Region(f) => f.neighbours(),
+            FactorKind::RelativePose(f) => f.neighbours(),
         }
     }
 }
@@ -642,6 +715,13 @@ impl FactorState {
         }
     }
 
+    /// Change the strength of the factor. The measurement precision of every
+    /// factor kind is proportional to `strength^-2`, so it is scaled to match.
+    pub fn set_strength(&mut self, strength: Float) {
+        self.measurement_precision *= Float::powi(self.strength / strength, 2);
+        self.strength = strength;
+    }
+
     /// Set the linearisation point
     fn with_linearisation_point(mut self, linearisation_point: Vector<Float>) -> Self {
         self.linearisation_point = linearisation_point;
@@ -739,3 +819,62 @@ impl FactorGraphNode for FactorNode {
 //         write!(f, "node_index: {:?}", self.node_index)?;
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::entity::Entity;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::factorgraph::factorgraph::{FactorGraph, VariableIndex};
+
+    fn interrobot_factor(priority_difference: i32) -> FactorNode {
+        FactorNode::new_interrobot_factor(
+            Entity::from_raw(0),
+            0.5,
+            Vector::<Float>::zeros(DOFS),
+            1.0.try_into().expect("1.0 > 0.0"),
+            2.2.try_into().expect("2.2 > 0.0"),
+            ExternalVariableId::new(Entity::from_raw(1), VariableIndex(NodeIndex::new(0))),
+            NonZeroUsize::MIN,
+            &[],
+            priority_difference,
+            2.0.try_into().expect("2.0 > 0.0"),
+            true,
+        )
+    }
+
+    #[test]
+    fn lower_priority_robot_yields_more() {
+        // A robot of priority 1 and one of priority 3 meet
+        let low = interrobot_factor(3 - 1);
+        let high = interrobot_factor(1 - 3);
+
+        assert_eq!(low.state.strength, 0.5 / 4.0);
+        assert_eq!(high.state.strength, 0.5 * 4.0);
+        assert!(low.state.measurement_precision[[0, 0]] > high.state.measurement_precision[[0, 0]]);
+    }
+
+    #[test]
+    fn changed_strengths_keep_the_priorities() {
+        let mut factorgraph = FactorGraph::new(Entity::from_raw(0));
+        let low = factorgraph.add_factor(interrobot_factor(2));
+        let high = factorgraph.add_factor(interrobot_factor(-2));
+
+        factorgraph.change_factor_strengths(&gbp_config::GbpSection {
+            sigma_factor_interrobot: 0.25,
+            priority_scaling: 2.0.try_into().expect("2.0 > 0.0"),
+            ..Default::default()
+        });
+
+        let strength = |index| {
+            factorgraph
+                .get_factor(index)
+                .expect("the factor was added")
+                .state
+                .strength
+        };
+        assert_eq!(strength(low), 0.25 / 4.0);
+        assert_eq!(strength(high), 0.25 * 4.0);
+    }
+}
@@ -3,6 +3,7 @@
 use std::{borrow::Cow, cell::Cell, sync::Mutex};
 
 use bevy::math::Vec2;
+use env_to_png::SdfSampler;
 use gbp_linalg::prelude::*;
 use ndarray::array;
 
@@ -112,6 +113,13 @@ impl ObstacleFactor {
     pub fn last_measurement(&self) -> LastMeasurement {
         self.last_measurement.lock().unwrap().get()
     }
+
+    /// Replace the signed distance field, e.g. when an obstacle of the
+    /// environment appears or disappears. The new SDF must have the same
+    /// dimensions as the old one.
+    pub fn set_sdf(&mut self, obstacle_sdf: SharedSdfImage) {
+        self.obstacle_sdf = obstacle_sdf;
+    }
 }
 
 impl Factor for ObstacleFactor {
@@ -0,0 +1,113 @@
+//! Region factor (extension)
+
+use std::sync::Arc;
+
+use bevy::math::Vec2;
+use gbp_environment::{RegionKind, WorldRegion};
+use gbp_linalg::prelude::*;
+use ndarray::array;
+
+use super::{Factor, FactorState, Measurement};
+
+/// Region factor: for following the rules of semantic regions in the
+/// environment. The factor has 0 energy, unless the variable is inside a
+/// speed limit region while moving faster than the limit, or inside a one-way
+/// region while moving against the preferred direction.
+pub struct RegionFactor {
+    /// The speed limit and one-way regions of the environment
+    regions: Arc<[WorldRegion]>,
+}
+
+#[allow(clippy::missing_fields_in_debug)]
+impl std::fmt::Debug for RegionFactor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegionFactor")
+            .field("regions", &self.regions.len())
+            .finish()
+    }
+}
+
+impl RegionFactor {
+    /// A region factor has a single edge to another variable
+    pub const NEIGHBORS: usize = 1;
+
+    /// Creates a new [`RegionFactor`].
+    #[must_use]
+    pub fn new(regions: Arc<[WorldRegion]>) -> Self {
+        Self { regions }
+    }
+
+    /// Whether the factor has any effect inside `region`
+    pub fn applies_to(region: &WorldRegion) -> bool {
+        matches!(
+            region.kind,
+            RegionKind::SpeedLimit { .. } | RegionKind::OneWay { .. }
+        )
+    }
+}
+
+impl Factor for RegionFactor {
+    #[inline]
+    fn name(&self) -> &'static str {
+        "RegionFactor"
+    }
+
+    fn color(&self) -> [u8; 3] {
+        // #f5a97f
+        [245, 169, 127]
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn measure(&self, _state: &FactorState, linearisation_point: &Vector<Float>) -> Measurement {
+        let position = Vec2::new(linearisation_point[0] as f32, linearisation_point[1] as f32);
+        let velocity = Vec2::new(linearisation_point[2] as f32, linearisation_point[3] as f32);
+
+        let violation: f32 = self
+            .regions
+            .iter()
+            .filter(|region| region.contains(position))
+            .map(|region| match region.kind {
+                RegionKind::SpeedLimit { max_speed } => {
+                    (velocity.length() - max_speed.get()).max(0.0)
+                }
+                RegionKind::OneWay { .. } => {
+                    let direction = region.kind.direction().expect("one-way has a direction");
+                    (-velocity.dot(direction)).max(0.0)
+                }
+                _ => 0.0,
+            })
+            .sum();
+
+        Measurement::new(array![Float::from(violation)])
+    }
+
+    #[inline(always)]
+    fn jacobian_delta(&self) -> Float {
+        1e-2
+    }
+
+    #[inline(always)]
+    fn skip(&self, _state: &FactorState) -> bool {
+        false
+    }
+
+    #[inline(always)]
+    fn linear(&self) -> bool {
+        false
+    }
+
+    #[inline(always)]
+    fn neighbours(&self) -> usize {
+        Self::NEIGHBORS
+    }
+}
+
+impl std::fmt::Display for RegionFactor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "regions:")?;
+        for region in self.regions.iter() {
+            writeln!(f, "  {}: {}", region.name, region.kind.name())?;
+        }
+        Ok(())
+    }
+}
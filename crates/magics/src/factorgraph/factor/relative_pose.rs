@@ -0,0 +1,126 @@
+//! Relative pose factor (extension)
+
+use bevy::math::Vec2;
+use gbp_linalg::prelude::*;
+use ndarray::array;
+
+use super::{Factor, FactorState, Measurement};
+use crate::factorgraph::{DOFS, factor::ExternalVariableId};
+
+/// Relative pose factor: for keeping a formation while travelling.
+/// The factor is created between the variables of a robot and the teammate it
+/// keeps its place relative to, at the same timestep. The measurement is the
+/// position of the robot in the frame of the teammate, where `x` points in the
+/// direction the teammate is heading, and `y` to its left. The factor has 0
+/// energy when the robot is at the target offset from its teammate, or within
+/// `slack` of it for an elastic formation.
+#[derive(Debug, Clone)]
+pub struct RelativePoseFactor {
+    pub external_variable: ExternalVariableId,
+    /// How far the robot can drift from the target offset, before it is
+    /// pulled back
+    slack: Float,
+    /// The sigma of the factor is divided by the stiffness
+    stiffness: f32,
+}
+
+impl RelativePoseFactor {
+    /// A relative pose factor has an edge to a variable of the robot, and one
+    /// to a variable of its teammate
+    pub const NEIGHBORS: usize = 2;
+
+    /// Creates a new [`RelativePoseFactor`]. A `slack` of 0 keeps the
+    /// formation rigid.
+    #[must_use]
+    pub fn new(external_variable: ExternalVariableId, slack: f32, stiffness: f32) -> Self {
+        Self {
+            external_variable,
+            slack: Float::from(slack),
+            stiffness,
+        }
+    }
+
+    /// The sigma of the factor is divided by the stiffness
+    #[inline(always)]
+    pub const fn stiffness(&self) -> f32 {
+        self.stiffness
+    }
+
+    /// The heading of the teammate in `linearisation_point`, falling back to
+    /// the heading of the robot while the teammate is standing still
+    #[allow(clippy::cast_possible_truncation)]
+    fn heading(linearisation_point: &Vector<Float>) -> Vec2 {
+        let velocity_of = |offset: usize| {
+            Vec2::new(
+                linearisation_point[offset + 2] as f32,
+                linearisation_point[offset + 3] as f32,
+            )
+        };
+        velocity_of(DOFS)
+            .try_normalize()
+            .or_else(|| velocity_of(0).try_normalize())
+            .unwrap_or(Vec2::X)
+    }
+}
+
+impl Factor for RelativePoseFactor {
+    #[inline]
+    fn name(&self) -> &'static str {
+        "RelativePoseFactor"
+    }
+
+    fn color(&self) -> [u8; 3] {
+        // #7dc4e4
+        [125, 196, 228]
+    }
+
+    fn measure(&self, state: &FactorState, linearisation_point: &Vector<Float>) -> Measurement {
+        let heading = Self::heading(linearisation_point);
+        let (cos, sin) = (Float::from(heading.x), Float::from(heading.y));
+        let dx = linearisation_point[0] - linearisation_point[DOFS];
+        let dy = linearisation_point[1] - linearisation_point[DOFS + 1];
+        // Rotate the difference into the frame of the teammate
+        let relative = array![dx * cos + dy * sin, -dx * sin + dy * cos];
+
+        if self.slack <= 0.0 {
+            return Measurement::new(relative);
+        }
+
+        // Only the part of the error outside the slack is measured
+        let target = &state.initial_measurement;
+        let error = &relative - target;
+        let distance = error.euclidean_norm();
+        if distance <= self.slack {
+            Measurement::new(target.clone())
+        } else {
+            Measurement::new(target + &(error * (1.0 - self.slack / distance)))
+        }
+    }
+
+    #[inline(always)]
+    fn jacobian_delta(&self) -> Float {
+        1e-2
+    }
+
+    #[inline(always)]
+    fn skip(&self, _state: &FactorState) -> bool {
+        false
+    }
+
+    #[inline(always)]
+    fn linear(&self) -> bool {
+        false
+    }
+
+    #[inline(always)]
+    fn neighbours(&self) -> usize {
+        Self::NEIGHBORS
+    }
+}
+
+impl std::fmt::Display for RelativePoseFactor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "slack: {}", self.slack)?;
+        writeln!(f, "stiffness: {}", self.stiffness)
+    }
+}
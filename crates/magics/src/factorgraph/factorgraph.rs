@@ -116,6 +116,15 @@ pub struct FactorGraph {
     /// List of indices of the tracking factors in the graph.
     /// Used to speed up iteration over tracking factors.
     tracking_factor_indices: Vec<NodeIndex>,
+
+    /// List of indices of the region factors in the graph.
+    /// Used to speed up iteration over region factors.
+    region_factor_indices: Vec<NodeIndex>,
+
+    /// List of indices of the relative pose factors in the graph.
+    /// Like the interrobot factors, they are connected to a variable in
+    /// another factorgraph.
+    relative_pose_factor_indices: Vec<NodeIndex>,
 }
 
 // macro_rules! internal_factor_iteration_inner {
@@ -155,6 +164,8 @@ impl FactorGraph {
             obstacle_factor_indices: Vec::new(),
             dynamic_factor_indices: Vec::new(),
             tracking_factor_indices: Vec::new(),
+            region_factor_indices: Vec::new(),
+            relative_pose_factor_indices: Vec::new(),
         }
     }
 
@@ -173,6 +184,8 @@ impl FactorGraph {
             obstacle_factor_indices: Vec::new(),
             dynamic_factor_indices: Vec::new(),
             tracking_factor_indices: Vec::new(),
+            region_factor_indices: Vec::new(),
+            relative_pose_factor_indices: Vec::new(),
         }
     }
 
@@ -219,6 +232,8 @@ impl FactorGraph {
             FactorKind::Dynamic(_) => self.dynamic_factor_indices.push(node_index),
             FactorKind::Obstacle(_) => self.obstacle_factor_indices.push(node_index),
             FactorKind::Tracking(_) => self.tracking_factor_indices.push(node_index),
+            FactorKind::Region(_) => self.region_factor_indices.push(node_index),
+            FactorKind::RelativePose(_) => self.relative_pose_factor_indices.push(node_index),
         }
 
         node_index.into()
@@ -263,6 +278,8 @@ impl FactorGraph {
             interrobot: self.interrobot_factor_indices.len(),
             dynamic: self.dynamic_factor_indices.len(),
             tracking: self.tracking_factor_indices.len(),
+            region: self.region_factor_indices.len(),
+            relative_pose: self.relative_pose_factor_indices.len(),
         }
     }
 
@@ -398,11 +415,13 @@ impl FactorGraph {
             let factor = node
                 .as_factor()
                 .expect("A factor index should point to a Factor in the graph");
-            let Some(interrobot) = factor.kind.try_as_inter_robot_ref() else {
-                continue;
+            let external_variable = match factor.kind {
+                FactorKind::InterRobot(ref interrobot) => interrobot.external_variable,
+                FactorKind::RelativePose(ref relative_pose) => relative_pose.external_variable,
+                _ => continue,
             };
 
-            if interrobot.external_variable.factorgraph_id == other {
+            if external_variable.factorgraph_id == other {
                 self.graph.remove_node(node_index).expect(
                     "The node index was retrieved from the graph in the previous statement",
                 );
@@ -413,6 +432,13 @@ impl FactorGraph {
             }
         }
 
+        // The indices of removed nodes are reused by the graph, so they must not be
+        // left behind in the lists of external factors
+        self.interrobot_factor_indices
+            .retain(|idx| !factor_indices_to_remove.contains(&FactorIndex(*idx)));
+        self.relative_pose_factor_indices
+            .retain(|idx| !factor_indices_to_remove.contains(&FactorIndex(*idx)));
+
         #[allow(clippy::needless_collect)]
         for node_index in self.graph.node_indices().collect::<Vec<_>>() {
             let node = &mut self.graph[node_index];
@@ -695,7 +721,7 @@ impl FactorGraph {
             }
 
             match factor.kind {
-                FactorKind::InterRobot(_) => continue,
+                FactorKind::InterRobot(_) | FactorKind::RelativePose(_) => continue,
                 FactorKind::Tracking(_) if self.iteration_count.factor < 10 => continue,
                 _ => (),
             }
@@ -712,16 +738,23 @@ impl FactorGraph {
     }
 
     /// External Factor Iteration in Gaussian Belief Propagation (GBP).
-    /// Only takes into account factors that are interrobot factors.
+    /// Only takes into account factors that are interrobot factors or relative
+    /// pose factors, as they are connected to variables in other factorgraphs.
     #[must_use]
     pub fn external_factor_iteration(&mut self) -> Vec<FactorToVariableMessage> {
-        // Each interrobot factor is connected to an internal variable
-        // So we can preallocate a vec of length the number of interrobot factors
+        let interrobot_factors = self.interrobot_factor_indices.len();
+        let external_factors = interrobot_factors + self.relative_pose_factor_indices.len();
+        // Each external factor is connected to an internal variable
+        // So we can preallocate a vec of length the number of external factors
         let mut messages_to_external_variables: Vec<FactorToVariableMessage> =
-            Vec::with_capacity(self.interrobot_factor_indices.len());
+            Vec::with_capacity(external_factors);
 
-        for i in 0..self.interrobot_factor_indices.len() {
-            let ix = self.interrobot_factor_indices[i];
+        for i in 0..external_factors {
+            let ix = if i < interrobot_factors {
+                self.interrobot_factor_indices[i]
+            } else {
+                self.relative_pose_factor_indices[i - interrobot_factors]
+            };
             if !self.graph.contains_node(ix) {
                 // TODO: document when this happens
                 continue;
@@ -939,6 +972,10 @@ pub struct FactorCount {
     pub dynamic: usize,
     /// Number of `TrackingFactor`s
     pub tracking: usize,
+    /// Number of `RegionFactor`s
+    pub region: usize,
+    /// Number of `RelativePoseFactor`s
+    pub relative_pose: usize,
 }
 
 /// Iterator over the factors in the factorgraph.
@@ -1466,6 +1503,17 @@ impl FactorGraph {
             f(inner);
         }
     }
+
+    pub fn modify_obstacle_factors(&mut self, mut f: impl FnMut(&mut ObstacleFactor)) {
+        for ix in &self.obstacle_factor_indices {
+            let node = &mut self.graph[*ix];
+            let factor = node.factor_mut();
+            let FactorKind::Obstacle(ref mut inner) = factor.kind else {
+                panic!("Expected an obstacle factor");
+            };
+            f(inner);
+        }
+    }
 }
 
 use super::graphviz;
@@ -1490,10 +1538,31 @@ impl graphviz::ExportGraph for FactorGraph {
                                 }
                             }
                             FactorKind::Tracking(_) => graphviz::NodeKind::TrackingFactor,
+                            FactorKind::Region(_) => graphviz::NodeKind::RegionFactor,
+                            FactorKind::RelativePose(ref inner) => {
+                                graphviz::NodeKind::RelativePoseFactor {
+                                    external_variable_id: inner.external_variable,
+                                }
+                            }
                         },
                         NodeKind::Variable(variable) => {
                             let [x, y] = variable.estimated_position();
-                            graphviz::NodeKind::Variable { x, y }
+                            graphviz::NodeKind::Variable {
+                                x,
+                                y,
+                                horizon_index: self
+                                    .variable_indices
+                                    .iter()
+                                    .position(|&ix| ix == node_index)
+                                    .expect("every variable is in variable_indices"),
+                                mean: variable.belief.mean.to_vec(),
+                                covariance: variable
+                                    .belief
+                                    .covariance_matrix
+                                    .iter()
+                                    .copied()
+                                    .collect(),
+                            }
                         }
                     },
                 }
@@ -1509,6 +1578,8 @@ impl graphviz::ExportGraph for FactorGraph {
                     .map(|(from, to)| graphviz::Edge {
                         from: from.index(),
                         to: to.index(),
+                        message_from: self.last_message(self.id, from.index(), to.index()),
+                        message_to: self.last_message(self.id, to.index(), from.index()),
                     })
             })
             .collect::<Vec<_>>();
@@ -1517,6 +1588,35 @@ impl graphviz::ExportGraph for FactorGraph {
     }
 }
 
+impl FactorGraph {
+    /// The last message the node at index `sender` in the factorgraph
+    /// `sender_graph` sent to the node at index `receiver` in this
+    /// factorgraph, from the inbox of `receiver`. The sender is in another
+    /// factorgraph for the external edges of interrobot and relative pose
+    /// factors.
+    pub(crate) fn last_message(
+        &self,
+        sender_graph: FactorGraphId,
+        sender: usize,
+        receiver: usize,
+    ) -> Option<graphviz::LastMessage> {
+        let sender = NodeIndex::new(sender);
+        let message = match &self.graph.node_weight(NodeIndex::new(receiver))?.kind {
+            NodeKind::Variable(variable) => variable
+                .inbox
+                .get(&FactorId::new(sender_graph, FactorIndex(sender))),
+            NodeKind::Factor(factor) => factor
+                .inbox
+                .get(&VariableId::new(sender_graph, VariableIndex(sender))),
+        }?;
+        let payload = message.payload()?;
+        Some(graphviz::LastMessage {
+            mean: payload.mean.to_vec(),
+            precision: payload.precision_matrix.iter().copied().collect(),
+        })
+    }
+}
+
 impl FactorGraph {
     pub fn change_factor_enabled(&mut self, settings: gbp_config::FactorsEnabledSection) {
         for &ix in self.factor_indices.iter() {
@@ -1526,7 +1626,32 @@ impl FactorGraph {
                 FactorKind::Obstacle(_) => settings.obstacle,
                 FactorKind::InterRobot(_) => settings.interrobot,
                 FactorKind::Tracking(_) => settings.tracking,
+                FactorKind::Region(_) => settings.region,
+                FactorKind::RelativePose(_) => settings.relative_pose,
+            };
+        }
+    }
+
+    /// Change the strength of every factor to the sigma of its kind in
+    /// `settings`
+    pub fn change_factor_strengths(&mut self, settings: &gbp_config::GbpSection) {
+        for &ix in &self.factor_indices {
+            let factor = self.graph[ix].factor_mut();
+            let sigma = match factor.kind {
+                FactorKind::Dynamic(_) => settings.sigma_factor_dynamics,
+                FactorKind::Obstacle(_) => settings.sigma_factor_obstacle,
+                FactorKind::InterRobot(ref inner) => {
+                    let priority_scaling = settings.priority_scaling.get();
+                    settings.sigma_factor_interrobot
+                        / priority_scaling.powi(inner.priority_difference())
+                }
+                FactorKind::Tracking(_) => settings.sigma_factor_tracking,
+                FactorKind::Region(_) => settings.sigma_factor_region,
+                FactorKind::RelativePose(ref inner) => {
+                    settings.sigma_factor_relative_pose / inner.stiffness()
+                }
             };
+            factor.state.set_strength(Float::from(sigma));
         }
     }
 
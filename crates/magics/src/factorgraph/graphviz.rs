@@ -28,12 +28,14 @@ impl Node {
 
 pub enum NodeKind {
     Variable {
-        // It is not dead `rustc` ...
-        #[allow(dead_code)]
         x: f64,
-        // It is not dead `rustc` ...
-        #[allow(dead_code)]
         y: f64,
+        /// Index of the variable along the horizon, the current state is 0
+        horizon_index: usize,
+        /// Mean of the belief of the variable
+        mean: Vec<f64>,
+        /// Covariance of the belief of the variable in row-major order
+        covariance: Vec<f64>,
     },
     InterRobotFactor {
         // It is not dead `rustc` ...
@@ -50,6 +52,10 @@ pub enum NodeKind {
     DynamicFactor,
     ObstacleFactor,
     TrackingFactor, // PoseFactor,
+    RegionFactor,
+    RelativePoseFactor {
+        external_variable_id: ExternalVariableId,
+    },
 }
 
 impl NodeKind {
@@ -60,7 +66,9 @@ impl NodeKind {
             Self::DynamicFactor => "#8aadf4",           // blue
             Self::ObstacleFactor => "#ee99a0",          // mauve (purple)
             // Self::PoseFactor => "#c6aof6",     // maroon (red)
-            Self::TrackingFactor => "#f4a15a", // orange
+            Self::TrackingFactor => "#f4a15a",            // orange
+            Self::RegionFactor => "#f5a97f",              // peach
+            Self::RelativePoseFactor { .. } => "#7dc4e4", // sapphire
         }
     }
 
@@ -79,9 +87,20 @@ impl NodeKind {
     }
 }
 
+/// The last message sent along an edge
+pub struct LastMessage {
+    pub mean: Vec<f64>,
+    /// The precision matrix of the message in row-major order
+    pub precision: Vec<f64>,
+}
+
 pub struct Edge {
     pub from: usize,
     pub to: usize,
+    /// The last message sent from `from` to `to`, if any
+    pub message_from: Option<LastMessage>,
+    /// The last message sent from `to` to `from`, if any
+    pub message_to: Option<LastMessage>,
 }
 
 pub trait ExportGraph {
@@ -1,9 +1,7 @@
-use std::collections::HashMap;
-
 use bevy::{app::AppExit, prelude::*, tasks::IoTaskPool};
 use bevy_notify::prelude::*;
 // use chrono::Duration;
-use gbp_config::{Config, DrawSetting};
+use gbp_config::{Config, DrawSetting, FactorGraphFormat};
 use leafwing_input_manager::prelude::*;
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
@@ -15,12 +13,13 @@ use super::{
 };
 use crate::{
     bevy_utils::run_conditions::event_exists,
-    factorgraph::{
-        graphviz::{ExportGraph, NodeKind},
-        prelude::FactorGraph,
-    },
+    export::factorgraphs::FactorGraphs,
+    factorgraph::prelude::FactorGraph,
     pause_play::PausePlay,
-    planner::{robot::RadioAntenna, RobotConnections, RobotId},
+    planner::{
+        robot::{RadioAntenna, StableRobotId},
+        RobotConnections,
+    },
     simulation_loader::SaveSettings,
     theme::CatppuccinTheme,
 };
@@ -194,165 +193,6 @@ fn bind_general_input(mut commands: Commands) {
     ));
 }
 
-fn export_factorgraphs_as_graphviz(
-    query: Query<(Entity, &FactorGraph, &RadioAntenna), With<RobotConnections>>,
-    config: &Config,
-) -> Option<String> {
-    if query.is_empty() {
-        // There are no factorgraph in the scene/world
-        warn!("There are no factorgraphs in the scene/world");
-        return None;
-    }
-
-    // let _external_edge_length = 8.0;
-    // let _internal_edge_length = 1.0;
-    let cluster_margin = 16;
-
-    let mut buf = String::with_capacity(4 * 1024); // 4 kB
-    let mut append_line_to_output = |line: &str| {
-        buf.push_str(line);
-        buf.push('\n');
-    };
-    append_line_to_output("graph {");
-    append_line_to_output("  dpi=96;");
-    append_line_to_output(r#"  label="factorgraph""#);
-    append_line_to_output("  node [style=filled];");
-    append_line_to_output("  layout=neato;");
-
-    // A hashmap used to keep track of which variable in another robots factorgraph,
-    // is connected to a interrobot factor in the current robots factorgraph.
-    let mut all_external_connections =
-        HashMap::<RobotId, HashMap<usize, (RobotId, usize, bool)>>::with_capacity(
-            query.iter().len(),
-        );
-
-    for (robot_id, factorgraph, antenna) in query.iter() {
-        let (nodes, edges) = factorgraph.export_graph();
-
-        // append_line_to_output(&format!(r#"  subgraph "cluster_{:?}" {{"#, robot_id));
-        append_line_to_output(&format!(r#"  subgraph "{:?}" {{"#, robot_id));
-        append_line_to_output(&format!("  margin={}", cluster_margin));
-        append_line_to_output(&format!(r#"  label="{:?}""#, robot_id));
-        // Add all nodes
-        for node in &nodes {
-            // let pos = match node.kind {
-            //     NodeKind::Variable { x, y } => Some((x, y)),
-            //     _ => None,
-            // };
-
-            let label = match node.kind {
-                NodeKind::Variable { .. } => format!("v{}", node.index),
-                NodeKind::InterRobotFactor { .. } => "fr".to_string(),
-                NodeKind::DynamicFactor => "fd".to_string(),
-                NodeKind::ObstacleFactor => "fo".to_string(),
-                NodeKind::TrackingFactor => "ft".to_string(),
-            };
-
-            let line = {
-                let mut line = String::with_capacity(32);
-                line.push_str(&format!(
-                    r#""{:?}_{:?}" [label="{}", fillcolor="{}", shape={}, width="{}""#,
-                    robot_id,
-                    node.index,
-                    label,
-                    // node.index,
-                    node.color(),
-                    node.shape(),
-                    node.width()
-                ));
-                // if let Some((_, _)) = pos {
-                //     // line.push_str(&format!(r#", pos="{x},{y}!""#));
-                //     // line.push_str(&format!(r#", pos="{x}, {y}""#));
-                // }
-                line.push(']');
-                line
-            };
-
-            append_line_to_output(&line);
-
-            // let mut line = String::with_capacity(32);
-            // line.push_str(&format!(
-            //    r#""{:?}_{:?}" [label="{}", fillcolor="{}", shape={},
-            // width="{}""#,    robot_id,
-            //    node.index * 10,
-            //    "fp",
-            //    // node.index,
-            //    "purple",
-            //    // node.color(),
-            //    node.shape(),
-            //    node.width()
-            //));
-        }
-        append_line_to_output("}");
-
-        append_line_to_output("");
-        // Add all internal edges
-        for edge in &edges {
-            let line = format!(
-                r#""{:?}_{:?}" -- "{:?}_{:?}""#,
-                robot_id, edge.from, robot_id, edge.to
-            );
-            append_line_to_output(&line);
-        }
-
-        let external_connections: HashMap<usize, (RobotId, usize, bool)> = nodes
-            .into_iter()
-            .filter_map(|node| match node.kind {
-                NodeKind::InterRobotFactor {
-                    active: _,
-                    external_variable_id,
-                } => Some((
-                    node.index,
-                    (
-                        external_variable_id.factorgraph_id,
-                        external_variable_id.variable_index.index(),
-                        antenna.active,
-                        // connection.id_of_robot_connected_with,
-                        // connection
-                        //     .index_of_connected_variable_in_other_robots_factorgraph
-                        //     .index(),
-                    ),
-                )),
-                _ => None,
-            })
-            .collect();
-
-        all_external_connections.insert(robot_id, external_connections);
-    }
-
-    // Add edges between interrobot factors and the variable they are connected to
-    // in another robots graph
-    for (from_robot_id, from_connections) in all_external_connections {
-        for (from_factor, (to_robot_id, to_variable_index, active)) in from_connections {
-            append_line_to_output(&format!(
-                r#" "{:?}_{:?}" -- "{:?}_{:?}" [len={}, style={}, color="{}", penwidth=3.0]"#,
-                from_robot_id,
-                from_factor,
-                to_robot_id,
-                to_variable_index,
-                if active {
-                    config.graphviz.interrobot.active.len
-                } else {
-                    config.graphviz.interrobot.inactive.len
-                },
-                if active {
-                    &config.graphviz.interrobot.active.style
-                } else {
-                    &config.graphviz.interrobot.inactive.style
-                },
-                if active {
-                    &config.graphviz.interrobot.active.color
-                } else {
-                    &config.graphviz.interrobot.inactive.color
-                }
-            ));
-        }
-    }
-
-    append_line_to_output("}"); // closing '}' for starting "graph {"
-    Some(buf)
-}
-
 fn cycle_theme(
     theme_event_writer: &mut EventWriter<CycleTheme>,
     catppuccin_theme: Res<CatppuccinTheme>,
@@ -371,14 +211,16 @@ fn cycle_theme(
 
 fn export_graph_on_event(
     mut evr_export_factorgraph_as_graphviz: EventReader<ExportFactorGraphAsGraphviz>,
-    query: Query<(Entity, &FactorGraph, &RadioAntenna), With<RobotConnections>>,
+    query: Query<(Entity, &FactorGraph, &RadioAntenna, &StableRobotId), With<RobotConnections>>,
     config: Res<Config>,
+    time: Res<Time<Virtual>>,
     evw_export_graph_finished: EventWriter<ExportFactorGraphAsGraphvizFinished>,
 ) {
     if evr_export_factorgraph_as_graphviz.read().next().is_some() {
         if let Err(e) = handle_export_graph(
             query,
             config.as_ref(),
+            time.elapsed_seconds_f64(),
             evw_export_graph_finished,
             // toast_event,
         ) {
@@ -398,12 +240,14 @@ pub enum ExportFactorGraphAsGraphvizFinished {
 }
 
 fn handle_export_graph(
-    q: Query<(Entity, &FactorGraph, &RadioAntenna), With<RobotConnections>>,
+    q: Query<(Entity, &FactorGraph, &RadioAntenna, &StableRobotId), With<RobotConnections>>,
     config: &Config,
+    t: f64,
     mut export_graph_finished_event: EventWriter<ExportFactorGraphAsGraphvizFinished>,
     // mut toast_event: EventWriter<ToastEvent>,
 ) -> std::io::Result<()> {
-    let Some(output) = export_factorgraphs_as_graphviz(q, config) else {
+    let graphs = FactorGraphs::collect(&q, t);
+    if graphs.is_empty() {
         warn!("There are no factorgraphs in the world");
         // toast_event.send(ToastEvent::warning(
         //     "There are no factorgraphs in the world".to_string(),
@@ -413,26 +257,32 @@ fn handle_export_graph(
         ));
 
         return Ok(());
-    };
+    }
 
-    let dot_output_path = std::path::PathBuf::from("factorgraphs.dot");
-    if dot_output_path.exists() {
-        warn!(
-            "output destination: ./{:#?} already exists!",
-            dot_output_path
-        );
-        warn!("overwriting ./{:#?}", dot_output_path);
+    let output_path = std::path::PathBuf::from("factorgraphs");
+    for format in &config.graphviz.formats {
+        let path = output_path.with_extension(format.extension());
+        if path.exists() {
+            warn!("output destination: ./{:#?} already exists!", path);
+            warn!("overwriting ./{:#?}", path);
+        }
+    }
+    let paths = graphs.write(&output_path, &config.graphviz)?;
+    for path in &paths {
+        info!("exported all factorgraphs to ./{:#?}", path);
     }
-    info!("exporting all factorgraphs to ./{:#?}", dot_output_path);
-    // toast_event.send(ToastEvent::info(format!(
-    //     "exporting all factorgraphs to ./{:#?}",
-    //     dot_output_path
-    // )));
     export_graph_finished_event.send(ExportFactorGraphAsGraphvizFinished::Success(
-        dot_output_path.to_string_lossy().to_string(),
+        paths
+            .iter()
+            .map(|path| path.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(", "),
     ));
 
-    std::fs::write(&dot_output_path, output.as_bytes())?;
+    if !config.graphviz.formats.contains(&FactorGraphFormat::Dot) {
+        return Ok(());
+    }
+    let dot_output_path = output_path.with_extension(FactorGraphFormat::Dot.extension());
 
     IoTaskPool::get()
         .spawn(async move {
@@ -520,7 +370,10 @@ fn quit_application_system(
 fn general_actions_system(
     mut theme_event: EventWriter<CycleTheme>,
     query: Query<&ActionState<GeneralAction>, With<GeneralInputs>>,
-    query_graphs: Query<(Entity, &FactorGraph, &RadioAntenna), With<RobotConnections>>,
+    query_graphs: Query<
+        (Entity, &FactorGraph, &RadioAntenna, &StableRobotId),
+        With<RobotConnections>,
+    >,
     config: Res<Config>,
     currently_changing: Res<ChangingBinding>,
     catppuccin_theme: Res<CatppuccinTheme>,
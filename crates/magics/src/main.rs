@@ -11,6 +11,7 @@ mod input;
 mod moveable_object;
 mod movement;
 pub(crate) mod pause_play;
+mod recorder;
 // mod scene;
 
 pub mod planner;
@@ -127,6 +128,65 @@ fn main() -> anyhow::Result<()> {
         return Ok(());
     }
 
+    if let Some(cli::Command::Report {
+        ref dir,
+        ref group_by,
+        format,
+        ref output,
+    }) = cli.command
+    {
+        let report = export::report::generate(dir, group_by, format)?;
+        match output {
+            Some(output) => std::fs::write(output, report)?,
+            None => print!("{report}"),
+        }
+
+        return Ok(());
+    }
+
+    if let Some(cli::Command::Plot {
+        ref exports,
+        format,
+        width,
+        ref out_dir,
+    }) = cli.command
+    {
+        let simulations_dir = Path::new(simulation_loader::SIMULATIONS_DIR);
+        for path in exports {
+            let svg = export::plot::render(path, simulations_dir, width)?;
+            let bytes = match format {
+                export::plot::PlotFormat::Svg => svg.into_bytes(),
+                export::plot::PlotFormat::Png => export::plot::rasterise(&svg)?,
+            };
+            let output = export::plot::output_path(path, out_dir.as_deref(), format);
+            std::fs::write(&output, bytes)?;
+            eprintln!("wrote {}", output.display());
+        }
+
+        return Ok(());
+    }
+
+    if let Some(ref scenario) = cli.print_resolved {
+        let dir = Path::new(simulation_loader::SIMULATIONS_DIR).join(scenario);
+        let resolved = simulation_loader::ResolvedScenario::load(&dir)?;
+        println!("# config.toml");
+        println!("{}", toml::to_string_pretty(&resolved.config)?);
+        println!("# environment.yaml");
+        println!("{}", serde_yaml::to_string(&resolved.environment)?);
+        println!("# formation.yaml");
+        println!("{}", serde_yaml::to_string(&resolved.formation_group)?);
+        if let Some(task_pool) = resolved.task_pool {
+            println!("# tasks.yaml");
+            println!("{}", serde_yaml::to_string(&task_pool)?);
+        }
+        if let Some(timeline) = resolved.timeline {
+            println!("# timeline.yaml");
+            println!("{}", serde_yaml::to_string(&timeline)?);
+        }
+
+        return Ok(());
+    }
+
     if let Some(ref working_dir) = cli.working_dir {
         std::env::set_current_dir(working_dir).expect("the given --working-dir exists");
         eprintln!("changed working_dir to: {:?}", working_dir);
@@ -210,9 +270,10 @@ fn main() -> anyhow::Result<()> {
             ui::EguiInterfacePlugin,
             planner::PlannerPlugin,
             bevy_notify::NotifyPlugin::default(),
-            export::ExportPlugin::default(),
+            export::ExportPlugin::new(cli.dump_factorgraphs_at.clone()),
             bevy_fullscreen::ToggleFullscreenPlugin::default(),
             goal_area::GoalAreaPlugin,
+            recorder::RecorderPlugin::new(cli.record),
         ))
         .add_systems(Update, draw_coordinate_system.run_if(input_just_pressed(KeyCode::F1)))
         .add_systems(PostUpdate, end_simulation.run_if(virtual_time_exceeds_max_time));
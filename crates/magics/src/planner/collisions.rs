@@ -12,6 +12,7 @@ use self::events::RobotCollisionClickedOn;
 use super::{robot::Ball, RobotConnections};
 use crate::{
     // environment::map_generator::Colliders,
+    environment::{floors::OnFloor, map_generator::FloorColliders},
     simulation_loader::{LoadSimulation, ReloadSimulation},
 };
 
@@ -71,13 +72,14 @@ fn clear_robot_robot_collisions(mut robot_collisions: ResMut<resources::RobotRob
 
 fn update_robot_robot_collisions(
     mut robot_collisions: ResMut<resources::RobotRobotCollisions>,
-    robots: Query<(Entity, &Transform, &Ball), With<RobotConnections>>,
+    robots: Query<(Entity, &Transform, &Ball, &OnFloor), With<RobotConnections>>,
     // PERF: store bounding spheres in a Local<> vec to reuse the allocation between system calls
     mut aabbs: Local<
         Vec<(
             Entity,
             Isometry<f32, Unit<Complex<f32>>, 2>,
             parry2d::bounding_volume::BoundingSphere,
+            usize,
         )>,
     >,
     mut evw_robots_collided: EventWriter<events::RobotRobotCollision>,
@@ -85,10 +87,10 @@ fn update_robot_robot_collisions(
 ) {
     aabbs.clear();
 
-    let iter = robots.iter().map(|(entity, tf, ball)| {
+    let iter = robots.iter().map(|(entity, tf, ball, on_floor)| {
         let position = parry2d::na::Isometry2::translation(tf.translation.x, tf.translation.z); // bevy uses xzy coordinates
         let bounding_volume = ball.bounding_sphere(&position);
-        (entity, position, bounding_volume)
+        (entity, position, bounding_volume, on_floor.0)
         // (entity, ball.aabb(&position))
     });
 
@@ -103,7 +105,8 @@ fn update_robot_robot_collisions(
         seq::upper_triangular_exclude_diagonal(aabbs.len().try_into().expect("more than one robot"))
             .expect("more than one robot")
     {
-        let is_colliding = aabbs[r].2.intersects(&aabbs[c].2);
+        // Robots on different floors can not collide
+        let is_colliding = aabbs[r].3 == aabbs[c].3 && aabbs[r].2.intersects(&aabbs[c].2);
         // aabbs[r].1.intersection()
         let collision_status = robot_collisions.update(aabbs[r].0, aabbs[c].0, is_colliding);
 
@@ -367,7 +370,8 @@ pub mod events {
 
 fn update_robot_environment_collisions(
     env_colliders: Res<Colliders>,
-    robots: Query<(Entity, &Transform, &Ball), With<RobotConnections>>,
+    floor_colliders: Option<Res<FloorColliders>>,
+    robots: Query<(Entity, &Transform, &Ball, &OnFloor), With<RobotConnections>>,
     mut robot_environment_collisions: ResMut<resources::RobotEnvironmentCollisions>,
     // aabbs: Local<
     //     Vec<(
@@ -397,8 +401,17 @@ fn update_robot_environment_collisions(
 
     // check every robot aabb against every environment aabb
 
-    for (robot_id, tf, ball) in &robots {
+    for (robot_id, tf, ball, on_floor) in &robots {
         let robot_pos = parry2d::na::Isometry2::translation(tf.translation.x, tf.translation.z);
+        // Robots only collide with the obstacles of the floor they are on
+        let Some(env_colliders) = floor_colliders
+            .as_ref()
+            .map_or(Some(&*env_colliders), |floor_colliders| {
+                floor_colliders.get(on_floor.0, &env_colliders)
+            })
+        else {
+            continue;
+        };
 
         for env_collider in env_colliders.iter() {
             let is_colliding: bool = parry2d::query::intersection_test(
@@ -0,0 +1,327 @@
+//! Connectivity of the communication graph of the robots over time.
+//!
+//! The communication graph has a node for every robot, and an edge between
+//! two robots whenever one of them is connected with the other through
+//! interrobot factors, see
+//! [`RobotConnections::robots_connected_with`](super::RobotConnections).
+//! It is measured together with the graph of the robots within communication
+//! range of each other, see
+//! [`RobotConnections::robots_within_comms_range`](super::RobotConnections),
+//! which is what the communication graph would be if every antenna was turned
+//! on and every interrobot factor was created.
+//! Every [`ConnectivitySection::every`](gbp_config::ConnectivitySection::every)
+//! fixed timesteps both are measured into the [`ConnectivityLog`], which is
+//! included in the export and plotted in the metrics window.
+
+use std::collections::{BTreeSet, HashMap};
+
+use bevy::prelude::*;
+use gbp_config::Config;
+
+use super::{
+    RobotConnections, RobotId,
+    robot::{RadioAntenna, StableRobotId},
+};
+use crate::{
+    bevy_utils::run_conditions::time::virtual_time_is_paused,
+    simulation_loader::{LoadSimulation, ReloadSimulation},
+};
+
+pub struct ConnectivityPlugin;
+
+impl Plugin for ConnectivityPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ConnectivityLog>()
+            .add_systems(
+                Update,
+                reset_connectivity_log
+                    .run_if(on_event::<LoadSimulation>().or_else(on_event::<ReloadSimulation>())),
+            )
+            .add_systems(
+                FixedUpdate,
+                measure_connectivity.run_if(not(virtual_time_is_paused)),
+            );
+    }
+}
+
+/// The connectivity of a graph of the robots
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct GraphConnectivity {
+    /// Number of pairs of robots connected with each other
+    pub edges: usize,
+    /// Number of connected components, isolated robots included
+    pub components: usize,
+    pub average_degree: f64,
+    /// The second smallest eigenvalue of the Laplacian of the graph, which
+    /// is 0 if and only if the graph is disconnected, and grows the better
+    /// it is connected
+    pub algebraic_connectivity: f64,
+}
+
+/// The connectivity of the communication graph at a point in time
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct ConnectivitySample {
+    /// Seconds since the simulation was loaded
+    pub t: f64,
+    pub robots: usize,
+    /// The graph of the robots connected through interrobot factors
+    pub connected: GraphConnectivity,
+    /// The graph of the robots within communication range of each other
+    pub in_range: GraphConnectivity,
+    /// Number of robots with their antenna turned off
+    pub antennas_off: usize,
+}
+
+impl ConnectivitySample {
+    /// Measure the communication graph of `robots`, given as the antenna
+    /// state of every robot and its connections
+    pub fn measure<'a>(
+        t: f64,
+        robots: impl IntoIterator<Item = (RobotId, bool, &'a RobotConnections)>,
+    ) -> Self {
+        let robots: Vec<_> = robots.into_iter().collect();
+        let n = robots.len();
+        let index: HashMap<RobotId, usize> = robots
+            .iter()
+            .enumerate()
+            .map(|(i, (robot, _, _))| (*robot, i))
+            .collect();
+
+        // Neither graph is always mutual, e.g. connections while one of the
+        // robots has its antenna turned off, or ranges of robots with
+        // different communication radii, so both are made undirected
+        let edges = |neighbours: fn(&RobotConnections) -> &BTreeSet<RobotId>| {
+            robots
+                .iter()
+                .enumerate()
+                .flat_map(|(i, (_, _, connections))| {
+                    neighbours(connections)
+                        .iter()
+                        .filter_map(|other| index.get(other))
+                        .filter(move |&&j| j != i)
+                        .map(move |&j| (i.min(j), i.max(j)))
+                })
+                .collect::<BTreeSet<(usize, usize)>>()
+        };
+
+        Self {
+            t,
+            robots: n,
+            connected: GraphConnectivity::measure(
+                n,
+                &edges(|connections| &connections.robots_connected_with),
+            ),
+            in_range: GraphConnectivity::measure(
+                n,
+                &edges(|connections| &connections.robots_within_comms_range),
+            ),
+            antennas_off: robots.iter().filter(|(_, active, _)| !active).count(),
+        }
+    }
+}
+
+impl GraphConnectivity {
+    /// Measure the undirected graph with `n` nodes and `edges`
+    #[allow(clippy::cast_precision_loss)]
+    fn measure(n: usize, edges: &BTreeSet<(usize, usize)>) -> Self {
+        let mut laplacian = parry2d::na::DMatrix::<f64>::zeros(n, n);
+        for &(i, j) in edges {
+            laplacian[(i, j)] -= 1.0;
+            laplacian[(j, i)] -= 1.0;
+            laplacian[(i, i)] += 1.0;
+            laplacian[(j, j)] += 1.0;
+        }
+        let algebraic_connectivity = if n < 2 {
+            0.0
+        } else {
+            let mut eigenvalues: Vec<f64> =
+                laplacian.symmetric_eigenvalues().iter().copied().collect();
+            eigenvalues.sort_by(f64::total_cmp);
+            // Rounding errors can make it slightly negative for a
+            // disconnected graph
+            eigenvalues[1].max(0.0)
+        };
+
+        Self {
+            edges: edges.len(),
+            components: components(n, edges),
+            average_degree: if n == 0 {
+                0.0
+            } else {
+                2.0 * edges.len() as f64 / n as f64
+            },
+            algebraic_connectivity,
+        }
+    }
+}
+
+/// Number of connected components of the graph with `n` nodes and `edges`
+fn components(n: usize, edges: &BTreeSet<(usize, usize)>) -> usize {
+    fn root(parents: &mut [usize], mut node: usize) -> usize {
+        while parents[node] != node {
+            parents[node] = parents[parents[node]];
+            node = parents[node];
+        }
+        node
+    }
+
+    let mut parents: Vec<usize> = (0..n).collect();
+    let mut components = n;
+    for &(i, j) in edges {
+        let (a, b) = (root(&mut parents, i), root(&mut parents, j));
+        if a != b {
+            parents[a] = b;
+            components -= 1;
+        }
+    }
+    components
+}
+
+/// **Bevy** [`Resource`] with the connectivity of the communication graph
+/// since the simulation was loaded, included in the export. Every sample is
+/// kept for the export, while the metrics window only plots the latest
+#[derive(Debug, Default, Resource)]
+pub struct ConnectivityLog {
+    loaded_at: f64,
+    samples: Vec<ConnectivitySample>,
+    /// Fixed timesteps since the last sample
+    steps: usize,
+}
+
+impl ConnectivityLog {
+    /// Every sample, in the order they were measured
+    pub fn samples(&self) -> &[ConnectivitySample] {
+        &self.samples
+    }
+
+    /// The latest `n` samples, or every sample if there are fewer
+    pub fn latest(&self, n: usize) -> &[ConnectivitySample] {
+        &self.samples[self.samples.len().saturating_sub(n)..]
+    }
+}
+
+fn reset_connectivity_log(mut log: ResMut<ConnectivityLog>, time: Res<Time<Virtual>>) {
+    *log = ConnectivityLog {
+        loaded_at: time.elapsed_seconds_f64(),
+        ..default()
+    };
+}
+
+/// **Bevy** system that measures the communication graph every
+/// [`ConnectivitySection::every`](gbp_config::ConnectivitySection::every)
+/// fixed timesteps
+fn measure_connectivity(
+    mut log: ResMut<ConnectivityLog>,
+    q_robots: Query<(Entity, &StableRobotId, &RadioAntenna, &RobotConnections)>,
+    config: Res<Config>,
+    time: Res<Time<Virtual>>,
+) {
+    log.steps += 1;
+    if log.steps < config.export.connectivity.every.get() {
+        return;
+    }
+    log.steps = 0;
+
+    // Ordered by stable id, so the graph is the same from run to run
+    let mut robots: Vec<_> = q_robots.iter().collect();
+    robots.sort_by_key(|(_, robot_id, _, _)| **robot_id);
+
+    let t = time.elapsed_seconds_f64() - log.loaded_at;
+    let sample = ConnectivitySample::measure(
+        t,
+        robots
+            .into_iter()
+            .map(|(entity, _, antenna, connections)| (entity, antenna.active, connections)),
+    );
+    log.samples.push(sample);
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn robots(n: u32) -> Vec<RobotId> {
+        (0..n).map(Entity::from_raw).collect()
+    }
+
+    /// Connections of robots within range of every other robot
+    fn connected_with(ids: &[RobotId], connected_with: &[RobotId]) -> RobotConnections {
+        RobotConnections {
+            robots_within_comms_range: ids.iter().copied().collect(),
+            robots_connected_with: connected_with.iter().copied().collect(),
+        }
+    }
+
+    #[test]
+    fn path_of_three_robots() {
+        let ids = robots(3);
+        let connections = [
+            connected_with(&ids, &[ids[1]]),
+            connected_with(&ids, &[ids[0], ids[2]]),
+            connected_with(&ids, &[ids[1]]),
+        ];
+        let sample = ConnectivitySample::measure(
+            1.0,
+            ids.iter()
+                .zip(&connections)
+                .map(|(&id, connections)| (id, true, connections)),
+        );
+
+        assert_eq!(sample.connected.edges, 2);
+        assert_eq!(sample.connected.components, 1);
+        assert_relative_eq!(sample.connected.average_degree, 4.0 / 3.0);
+        // The Laplacian of a path of 3 nodes has eigenvalues 0, 1 and 3
+        assert_relative_eq!(sample.connected.algebraic_connectivity, 1.0, epsilon = 1e-9);
+        assert_eq!(sample.antennas_off, 0);
+    }
+
+    #[test]
+    fn one_sided_connections_are_edges_and_isolated_robots_are_components() {
+        let ids = robots(3);
+        let connections = [
+            connected_with(&ids, &[ids[1]]),
+            connected_with(&ids, &[]),
+            connected_with(&ids, &[]),
+        ];
+        let sample = ConnectivitySample::measure(
+            1.0,
+            ids.iter()
+                .zip(&connections)
+                .zip([true, false, true])
+                .map(|((&id, connections), active)| (id, active, connections)),
+        );
+
+        assert_eq!(sample.connected.edges, 1);
+        assert_eq!(sample.connected.components, 2);
+        assert_relative_eq!(sample.connected.algebraic_connectivity, 0.0, epsilon = 1e-9);
+        assert_eq!(sample.antennas_off, 1);
+    }
+
+    #[test]
+    fn robots_in_range_form_their_own_graph() {
+        let ids = robots(3);
+        let connections = [
+            connected_with(&ids, &[]),
+            connected_with(&ids, &[]),
+            connected_with(&ids, &[]),
+        ];
+        let sample = ConnectivitySample::measure(
+            1.0,
+            ids.iter()
+                .zip(&connections)
+                .map(|(&id, connections)| (id, false, connections)),
+        );
+
+        assert_eq!(sample.connected.edges, 0);
+        assert_eq!(sample.connected.components, 3);
+        // Every robot is within range of every other robot
+        assert_eq!(sample.in_range.edges, 3);
+        assert_eq!(sample.in_range.components, 1);
+        // The Laplacian of a complete graph of 3 nodes has eigenvalues 0, 3
+        // and 3
+        assert_relative_eq!(sample.in_range.algebraic_connectivity, 3.0, epsilon = 1e-9);
+    }
+}
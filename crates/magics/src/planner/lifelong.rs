@@ -0,0 +1,290 @@
+//! Lifelong operation, where robots are given a new goal every time they
+//! reach their last one, instead of finishing.
+//!
+//! The goals come from the [`GoalGenerator`] of the formation the robot was
+//! spawned by. Every reached goal is recorded in the [`LifelongLog`], from
+//! which the throughput and the latency of the goals are reported.
+
+use bevy::prelude::*;
+use bevy_prng::WyRand;
+use bevy_rand::component::EntropyComponent;
+use gbp_config::{
+    Config,
+    formation::{GoalGenerator, WorldDimensions},
+    geometry::Point,
+};
+use gbp_environment::Environment;
+use rand::Rng;
+
+use super::{
+    robot::{
+        FinishedPath, Mission, RobotDespawned, RobotFinishedRoute, StableRobotId, StateVector,
+        TargetSpeed, reached_waypoint,
+    },
+    spawner::{WaypointCreated, world_dimensions},
+};
+use crate::{
+    bevy_utils::run_conditions::time::virtual_time_is_paused,
+    environment::floors::OnFloor,
+    simulation_loader::{FloorSdfs, LoadSimulation, ReloadSimulation, Sdf},
+};
+
+pub struct LifelongPlugin;
+
+impl Plugin for LifelongPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LifelongGoalReached>()
+            .init_resource::<LifelongLog>()
+            .add_systems(
+                Update,
+                reset_lifelong_log
+                    .run_if(on_event::<LoadSimulation>().or_else(on_event::<ReloadSimulation>())),
+            )
+            .add_systems(
+                FixedUpdate,
+                assign_lifelong_goals
+                    .after(reached_waypoint)
+                    .run_if(not(virtual_time_is_paused)),
+            );
+    }
+}
+
+/// **Bevy** [`Component`] attached to robots in lifelong operation
+#[derive(Debug, Component)]
+pub struct LifelongGoals {
+    generator: GoalGenerator,
+    /// Index of the next goal, for the stations and scripted goals
+    next: usize,
+    /// Seconds since the simulation was loaded, at which the robot was given
+    /// its current goal. `None` until the robot has been seen the first time.
+    issued_at: Option<f32>,
+    /// Whether the reached goal has been recorded, and the robot is waiting
+    /// for a new one
+    recorded: bool,
+    /// Whether the robot has run out of goals
+    finished: bool,
+}
+
+impl LifelongGoals {
+    /// Create the goals of robot number `robot` of a formation. With
+    /// [`GoalGenerator::Stations`] each robot starts at a different station.
+    pub fn new(generator: GoalGenerator, robot: usize) -> Self {
+        let next = match &generator {
+            GoalGenerator::Stations(stations) => robot % stations.len(),
+            GoalGenerator::Random | GoalGenerator::Script(_) => 0,
+        };
+        Self {
+            generator,
+            next,
+            issued_at: None,
+            recorded: false,
+            finished: false,
+        }
+    }
+}
+
+/// **Bevy** [`Event`] sent when a robot in lifelong operation has reached a
+/// goal
+#[derive(Debug, Clone, Copy, Event)]
+pub struct LifelongGoalReached {
+    pub robot: Entity,
+    /// Seconds from when the robot was given the goal until it was reached
+    pub latency: f32,
+}
+
+/// A goal reached by a robot in lifelong operation, in seconds since the
+/// simulation was loaded
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct GoalRecord {
+    pub robot: StableRobotId,
+    pub position: [f32; 2],
+    pub issued_at: f32,
+    pub reached_at: f32,
+    pub latency: f32,
+}
+
+/// **Bevy** [`Resource`] recording every goal reached in lifelong operation
+/// since the simulation was loaded, included in the export
+#[derive(Debug, Default, Resource)]
+pub struct LifelongLog {
+    loaded_at: f32,
+    goals: Vec<GoalRecord>,
+}
+
+impl LifelongLog {
+    /// Every goal reached, in the order they were reached
+    pub fn goals(&self) -> &[GoalRecord] {
+        &self.goals
+    }
+
+    /// Number of goals reached per minute since the simulation was loaded
+    #[allow(clippy::cast_precision_loss)]
+    pub fn goals_per_minute(&self, time: &Time<Virtual>) -> f32 {
+        let minutes = (time.elapsed_seconds() - self.loaded_at) / 60.0;
+        if minutes > 0.0 {
+            self.goals.len() as f32 / minutes
+        } else {
+            0.0
+        }
+    }
+
+    /// Mean latency of the goals reached, `None` if no goal has been reached
+    #[allow(clippy::cast_precision_loss)]
+    pub fn mean_latency(&self) -> Option<f32> {
+        (!self.goals.is_empty()).then(|| {
+            self.goals.iter().map(|goal| goal.latency).sum::<f32>() / self.goals.len() as f32
+        })
+    }
+}
+
+fn reset_lifelong_log(mut lifelong_log: ResMut<LifelongLog>, time: Res<Time<Virtual>>) {
+    *lifelong_log = LifelongLog {
+        loaded_at: time.elapsed_seconds(),
+        goals: Vec::new(),
+    };
+}
+
+/// Sample a random position in the free space of `sdf`, where it is white
+fn sample_free_position(
+    sdf: &dyn env_to_png::SdfSampler,
+    world_dims: WorldDimensions,
+    rng: &mut impl Rng,
+) -> Option<Vec2> {
+    const ATTEMPTS: usize = 1000;
+    (0..ATTEMPTS).find_map(|_| {
+        let point = Point::new(rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0));
+        // the y axis is flipped in the image, like in the obstacle factor
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let pixel = sdf.get_pixel_checked(
+            (point.x * f64::from(sdf.width())) as u32,
+            ((1.0 - point.y) * f64::from(sdf.height())) as u32,
+        )?;
+        (pixel[0] == u8::MAX).then(|| world_dims.point_to_world_position(point))
+    })
+}
+
+/// **Bevy** system that records the goals reached by robots in lifelong
+/// operation, and gives them their next goal
+#[allow(clippy::too_many_arguments)]
+fn assign_lifelong_goals(
+    mut evw_lifelong_goal_reached: EventWriter<LifelongGoalReached>,
+    mut evw_waypoint_created: EventWriter<WaypointCreated>,
+    mut evw_robot_finished_route: EventWriter<RobotFinishedRoute>,
+    mut evw_robot_despawned: EventWriter<RobotDespawned>,
+    mut robots: Query<(
+        Entity,
+        &mut LifelongGoals,
+        &mut Mission,
+        &mut FinishedPath,
+        &mut EntropyComponent<WyRand>,
+        &Transform,
+        Option<&OnFloor>,
+        Option<&TargetSpeed>,
+        &StableRobotId,
+    )>,
+    mut lifelong_log: ResMut<LifelongLog>,
+    env_config: Res<Environment>,
+    sdf: Res<Sdf>,
+    floor_sdfs: Res<FloorSdfs>,
+    config: Res<Config>,
+    time_virtual: Res<Time<Virtual>>,
+    time: Res<Time>,
+) {
+    let now = time_virtual.elapsed_seconds() - lifelong_log.loaded_at;
+    let world_dims = world_dimensions(&env_config);
+
+    for (
+        robot,
+        mut goals,
+        mut mission,
+        mut finished_path,
+        mut prng,
+        transform,
+        on_floor,
+        target_speed,
+        &stable_id,
+    ) in &mut robots
+    {
+        if goals.finished {
+            continue;
+        }
+        let issued_at = *goals.issued_at.get_or_insert(now);
+        if !mission.is_completed() {
+            continue;
+        }
+
+        if !goals.recorded {
+            goals.recorded = true;
+            let latency = now - issued_at;
+            let position = mission
+                .taskpoints
+                .last()
+                .map_or(Vec2::ZERO, StateVector::position);
+            lifelong_log.goals.push(GoalRecord {
+                robot: stable_id,
+                position: position.to_array(),
+                issued_at,
+                reached_at: now,
+                latency,
+            });
+            evw_lifelong_goal_reached.send(LifelongGoalReached { robot, latency });
+        }
+
+        let floor = on_floor.map_or(0, |on_floor| on_floor.0);
+        let goal = match &goals.generator {
+            GoalGenerator::Random => {
+                let Some(sdf) = floor_sdfs.get(floor, &sdf) else {
+                    error!("no SDF for floor {} of robot {:?}", floor, robot);
+                    continue;
+                };
+                sample_free_position(sdf.as_ref(), world_dims, &mut *prng)
+            }
+            GoalGenerator::Stations(stations) => Some(
+                world_dims
+                    .point_to_world_position(stations.as_slice()[goals.next % stations.len()]),
+            ),
+            GoalGenerator::Script(script) => script
+                .as_slice()
+                .get(goals.next)
+                .map(|&point| world_dims.point_to_world_position(point)),
+        };
+
+        let Some(goal) = goal else {
+            if matches!(goals.generator, GoalGenerator::Script(_)) {
+                info!("robot {:?} reached the last goal of its script", robot);
+                goals.finished = true;
+                evw_robot_finished_route.send(RobotFinishedRoute(robot));
+                if config.simulation.despawn_robot_when_final_waypoint_reached {
+                    evw_robot_despawned.send(RobotDespawned(robot));
+                }
+            } else {
+                warn!(
+                    "found no free position for the next goal of robot {:?}",
+                    robot
+                );
+            }
+            continue;
+        };
+
+        let position = transform.translation.xz();
+        let velocity =
+            (goal - position).normalize_or_zero() * TargetSpeed::of(target_speed, &config);
+        mission.append_taskpoints(
+            [StateVector::from(Vec4::new(
+                goal.x, goal.y, velocity.x, velocity.y,
+            ))],
+            position,
+            &time,
+        );
+        finished_path.0 = false;
+        goals.next += 1;
+        goals.issued_at = Some(now);
+        goals.recorded = false;
+
+        debug!("gave robot {:?} a new goal at {:?}", robot, goal);
+        evw_waypoint_created.send(WaypointCreated {
+            for_robot: robot,
+            position: goal,
+        });
+    }
+}
@@ -1,6 +1,10 @@
 pub mod collisions;
+pub mod connectivity;
+pub mod lifelong;
 pub mod robot;
 pub mod spawner;
+pub mod tasks;
+pub mod timeline;
 pub mod tracking;
 mod visualiser;
 
@@ -21,7 +25,11 @@ impl Plugin for PlannerPlugin {
             RobotSpawnerPlugin,
             VisualiserPlugin,
             collisions::RobotCollisionsPlugin,
+            connectivity::ConnectivityPlugin,
             tracking::TrackingPlugin,
+            tasks::TaskAllocationPlugin,
+            lifelong::LifelongPlugin,
+            timeline::TimelinePlugin,
         ));
     }
 }
@@ -1,6 +1,7 @@
 use std::{
     collections::{BTreeSet, HashMap},
     num::NonZeroUsize,
+    sync::Arc,
     time::Duration,
 };
 
@@ -12,7 +13,7 @@ use bevy::{
 use bevy_prng::WyRand;
 use bevy_rand::{component::EntropyComponent, prelude::GlobalEntropy};
 use gbp_config::{
-    Config,
+    Config, RobotClass,
     formation::{CheckIntersectionWith, IntersectionDistance, PlanningStrategy, ReachedWhen},
 };
 use gbp_global_planner::PathfindingTask;
@@ -23,14 +24,20 @@ use rand::Rng;
 
 use super::{
     collisions::resources::{RobotEnvironmentCollisions, RobotRobotCollisions},
+    lifelong::LifelongGoals,
     spawner::RobotClickedOn,
+    tasks::TaskAgent,
 };
 use crate::{
     bevy_utils::run_conditions::time::virtual_time_is_paused,
+    environment::{
+        floors::OnFloor,
+        map_generator::{FloorColliders, KeepOutColliders},
+    },
     export::events::TakeSnapshotOfRobot,
     factorgraph::{
         DOFS,
-        factor::{ExternalVariableId, FactorNode},
+        factor::{ExternalVariableId, FactorNode, region::RegionFactor},
         factorgraph::{FactorGraph, NodeIndex, VariableIndex},
         id::{FactorId, VariableId},
         message::VariableToFactorMessage,
@@ -322,6 +329,87 @@ pub struct RobotReachedWaypoint {
 #[derive(Component, Debug, Deref, DerefMut)]
 pub struct Radius(pub f32);
 
+/// Component for robots with a target speed of their own, instead of
+/// `config.robot.target_speed`
+/// SI unit: m/s
+#[derive(Component, Debug, Clone, Copy, Deref)]
+pub struct TargetSpeed(pub f32);
+
+impl TargetSpeed {
+    /// The target speed of a robot, that may not have a [`TargetSpeed`] of its
+    /// own
+    #[inline]
+    #[must_use]
+    pub fn of(target_speed: Option<&Self>, config: &Config) -> f32 {
+        target_speed.map_or_else(|| config.robot.target_speed.get(), |speed| speed.0)
+    }
+}
+
+/// Component for robots of a named robot class, with settings of its own in
+/// place of the global ones
+#[derive(Component, Debug, Clone)]
+pub struct OfClass(pub String);
+
+impl OfClass {
+    /// The class of a robot, that may not be of any class
+    #[inline]
+    #[must_use]
+    pub fn of<'a>(of_class: Option<&Self>, config: &'a Config) -> Option<&'a RobotClass> {
+        of_class.and_then(|of_class| config.robot.classes.get(&of_class.0))
+    }
+}
+
+/// Component with the index in `formation.yaml` of the formation that spawned
+/// the robot
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Deref)]
+pub struct FormationIndex(pub usize);
+
+/// Component with an identifier of a robot that is the same in every run of
+/// a scenario, unlike its [`Entity`]. It is made of the index in
+/// `formation.yaml` of the formation that spawned the robot, the wave of the
+/// formation it was spawned in, counting from 0, and the index of the robot
+/// within the formation. Displayed and serialized as `f<formation>-w<wave>-r<index>`.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StableRobotId {
+    pub formation: usize,
+    pub wave: usize,
+    pub index: usize,
+}
+
+impl std::fmt::Display for StableRobotId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "f{}-w{}-r{}", self.formation, self.wave, self.index)
+    }
+}
+
+impl serde::Serialize for StableRobotId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Component with the priority of a robot, given by its formation or class.
+/// Robots of lower priority yield more to robots of higher priority, see
+/// `gbp.priority-scaling` in the config.
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deref)]
+pub struct Priority(pub u8);
+
+/// Component for robots keeping a formation, with the teammate the robot
+/// keeps its place relative to. Relative pose factors are created towards the
+/// teammate, alongside the interrobot factors, whenever the two robots are
+/// within communication range.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Teammate {
+    pub robot: RobotId,
+    /// The target offset from the teammate, in its frame where `x` points in
+    /// the direction it is heading
+    pub offset: Vec2,
+    /// How far the robot can drift from the target offset, 0 if the
+    /// formation is rigid
+    pub slack: f32,
+    pub stiffness: f32,
+}
+
 /// Represents a robotic route consisting of several waypoints that define
 /// positions and velocities the robot should achieve as it progresses along the
 /// path.
@@ -489,12 +577,19 @@ pub struct RadioAntenna {
     pub radius: f32,
     /// Whether the antenna is currently active
     pub active: bool,
+    /// Whether the antenna has been knocked out, in which case it stays
+    /// inactive until restored
+    pub knocked_out: bool,
 }
 
 impl RadioAntenna {
     /// Creates a new radio antenna.
     pub fn new(radius: f32, active: bool) -> Self {
-        Self { radius, active }
+        Self {
+            radius,
+            active,
+            knocked_out: false,
+        }
     }
 
     /// Toggle the state of the antenna between on and off
@@ -558,20 +653,56 @@ impl FromWorld for GbpIterationSchedule {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn progress_missions(
     mut commands: Commands,
-    mut q: Query<(Entity, &mut Mission, &PlanningStrategy)>,
+    mut q: Query<(
+        Entity,
+        &mut Mission,
+        &PlanningStrategy,
+        Option<&OnFloor>,
+        Option<&TargetSpeed>,
+        Option<&OfClass>,
+    )>,
     mut pathfinders: Query<(Entity, &mut EntropyComponent<WyRand>), Without<PathfindingTask>>,
     mut tasks: Query<&mut PathfindingTask>,
     mut factorgraphs: Query<(&mut FactorGraph, &VariableTimesteps)>,
     config: Res<Config>,
     time: Res<Time>,
     colliders: Res<gbp_global_planner::Colliders>,
+    keep_out_colliders: Option<Res<KeepOutColliders>>,
+    floor_colliders: Option<Res<FloorColliders>>,
 ) {
-    for (robot_entity, mut mission, plannning_strategy) in &mut q {
+    for (robot_entity, mut mission, plannning_strategy, on_floor, target_speed, of_class) in &mut q
+    {
+        let target_speed = TargetSpeed::of(target_speed, &config);
+        let planning_horizon = OfClass::of(of_class, &config)
+            .and_then(|class| class.planning_horizon)
+            .unwrap_or(config.robot.planning_horizon)
+            .get();
         match (mission.state, plannning_strategy) {
             (MissionState::Idle { .. }, PlanningStrategy::OnlyLocal) => {
-                // no need to do anything
+                // no planning needed, but if taskpoints have been appended to a completed
+                // mission, the tracking factors have to follow the new route
+                if mission.take_resumed() {
+                    if let (Some(route), Ok((mut fgraph, _))) =
+                        (mission.active_route(), factorgraphs.get_mut(robot_entity))
+                    {
+                        let waypoints = route
+                            .waypoints()
+                            .iter()
+                            .map(StateVector::position)
+                            .collect_vec();
+                        let index = route.current_waypoint_index().unwrap_or(1);
+                        if let Ok(waypoints) = min_len_vec::TwoOrMore::new(waypoints) {
+                            fgraph.modify_tracking_factors(|tracking| {
+                                tracking.set_tracking_path(waypoints.clone());
+                                tracking.set_tracking_index(index);
+                            });
+                            fgraph.reset_tracking_factors();
+                        }
+                    }
+                }
                 mission.state = MissionState::Active;
             }
             (
@@ -611,12 +742,33 @@ fn progress_missions(
                     );
 
                     // dbg!(&colliders);
+                    let floor = on_floor.map_or(0, |on_floor| on_floor.0);
+                    let Some(mut colliders) = floor_colliders
+                        .as_ref()
+                        .map_or(Some(&*colliders), |floor_colliders| {
+                            floor_colliders.get(floor, &colliders)
+                        })
+                        .cloned()
+                    else {
+                        error!(
+                            "no colliders for floor {} of robot {:?}",
+                            floor, robot_entity
+                        );
+                        continue;
+                    };
+                    // Impassable keep-out regions are obstacles for the global planner, they
+                    // only exist on the ground floor
+                    if let Some(keep_out_colliders) =
+                        keep_out_colliders.as_ref().filter(|_| floor == 0)
+                    {
+                        colliders.extend_from(keep_out_colliders);
+                    }
                     gbp_global_planner::rrtstar::spawn_pathfinding_task(
                         &mut commands,
                         start,
                         end,
                         config.rrt.clone(),
-                        colliders.clone(),
+                        colliders,
                         pathfinder,
                         Some(Box::new(prng.clone())),
                     );
@@ -659,7 +811,7 @@ fn progress_missions(
                                             dir = Vec2::ZERO;
                                         }
 
-                                        let vel = config.robot.target_speed.get() * dir;
+                                        let vel = target_speed * dir;
                                         Vec4::new(from.x, from.y, vel.x, vel.y)
                                     })
                                     .map_into()
@@ -742,9 +894,7 @@ fn progress_missions(
                                     // part be the normalized direction times max_speed
                                     // let next = next.length() * 0.8 * dir_normalized;
                                     let next = {
-                                        let l = (config.robot.target_speed
-                                            * config.robot.planning_horizon)
-                                            .get();
+                                        let l = target_speed * planning_horizon;
                                         let max = dir.length() * 0.9;
                                         let s = if l < max { l } else { max };
                                         start + s * dir_normalized
@@ -754,8 +904,7 @@ fn progress_missions(
                                         .map(|i| i as f32 / n as f32)
                                         .map(|r| {
                                             let pos = start.xy().lerp(next.xy(), r);
-                                            let vel =
-                                                config.robot.target_speed.get() * dir_normalized;
+                                            let vel = target_speed * dir_normalized;
                                             Vec4::new(pos.x, pos.y, vel.x, vel.y)
                                         })
                                         .map(|it| it.as_dvec4().to_array())
@@ -805,7 +954,7 @@ fn progress_missions(
                     mission.next_route(&time);
                 }
             }
-            (MissionState::Completed, _) => {}
+            (MissionState::InTransit | MissionState::Completed, _) => {}
         }
     }
 }
@@ -821,6 +970,9 @@ pub struct Mission {
     pub state: MissionState,
     finished_when_intersects: ReachedWhen,
     taskpoint_reached_when_intersects: ReachedWhen,
+    /// Whether the mission has been resumed by appending taskpoints to it after
+    /// it was completed, and has not been started again since
+    resumed: bool,
 }
 
 // impl std::fmt::Display for RobotMission {
@@ -853,6 +1005,7 @@ impl Mission {
             state: MissionState::Active,
             finished_when_intersects,
             taskpoint_reached_when_intersects: waypoint_reached_when_intersects,
+            resumed: false,
         }
 
         // Self::new(waypoints, started_at, RobotMissionState::Active)
@@ -903,6 +1056,7 @@ impl Mission {
             state,
             finished_when_intersects,
             taskpoint_reached_when_intersects: waypoint_reached_when_intersects,
+            resumed: false,
         }
     }
 
@@ -916,6 +1070,20 @@ impl Mission {
         self.finished_at
     }
 
+    /// How much longer the mission has taken than driving straight between
+    /// its taskpoints at `target_speed` would, in seconds. Missions that are
+    /// not finished are measured up until `now`.
+    pub fn delay(&self, target_speed: f32, now: f64) -> f64 {
+        let duration = self.finished_at.unwrap_or(now) - self.started_at;
+        let length: f32 = self
+            .taskpoints
+            .iter()
+            .tuple_windows()
+            .map(|(from, to)| from.position().distance(to.position()))
+            .sum();
+        (duration - f64::from(length / target_speed)).max(0.0)
+    }
+
     pub fn is_completed(&self) -> bool {
         self.state == MissionState::Completed
     }
@@ -1001,21 +1169,103 @@ impl Mission {
         }
     }
 
+    /// Position the robot is heading for. While idle or in transit this is
+    /// the end of the active route.
+    pub fn target(&self) -> Option<Vec2> {
+        match self.state {
+            MissionState::Active => self.next_waypoint().map(StateVector::position),
+            MissionState::Idle { .. } | MissionState::InTransit => self
+                .taskpoints
+                .get(self.active_route + 1)
+                .map(StateVector::position),
+            MissionState::Completed => None,
+        }
+    }
+
+    /// Continue the mission after arriving at the exit of a transfer, which
+    /// counts as reaching the waypoint at the exit
+    pub fn complete_transfer(&mut self, time: &Time) {
+        if matches!(self.state, MissionState::InTransit) {
+            self.state = MissionState::Active;
+            self.advance_to_next_waypoint(time);
+        }
+    }
+
     pub fn waypoints(&self) -> impl Iterator<Item = &StateVector> + '_ {
         self.routes.iter().flat_map(|r| r.waypoints())
     }
+
+    /// Number of taskpoints reached so far, including the one the mission
+    /// started at
+    pub fn reached_taskpoints(&self) -> usize {
+        self.active_route + 1
+    }
+
+    /// Extend the mission with `taskpoints`, and return the index of the first
+    /// of them. If the mission has been completed, it is resumed from
+    /// `position` towards the first of the new taskpoints.
+    pub fn append_taskpoints(
+        &mut self,
+        taskpoints: impl IntoIterator<Item = StateVector>,
+        position: Vec2,
+        time: &Time,
+    ) -> usize {
+        let first = self.taskpoints.len();
+        self.taskpoints.extend(taskpoints);
+        if self.is_completed() && self.taskpoints.len() > first {
+            self.taskpoints[self.active_route].update_position(position);
+            let waypoints = self.taskpoints[self.active_route..=self.active_route + 1].to_vec();
+            self.routes.push(Route::new(
+                waypoints
+                    .try_into()
+                    .expect("a route from one taskpoint to the next"),
+                time.elapsed_seconds_f64(),
+            ));
+            self.finished_at = None;
+            self.state = MissionState::Idle {
+                waiting_for_waypoints: false,
+            };
+            self.resumed = true;
+        }
+        first
+    }
+
+    /// Returns true if the mission has been resumed by
+    /// [`Mission::append_taskpoints`] since it was last started, and clears it
+    pub fn take_resumed(&mut self) -> bool {
+        std::mem::take(&mut self.resumed)
+    }
+
+    /// Plan a new path for the active route, starting from `position`, e.g.
+    /// because the obstacles of the environment have changed. Only has an
+    /// effect if the mission is active, as idle missions are about to plan
+    /// anyway.
+    pub fn replan_from(&mut self, position: Vec2) {
+        if matches!(self.state, MissionState::Active) {
+            self.taskpoints[self.active_route].update_position(position);
+            self.state = MissionState::Idle {
+                waiting_for_waypoints: false,
+            };
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MissionState {
-    Idle { waiting_for_waypoints: bool },
+    Idle {
+        waiting_for_waypoints: bool,
+    },
     Active,
+    /// Moving between two floors, see [`crate::environment::floors`]
+    InTransit,
     Completed,
 }
 
 impl MissionState {
+    /// Whether the robot is standing still, either waiting for its next route,
+    /// or in transit between two floors
     pub fn idle(&self) -> bool {
-        matches!(self, MissionState::Idle { .. })
+        matches!(self, MissionState::Idle { .. } | MissionState::InTransit)
     }
 }
 
@@ -1054,6 +1304,8 @@ pub struct RobotBundle {
     /// TODO: should probably be modelled as an enum instead, too easier support
     /// additional states in the future
     finished_path: FinishedPath,
+    /// Whether the robot has been told to stand still
+    pub stopped: Stopped,
 
     pub mission: Mission,
     // / Criteria determining when the robot is considered to have reached a
@@ -1137,6 +1389,7 @@ impl RobotBundle {
         config: &Config,
         env_config: &gbp_environment::Environment,
         radius: f32,
+        target_speed: f32,
         sdf: SharedSdfImage,
         started_at: f64,
         waypoints: min_len_vec::TwoOrMore<StateVector>,
@@ -1161,7 +1414,7 @@ impl RobotBundle {
         let horizon = start
             + f32::min(
                 start2goal.length(),
-                (config.robot.planning_horizon * config.robot.target_speed).get(),
+                config.robot.planning_horizon.get() * target_speed,
             ) * start2goal.normalize();
 
         let mut factorgraph = FactorGraph::new(robot_id);
@@ -1218,7 +1471,7 @@ impl RobotBundle {
             variable_node_indices.push(variable_index);
         }
 
-        let t0 = radius / 2.0 / config.robot.target_speed.get();
+        let t0 = radius / 2.0 / target_speed;
 
         // Create Dynamic factors between variables
         for i in 0..variable_timesteps.len() - 1 {
@@ -1280,6 +1533,32 @@ impl RobotBundle {
             );
         }
 
+        // Create Region factors for all variables excluding start and
+        // horizon state, if the environment has any regions they apply to
+        let regions: Arc<[gbp_environment::WorldRegion]> = env_config
+            .world_regions()
+            .into_iter()
+            .filter(RegionFactor::applies_to)
+            .collect();
+        if !regions.is_empty() {
+            for &variable_index in &variable_node_indices[1..variable_node_indices.len() - 1] {
+                let region_factor = FactorNode::new_region_factor(
+                    factorgraph.id(),
+                    Float::from(config.gbp.sigma_factor_region),
+                    array![0.0],
+                    Arc::clone(&regions),
+                    config.gbp.factors_enabled.region,
+                );
+
+                let factor_node_index = factorgraph.add_factor(region_factor);
+                let factor_id = FactorId::new(factorgraph.id(), factor_node_index);
+                let _ = factorgraph.add_internal_edge(
+                    VariableId::new(factorgraph.id(), variable_index),
+                    factor_id,
+                );
+            }
+        }
+
         let mission = match planning_strategy {
             PlanningStrategy::OnlyLocal => Mission::local(
                 waypoints.try_into().unwrap(),
@@ -1339,6 +1618,7 @@ impl RobotBundle {
             // route,
             // initial_state,
             finished_path: FinishedPath::default(),
+            stopped: Stopped::default(),
             t0: T0(t0),
             gbp_iteration_schedule: GbpIterationSchedule(config.gbp.iteration_schedule),
             // task_state:
@@ -1355,26 +1635,43 @@ impl RobotBundle {
 pub struct VariableTimesteps(Vec<u32>);
 
 /// Called `Simulator::calculateRobotNeighbours` in **gbpplanner**
+///
+/// Only robots on the same floor are neighbours, and robots in transit between
+/// two floors have no neighbours.
 fn update_robot_neighbours(
-    robots: Query<(Entity, &Transform), With<RobotConnections>>,
-    mut query: Query<(Entity, &Transform, &mut RobotConnections)>,
-    config: Res<Config>,
+    robots: Query<(Entity, &Transform, &OnFloor, &RadioAntenna), With<RobotConnections>>,
+    mut query: Query<(
+        Entity,
+        &Transform,
+        &mut RobotConnections,
+        &RadioAntenna,
+        Option<&OnFloor>,
+    )>,
 ) {
     // TODO: use kdtree to speed up, and to have something in the report
-    for (robot_id, transform, mut robotstate) in &mut query {
+    for (robot_id, transform, mut robotstate, antenna, on_floor) in &mut query {
+        let Some(&on_floor) = on_floor else {
+            robotstate.robots_within_comms_range.clear();
+            continue;
+        };
         robotstate.robots_within_comms_range = robots
             .iter()
-            .filter_map(|(other_robot_id, other_transform)| {
-                if other_robot_id == robot_id
-                    || config.robot.communication.radius.get()
-                        < transform.translation.distance(other_transform.translation)
-                {
-                    // Do not compute the distance to self
-                    None
-                } else {
-                    Some(other_robot_id)
-                }
-            })
+            .filter_map(
+                |(other_robot_id, other_transform, &other_floor, other_antenna)| {
+                    // Robots of different classes can have different communication radii, the
+                    // smallest of them decides whether they are within range of each other
+                    if other_robot_id == robot_id
+                        || other_floor != on_floor
+                        || antenna.radius.min(other_antenna.radius)
+                            < transform.translation.distance(other_transform.translation)
+                    {
+                        // Do not compute the distance to self
+                        None
+                    } else {
+                        Some(other_robot_id)
+                    }
+                },
+            )
             .collect();
     }
 }
@@ -1435,8 +1732,17 @@ fn delete_interrobot_factors(mut query: Query<(Entity, &mut FactorGraph, &mut Ro
 }
 
 fn create_interrobot_factors(
-    mut query: Query<(Entity, &mut FactorGraph, &mut RobotConnections, &Radius)>,
+    mut query: Query<(
+        Entity,
+        &mut FactorGraph,
+        &mut RobotConnections,
+        &Radius,
+        Option<&Teammate>,
+        Option<&OfClass>,
+        &Priority,
+    )>,
     config: Res<Config>,
+    environment: Res<gbp_environment::Environment>,
     mut robot_number_gen: ResMut<RobotNumberGenerator>,
 ) {
     // a mapping between a robot and the other robots it should create a interrobot
@@ -1444,7 +1750,7 @@ fn create_interrobot_factors(
     // {a -> [b, c, d], b -> [a, c], c -> [a, b], d -> [c]}
     let new_connections_to_establish: HashMap<RobotId, Vec<RobotId>> = query
         .iter()
-        .map(|(entity, _, robotstate, _)| {
+        .map(|(entity, _, robotstate, _, _, _, _)| {
             let new_connections = robotstate
                 .robots_within_comms_range
                 .difference(&robotstate.robots_connected_with)
@@ -1460,7 +1766,7 @@ fn create_interrobot_factors(
     // PERF(kpbaks): store a slice instead of a Vec<NodeIndex>
     let variable_indices_of_each_factorgraph: HashMap<RobotId, Vec<NodeIndex>> = query
         .iter()
-        .map(|(robot_id, factorgraph, _, _)| {
+        .map(|(robot_id, factorgraph, _, _, _, _, _)| {
             let variable_indices = factorgraph
                 .variable_indices_ordered_by_creation()
                 .skip(1) // skip current variable
@@ -1483,8 +1789,27 @@ fn create_interrobot_factors(
 
     let mut external_edges_to_add = Vec::new();
 
-    for (robot_id, mut factorgraph, mut robotstate, radius) in &mut query {
+    let regions = environment.world_regions();
+
+    // Robots of different classes can have different radii, so the safety distance
+    // of an interrobot factor is based on the radii of both robots
+    let radii: HashMap<RobotId, f32> = query
+        .iter()
+        .map(|(robot_id, _, _, radius, _, _, _)| (robot_id, radius.0))
+        .collect();
+
+    let priorities: HashMap<RobotId, Priority> = query
+        .iter()
+        .map(|(robot_id, _, _, _, _, _, &priority)| (robot_id, priority))
+        .collect();
+
+    for (robot_id, mut factorgraph, mut robotstate, radius, teammate, of_class, priority) in
+        &mut query
+    {
         let num_variables = factorgraph.node_count().variables;
+        let sigma_factor_interrobot = OfClass::of(of_class, &config)
+            .and_then(|class| class.sigma_factor_interrobot)
+            .unwrap_or(config.gbp.sigma_factor_interrobot);
         for other_robot_id in new_connections_to_establish
             .get(&robot_id)
             .expect("the key is in the map")
@@ -1492,6 +1817,14 @@ fn create_interrobot_factors(
             let other_variable_indices = variable_indices_of_each_factorgraph
                 .get(other_robot_id)
                 .expect("the key is in the map");
+            let mean_radius =
+                (radius.0 + radii.get(other_robot_id).expect("the key is in the map")) / 2.0;
+            let priority_difference = i32::from(
+                priorities
+                    .get(other_robot_id)
+                    .expect("the key is in the map")
+                    .0,
+            ) - i32::from(priority.0);
 
             for i in 1..num_variables {
                 let initial_measurement = Vector::<Float>::zeros(DOFS);
@@ -1510,9 +1843,9 @@ fn create_interrobot_factors(
                 //
                 let interrobot_factor = FactorNode::new_interrobot_factor(
                     factorgraph.id(),
-                    Float::from(config.gbp.sigma_factor_interrobot),
+                    Float::from(sigma_factor_interrobot),
                     initial_measurement,
-                    Float::from(radius.0).try_into().expect("> 0.0"),
+                    Float::from(mean_radius).try_into().expect("> 0.0"),
                     Float::from(config.robot.inter_robot_safety_distance_multiplier.get())
                         .try_into()
                         .expect("> 0.0"),
@@ -1521,6 +1854,11 @@ fn create_interrobot_factors(
                     //     .expect("safe radius is positive and finite"),
                     external_variable_id,
                     robot_number_gen.next(),
+                    &regions,
+                    priority_difference,
+                    Float::from(config.gbp.priority_scaling.get())
+                        .try_into()
+                        .expect("> 0.0"),
                     config.gbp.factors_enabled.interrobot,
                 );
 
@@ -1534,6 +1872,31 @@ fn create_interrobot_factors(
                 let graph_id = factorgraph.id();
                 factorgraph.add_internal_edge(VariableId::new(graph_id, variable_index), factor_id);
                 external_edges_to_add.push((robot_id, factor_index, *other_robot_id, i));
+
+                // Keep the place in the formation relative to the teammate at the same
+                // timestep
+                let Some(teammate) = teammate.filter(|teammate| teammate.robot == *other_robot_id)
+                else {
+                    continue;
+                };
+                let relative_pose_factor = FactorNode::new_relative_pose_factor(
+                    graph_id,
+                    Float::from(config.gbp.sigma_factor_relative_pose / teammate.stiffness),
+                    array![
+                        Float::from(teammate.offset.x),
+                        Float::from(teammate.offset.y)
+                    ],
+                    external_variable_id,
+                    teammate.slack,
+                    teammate.stiffness,
+                    config.gbp.factors_enabled.relative_pose,
+                );
+                let factor_index = factorgraph.add_factor(relative_pose_factor);
+                factorgraph.add_internal_edge(
+                    VariableId::new(graph_id, variable_index),
+                    FactorId::new(robot_id, factor_index),
+                );
+                external_edges_to_add.push((robot_id, factor_index, *other_robot_id, i));
             }
 
             robotstate.robots_connected_with.insert(*other_robot_id);
@@ -1546,7 +1909,7 @@ fn create_interrobot_factors(
         // TODO: use query.get_mut()
         let mut other_factorgraph = query
             .iter_mut()
-            .find(|(id, _, _, _)| *id == other_robot_id)
+            .find(|(id, _, _, _, _, _)| *id == other_robot_id)
             .expect("the other_robot_id should be in the query")
             .1;
 
@@ -1566,7 +1929,7 @@ fn create_interrobot_factors(
         // TODO: use query.get_mut()
         let mut factorgraph = query
             .iter_mut()
-            .find(|(id, _, _, _)| *id == robot_id)
+            .find(|(id, _, _, _, _, _)| *id == robot_id)
             .expect("the robot_id should be in the query")
             .1;
 
@@ -1592,7 +1955,10 @@ fn update_failed_comms(
     mut prng: ResMut<GlobalEntropy<WyRand>>,
 ) {
     for mut antenna in &mut antennas {
-        antenna.active = !prng.gen_bool(config.robot.communication.failure_rate.into());
+        // Drawn for knocked out antennas too, to not change the failures of the
+        // other antennas
+        let failed = prng.gen_bool(config.robot.communication.failure_rate.into());
+        antenna.active = !failed && !antenna.knocked_out;
     }
 }
 
@@ -2073,13 +2439,15 @@ fn iterate_gbp_v2(
 //     }
 // }
 
-fn reached_waypoint(
+pub(crate) fn reached_waypoint(
     mut q: Query<(
         Entity,
         &mut FactorGraph,
         &Radius,
         // &Transform,
         &mut Mission,
+        Has<TaskAgent>,
+        Has<LifelongGoals>,
         //&ReachedWhenIntersects,
         //&PlanningStrategy,
     )>,
@@ -2090,7 +2458,7 @@ fn reached_waypoint(
     mut evw_robot_despawned: EventWriter<RobotDespawned>,
     mut evw_robot_finalized_path: EventWriter<RobotFinishedRoute>,
 ) {
-    for (robot_entity, mut fgraph, r, mut mission) in &mut q {
+    for (robot_entity, mut fgraph, r, mut mission, task_agent, lifelong) in &mut q {
         let Some(next_waypoint) = mission.next_waypoint() else {
             continue;
         };
@@ -2161,7 +2529,9 @@ fn reached_waypoint(
             }
         }
 
-        if mission.is_completed() {
+        // robots taking tasks from the task pool are finished by `super::tasks`, once all
+        // tasks have been completed, and robots in lifelong operation by `super::lifelong`
+        if mission.is_completed() && !task_agent && !lifelong {
             info!("robot {:?} completed its mission", robot_entity);
             evw_robot_finalized_path.send(RobotFinishedRoute(robot_entity));
             if config.simulation.despawn_robot_when_final_waypoint_reached {
@@ -2174,6 +2544,11 @@ fn reached_waypoint(
 #[derive(Component, Debug, Default)]
 pub struct FinishedPath(pub bool);
 
+/// Boolean component used to make a robot stand still where it is, without
+/// giving up its mission
+#[derive(Component, Debug, Default)]
+pub struct Stopped(pub bool);
+
 /// Called `Robot::updateHorizon` in **gbpplanner**
 fn update_prior_of_horizon_state(
     config: Res<Config>,
@@ -2185,6 +2560,8 @@ fn update_prior_of_horizon_state(
             // &mut Route,
             &Mission,
             &mut FinishedPath,
+            &Stopped,
+            Option<&TargetSpeed>,
             // &Radius,
             // &RadioAntenna,
             // &GbpIterationSchedule,
@@ -2200,12 +2577,12 @@ fn update_prior_of_horizon_state(
 ) {
     let delta_t = Float::from(time_fixed.delta_seconds());
 
-    let max_speed = Float::from(config.robot.target_speed.get());
-
     let mut robots_to_despawn = Vec::new();
 
-    for (robot_id, mut factorgraph, mission, mut finished_path) in &mut query {
-        if finished_path.0 || mission.state.idle()
+    for (robot_id, mut factorgraph, mission, mut finished_path, stopped, target_speed) in &mut query
+    {
+        let max_speed = Float::from(TargetSpeed::of(target_speed, &config));
+        if finished_path.0 || stopped.0 || mission.state.idle()
         // || !antenna.active
         {
             continue;
@@ -2266,7 +2643,8 @@ fn update_prior_of_horizon_state(
 
     // Send messages to external factors
     for message in all_messages_to_external_factors.drain(..) {
-        let Ok((_, mut external_factorgraph, _, _)) = query.get_mut(message.to.factorgraph_id)
+        let Ok((_, mut external_factorgraph, _, _, _, _)) =
+            query.get_mut(message.to.factorgraph_id)
         else {
             continue;
         };
@@ -2285,6 +2663,7 @@ fn update_prior_of_current_state_v3(
             &mut Transform,
             &T0,
             &Mission,
+            &Stopped,
             // &RadioAntenna,
         ),
         With<RobotConnections>,
@@ -2294,8 +2673,8 @@ fn update_prior_of_current_state_v3(
 ) {
     // let mut messages_to_external_factors: Vec<FactorToVariableMessage> = vec![];
 
-    for (mut factorgraph, mut transform, &t0, mission) in &mut query {
-        if mission.state.idle()
+    for (mut factorgraph, mut transform, &t0, mission, stopped) in &mut query {
+        if stopped.0 || mission.state.idle()
         // || !antenna.active
         {
             continue;
@@ -2484,6 +2863,7 @@ fn on_robot_clicked(
         &RadioAntenna,
         &Mission,
         &PlanningStrategy,
+        &StableRobotId,
     )>,
     robot_robot_collisions: Res<RobotRobotCollisions>,
     robot_environment_collisions: Res<RobotEnvironmentCollisions>,
@@ -2500,6 +2880,7 @@ fn on_robot_clicked(
             antenna,
             mission,
             planning_strategy,
+            stable_id,
         )) = robots.get(*robot_id)
         else {
             error!("robot_id {:?} does not exist", robot_id);
@@ -2508,6 +2889,7 @@ fn on_robot_clicked(
 
         println!("----- robot clicked on -----");
         println!("{}: {:?}", "robot".blue(), robot_id);
+        println!("  {}: {}", "stable id".magenta(), stable_id);
         println!("  {}: {}", "radius".magenta(), radius.0);
         println!("  {}:", "antenna".magenta());
         println!("    {}: {}", "radius".cyan(), antenna.radius);
@@ -2572,6 +2954,12 @@ fn on_robot_clicked(
             "tracking".yellow(),
             factor_counts.tracking
         );
+        println!("        {}: {}", "region".yellow(), factor_counts.region);
+        println!(
+            "        {}: {}",
+            "relative pose".yellow(),
+            factor_counts.relative_pose
+        );
 
         println!("  {}:", "messages".magenta());
         // let message_count = factorgraph.message_count();
@@ -1,28 +1,50 @@
-use std::{num::NonZeroUsize, ops::DerefMut, sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    ops::DerefMut,
+    sync::Arc,
+    time::Duration,
+};
 
 use bevy::prelude::*;
 use bevy_mod_picking::prelude::*;
 use bevy_notify::ToastEvent;
-use bevy_rand::prelude::{ForkableRng, GlobalEntropy};
+use bevy_prng::WyRand;
+use bevy_rand::{
+    component::EntropyComponent,
+    prelude::{ForkableRng, GlobalEntropy},
+};
 use gbp_config::{
-    Config,
-    formation::{RepeatTimes, WorldDimensions},
+    Config, RobotMesh,
+    formation::{
+        self, Arrivals, Formation, KeepingMode, RepeatTimes, WorldDimensions,
+        poisson_interarrival_time,
+    },
+    tasks::TaskPool,
 };
 use itertools::Itertools;
 use rand::{Rng, seq::IteratorRandom};
 use strum::IntoEnumIterator;
+use typed_floats::StrictlyPositiveFinite;
 
 use super::{
-    RobotId,
-    robot::{RobotFinishedRoute, RobotSpawned},
+    RobotConnections, RobotId,
+    lifelong::LifelongGoals,
+    robot::{Radius, RobotFinishedRoute, RobotSpawned},
+    tasks::TaskAgent,
 };
 use crate::{
     // asset_loader::SceneAssets,
-    environment::FollowCameraMe,
+    environment::{
+        FollowCameraMe,
+        floors::{OnFloor, insert_transfers, robot_translation},
+    },
     pause_play::PausePlay,
-    planner::robot::{RobotBundle, StateVector},
+    planner::robot::{
+        FormationIndex, OfClass, Priority, RobotBundle, StableRobotId, StateVector, TargetSpeed,
+        Teammate,
+    },
     simulation_loader::{
-        self, EndSimulation, LoadSimulation, ReloadSimulation, Sdf, SimulationManager,
+        self, EndSimulation, FloorSdfs, LoadSimulation, ReloadSimulation, Sdf, SimulationManager,
     },
     theme::{CatppuccinTheme, ColorAssociation, ColorFromCatppuccinColourExt, DisplayColour},
     utils::get_variable_timesteps,
@@ -37,6 +59,8 @@ impl Plugin for RobotSpawnerPlugin {
             .add_event::<WaypointCreated>()
             // .add_event::<RobotReachedWaypoint>()
             .add_event::<AllFormationsFinished>()
+            .init_resource::<SpawningPaused>()
+            .init_resource::<ArrivalLog>()
             .add_systems(
                 Update,
                 (
@@ -219,11 +243,114 @@ impl RepeatingTimer {
     // }
 }
 
+/// Where the arrivals of a formation spawning at random arrivals come from
+#[derive(Debug)]
+enum ArrivalSource {
+    Poisson { rate: f32, remaining: RepeatTimes },
+    Trace(std::vec::IntoIter<Duration>),
+}
+
+impl ArrivalSource {
+    /// The source of `arrivals`, reading trace files from `dir`
+    fn new(arrivals: &Arrivals, dir: &std::path::Path) -> Result<Self, formation::TraceError> {
+        Ok(match arrivals {
+            Arrivals::Poisson { rate, times } => Self::Poisson {
+                rate: rate.get(),
+                remaining: *times,
+            },
+            Arrivals::Trace { file } => {
+                Self::Trace(formation::read_trace(dir.join(file))?.into_iter())
+            }
+        })
+    }
+
+    /// Number of arrivals left, `usize::MAX` if they keep coming
+    fn remaining(&self) -> usize {
+        match self {
+            Self::Poisson {
+                remaining: RepeatTimes::Infinite,
+                ..
+            } => usize::MAX,
+            Self::Poisson {
+                remaining: RepeatTimes::Finite(remaining),
+                ..
+            } => *remaining,
+            Self::Trace(times) => times.len(),
+        }
+    }
+
+    /// Time of the arrival after the one at `previous`, if any
+    fn next(&mut self, previous: Duration, rng: &mut impl Rng) -> Option<Duration> {
+        match self {
+            Self::Poisson { rate, remaining } => {
+                if remaining.exhausted() {
+                    return None;
+                }
+                remaining.decrement();
+                Some(previous.saturating_add(poisson_interarrival_time(*rate, rng)))
+            }
+            Self::Trace(times) => times.next(),
+        }
+    }
+}
+
+/// The arrivals of a formation spawning at random arrivals, and the arrivals
+/// waiting for the entry of the formation to be free
+#[derive(Debug)]
+struct ArrivalQueue {
+    source: ArrivalSource,
+    /// Generator the arrivals are drawn from, of its own so they do not depend
+    /// on what else draws from the global generator
+    rng: EntropyComponent<WyRand>,
+    /// Time since the spawner became active
+    elapsed: Duration,
+    /// Time of the next arrival since the spawner became active, `None` if
+    /// there are no more arrivals
+    next: Option<Duration>,
+    /// Elapsed virtual time of every waiting arrival, the first in line first
+    waiting: VecDeque<f64>,
+}
+
+impl ArrivalQueue {
+    const fn new(source: ArrivalSource, rng: EntropyComponent<WyRand>) -> Self {
+        Self {
+            source,
+            rng,
+            elapsed: Duration::ZERO,
+            next: None,
+            waiting: VecDeque::new(),
+        }
+    }
+
+    /// Start the arrivals, when the spawner becomes active
+    fn start(&mut self) {
+        self.next = self.source.next(Duration::ZERO, &mut self.rng);
+    }
+
+    /// Queue every arrival in the `delta` up until the elapsed virtual time
+    /// `now`
+    fn tick(&mut self, delta: Duration, now: f64) {
+        self.elapsed += delta;
+        while let Some(at) = self.next.filter(|at| *at <= self.elapsed) {
+            self.waiting
+                .push_back(now - (self.elapsed - at).as_secs_f64());
+            self.next = self.source.next(at, &mut self.rng);
+        }
+    }
+
+    /// Returns true if all arrivals have arrived and left the queue
+    fn exhausted(&self) -> bool {
+        self.next.is_none() && self.waiting.is_empty()
+    }
+}
+
 #[derive(Debug, Component)]
 pub struct FormationSpawner {
     pub formation_group_index: usize,
     initial_delay: Timer,
     timer: RepeatingTimer,
+    /// Random arrivals, in place of the timer
+    arrivals: Option<ArrivalQueue>,
     spawned: usize,
     state: FormationSpawnerState,
 }
@@ -250,11 +377,34 @@ impl FormationSpawner {
             formation_group_index,
             initial_delay: Timer::new(initial_delay, TimerMode::Once),
             timer,
+            arrivals: None,
             spawned: 0,
             state: FormationSpawnerState::Inactive,
         }
     }
 
+    /// Spawn at the arrivals of `source` drawn from `rng`, instead of when the
+    /// timer finishes
+    #[must_use]
+    fn with_arrivals(mut self, source: ArrivalSource, rng: EntropyComponent<WyRand>) -> Self {
+        self.arrivals = Some(ArrivalQueue::new(source, rng));
+        self
+    }
+
+    /// Returns true if the formation spawns at random arrivals
+    #[inline]
+    const fn arrives_randomly(&self) -> bool {
+        self.arrivals.is_some()
+    }
+
+    /// Number of arrivals waiting for the entry of the formation to be free
+    #[inline]
+    fn queue_length(&self) -> usize {
+        self.arrivals
+            .as_ref()
+            .map_or(0, |arrivals| arrivals.waiting.len())
+    }
+
     // #[inline]
     // const fn is_active(&self) -> bool {
     //     // self.initial_delay.finished()
@@ -270,13 +420,23 @@ impl FormationSpawner {
         matches!(self.state, FormationSpawnerState::Finished)
     }
 
-    fn tick(&mut self, delta: Duration) {
+    fn tick(&mut self, delta: Duration, now: f64) {
         use FormationSpawnerState::{Active, Finished, Inactive};
         match self.state {
             Inactive => {
                 self.initial_delay.tick(delta);
                 if self.initial_delay.just_finished() {
                     self.state = Active { on_cooldown: false };
+                    if let Some(arrivals) = &mut self.arrivals {
+                        arrivals.start();
+                    }
+                }
+            }
+            Active { .. } if self.arrivals.is_some() => {
+                let arrivals = self.arrivals.as_mut().expect("checked above");
+                arrivals.tick(delta, now);
+                if arrivals.exhausted() {
+                    self.state = Finished;
                 }
             }
             Active { on_cooldown: true } => {
@@ -299,7 +459,14 @@ impl FormationSpawner {
         self.spawned
     }
 
-    fn spawn(&mut self) {
+    /// Spawn the formation. Returns the elapsed virtual time the arrival
+    /// that spawned it arrived at, if it spawns at random arrivals.
+    fn spawn(&mut self) -> Option<f64> {
+        if let Some(arrivals) = &mut self.arrivals {
+            let arrived_at = arrivals.waiting.pop_front()?;
+            self.spawned += 1;
+            return Some(arrived_at);
+        }
         if matches!(
             self.state,
             FormationSpawnerState::Active { on_cooldown: false }
@@ -307,14 +474,21 @@ impl FormationSpawner {
             self.state = FormationSpawnerState::Active { on_cooldown: true };
             self.spawned += 1;
         };
+        None
     }
 
     #[inline]
     fn ready_to_spawn(&mut self) -> bool {
-        matches!(
-            self.state,
-            FormationSpawnerState::Active { on_cooldown: false }
-        )
+        match self.arrivals {
+            Some(ref arrivals) => {
+                matches!(self.state, FormationSpawnerState::Active { .. })
+                    && !arrivals.waiting.is_empty()
+            }
+            None => matches!(
+                self.state,
+                FormationSpawnerState::Active { on_cooldown: false }
+            ),
+        }
     }
 
     // #[inline]
@@ -341,6 +515,8 @@ pub struct Scoreboard {
 
 fn create_formation_group_spawners(
     mut commands: Commands,
+    mut evw_toast: EventWriter<ToastEvent>,
+    mut prng: ResMut<GlobalEntropy<WyRand>>,
     simulation_manager: Res<SimulationManager>,
 ) {
     let Some(formation_group) = simulation_manager.active_formation_group() else {
@@ -348,7 +524,7 @@ fn create_formation_group_spawners(
         return;
     };
 
-    let robots_to_spawn = formation_group.robots_to_spawn();
+    let mut robots_to_spawn = 0usize;
 
     for (i, formation) in formation_group.formations.iter().enumerate() {
         #[allow(clippy::option_if_let_else)] // find it more readable with a match here
@@ -362,12 +538,137 @@ fn create_formation_group_spawners(
             formation.delay, repeating_timer
         );
 
-        commands.spawn(FormationSpawner::new(i, formation.delay, repeating_timer));
+        let mut spawner = FormationSpawner::new(i, formation.delay, repeating_timer);
+        let mut spawns = match formation.repeat.map(|repeat| repeat.times) {
+            Some(RepeatTimes::Infinite) => usize::MAX,
+            Some(RepeatTimes::Finite(times)) => times,
+            None => 1,
+        };
+        if let Some(arrivals) = &formation.arrivals {
+            let dir = simulation_manager.active_dir().unwrap_or_default();
+            match ArrivalSource::new(arrivals, &dir) {
+                Ok(source) => {
+                    spawns = source.remaining();
+                    spawner = spawner.with_arrivals(source, prng.fork_rng());
+                }
+                Err(err) => {
+                    let message = format!("failed to read the arrivals of formation {i}: {err}");
+                    error!(message);
+                    evw_toast.send(ToastEvent::error(message));
+                    continue;
+                }
+            }
+        }
+        robots_to_spawn =
+            robots_to_spawn.saturating_add(formation.robots.len().saturating_mul(spawns));
+
+        commands.spawn(spawner);
     }
     commands.insert_resource(Scoreboard {
         robots_left: robots_to_spawn,
         game_over: false,
     });
+    commands.insert_resource(SpawningPaused::default());
+    commands.insert_resource(ArrivalLog::default());
+}
+
+/// **Bevy** [`Resource`] that holds back the formation spawners while set.
+/// The spawners continue where they were once it is unset again.
+#[derive(Debug, Default, Resource, Deref, DerefMut)]
+pub struct SpawningPaused(pub bool);
+
+/// An arrival of a formation spawning at random arrivals
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct ArrivalRecord {
+    /// Index of the formation in `formation.yaml`
+    pub formation: usize,
+    /// Elapsed virtual time of the arrival
+    pub arrived_at: f64,
+    /// Elapsed virtual time the formation was spawned, once the entry was free
+    pub spawned_at: f64,
+}
+
+impl ArrivalRecord {
+    /// Time the arrival waited in the queue
+    #[inline]
+    pub fn waited(&self) -> f64 {
+        self.spawned_at - self.arrived_at
+    }
+}
+
+/// Length of the queue of arrivals of a formation
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QueueLength {
+    /// Arrivals waiting right now
+    pub current: usize,
+    /// Most arrivals waiting at once
+    pub max: usize,
+}
+
+/// **Bevy** [`Resource`] recording the arrivals of the formations spawning
+/// at random arrivals since the simulation was loaded, included in the export
+#[derive(Debug, Default, Resource)]
+pub struct ArrivalLog {
+    arrivals: Vec<ArrivalRecord>,
+    queues: BTreeMap<usize, QueueLength>,
+}
+
+impl ArrivalLog {
+    /// Every arrival that has spawned its formation, in the order they spawned
+    pub fn arrivals(&self) -> &[ArrivalRecord] {
+        &self.arrivals
+    }
+
+    /// Length of the queue of each formation spawning at random arrivals, by
+    /// the index of the formation
+    pub const fn queues(&self) -> &BTreeMap<usize, QueueLength> {
+        &self.queues
+    }
+
+    fn record_queue_length(&mut self, formation: usize, length: usize) {
+        let queue = self.queues.entry(formation).or_default();
+        queue.current = length;
+        queue.max = queue.max.max(length);
+    }
+}
+
+/// Dimensions of the world of `env_config`, in meters
+pub(crate) fn world_dimensions(env_config: &gbp_environment::Environment) -> WorldDimensions {
+    let tile_size = env_config.tiles.settings.tile_size as f64;
+    let width = tile_size * env_config.tiles.grid.ncols() as f64;
+    let height = tile_size * env_config.tiles.grid.nrows() as f64;
+    WorldDimensions::new(width, height)
+}
+
+/// Largest radius a robot of `formation` can have
+fn max_radius(formation: &Formation, config: &Config) -> f32 {
+    let radius = formation
+        .class
+        .as_deref()
+        .and_then(|name| config.robot.classes.get(name))
+        .and_then(|class| class.radius)
+        .unwrap_or(config.robot.radius)
+        .max
+        .get();
+    (0..formation.robots.len())
+        .filter_map(|i| formation.robots.tasks_of(i).and_then(|tasks| tasks.radius))
+        .map(StrictlyPositiveFinite::get)
+        .fold(radius, f32::max)
+}
+
+/// Returns true if one of `robots` is so close to the entry of `formation`,
+/// that robots spawned there could overlap it
+fn entry_occupied(
+    formation: &Formation,
+    world_dims: WorldDimensions,
+    clearance: f32,
+    robots: &Query<(&Transform, &Radius, &OnFloor), With<RobotConnections>>,
+) -> bool {
+    robots.iter().any(|(transform, radius, on_floor)| {
+        formation
+            .distance_to_entry(world_dims, transform.translation.xz(), on_floor.0)
+            .is_some_and(|distance| distance < radius.0 + clearance)
+    })
 }
 
 /// Event that is sent when a formation should be spawned.
@@ -379,29 +680,65 @@ fn create_formation_group_spawners(
 #[derive(Debug, Event)]
 pub struct RobotFormationSpawned {
     pub formation_group_index: usize,
+    /// How many times the formation has been spawned before
+    pub wave: usize,
 }
 
 /// Advance time for each `FormationSpawnerCountdown` entity with
 /// `Time::delta()`. If the timer has just finished, send a
 /// `FormationSpawnEvent`.
+#[allow(clippy::too_many_arguments)]
 fn advance_time(
     mut spawners: Query<&mut FormationSpawner>,
     mut evw_robot_formation_spawned: EventWriter<RobotFormationSpawned>,
     mut evw_pause_play: EventWriter<PausePlay>,
+    mut arrival_log: ResMut<ArrivalLog>,
+    robots: Query<(&Transform, &Radius, &OnFloor), With<RobotConnections>>,
     time: Res<Time>,
     config: Res<Config>,
+    env_config: Res<gbp_environment::Environment>,
+    simulation_manager: Res<SimulationManager>,
+    spawning_paused: Res<SpawningPaused>,
 ) {
+    if **spawning_paused {
+        return;
+    }
+
+    let now = time.elapsed_seconds_f64();
+    let world_dims = world_dimensions(&env_config);
+
     for mut spawner in &mut spawners {
-        spawner.tick(time.delta());
+        spawner.tick(time.delta(), now);
+        let index = spawner.formation_group_index;
+
+        // Arrivals wait in line while the robots spawned before them are still at the
+        // entry
+        let entry_free = || {
+            simulation_manager
+                .active_formation_group()
+                .and_then(|group| group.formations.get(index))
+                .is_none_or(|formation| {
+                    !entry_occupied(
+                        formation,
+                        world_dims,
+                        max_radius(formation, &config),
+                        &robots,
+                    )
+                })
+        };
 
-        if spawner.ready_to_spawn() {
-            spawner.spawn();
-            info!(
-                "FormationSpawner[{}] ready to spawn!",
-                spawner.formation_group_index
-            );
+        if spawner.ready_to_spawn() && (!spawner.arrives_randomly() || entry_free()) {
+            if let Some(arrived_at) = spawner.spawn() {
+                arrival_log.arrivals.push(ArrivalRecord {
+                    formation: index,
+                    arrived_at,
+                    spawned_at: now,
+                });
+            }
+            info!("FormationSpawner[{}] ready to spawn!", index);
             evw_robot_formation_spawned.send(RobotFormationSpawned {
-                formation_group_index: spawner.formation_group_index,
+                formation_group_index: index,
+                wave: spawner.spawned().saturating_sub(1),
             });
 
             if config.simulation.pause_on_spawn {
@@ -409,6 +746,10 @@ fn advance_time(
                 evw_pause_play.send(PausePlay::Pause);
             }
         }
+
+        if spawner.arrives_randomly() {
+            arrival_log.record_queue_length(index, spawner.queue_length());
+        }
     }
 }
 
@@ -424,10 +765,12 @@ fn spawn_formation(
     theme: Res<CatppuccinTheme>,
     simulation_manager: Res<SimulationManager>,
     sdf: Res<Sdf>,
+    floor_sdfs: Res<FloorSdfs>,
     mut prng: ResMut<GlobalEntropy<bevy_prng::WyRand>>,
     mut mesh_assets: ResMut<Assets<Mesh>>,
     // time_virtual: Res<Time<Virtual>>,
     time_fixed: Res<Time<Fixed>>,
+    task_pool: Option<Res<TaskPool>>,
 ) {
     for event in evr_robot_formation_spawned.read() {
         let formation_group = simulation_manager
@@ -437,116 +780,140 @@ fn spawn_formation(
         let formation = &formation_group.formations[event.formation_group_index];
         // TODO: check this gets reloaded correctly
 
-        let world_dims = {
-            let tile_size = env_config.tiles.settings.tile_size as f64;
-            let width = tile_size * env_config.tiles.grid.ncols() as f64;
-            let height = tile_size * env_config.tiles.grid.nrows() as f64;
-            WorldDimensions::new(width, height)
+        // Robots of a class use the settings of the class in place of the global ones
+        let class_config = match formation.class.as_deref() {
+            Some(name) => {
+                let Some(class_config) = config.for_class(name) else {
+                    error!(
+                        "failed to spawn formation {}, reason: there is no robot class '{}' in \
+                         the config, skipping",
+                        event.formation_group_index, name
+                    );
+                    continue;
+                };
+                Some(class_config)
+            }
+            None => None,
         };
-
-        let max_placement_attempts = NonZeroUsize::new(1000).expect("1000 is not zero");
-
-        let radii = (0..formation.robots)
-            .map(|_| prng.gen_range(config.robot.radius.range()))
+        let config: &Config = class_config.as_ref().unwrap_or(&config);
+        let class = formation
+            .class
+            .as_deref()
+            .and_then(|name| config.robot.classes.get(name));
+
+        let world_dims = world_dimensions(&env_config);
+
+        // Robots listed explicitly in the formation can have a radius of their own
+        let radii = (0..formation.robots.len())
+            .map(|i| {
+                formation
+                    .robots
+                    .tasks_of(i)
+                    .and_then(|tasks| tasks.radius)
+                    .map_or_else(
+                        || prng.gen_range(config.robot.radius.range()),
+                        StrictlyPositiveFinite::get,
+                    )
+            })
             .collect::<Vec<_>>();
 
-        let Some((initial_position_for_each_robot, waypoint_positions_for_each_robot)) = formation
-            .as_positions(
-                world_dims,
-                &radii, /* config.robot.radius,
-                         * max_placement_attempts,
-                         * &mut prng.rng as &mut dyn Rng,
-                         * prng as &mut dyn Rng, */
-                prng.deref_mut(),
-            )
-        else {
+        let Some(routes) = formation.robot_routes(world_dims, &radii, prng.deref_mut()) else {
             error!(
-                "failed to spawn formation {}, reason: was not able to place robots along line \
-                 segment after {} attempts, skipping",
+                "failed to spawn formation {}, reason: was not able to place the robots without \
+                 overlapping, skipping",
                 event.formation_group_index,
-                max_placement_attempts.get()
             );
             return;
         };
 
-        let initial_pose_for_each_robot: Vec<Vec4> = initial_position_for_each_robot
-            .iter()
-            .zip(
-                waypoint_positions_for_each_robot
-                    .first()
-                    .expect("there is at least one waypoint"),
-            )
-            .map(|(from, to)| {
-                let d = *to - *from;
-                let v = d.normalize_or_zero() * config.robot.target_speed.get();
-                Vec4::new(from.x, from.y, v.x, v.y)
-            })
-            .collect();
-
-        let waypoint_poses_for_each_robot: Vec<Vec<Vec4>> = waypoint_positions_for_each_robot
-            .iter()
-            .chain(waypoint_positions_for_each_robot.last().into_iter())
-            .tuple_windows()
-            .map(|(a, b)| {
-                a.iter()
-                    .zip(b.iter())
-                    .map(|(from, to)| {
-                        let d = *to - *from;
-                        let v = d.normalize_or_zero() * config.robot.target_speed.get();
-                        Vec4::new(from.x, from.y, v.x, v.y)
-                    })
-                    .collect::<Vec<_>>()
-            })
-            .collect();
-
-        // #[rustfmt::skip]
-        // let Some(min_radius) = radii.iter().copied().map(ordered_float::OrderedFloat).min() else {
-        //     return;
-        // };
-        // #[rustfmt::skip]
-        // let Some(max_radius) = radii.iter().copied().map(ordered_float::OrderedFloat).max() else {
-        //     return;
-        // };
-
-        for (i, initial_pose) in initial_pose_for_each_robot.iter().enumerate() {
-            let waypoints: Vec<Vec4> = waypoint_poses_for_each_robot
+        // The robots spawned so far, for the robots keeping a formation to find
+        // their teammate among
+        let mut spawned: Vec<Option<Entity>> = vec![None; routes.len()];
+
+        for (i, route) in routes.into_iter().enumerate() {
+            if let Some(floor) = route
+                .floors
                 .iter()
-                .map(|wps| wps[i])
-                .collect();
+                .find(|&&floor| floor >= env_config.floor_count())
+            {
+                error!(
+                    "failed to spawn robot {} of formation {}, reason: the environment has no \
+                     floor {}, skipping",
+                    i, event.formation_group_index, floor
+                );
+                continue;
+            }
+            let initial_floor = route.floors[0];
+            let Some(initial_sdf) = floor_sdfs.get(initial_floor, &sdf) else {
+                error!(
+                    "failed to spawn robot {} of formation {}, reason: no SDF for floor {}, \
+                     skipping",
+                    i, event.formation_group_index, initial_floor
+                );
+                continue;
+            };
+
+            let speed_override = formation
+                .robots
+                .tasks_of(i)
+                .and_then(|tasks| tasks.speed)
+                .map(StrictlyPositiveFinite::get);
+            let target_speed = speed_override.unwrap_or_else(|| config.robot.target_speed.get());
+
+            // Each pose heads for the next waypoint, and the last waypoint keeps the
+            // velocity of the one before it
+            let positions = std::iter::once(route.start)
+                .chain(route.goals.iter().copied())
+                .collect::<Vec<_>>();
+            let mut waypoints = positions
+                .iter()
+                .chain(positions.last())
+                .tuple_windows()
+                .map(|(from, to)| {
+                    let v = (*to - *from).normalize_or_zero() * target_speed;
+                    Vec4::new(from.x, from.y, v.x, v.y)
+                })
+                .map_into::<StateVector>()
+                .collect::<Vec<_>>();
+
+            let second_last = waypoints.get(waypoints.len() - 2).copied().unwrap();
+            let last = waypoints.last_mut().unwrap();
+            last.update_velocity(second_last.velocity());
+
+            let initial_pose: Vec4 = waypoints[0].into();
             trace!(
                 "initial pose: {:?}, waypoints: {:?}",
-                initial_pose, waypoints
+                initial_pose,
+                &waypoints[1..]
             );
 
             let initial_direction = initial_pose.yz().extend(0.0);
-            let initial_translation = Vec3::new(initial_pose.x, -1.5, initial_pose.y);
-            // let initial_translation = Vec3::new(initial_pose.x, -5.5, initial_pose.y);
+            let initial_translation =
+                robot_translation(&env_config, initial_pose.xy(), initial_floor);
 
+            let stable_id = StableRobotId {
+                formation: event.formation_group_index,
+                wave: event.wave,
+                index: i,
+            };
             let mut entity = commands.spawn_empty();
             let robot_entity = entity.id();
-            evw_waypoint_created.send_batch(waypoints.iter().map(|pose| WaypointCreated {
+            evw_waypoint_created.send_batch(route.goals.iter().map(|&position| WaypointCreated {
                 for_robot: robot_entity,
-                position: pose.xy(),
+                position,
             }));
 
-            // let second_last = waypoints.get(waypoints.len() - 2).copied().unwrap();
-            // let last = waypoints.last_mut().unwrap();
-            // last.z = second_last.z;
-            // last.w = second_last.w;
-
-            // let mu
-            let mut waypoints = std::iter::once(initial_pose)
-                .chain(waypoints.iter())
-                .copied()
-                .map_into::<StateVector>()
-                .collect::<Vec<_>>();
-
-            let second_last = waypoints.get(waypoints.len() - 2).copied().unwrap();
-            let last = waypoints.last_mut().unwrap();
-            last.update_velocity(second_last.velocity());
-            // last.z = second_last.z;
-            // last.w = second_last.w;
-            //
+            let Some((waypoints, transfer_plan)) =
+                insert_transfers(&env_config, &waypoints, &route.floors, target_speed)
+            else {
+                error!(
+                    "failed to spawn robot {} of formation {}, reason: its waypoints are on \
+                     floors that are not connected, skipping",
+                    i, event.formation_group_index
+                );
+                entity.despawn();
+                continue;
+            };
 
             // let lookahead_horizon = (5.0 / 0.25) as u32;
             // let lookahead_multiple = 3;
@@ -561,7 +928,7 @@ fn spawn_formation(
             // let lookahead_horizon: u32 = (config.robot.planning_horizon.get() / divisor) as u32;
             // let lookahead_horizon: u32 = config.robot.planning_horizon.get() as u32;
             let lookahead_horizon: u32 =
-                (config.robot.target_speed * config.robot.planning_horizon).get() as u32;
+                (target_speed * config.robot.planning_horizon.get()) as u32;
             // let lookahead_horizon: u32 = (config.robot.planning_horizon.get()
             //     / radii.iter().map(ordered_float::OrderedFloat).min().unwrap())
             //     as u32;
@@ -570,17 +937,18 @@ fn spawn_formation(
 
             let robotbundle = RobotBundle::new(
                 robot_entity,
-                StateVector::new(*initial_pose),
+                StateVector::new(initial_pose),
                 // route,
                 variable_timesteps.as_slice(),
-                &config,
+                config,
                 &env_config,
                 radii[i],
-                Arc::clone(&sdf.0),
+                target_speed,
+                Arc::clone(&initial_sdf),
                 time_fixed.elapsed().as_secs_f64(),
                 waypoints.try_into().unwrap(),
                 // config
-                formation.planning_strategy,
+                formation.planning_strategy_of(i),
                 formation.waypoint_reached_when_intersects,
                 formation.finished_when_intersects,
                 // matches!(formation.planning_strategy, PlanningStrategy::RrtStar
@@ -593,21 +961,41 @@ fn spawn_formation(
                 Visibility::Hidden
             };
 
-            let random_color = DisplayColour::iter()
-                .choose(prng.deref_mut())
-                .expect("there is more than 0 colors");
+            let class_colour = class
+                .and_then(|class| class.colour.as_deref())
+                .and_then(|colour| {
+                    colour
+                        .parse::<DisplayColour>()
+                        .map_err(|_| {
+                            warn!(
+                                "unknown robot class colour '{}', using a random colour",
+                                colour
+                            )
+                        })
+                        .ok()
+                });
+            let colour = class_colour.unwrap_or_else(|| {
+                DisplayColour::iter()
+                    .choose(prng.deref_mut())
+                    .expect("there is more than 0 colors")
+            });
 
             let material = materials.add(StandardMaterial {
-                base_color: Color::from_catppuccin_colour(theme.get_display_colour(&random_color)),
+                base_color: Color::from_catppuccin_colour(theme.get_display_colour(&colour)),
                 ..Default::default()
             });
 
-            let mesh = mesh_assets.add(
-                Sphere::new(radii[i])
+            let mesh = mesh_assets.add(match class.map(|class| class.mesh).unwrap_or_default() {
+                RobotMesh::Sphere => Sphere::new(radii[i])
                     .mesh()
                     .ico(2)
                     .expect("4 subdivisions is less than the maximum allowed of 80"),
-            );
+                RobotMesh::Cylinder => Cylinder::new(radii[i], radii[i]).into(),
+                // The corners of the cuboid touch the circle of the radius
+                RobotMesh::Cuboid => {
+                    Cuboid::from_size(Vec3::splat(radii[i] * std::f32::consts::SQRT_2)).into()
+                }
+            });
 
             let pbrbundle = PbrBundle {
                 mesh,
@@ -628,7 +1016,7 @@ fn spawn_formation(
                 super::tracking::VelocityTracker::new(10000, Duration::from_millis(100)),
                 PickableBundle::default(),
                 On::<Pointer<Click>>::send_event::<RobotClickedOn>(),
-                ColorAssociation { name: random_color },
+                ColorAssociation { name: colour },
                 FollowCameraMe::new(0.0, 30.0, 0.0)
                     .with_up_direction(Direction3d::new(initial_direction).expect(
                         "Vector between initial position and first waypoint should be different \
@@ -638,8 +1026,50 @@ fn spawn_formation(
                 crate::goal_area::components::Collider(Box::new(parry2d::shape::Ball::new(
                     radii[i],
                 ))),
+                OnFloor(initial_floor),
+                Priority(formation.priority(&config.robot.classes)),
+                // nested, as a bundle can have at most 15 components
+                (
+                    FormationIndex(event.formation_group_index),
+                    stable_id,
+                    Name::new(format!("robot {stable_id}")),
+                ),
+                transfer_plan,
             ));
+            if speed_override.is_some() || class.is_some() {
+                entity.insert(TargetSpeed(target_speed));
+            }
+            if let Some(name) = &formation.class {
+                entity.insert(OfClass(name.clone()));
+            }
+            if task_pool.is_some() {
+                entity.insert(TaskAgent::default());
+            }
+            if let Some(generator) = &formation.lifelong {
+                entity.insert(LifelongGoals::new(generator.clone(), i));
+            }
+            if let Some(keeping) = &formation.formation_keeping {
+                if let Some((teammate, offset)) = keeping.teammate_of(i) {
+                    if let Some(robot) = spawned.get(teammate).copied().flatten() {
+                        entity.insert(Teammate {
+                            robot,
+                            offset,
+                            slack: match keeping.mode {
+                                KeepingMode::Rigid => 0.0,
+                                KeepingMode::Elastic { slack } => slack.get(),
+                            },
+                            stiffness: keeping.stiffness.get(),
+                        });
+                    } else {
+                        warn!(
+                            "robot {} of formation {} has no teammate to keep the formation with",
+                            i, event.formation_group_index
+                        );
+                    }
+                }
+            }
 
+            spawned[i] = Some(robot_entity);
             evw_robot_spawned.send(RobotSpawned(robot_entity));
         }
     }
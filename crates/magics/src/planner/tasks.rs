@@ -0,0 +1,344 @@
+//! Allocation of the tasks of a [`TaskPool`] to robots.
+//!
+//! Once a task has arrived, it is assigned to one of the robots without a task
+//! that have completed their mission, using the allocator chosen in
+//! `tasks.yaml`. The pickup and dropoff of the task are appended to the
+//! [`Mission`] of the robot, which resumes it from where it stands. When every
+//! task has been completed the robots finish, as they would at the end of
+//! their formation.
+
+use bevy::prelude::*;
+use gbp_config::{
+    Config,
+    tasks::{AllocatorKind, TaskPool},
+};
+use gbp_environment::Environment;
+use task_allocation::{Allocator, Auction, GreedyNearest, Hungarian};
+
+use super::{
+    robot::{
+        FinishedPath, Mission, RobotDespawned, RobotFinishedRoute, StableRobotId, StateVector,
+        TargetSpeed, reached_waypoint,
+    },
+    spawner::{WaypointCreated, world_dimensions},
+};
+use crate::{
+    bevy_utils::run_conditions::time::virtual_time_is_paused,
+    environment::floors::OnFloor,
+    simulation_loader::{LoadSimulation, ReloadSimulation},
+};
+
+pub struct TaskAllocationPlugin;
+
+impl Plugin for TaskAllocationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TaskAssigned>()
+            .add_event::<TaskCompleted>()
+            .init_resource::<TaskLog>()
+            .add_systems(
+                Update,
+                reset_task_log
+                    .run_if(on_event::<LoadSimulation>().or_else(on_event::<ReloadSimulation>())),
+            )
+            .add_systems(
+                FixedUpdate,
+                (allocate_tasks, track_tasks)
+                    .chain()
+                    .after(reached_waypoint)
+                    .run_if(resource_exists::<TaskPool>.and_then(not(virtual_time_is_paused))),
+            );
+    }
+}
+
+/// **Bevy** [`Component`] attached to robots that take tasks from the
+/// [`TaskPool`]
+#[derive(Debug, Default, Component)]
+pub struct TaskAgent {
+    active: Option<ActiveTask>,
+    /// Whether the robot has been finished, after all tasks were completed
+    finished: bool,
+}
+
+impl TaskAgent {
+    /// Index of the task the robot is working on, if any
+    pub fn task(&self) -> Option<usize> {
+        self.active.map(|active| active.task)
+    }
+}
+
+/// A task assigned to a robot
+#[derive(Debug, Clone, Copy)]
+struct ActiveTask {
+    /// Index of the task in [`TaskPool::tasks`]
+    task: usize,
+    /// Index of the taskpoint of the pickup in the mission of the robot, the
+    /// dropoff is the one after it
+    pickup: usize,
+    picked_up: bool,
+}
+
+/// **Bevy** [`Event`] sent when a task has been assigned to a robot
+#[derive(Debug, Clone, Copy, Event)]
+pub struct TaskAssigned {
+    /// Index of the task in [`TaskPool::tasks`]
+    pub task: usize,
+    pub robot: Entity,
+}
+
+/// **Bevy** [`Event`] sent when a robot has reached the dropoff of its task
+#[derive(Debug, Clone, Copy, Event)]
+pub struct TaskCompleted {
+    /// Index of the task in [`TaskPool::tasks`]
+    pub task: usize,
+    pub robot: Entity,
+    /// Seconds from the arrival of the task until it was picked up
+    pub waiting_time: f32,
+}
+
+/// What happened to a task, in seconds since the simulation was loaded
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+pub struct TaskRecord {
+    pub arrived_at: Option<f32>,
+    pub assigned_to: Option<StableRobotId>,
+    pub assigned_at: Option<f32>,
+    pub picked_up_at: Option<f32>,
+    pub completed_at: Option<f32>,
+    /// Seconds from the arrival of the task until it was picked up
+    pub waiting_time: Option<f32>,
+}
+
+/// **Bevy** [`Resource`] recording the progress of every task since the
+/// simulation was loaded, included in the export
+#[derive(Debug, Default, Resource)]
+pub struct TaskLog {
+    loaded_at: f32,
+    records: Vec<TaskRecord>,
+}
+
+impl TaskLog {
+    /// The record of every task, in the order of [`TaskPool::tasks`]
+    pub fn records(&self) -> &[TaskRecord] {
+        &self.records
+    }
+
+    /// Whether every task of `task_pool` has been completed
+    fn all_completed(&self, task_pool: &TaskPool) -> bool {
+        self.records.len() == task_pool.tasks.len()
+            && self
+                .records
+                .iter()
+                .all(|record| record.completed_at.is_some())
+    }
+}
+
+fn reset_task_log(mut task_log: ResMut<TaskLog>, time: Res<Time<Virtual>>) {
+    *task_log = TaskLog {
+        loaded_at: time.elapsed_seconds(),
+        records: Vec::new(),
+    };
+}
+
+/// Create the allocator of the given kind
+fn allocator(kind: AllocatorKind) -> Box<dyn Allocator> {
+    match kind {
+        AllocatorKind::GreedyNearest => Box::new(GreedyNearest),
+        AllocatorKind::Hungarian => Box::new(Hungarian),
+        AllocatorKind::Auction { epsilon } => Box::new(Auction::new(epsilon.get())),
+    }
+}
+
+/// **Bevy** system that assigns the tasks that have arrived to the robots
+/// without a task, that have completed their mission
+#[allow(clippy::too_many_arguments)]
+fn allocate_tasks(
+    mut evw_task_assigned: EventWriter<TaskAssigned>,
+    mut evw_waypoint_created: EventWriter<WaypointCreated>,
+    mut robots: Query<(
+        Entity,
+        &mut TaskAgent,
+        &mut Mission,
+        &mut FinishedPath,
+        &Transform,
+        Option<&OnFloor>,
+        Option<&TargetSpeed>,
+        &StableRobotId,
+    )>,
+    mut task_log: ResMut<TaskLog>,
+    task_pool: Res<TaskPool>,
+    env_config: Res<Environment>,
+    config: Res<Config>,
+    time_virtual: Res<Time<Virtual>>,
+    time: Res<Time>,
+) {
+    let now = time_virtual.elapsed_seconds() - task_log.loaded_at;
+    if task_log.records.len() != task_pool.tasks.len() {
+        task_log.records = vec![TaskRecord::default(); task_pool.tasks.len()];
+    }
+
+    let mut pending = Vec::new();
+    for i in task_pool.by_arrival() {
+        let task = &task_pool.tasks[i];
+        if task.arrives_at.as_secs_f32() > now {
+            break;
+        }
+        let record = &mut task_log.records[i];
+        if record.arrived_at.is_none() {
+            info!("task {} arrived", i);
+            record.arrived_at = Some(now);
+        }
+        if record.assigned_to.is_none() {
+            pending.push(i);
+        }
+    }
+    if pending.is_empty() {
+        return;
+    }
+
+    let world_dims = world_dimensions(&env_config);
+    let allocator = allocator(task_pool.allocator);
+
+    // Tasks can only be done by robots on the same floor, so each floor is
+    // allocated on its own
+    let mut floors = pending
+        .iter()
+        .map(|&i| task_pool.tasks[i].floor)
+        .collect::<Vec<_>>();
+    floors.sort_unstable();
+    floors.dedup();
+
+    for floor in floors {
+        let tasks = pending
+            .iter()
+            .copied()
+            .filter(|&i| task_pool.tasks[i].floor == floor)
+            .collect::<Vec<_>>();
+        let mut free_robots = robots
+            .iter_mut()
+            .filter(|(_, agent, mission, _, _, on_floor, ..)| {
+                agent.active.is_none()
+                    && !agent.finished
+                    && mission.is_completed()
+                    && on_floor.map_or(0, |on_floor| on_floor.0) == floor
+            })
+            .collect::<Vec<_>>();
+        if free_robots.is_empty() {
+            continue;
+        }
+
+        // costs[robot][task]
+        let costs = free_robots
+            .iter()
+            .map(|(_, _, _, _, transform, ..)| {
+                let position = transform.translation.xz();
+                tasks
+                    .iter()
+                    .map(|&i| {
+                        let pickup = world_dims.point_to_world_position(task_pool.tasks[i].pickup);
+                        position.distance(pickup)
+                    })
+                    .collect()
+            })
+            .collect::<Vec<Vec<f32>>>();
+
+        for assignment in allocator.allocate(&costs) {
+            let task_index = tasks[assignment.task];
+            let task = &task_pool.tasks[task_index];
+            let (robot, agent, mission, finished_path, transform, _, target_speed, stable_id) =
+                &mut free_robots[assignment.robot];
+
+            let pickup = world_dims.point_to_world_position(task.pickup);
+            let dropoff = world_dims.point_to_world_position(task.dropoff);
+            let velocity =
+                (dropoff - pickup).normalize_or_zero() * TargetSpeed::of(*target_speed, &config);
+            let taskpoints = [pickup, dropoff].map(|position| {
+                StateVector::from(Vec4::new(position.x, position.y, velocity.x, velocity.y))
+            });
+
+            let pickup_index =
+                mission.append_taskpoints(taskpoints, transform.translation.xz(), &time);
+            finished_path.0 = false;
+            agent.active = Some(ActiveTask {
+                task: task_index,
+                pickup: pickup_index,
+                picked_up: false,
+            });
+
+            let record = &mut task_log.records[task_index];
+            record.assigned_to = Some(**stable_id);
+            record.assigned_at = Some(now);
+
+            info!("assigned task {} to robot {}", task_index, stable_id);
+            evw_task_assigned.send(TaskAssigned {
+                task: task_index,
+                robot: *robot,
+            });
+            evw_waypoint_created.send_batch([pickup, dropoff].map(|position| WaypointCreated {
+                for_robot: *robot,
+                position,
+            }));
+        }
+    }
+}
+
+/// **Bevy** system that follows the robots along their tasks, and finishes
+/// the robots once every task has been completed
+fn track_tasks(
+    mut evw_task_completed: EventWriter<TaskCompleted>,
+    mut evw_robot_finished_route: EventWriter<RobotFinishedRoute>,
+    mut evw_robot_despawned: EventWriter<RobotDespawned>,
+    mut robots: Query<(Entity, &mut TaskAgent, &Mission)>,
+    mut task_log: ResMut<TaskLog>,
+    task_pool: Res<TaskPool>,
+    config: Res<Config>,
+    time_virtual: Res<Time<Virtual>>,
+) {
+    let now = time_virtual.elapsed_seconds() - task_log.loaded_at;
+
+    for (robot, mut agent, mission) in &mut robots {
+        let Some(active) = agent.active.as_mut() else {
+            continue;
+        };
+        let reached = mission.reached_taskpoints();
+        let Some(record) = task_log.records.get_mut(active.task) else {
+            continue;
+        };
+
+        if !active.picked_up && reached > active.pickup {
+            active.picked_up = true;
+            record.picked_up_at = Some(now);
+            record.waiting_time = record.arrived_at.map(|arrived_at| now - arrived_at);
+            info!("robot {:?} picked up task {}", robot, active.task);
+        }
+
+        if reached > active.pickup + 1 {
+            record.completed_at = Some(now);
+            let waiting_time = record.waiting_time.unwrap_or_default();
+            info!(
+                "robot {:?} completed task {}, after waiting {:.2}s for pickup",
+                robot, active.task, waiting_time
+            );
+            evw_task_completed.send(TaskCompleted {
+                task: active.task,
+                robot,
+                waiting_time,
+            });
+            agent.active = None;
+        }
+    }
+
+    if !task_log.all_completed(&task_pool) {
+        return;
+    }
+
+    for (robot, mut agent, mission) in &mut robots {
+        if agent.finished || agent.active.is_some() || !mission.is_completed() {
+            continue;
+        }
+        agent.finished = true;
+        info!("robot {:?} completed its mission", robot);
+        evw_robot_finished_route.send(RobotFinishedRoute(robot));
+        if config.simulation.despawn_robot_when_final_waypoint_reached {
+            evw_robot_despawned.send(RobotDespawned(robot));
+        }
+    }
+}
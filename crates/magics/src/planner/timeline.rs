@@ -0,0 +1,270 @@
+//! Execution of the [`Timeline`] of a simulation.
+//!
+//! Every action of the timeline is executed once the virtual time since the
+//! simulation was loaded has passed its time. Loading or reloading the
+//! simulation starts the timeline over, and unblocks the regions it blocked.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_notify::ToastEvent;
+use bevy_prng::WyRand;
+use bevy_rand::prelude::GlobalEntropy;
+use gbp_config::{
+    Config,
+    timeline::{self, Action, RobotSelection, Timeline},
+};
+use gbp_environment::{Environment, RegionKind};
+use rand::seq::IteratorRandom;
+
+use super::{
+    RobotConnections,
+    robot::{FormationIndex, GbpScheduleChanged, OfClass, RadioAntenna, Stopped},
+    spawner::SpawningPaused,
+};
+use crate::{
+    environment::{
+        dynamic_obstacles::{ObstacleStates, PendingSdf, replan_global_paths},
+        map_generator::KeepOutColliders,
+    },
+    export::events::Export,
+    factorgraph::prelude::FactorGraph,
+    input::{DrawSettingsEvent, screenshot::TakeScreenshot},
+    simulation_loader::{LoadSimulation, ReloadSimulation, hot_reload::apply_live_settings},
+};
+
+pub struct TimelinePlugin;
+
+impl Plugin for TimelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RegionsChanged>()
+            .init_resource::<TimelineProgress>()
+            .init_resource::<BlockedRegions>()
+            .add_systems(
+                Update,
+                reset_timeline
+                    .run_if(on_event::<LoadSimulation>().or_else(on_event::<ReloadSimulation>())),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    execute_timeline.run_if(resource_exists::<Timeline>),
+                    (
+                        rebuild_keep_out_regions,
+                        replan_global_paths
+                            .run_if(resource_exists::<gbp_global_planner::Colliders>),
+                    )
+                        .chain()
+                        .run_if(on_event::<RegionsChanged>()),
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// **Bevy** [`Event`] sent when the timeline has blocked or unblocked regions
+/// of the environment
+#[derive(Debug, Clone, Copy, Event)]
+pub struct RegionsChanged;
+
+/// **Bevy** [`Resource`] with how far the timeline has come
+#[derive(Debug, Default, Resource)]
+struct TimelineProgress {
+    /// Elapsed virtual time when the simulation was loaded, the times of the
+    /// timeline are relative to this
+    loaded_at: f32,
+    /// Index of the next event of the timeline to execute
+    next: usize,
+}
+
+/// **Bevy** [`Resource`] with the kind every blocked region had before it was
+/// blocked, by the name of the region
+#[derive(Debug, Default, Resource, Deref, DerefMut)]
+struct BlockedRegions(HashMap<String, RegionKind>);
+
+fn reset_timeline(
+    mut evr_load_simulation: EventReader<LoadSimulation>,
+    mut evw_regions_changed: EventWriter<RegionsChanged>,
+    mut progress: ResMut<TimelineProgress>,
+    mut blocked_regions: ResMut<BlockedRegions>,
+    mut env_config: ResMut<Environment>,
+    time: Res<Time<Virtual>>,
+) {
+    *progress = TimelineProgress {
+        loaded_at: time.elapsed_seconds(),
+        next: 0,
+    };
+
+    // Loading a simulation replaces the environment, while reloading keeps it
+    if evr_load_simulation.read().count() > 0 {
+        blocked_regions.clear();
+    } else if !blocked_regions.is_empty() {
+        for (name, kind) in blocked_regions.drain() {
+            if let Some(region) = env_config.regions.get_mut(&name) {
+                region.kind = kind;
+            }
+        }
+        evw_regions_changed.send(RegionsChanged);
+    }
+}
+
+/// The robots in `selection`, out of `robots`
+fn select(
+    selection: RobotSelection,
+    robots: &Query<(Entity, &FormationIndex), With<RobotConnections>>,
+    rng: &mut GlobalEntropy<WyRand>,
+) -> Vec<Entity> {
+    match selection {
+        RobotSelection::All => robots.iter().map(|(robot, _)| robot).collect(),
+        RobotSelection::Formation(index) => robots
+            .iter()
+            .filter(|(_, formation)| formation.0 == index)
+            .map(|(robot, _)| robot)
+            .collect(),
+        RobotSelection::Fraction(fraction) => {
+            let all: Vec<Entity> = robots.iter().map(|(robot, _)| robot).collect();
+            #[allow(
+                clippy::cast_precision_loss,
+                clippy::cast_possible_truncation,
+                clippy::cast_sign_loss
+            )]
+            let amount = (fraction.get() * all.len() as f64).round() as usize;
+            all.into_iter().choose_multiple(rng, amount)
+        }
+    }
+}
+
+/// **Bevy** system that executes the actions of the timeline whose time has
+/// come
+#[allow(clippy::too_many_arguments, clippy::too_many_lines)]
+fn execute_timeline(
+    (
+        mut evw_gbp_schedule_changed,
+        mut evw_draw_settings,
+        mut evw_regions_changed,
+        mut evw_screenshot,
+        mut evw_export,
+        mut evw_toast,
+    ): (
+        EventWriter<GbpScheduleChanged>,
+        EventWriter<DrawSettingsEvent>,
+        EventWriter<RegionsChanged>,
+        EventWriter<TakeScreenshot>,
+        EventWriter<Export>,
+        EventWriter<ToastEvent>,
+    ),
+    mut progress: ResMut<TimelineProgress>,
+    mut blocked_regions: ResMut<BlockedRegions>,
+    mut spawning_paused: ResMut<SpawningPaused>,
+    mut config: ResMut<Config>,
+    mut env_config: ResMut<Environment>,
+    mut prng: ResMut<GlobalEntropy<WyRand>>,
+    mut factorgraphs: Query<(&mut FactorGraph, Option<&OfClass>)>,
+    mut antennas: Query<&mut RadioAntenna>,
+    mut stopped: Query<&mut Stopped>,
+    robots: Query<(Entity, &FormationIndex), With<RobotConnections>>,
+    timeline: Res<Timeline>,
+    time: Res<Time<Virtual>>,
+) {
+    let now = time.elapsed_seconds() - progress.loaded_at;
+
+    while let Some(event) = timeline.events.get(progress.next) {
+        if event.at.as_secs_f32() > now {
+            break;
+        }
+        progress.next += 1;
+        info!("timeline at {:.2}s: {:?}", now, event.action);
+
+        match &event.action {
+            Action::SetConfig { key, value } => {
+                match timeline::set_config(&config, key, value.clone()) {
+                    Ok(new_config) => {
+                        let previous_draw = config.visualisation.draw;
+                        *config = new_config;
+                        apply_live_settings(
+                            &config,
+                            previous_draw,
+                            &mut factorgraphs,
+                            &mut evw_gbp_schedule_changed,
+                            &mut evw_draw_settings,
+                        );
+                    }
+                    Err(err) => {
+                        error!("timeline failed to set '{}': {}", key, err);
+                        evw_toast.send(ToastEvent::warning(err.to_string()));
+                    }
+                }
+            }
+            Action::KnockOutComms { robots: selection }
+            | Action::RestoreComms { robots: selection } => {
+                let knocked_out = matches!(event.action, Action::KnockOutComms { .. });
+                for robot in select(*selection, &robots, &mut prng) {
+                    if let Ok(mut antenna) = antennas.get_mut(robot) {
+                        antenna.knocked_out = knocked_out;
+                        antenna.active &= !knocked_out;
+                    }
+                }
+            }
+            Action::StopRobots { robots: selection }
+            | Action::ResumeRobots { robots: selection } => {
+                let stop = matches!(event.action, Action::StopRobots { .. });
+                for robot in select(*selection, &robots, &mut prng) {
+                    if let Ok(mut stopped) = stopped.get_mut(robot) {
+                        stopped.0 = stop;
+                    }
+                }
+            }
+            Action::BlockRegion { region: name } => {
+                let Some(region) = env_config.regions.get_mut(name) else {
+                    warn!("timeline cannot block unknown region '{}'", name);
+                    evw_toast.send(ToastEvent::warning(format!("unknown region '{name}'")));
+                    continue;
+                };
+                let kind = std::mem::replace(&mut region.kind, RegionKind::KeepOut { cost: 1.0 });
+                // Blocking a region twice keeps the kind it had at first
+                blocked_regions.entry(name.clone()).or_insert(kind);
+                evw_regions_changed.send(RegionsChanged);
+            }
+            Action::UnblockRegion { region: name } => {
+                let Some(kind) = blocked_regions.remove(name) else {
+                    warn!(
+                        "timeline cannot unblock region '{}', as it is not blocked",
+                        name
+                    );
+                    continue;
+                };
+                if let Some(region) = env_config.regions.get_mut(name) {
+                    region.kind = kind;
+                }
+                evw_regions_changed.send(RegionsChanged);
+            }
+            Action::PauseSpawning => **spawning_paused = true,
+            Action::ResumeSpawning => **spawning_paused = false,
+            Action::Screenshot => {
+                evw_screenshot.send(TakeScreenshot::default());
+            }
+            Action::Export => {
+                evw_export.send(Export {
+                    toast: true,
+                    ..Default::default()
+                });
+            }
+        }
+    }
+}
+
+/// **Bevy** system that rebuilds everything derived from the keep-out regions
+/// of the environment, i.e. the [`KeepOutColliders`] avoided by the global
+/// planner, and in the background the SDF used by the obstacle factors
+fn rebuild_keep_out_regions(
+    mut commands: Commands,
+    mut pending_sdf: ResMut<PendingSdf>,
+    env_config: Res<Environment>,
+    obstacle_states: Res<ObstacleStates>,
+    time: Res<Time<Virtual>>,
+) {
+    commands.insert_resource(KeepOutColliders::from_environment(&env_config));
+
+    let t = time.elapsed_seconds() - obstacle_states.loaded_at();
+    pending_sdf.rebuild(env_config.active_at(t));
+}
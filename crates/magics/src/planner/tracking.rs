@@ -138,6 +138,8 @@ fn track_positions(
 #[derive(Clone, Copy, serde::Serialize)]
 pub struct VelocityMeasurement {
     pub velocity: Vec3,
+    /// Position at the end of the measurement
+    pub position: Vec3,
     // pub timestamp:     Instant,
     pub timestamp: f64,
     pub measured_over: Duration,
@@ -221,6 +223,7 @@ fn track_velocities(
                 let dt = now - previous_position.timestamp;
                 let measurement = VelocityMeasurement {
                     velocity: (transform.translation - previous_position.position) / dt as f32,
+                    position: transform.translation,
                     timestamp: now,
                     measured_over: Duration::from_secs_f64(dt),
                 };
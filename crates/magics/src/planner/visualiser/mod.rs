@@ -4,6 +4,7 @@ pub mod communication_radius;
 pub mod factorgraphs;
 mod interrobot;
 mod obstacle;
+mod region;
 mod robot;
 mod tracer;
 mod tracking;
@@ -41,6 +42,7 @@ impl Plugin for VisualiserPlugin {
             interrobot::InterRobotFactorVisualizerPlugin,
             collider::ColliderVisualizerPlugin,
             tracking::TrackingVisualizerPlugin,
+            region::RegionVisualizerPlugin,
         ));
     }
 }
@@ -0,0 +1,60 @@
+//! Visualise the semantic regions of the environment, e.g. speed zones,
+//! one-way lanes and keep-out zones.
+
+use bevy::prelude::*;
+use gbp_config::Config;
+use gbp_environment::{Environment, RegionKind};
+use itertools::Itertools;
+
+#[derive(Default)]
+pub struct RegionVisualizerPlugin;
+
+impl Plugin for RegionVisualizerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, render.run_if(enabled));
+    }
+}
+
+/// **Bevy** run condition for drawing regions
+fn enabled(config: Res<Config>) -> bool {
+    config.visualisation.draw.regions
+}
+
+const fn color(kind: &RegionKind) -> Color {
+    match kind {
+        RegionKind::SpeedLimit { .. } => Color::YELLOW,
+        RegionKind::OneWay { .. } => Color::CYAN,
+        RegionKind::KeepOut { .. } => Color::RED,
+        RegionKind::SharedSpace { .. } => Color::GREEN,
+    }
+}
+
+/// **Bevy** system that draws the outline of every region, and an arrow in
+/// the preferred direction of one-way regions
+#[allow(clippy::cast_precision_loss)]
+fn render(mut gizmos: Gizmos, config: Res<Config>, env_config: Res<Environment>) {
+    let height = -config.visualisation.height.objects;
+    let to_3d = |p: Vec2| Vec3::new(p.x, height, p.y);
+
+    for region in env_config.world_regions() {
+        let color = color(&region.kind);
+        for polygon in &region.polygons {
+            let Some(first) = polygon.first() else {
+                continue;
+            };
+            for (p1, p2) in polygon.iter().chain(std::iter::once(first)).tuple_windows() {
+                gizmos.line(to_3d(*p1), to_3d(*p2), color);
+            }
+
+            if let Some(direction) = region.kind.direction() {
+                let centroid = polygon.iter().sum::<Vec2>() / polygon.len() as f32;
+                let length = env_config.tile_size() / 4.0;
+                gizmos.arrow(
+                    to_3d(centroid - direction * length / 2.0),
+                    to_3d(centroid + direction * length / 2.0),
+                    color,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,578 @@
+//! Recording of the simulation to an image sequence or an animation.
+//!
+//! When started with `--record`, a recording is started whenever a simulation
+//! is loaded or reloaded. Frames are captured at
+//! [`RecordingSection::fps`](gbp_config::RecordingSection::fps) frames per
+//! second of simulated time, so the recording plays back at the speed of the
+//! simulation, no matter how fast the application renders. A frame rendered
+//! after several frame intervals have passed is shown for all of them.
+//!
+//! The frames are written to `recording_<scenario>_<n>/`, with a
+//! `frames.ffconcat` manifest giving the duration of every frame, or encoded
+//! to `recording_<scenario>_<n>.gif` or `recording_<scenario>_<n>.png` when
+//! the recording finishes.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    app::AppExit, diagnostic::DiagnosticsStore, prelude::*,
+    render::view::screenshot::ScreenshotManager, tasks::AsyncComputeTaskPool,
+    window::PrimaryWindow,
+};
+use gbp_config::{Config, RecordingOutput};
+
+use crate::{
+    asset_loader::Fonts,
+    diagnostic::prelude::RobotDiagnosticsPlugin,
+    planner::spawner::AllFormationsFinished,
+    simulation_loader::{LoadSimulation, ReloadSimulation, SimulationManager},
+};
+
+pub struct RecorderPlugin {
+    /// Whether to record, set with `--record`
+    record: bool,
+}
+
+impl RecorderPlugin {
+    pub const fn new(record: bool) -> Self {
+        Self { record }
+    }
+}
+
+impl Plugin for RecorderPlugin {
+    fn build(&self, app: &mut App) {
+        if !self.record {
+            return;
+        }
+
+        app.init_resource::<Recorder>()
+            .add_systems(
+                Update,
+                (
+                    start_recording.run_if(
+                        on_event::<LoadSimulation>().or_else(on_event::<ReloadSimulation>()),
+                    ),
+                    stop_recording_on_finish.run_if(on_event::<AllFormationsFinished>()),
+                    finish_stopped_recording
+                        .run_if(|recorder: Res<Recorder>| recorder.is_stopped()),
+                    update_overlay.run_if(|recorder: Res<Recorder>| recorder.is_recording()),
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                capture_frame.run_if(|recorder: Res<Recorder>| recorder.is_recording()),
+            )
+            .add_systems(Last, finish_recording_on_exit.run_if(on_event::<AppExit>()));
+    }
+}
+
+/// Updates to wait for the frames of a stopped recording to be written,
+/// before finishing it without the frames that never arrived
+const MAX_UPDATES_TO_WAIT_FOR_FRAMES: usize = 60;
+
+/// A recording of the running simulation
+struct Recording {
+    /// Basename of the recording, e.g. `recording_junction_0`
+    name: String,
+    /// Directory the frames are written to
+    dir: PathBuf,
+    output: RecordingOutput,
+    fps: usize,
+    /// Elapsed virtual time when the recording started
+    started_at: f64,
+    /// The frame interval every requested frame was captured in, in order
+    frames: Vec<u64>,
+    /// Number of requested frames that have been written, or failed to
+    written: Arc<AtomicUsize>,
+    /// Updates since the recording was stopped, if it has been
+    stopped_for: Option<usize>,
+}
+
+impl Recording {
+    /// The frame interval the simulation is in, at elapsed virtual time
+    /// `elapsed`
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn interval_at(&self, elapsed: f64) -> u64 {
+        ((elapsed - self.started_at).max(0.0) * self.fps as f64) as u64
+    }
+
+    fn frame_path(&self, frame: usize) -> PathBuf {
+        self.dir.join(format!("frame_{frame:06}.png"))
+    }
+
+    fn all_frames_written(&self) -> bool {
+        self.written.load(Ordering::Acquire) >= self.frames.len()
+    }
+
+    /// The written frames, and how many frame intervals each of them is shown
+    /// for. Frames that were requested but never written are left out.
+    fn written_frames(&self) -> Vec<(PathBuf, u64)> {
+        let frames: Vec<(PathBuf, u64)> = self
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(i, &interval)| (self.frame_path(i), interval))
+            .filter(|(path, _)| path.exists())
+            .collect();
+
+        let next_intervals = frames
+            .iter()
+            .skip(1)
+            .map(|(_, interval)| *interval)
+            .chain(frames.last().map(|(_, interval)| interval + 1));
+        frames
+            .iter()
+            .zip(next_intervals)
+            .map(|((path, interval), next)| (path.clone(), next - interval))
+            .collect()
+    }
+
+    /// Write the manifest of the frames, or encode them to an animation, and
+    /// return the path of the result
+    fn finish(self) -> anyhow::Result<PathBuf> {
+        let frames = self.written_frames();
+        if frames.is_empty() {
+            anyhow::bail!("no frames were captured");
+        }
+
+        let path = match self.output {
+            RecordingOutput::ImageSequence => self.dir.join("frames.ffconcat"),
+            RecordingOutput::Gif => self.dir.with_file_name(format!("{}.gif", self.name)),
+            RecordingOutput::Apng => self.dir.with_file_name(format!("{}.png", self.name)),
+        };
+        match self.output {
+            RecordingOutput::ImageSequence => write_ffconcat(&frames, self.fps, &self.name, &path)?,
+            RecordingOutput::Gif => encode_gif(&frames, self.fps, &path)?,
+            RecordingOutput::Apng => encode_apng(&frames, self.fps, &path)?,
+        }
+        // The frames of an animation are only kept until it is encoded
+        if self.output != RecordingOutput::ImageSequence {
+            std::fs::remove_dir_all(&self.dir)?;
+        }
+        Ok(path)
+    }
+}
+
+/// **Bevy** [`Resource`] with the recording of the running simulation
+#[derive(Resource, Default)]
+struct Recorder(Option<Recording>);
+
+impl Recorder {
+    fn is_recording(&self) -> bool {
+        self.0
+            .as_ref()
+            .is_some_and(|recording| recording.stopped_for.is_none())
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.0
+            .as_ref()
+            .is_some_and(|recording| recording.stopped_for.is_some())
+    }
+}
+
+/// **Bevy** marker [`Component`] for the text drawn on top of the recording
+#[derive(Component)]
+struct RecordingOverlayText;
+
+/// Log where a finished recording was saved to, or why it failed
+fn report(result: anyhow::Result<PathBuf>) {
+    match result {
+        Ok(path) => info!("saved recording to '{}'", path.display()),
+        Err(err) => error!("failed to save recording: {err}"),
+    }
+}
+
+/// Finish `recording` in the background, as encoding an animation takes a
+/// while
+fn finish_in_background(recording: Recording) {
+    AsyncComputeTaskPool::get()
+        .spawn(async move { report(recording.finish()) })
+        .detach();
+}
+
+/// **Bevy** system that finishes the previous recording, and starts a new
+/// one, whenever a simulation is loaded or reloaded
+#[allow(clippy::too_many_arguments)]
+fn start_recording(
+    mut commands: Commands,
+    mut recorder: ResMut<Recorder>,
+    mut evw_toast: EventWriter<bevy_notify::ToastEvent>,
+    q_overlay: Query<Entity, With<RecordingOverlayText>>,
+    sim_manager: Res<SimulationManager>,
+    config: Res<Config>,
+    fonts: Res<Fonts>,
+    time_virtual: Res<Time<Virtual>>,
+) {
+    if let Some(recording) = recorder.0.take() {
+        finish_in_background(recording);
+    }
+    for entity in &q_overlay {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let environment = sim_manager.active_name().unwrap_or_default();
+    let prefix = format!("recording_{}_", environment.to_lowercase());
+    let name = format!("{prefix}{}", crate::export::next_export_number(&prefix));
+    let dir = std::env::current_dir()
+        .expect("current directory exists")
+        .join(&name);
+    if let Err(err) = std::fs::create_dir_all(&dir) {
+        let err_msg = format!("Failed to create {}: {}", dir.display(), err);
+        error!(err_msg);
+        evw_toast.send(bevy_notify::ToastEvent::error(err_msg));
+        return;
+    }
+
+    info!("recording to '{}'", dir.display());
+    recorder.0 = Some(Recording {
+        name,
+        dir,
+        output: config.recording.output,
+        fps: config.recording.fps.get(),
+        started_at: time_virtual.elapsed_seconds_f64(),
+        frames: Vec::new(),
+        written: Arc::new(AtomicUsize::new(0)),
+        stopped_for: None,
+    });
+
+    if config.recording.overlay.any() {
+        commands.spawn((
+            RecordingOverlayText,
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: fonts.main.clone(),
+                    font_size: 18.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(8.0),
+                top: Val::Px(8.0),
+                padding: UiRect::all(Val::Px(4.0)),
+                ..default()
+            })
+            .with_background_color(Color::BLACK.with_a(0.5)),
+        ));
+    }
+}
+
+/// **Bevy** system that requests a screenshot of the primary window, when the
+/// simulation has entered a new frame interval since the last one
+fn capture_frame(
+    mut recorder: ResMut<Recorder>,
+    mut screenshot_manager: ResMut<ScreenshotManager>,
+    primary_window: Query<Entity, With<PrimaryWindow>>,
+    time_virtual: Res<Time<Virtual>>,
+) {
+    let Some(recording) = recorder.0.as_mut() else {
+        return;
+    };
+    let Ok(window) = primary_window.get_single() else {
+        return;
+    };
+
+    let interval = recording.interval_at(time_virtual.elapsed_seconds_f64());
+    if recording
+        .frames
+        .last()
+        .is_some_and(|&last| interval <= last)
+    {
+        return;
+    }
+
+    let path = recording.frame_path(recording.frames.len());
+    let written = Arc::clone(&recording.written);
+    let requested = screenshot_manager.take_screenshot(window, move |image| {
+        AsyncComputeTaskPool::get()
+            .spawn(async move {
+                if let Err(err) = save_frame(image, &path) {
+                    error!("failed to save frame to {}: {}", path.display(), err);
+                }
+                written.fetch_add(1, Ordering::Release);
+            })
+            .detach();
+    });
+
+    // Another screenshot has been requested this frame, the frame is captured
+    // in the next one instead
+    if requested.is_ok() {
+        recording.frames.push(interval);
+    }
+}
+
+/// Write a screenshot as a PNG
+fn save_frame(image: Image, path: &Path) -> anyhow::Result<()> {
+    // The image crate of bevy is a different version, so the pixels are moved
+    // over as raw bytes
+    let rgba = image.try_into_dynamic()?.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let frame = image::RgbaImage::from_raw(width, height, rgba.into_raw())
+        .expect("the buffer has the size of the image");
+    frame.save(path)?;
+    Ok(())
+}
+
+/// **Bevy** system that stops the recording when every formation has
+/// finished, if enabled
+fn stop_recording_on_finish(mut recorder: ResMut<Recorder>, config: Res<Config>) {
+    if !config.recording.stop_on_finish {
+        return;
+    }
+    if let Some(recording) = recorder.0.as_mut() {
+        info!("every formation has finished, stopping the recording");
+        recording.stopped_for.get_or_insert(0);
+    }
+}
+
+/// **Bevy** system that finishes a stopped recording, once its frames have
+/// been written
+fn finish_stopped_recording(
+    mut commands: Commands,
+    mut recorder: ResMut<Recorder>,
+    q_overlay: Query<Entity, With<RecordingOverlayText>>,
+) {
+    let Some(recording) = recorder.0.as_mut() else {
+        return;
+    };
+    let stopped_for = recording.stopped_for.get_or_insert(0);
+    *stopped_for += 1;
+    if !recording.all_frames_written() && *stopped_for < MAX_UPDATES_TO_WAIT_FOR_FRAMES {
+        return;
+    }
+
+    for entity in &q_overlay {
+        commands.entity(entity).despawn_recursive();
+    }
+    if let Some(recording) = recorder.0.take() {
+        finish_in_background(recording);
+    }
+}
+
+/// **Bevy** system that finishes the recording when the application exits.
+/// The application does not wait for background tasks, so the recording is
+/// finished on the main thread.
+fn finish_recording_on_exit(mut recorder: ResMut<Recorder>) {
+    let Some(recording) = recorder.0.take() else {
+        return;
+    };
+
+    // Frames requested in the last updates may never be captured, so only
+    // wait a moment for them
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while !recording.all_frames_written() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    report(recording.finish());
+}
+
+/// **Bevy** system that updates the text drawn on top of the recording
+fn update_overlay(
+    mut q_overlay: Query<&mut Text, With<RecordingOverlayText>>,
+    recorder: Res<Recorder>,
+    sim_manager: Res<SimulationManager>,
+    diagnostics: Res<DiagnosticsStore>,
+    config: Res<Config>,
+    time_virtual: Res<Time<Virtual>>,
+) {
+    let Some(recording) = recorder.0.as_ref() else {
+        return;
+    };
+    let overlay = &config.recording.overlay;
+
+    let mut lines = Vec::new();
+    if overlay.scenario {
+        lines.push(sim_manager.active_name().unwrap_or_default().to_string());
+    }
+    if overlay.time {
+        let elapsed = time_virtual.elapsed_seconds_f64() - recording.started_at;
+        lines.push(format!("t = {elapsed:.2} s"));
+    }
+    if overlay.metrics {
+        for (name, diagnostic_path) in [
+            ("robots", &RobotDiagnosticsPlugin::ROBOT_COUNT),
+            ("collisions", &RobotDiagnosticsPlugin::ROBOT_COLLISION_COUNT),
+        ] {
+            #[allow(clippy::cast_possible_truncation)]
+            if let Some(value) = diagnostics
+                .get_measurement(diagnostic_path)
+                .map(|d| d.value as i64)
+            {
+                lines.push(format!("{name}: {value}"));
+            }
+        }
+        // only measured for robots in lifelong operation
+        if let Some(value) = diagnostics
+            .get_measurement(&RobotDiagnosticsPlugin::GOALS_PER_MINUTE)
+            .map(|d| d.value)
+        {
+            lines.push(format!("goals/min: {value:.2}"));
+        }
+    }
+
+    let value = lines.join("\n");
+    for mut text in &mut q_overlay {
+        text.sections[0].value.clone_from(&value);
+    }
+}
+
+/// Write an `ffconcat` manifest of `frames`, each shown for its number of
+/// frame intervals, which `ffmpeg` can encode to a video
+#[allow(clippy::cast_precision_loss)]
+fn write_ffconcat(
+    frames: &[(PathBuf, u64)],
+    fps: usize,
+    name: &str,
+    path: &Path,
+) -> anyhow::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "ffconcat version 1.0")?;
+    writeln!(
+        file,
+        "# ffmpeg -f concat -i frames.ffconcat -fps_mode vfr -pix_fmt yuv420p {name}.mp4"
+    )?;
+    for (frame, intervals) in frames {
+        let file_name = frame
+            .file_name()
+            .expect("frames have a file name")
+            .to_string_lossy();
+        writeln!(file, "file '{file_name}'")?;
+        writeln!(file, "duration {:.6}", *intervals as f64 / fps as f64)?;
+    }
+    // ffmpeg ignores the duration of the last file, unless it is listed again
+    if let Some((last, _)) = frames.last() {
+        let file_name = last
+            .file_name()
+            .expect("frames have a file name")
+            .to_string_lossy();
+        writeln!(file, "file '{file_name}'")?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+/// Encode `frames` to an animated GIF, that loops forever
+#[allow(clippy::cast_possible_truncation)]
+fn encode_gif(frames: &[(PathBuf, u64)], fps: usize, path: &Path) -> anyhow::Result<()> {
+    use image::codecs::gif::{GifEncoder, Repeat};
+
+    let mut encoder = GifEncoder::new(BufWriter::new(File::create(path)?));
+    encoder.set_repeat(Repeat::Infinite)?;
+    for (frame, intervals) in frames {
+        let delay = image::Delay::from_numer_denom_ms(*intervals as u32 * 1000, fps as u32);
+        let buffer = image::open(frame)?.to_rgba8();
+        encoder.encode_frame(image::Frame::from_parts(buffer, 0, 0, delay))?;
+    }
+    Ok(())
+}
+
+/// The delays of the APNG frames showing a frame for `intervals`. The delay
+/// of an APNG frame is at most `u16::MAX` intervals, so a frame shown for
+/// longer is repeated.
+#[allow(clippy::cast_possible_truncation)]
+fn apng_delays(intervals: u64) -> impl Iterator<Item = u16> {
+    let max = u64::from(u16::MAX);
+    (0..intervals.div_ceil(max)).map(move |i| (intervals - i * max).min(max) as u16)
+}
+
+/// Encode `frames` to an animated PNG, that loops forever
+#[allow(clippy::cast_possible_truncation)]
+fn encode_apng(frames: &[(PathBuf, u64)], fps: usize, path: &Path) -> anyhow::Result<()> {
+    let Some((first, _)) = frames.first() else {
+        anyhow::bail!("an animation needs at least one frame");
+    };
+    let (width, height) = image::image_dimensions(first)?;
+    let fps = u16::try_from(fps)
+        .map_err(|_| anyhow::anyhow!("APNG supports at most {} fps", u16::MAX))?;
+    let delays: Vec<Vec<u16>> = frames
+        .iter()
+        .map(|(_, intervals)| apng_delays(*intervals).collect())
+        .collect();
+
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(delays.iter().map(Vec::len).sum::<usize>() as u32, 0)?;
+    let mut writer = encoder.write_header()?;
+    for ((frame, _), delays) in frames.iter().zip(&delays) {
+        let data = image::open(frame)?.to_rgba8();
+        for &delay in delays {
+            writer.set_frame_delay(delay, fps)?;
+            writer.write_image_data(data.as_raw())?;
+        }
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::export::empty_test_dir;
+
+    fn recording(dir: PathBuf, frames: Vec<u64>) -> Recording {
+        Recording {
+            name: "recording_test_0".to_string(),
+            dir,
+            output: RecordingOutput::ImageSequence,
+            fps: 10,
+            started_at: 2.0,
+            frames,
+            written: Arc::new(AtomicUsize::new(0)),
+            stopped_for: None,
+        }
+    }
+
+    #[test]
+    fn intervals_are_counted_from_the_start_of_the_recording() {
+        let recording = recording(PathBuf::new(), Vec::new());
+        assert_eq!(recording.interval_at(1.0), 0);
+        assert_eq!(recording.interval_at(2.05), 0);
+        assert_eq!(recording.interval_at(2.35), 3);
+    }
+
+    #[test]
+    fn frames_are_shown_until_the_next_frame() {
+        let recording = recording(empty_test_dir("recorder"), vec![0, 1, 4, 5]);
+        // the third frame was never written
+        for frame in [0, 1, 3] {
+            File::create(recording.frame_path(frame)).expect("temp dir is writable");
+        }
+
+        let intervals: Vec<u64> = recording
+            .written_frames()
+            .into_iter()
+            .map(|(_, intervals)| intervals)
+            .collect();
+
+        assert_eq!(intervals, vec![1, 4, 1]);
+    }
+
+    #[test]
+    fn long_apng_delays_are_split() {
+        let max = u16::MAX;
+        assert_eq!(apng_delays(3).collect::<Vec<_>>(), vec![3]);
+        assert_eq!(apng_delays(u64::from(max)).collect::<Vec<_>>(), vec![max]);
+        assert_eq!(
+            apng_delays(2 * u64::from(max) + 7).collect::<Vec<_>>(),
+            vec![max, max, 7]
+        );
+    }
+}
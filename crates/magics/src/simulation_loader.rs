@@ -1,5 +1,8 @@
+pub mod hot_reload;
+
 use std::{
     collections::{BTreeMap, VecDeque},
+    path::{Path, PathBuf},
     sync::Arc,
     time::Duration,
 };
@@ -10,7 +13,7 @@ use bevy::{
     time::common_conditions::{on_real_timer, on_timer},
 };
 use bevy_notify::{ToastEvent, ToastLevel, ToastOptions};
-use gbp_config::{Config, FormationGroup};
+use gbp_config::{Config, FormationGroup, tasks::TaskPool, timeline::Timeline};
 use gbp_environment::Environment;
 use smol_str::SmolStr;
 
@@ -51,12 +54,62 @@ impl Default for SimulationLoaderPlugin {
 // }
 
 pub type SdfImage = image::ImageBuffer<image::Rgb<u8>, Vec<u8>>;
-pub type SharedSdfImage = Arc<SdfImage>;
+/// Either a [`SdfImage`] of the whole environment, or an
+/// [`env_to_png::TiledSdf`] that renders it in chunks on demand.
+pub type SharedSdfImage = Arc<dyn env_to_png::SdfSampler>;
 pub type RawImage = image::ImageBuffer<image::Rgb<u8>, Vec<u8>>;
 
 #[derive(Debug, Clone, Resource, Deref, DerefMut)]
 pub struct Sdf(pub SharedSdfImage);
 
+/// **Bevy** [`Resource`] with the SDF of every floor above the ground floor,
+/// whose SDF is [`Sdf`]
+#[derive(Debug, Clone, Default, Resource)]
+pub struct FloorSdfs(Vec<SharedSdfImage>);
+
+impl FloorSdfs {
+    /// Generate the SDF of every floor of `environment` above the ground floor
+    pub fn build(environment: &Environment) -> Self {
+        Self(
+            (1..environment.floor_count())
+                .filter_map(|floor| environment.floor(floor))
+                .map(|floor| build_sdf(&floor))
+                .collect(),
+        )
+    }
+
+    /// The SDF of floor number `floor`, where floor `0` uses the `ground` SDF
+    pub fn get(&self, floor: usize, ground: &Sdf) -> Option<SharedSdfImage> {
+        match floor {
+            0 => Some(Arc::clone(&ground.0)),
+            _ => self.0.get(floor - 1).cloned(),
+        }
+    }
+}
+
+/// Generate the SDF of `environment`, using its SDF settings
+pub fn build_sdf(environment: &Environment) -> SharedSdfImage {
+    let sdf_settings = &environment.tiles.settings.sdf;
+    let resolution = env_to_png::PixelsPerTile::new(sdf_settings.resolution);
+    let expansion = env_to_png::Percentage::new(sdf_settings.expansion);
+    let blur = env_to_png::Percentage::new(sdf_settings.blur);
+    if let Some(chunks) = &sdf_settings.chunks {
+        Arc::new(env_to_png::TiledSdf::new(
+            environment.clone(),
+            resolution,
+            expansion,
+            blur,
+            chunks.size,
+            chunks.memory_budget as usize * 1024 * 1024,
+        ))
+    } else {
+        Arc::new(
+            env_to_png::env_to_sdf_image(environment, resolution, expansion, blur)
+                .expect("it all just works"),
+        )
+    }
+}
+
 #[derive(Debug, Clone, Resource, Deref, DerefMut)]
 pub struct Raw(pub RawImage);
 
@@ -66,7 +119,7 @@ pub struct Raw(pub RawImage);
 // struct Simulations(BTreeMap<String, Simulation>);
 type Simulations = BTreeMap<String, Simulation>;
 
-const SIMULATIONS_DIR: &'static str = "./config/scenarios";
+pub(crate) const SIMULATIONS_DIR: &'static str = "./config/scenarios";
 
 impl SimulationLoaderPlugin {
     pub fn new(initial_simulation: Option<String>) -> Self {
@@ -139,27 +192,8 @@ impl Plugin for SimulationLoaderPlugin {
                     .into_string()
                     .expect("failed to parse simulation name");
                 // println!("about to load: {name:?}");
-                let config_path = dir.path().join("config.toml");
-                let config = Config::from_file(config_path)
-                    .expect(format!("failed to load config for simulation: {name:?}").as_str());
-                let environment_path = dir.path().join("environment.yaml");
-                let environment = Environment::from_file(environment_path).expect(
-                    format!("failed to load environment for simulation: {name:?}").as_str(),
-                );
-                let formation_path = dir.path().join("formation.yaml");
-                let formation = FormationGroup::from_yaml_file(formation_path)
-                    .expect(format!("failed to load formation for simulation: {name:?}").as_str());
-
-                // println!("name: {name:?}");
-                let sdf_image_buffer = env_to_png::env_to_sdf_image(
-                    &environment,
-                    env_to_png::PixelsPerTile::new(
-                        environment.tiles.settings.sdf.resolution as u32,
-                    ),
-                    env_to_png::Percentage::new(environment.tiles.settings.sdf.expansion),
-                    env_to_png::Percentage::new(environment.tiles.settings.sdf.blur),
-                )
-                .expect("it all just works");
+                let simulation = Simulation::load(name.clone(), &dir.path())
+                    .unwrap_or_else(|err| panic!("failed to load simulation {name:?}: {err}"));
 
                 // let sdf_path = PathBuf::new()
                 //     .join("crates/magics/assets/imgs/obstacles")
@@ -179,15 +213,6 @@ impl Plugin for SimulationLoaderPlugin {
                 // let raw_image_buffer =
                 // image::io::Reader::open(raw_path).unwrap().decode().unwrap();
 
-                let simulation = Simulation {
-                    name: name.clone(),
-                    config,
-                    environment,
-                    formation_group: formation,
-                    sdf: Sdf(Arc::new(sdf_image_buffer.into())),
-                    // raw: Raw(raw_image_buffer.into()),
-                };
-
                 // println!("loaded: {name:?}");
 
                 (name, simulation)
@@ -217,6 +242,9 @@ impl Plugin for SimulationLoaderPlugin {
         let formation_group = initial_simulation.formation_group.clone();
         let environment = initial_simulation.environment.clone();
         let sdf = initial_simulation.sdf.clone();
+        let floor_sdfs = initial_simulation.floor_sdfs.clone();
+        let task_pool = initial_simulation.task_pool.clone();
+        let timeline = initial_simulation.timeline.clone();
         // let raw = initial_simulation.raw.clone();
 
         let initial_simulation_name = initial_simulation.name.clone();
@@ -230,6 +258,7 @@ impl Plugin for SimulationLoaderPlugin {
             .insert_resource(formation_group)
             .insert_resource(environment)
             .insert_resource(sdf)
+            .insert_resource(floor_sdfs)
             // .insert_resource(raw)
             .add_event::<ReloadSimulation>()
             .add_event::<LoadSimulation>()
@@ -245,7 +274,15 @@ impl Plugin for SimulationLoaderPlugin {
                     load_previous_simulation.run_if(input_just_pressed(KeyCode::F4)),
                     save_settings.run_if(on_event::<SaveSettings>()),
                 )
-            );
+            )
+            .add_plugins(hot_reload::HotReloadPlugin);
+
+        if let Some(task_pool) = task_pool {
+            app.insert_resource(task_pool);
+        }
+        if let Some(timeline) = timeline {
+            app.insert_resource(timeline);
+        }
 
         if let Some(after) = self.reload_after {
             app.add_systems(
@@ -270,9 +307,384 @@ pub struct Simulation {
     pub formation_group: FormationGroup,
     // pub sdf: Handle<Image>,
     pub sdf: Sdf,
+    pub floor_sdfs: FloorSdfs,
+    /// Tasks from the optional `tasks.yaml`
+    pub task_pool: Option<TaskPool>,
+    /// Scripted events from the optional `timeline.yaml`
+    pub timeline: Option<Timeline>,
+    /// The directory of the scenario, followed by the directories of the
+    /// scenarios it extends
+    pub dirs: Vec<PathBuf>,
     // pub raw: Raw,
 }
 
+impl Simulation {
+    /// Load the simulation named `name` from the `config.toml`,
+    /// `environment.yaml` and `formation.yaml` files in `dir`, and the
+    /// `tasks.yaml` and `timeline.yaml` files if there are any. The files of
+    /// the scenario it extends, if any, are merged in first.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if one of the files can not be read or parsed
+    pub fn load(name: String, dir: &Path) -> Result<Self, SimulationFileError> {
+        let ResolvedScenario {
+            config,
+            environment,
+            formation_group,
+            task_pool,
+            timeline,
+            dirs,
+        } = ResolvedScenario::load(dir)?;
+
+        let sdf = Sdf(build_sdf(&environment));
+        let floor_sdfs = FloorSdfs::build(&environment);
+
+        Ok(Self {
+            name,
+            config,
+            environment,
+            formation_group,
+            sdf,
+            floor_sdfs,
+            task_pool,
+            timeline,
+            dirs,
+        })
+    }
+}
+
+/// The config, environment, formation group, tasks and timeline of a scenario,
+/// with the files of the scenario it extends merged in. See
+/// [`gbp_config::scenario`].
+#[derive(Debug, Clone)]
+pub struct ResolvedScenario {
+    pub config: Config,
+    pub environment: Environment,
+    pub formation_group: FormationGroup,
+    /// Tasks from the optional `tasks.yaml`
+    pub task_pool: Option<TaskPool>,
+    /// Scripted events from the optional `timeline.yaml`
+    pub timeline: Option<Timeline>,
+    /// The directory of the scenario, followed by the directories of the
+    /// scenarios it extends
+    pub dirs: Vec<PathBuf>,
+}
+
+impl ResolvedScenario {
+    /// Resolve the scenario in `dir`
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if one of the files of the scenario, or of the
+    /// scenarios it extends, can not be read or parsed, or if the scenarios
+    /// extend each other in a cycle
+    pub fn load(dir: &Path) -> Result<Self, SimulationFileError> {
+        let mut dirs = Vec::new();
+        let files = ScenarioFiles::resolve(dir, &mut dirs)?;
+
+        let config = files.parse_config(dir)?;
+        let environment = files.parse(dir, "environment.yaml", |contents| {
+            Environment::parse(contents).map_err(|err| match err {
+                gbp_environment::ParseError::Yaml(ref yaml) => {
+                    (err.to_string(), yaml_location(yaml))
+                }
+                _ => (err.to_string(), None),
+            })
+        })?;
+        let formation_group = files.parse(dir, "formation.yaml", |contents| {
+            FormationGroup::parse_from_yaml(contents)
+                .map_err(|err| match err {
+                    gbp_config::formation::ParseError::Yaml(ref yaml) => {
+                        (err.to_string(), yaml_location(yaml))
+                    }
+                    gbp_config::formation::ParseError::Io(_) => (err.to_string(), None),
+                })
+                .and_then(|formation_group| {
+                    formation_group
+                        .validate(&config.robot)
+                        .map(|()| formation_group)
+                        .map_err(|err| (err.to_string(), None))
+                })
+        })?;
+        let task_pool = if files.contents.contains_key("tasks.yaml") {
+            Some(files.parse(dir, "tasks.yaml", |contents| {
+                TaskPool::parse_from_yaml(contents).map_err(|err| match err {
+                    gbp_config::tasks::ParseError::Yaml(ref yaml) => {
+                        (err.to_string(), yaml_location(yaml))
+                    }
+                    gbp_config::tasks::ParseError::Io(_) => (err.to_string(), None),
+                })
+            })?)
+        } else {
+            None
+        };
+        let timeline = if files.contents.contains_key("timeline.yaml") {
+            Some(files.parse(dir, "timeline.yaml", |contents| {
+                Timeline::parse_from_yaml(contents).map_err(|err| match err {
+                    gbp_config::timeline::ParseError::Yaml(ref yaml) => {
+                        (err.to_string(), yaml_location(yaml))
+                    }
+                    gbp_config::timeline::ParseError::Io(_) => (err.to_string(), None),
+                })
+            })?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            config,
+            environment,
+            formation_group,
+            task_pool,
+            timeline,
+            dirs,
+        })
+    }
+
+    /// Resolve only the config of the scenario in `dir`, and the directories
+    /// of the scenarios it extends, without parsing any of its other files
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the files of the scenario, or of the scenarios it
+    /// extends, can not be read, if one of their `config.toml` can not be
+    /// parsed, or if the scenarios extend each other in a cycle
+    pub fn load_config(dir: &Path) -> Result<(Config, Vec<PathBuf>), SimulationFileError> {
+        let mut dirs = Vec::new();
+        let files = ScenarioFiles::resolve(dir, &mut dirs)?;
+        Ok((files.parse_config(dir)?, dirs))
+    }
+}
+
+/// The contents of the files of a scenario, with the files of the scenario it
+/// extends merged in
+struct ScenarioFiles {
+    /// Contents of each file, by file name
+    contents: BTreeMap<&'static str, String>,
+    /// Whether the files have been merged with the files of an extended
+    /// scenario, in which case the location of a parse error would point into
+    /// the merged contents, rather than into a file
+    merged: bool,
+}
+
+impl ScenarioFiles {
+    /// The files of a scenario. `config.toml` is required, while the others
+    /// can be left out when the scenario extends another scenario.
+    const NAMES: [&'static str; 5] = [
+        "config.toml",
+        "environment.yaml",
+        "formation.yaml",
+        "tasks.yaml",
+        "timeline.yaml",
+    ];
+
+    /// Read the files of the scenario in `dir`, and merge them into the files
+    /// of the scenario it extends. `visited` are the directories of the
+    /// scenarios that extend this one, used to detect cycles.
+    fn resolve(dir: &Path, visited: &mut Vec<PathBuf>) -> Result<Self, SimulationFileError> {
+        let config_path = dir.join("config.toml");
+        let canonical = dir.canonicalize().map_err(|err| SimulationFileError {
+            path: config_path.clone(),
+            message: err.to_string(),
+            location: None,
+        })?;
+        if visited.contains(&canonical) {
+            return Err(SimulationFileError {
+                path: config_path,
+                message: "the scenario extends itself through the scenarios it extends".to_string(),
+                location: None,
+            });
+        }
+        visited.push(canonical);
+
+        let config_contents =
+            SimulationFileError::read(config_path.clone(), |contents| Ok(contents.to_string()))?;
+        let mut config = SimulationFileError::parse(config_path, &config_contents, |contents| {
+            toml::from_str::<toml::Value>(contents).map_err(|err| {
+                (
+                    err.message().to_string(),
+                    err.span().map(|span| line_and_column(contents, span.start)),
+                )
+            })
+        })?;
+
+        let mut contents = BTreeMap::from([("config.toml", config_contents)]);
+        for name in Self::NAMES.into_iter().skip(1) {
+            let path = dir.join(name);
+            if path.exists() {
+                let file = SimulationFileError::read(path, |contents| Ok(contents.to_string()))?;
+                contents.insert(name, file);
+            }
+        }
+
+        let Some(extends) = gbp_config::scenario::take_extends(&mut config) else {
+            return Ok(Self {
+                contents,
+                merged: false,
+            });
+        };
+
+        let mut base = Self::resolve(&dir.join(extends), visited)?;
+
+        let mut merged_config: toml::Value =
+            toml::from_str(&base.contents["config.toml"]).expect("parsed when it was resolved");
+        gbp_config::scenario::merge_toml(&mut merged_config, config);
+        let merged_config = toml::to_string(&merged_config).map_err(|err| SimulationFileError {
+            path: dir.join("config.toml"),
+            message: err.to_string(),
+            location: None,
+        })?;
+        base.contents.insert("config.toml", merged_config);
+
+        for name in Self::NAMES.into_iter().skip(1) {
+            let Some(overrides) = contents.remove(name) else {
+                continue;
+            };
+            let merged = match base.contents.remove(name) {
+                None => overrides,
+                Some(base) => {
+                    let path = dir.join(name);
+                    let overrides =
+                        SimulationFileError::parse(path.clone(), &overrides, |contents| {
+                            serde_yaml::from_str::<serde_yaml::Value>(contents)
+                                .map_err(|err| (err.to_string(), yaml_location(&err)))
+                        })?;
+                    let mut merged: serde_yaml::Value =
+                        serde_yaml::from_str(&base).map_err(|err| SimulationFileError {
+                            path: path.clone(),
+                            message: format!("in an extended scenario: {err}"),
+                            location: None,
+                        })?;
+                    gbp_config::scenario::merge_yaml(&mut merged, overrides);
+                    serde_yaml::to_string(&merged).map_err(|err| SimulationFileError {
+                        path,
+                        message: err.to_string(),
+                        location: None,
+                    })?
+                }
+            };
+            base.contents.insert(name, merged);
+        }
+
+        Ok(Self {
+            contents: base.contents,
+            merged: true,
+        })
+    }
+
+    /// Parse the file `name` with `parse`, which returns the error message and
+    /// location on failure
+    fn parse<T>(
+        &self,
+        dir: &Path,
+        name: &str,
+        parse: impl FnOnce(&str) -> Result<T, (String, Option<(usize, usize)>)>,
+    ) -> Result<T, SimulationFileError> {
+        let path = dir.join(name);
+        let Some(contents) = self.contents.get(name) else {
+            return Err(SimulationFileError {
+                path,
+                message: "not found, and not given by an extended scenario".to_string(),
+                location: None,
+            });
+        };
+
+        SimulationFileError::parse(path, contents, parse).map_err(|err| SimulationFileError {
+            location: if self.merged { None } else { err.location },
+            ..err
+        })
+    }
+
+    /// Parse the `config.toml` of the scenario in `dir`
+    fn parse_config(&self, dir: &Path) -> Result<Config, SimulationFileError> {
+        self.parse(dir, "config.toml", |contents| {
+            Config::parse(contents).map_err(|err| match err {
+                gbp_config::ParseError::Toml(err) => (
+                    err.message().to_string(),
+                    err.span().map(|span| line_and_column(contents, span.start)),
+                ),
+                gbp_config::ParseError::Io(err) => (err.to_string(), None),
+            })
+        })
+    }
+}
+
+/// Error from reading or parsing one of the files of a [`Simulation`]
+#[derive(Debug)]
+pub struct SimulationFileError {
+    pub path: PathBuf,
+    pub message: String,
+    /// Line and column of the error, both starting from 1
+    pub location: Option<(usize, usize)>,
+}
+
+impl std::fmt::Display for SimulationFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let Some((line, column)) = self.location {
+            write!(f, ":{line}:{column}")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl std::error::Error for SimulationFileError {}
+
+impl SimulationFileError {
+    /// Read the file at `path` and parse its contents with `parse`, which
+    /// returns the error message and location on failure
+    fn read<T>(
+        path: PathBuf,
+        parse: impl FnOnce(&str) -> Result<T, (String, Option<(usize, usize)>)>,
+    ) -> Result<T, Self> {
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                return Err(Self {
+                    path,
+                    message: err.to_string(),
+                    location: None,
+                });
+            }
+        };
+
+        Self::parse(path, &contents, parse)
+    }
+
+    /// Parse `contents` of the file at `path` with `parse`
+    fn parse<T>(
+        path: PathBuf,
+        contents: &str,
+        parse: impl FnOnce(&str) -> Result<T, (String, Option<(usize, usize)>)>,
+    ) -> Result<T, Self> {
+        parse(contents).map_err(|(message, location)| Self {
+            path,
+            message,
+            location,
+        })
+    }
+}
+
+/// Line and column of a YAML error, both starting from 1
+fn yaml_location(err: &serde_yaml::Error) -> Option<(usize, usize)> {
+    err.location()
+        .map(|location| (location.line(), location.column()))
+}
+
+/// Line and column of the byte `offset` in `contents`, both starting from 1
+fn line_and_column(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset.min(contents.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rfind('\n')
+        .map_or(before.chars().count(), |newline| {
+            before[newline + 1..].chars().count()
+        })
+        + 1;
+    (line, column)
+}
+
 #[derive(Debug, Resource)]
 pub struct SimulationManager {
     // _phantom_data: PhantomData<()>,
@@ -352,6 +764,9 @@ pub struct SimulationManager {
 enum Request {
     // LoadInitial,
     Load(SimulationId),
+    /// Load a simulation again, even if it is the active one, as its files
+    /// have changed
+    Refresh(SimulationId),
     Reload,
     // End,
 }
@@ -467,6 +882,30 @@ impl SimulationManager {
     //     self.simulations.get(id.0).map(|s| &s.formation_group)
     // }
 
+    /// Directory the active simulation was loaded from
+    pub fn active_dir(&self) -> Option<PathBuf> {
+        self.active_name()
+            .map(|name| Path::new(SIMULATIONS_DIR).join(name))
+    }
+
+    /// Replace the active simulation with `simulation`, e.g. after its files
+    /// have changed, and load it again
+    pub(crate) fn replace_active(&mut self, simulation: Simulation) {
+        let Some(index) = self.active else {
+            return;
+        };
+        self.simulations[index] = simulation;
+        self.requests
+            .push_back(Request::Refresh(SimulationId(index)));
+    }
+
+    /// Replace the config of the active simulation, without loading it again
+    pub(crate) fn set_active_config(&mut self, config: Config) {
+        if let Some(index) = self.active {
+            self.simulations[index].config = config;
+        }
+    }
+
     pub fn active_formation_group(&self) -> Option<&FormationGroup> {
         let index = self.active?;
         self.simulations.get(index).map(|s| &s.formation_group)
@@ -605,6 +1044,7 @@ fn handle_requests(
     // mut variable_timesteps: ResMut<VariableTimesteps>,
     mut environment: ResMut<Environment>,
     mut sdf: ResMut<Sdf>,
+    mut floor_sdfs: ResMut<FloorSdfs>,
     // mut raw: ResMut<Raw>,
     mut rng: ResMut<bevy_rand::prelude::GlobalEntropy<bevy_prng::WyRand>>,
     reloadable_entities: Query<Entity, With<Reloadable>>,
@@ -630,7 +1070,7 @@ fn handle_requests(
             warn!("simulation already loaded with id: {}", id.0);
             evw_toast.send(ToastEvent::warning("simulation already loaded"));
         }
-        Request::Load(id) => {
+        Request::Load(id) | Request::Refresh(id) => {
             for entity in &reloadable_entities {
                 // commands.entity(entity).despawn_recursive();
                 commands.entity(entity).despawn();
@@ -644,6 +1084,15 @@ fn handle_requests(
             // config.simulation.t0 =
             *environment = simulation_manager.simulations[id.0].environment.clone();
             *sdf = simulation_manager.simulations[id.0].sdf.clone();
+            *floor_sdfs = simulation_manager.simulations[id.0].floor_sdfs.clone();
+            match simulation_manager.simulations[id.0].task_pool.clone() {
+                Some(task_pool) => commands.insert_resource(task_pool),
+                None => commands.remove_resource::<TaskPool>(),
+            }
+            match simulation_manager.simulations[id.0].timeline.clone() {
+                Some(timeline) => commands.insert_resource(timeline),
+                None => commands.remove_resource::<Timeline>(),
+            }
 
             time_virtual.set_relative_speed(config.simulation.time_scale.get());
             // *raw = simulation_manager.simulations[id.0].raw.clone();
@@ -710,7 +1159,7 @@ fn handle_requests(
     }
 
     match request {
-        Request::Load(_) | Request::Reload => {
+        Request::Load(_) | Request::Refresh(_) | Request::Reload => {
             let is_paused = time_virtual.is_paused();
             // let relative_speed = time_virtual.
 
@@ -0,0 +1,312 @@
+//! Hot reloading of the files of the active simulation.
+//!
+//! The `config.toml`, `environment.yaml`, `formation.yaml`, `tasks.yaml` and
+//! `timeline.yaml` of the active simulation, and of the scenarios it extends,
+//! are polled for changes. Once they have stopped changing for
+//! [`HotReloadPlugin::DEBOUNCE`] the simulation is loaded again. If only the
+//! live settings of `config.toml` have changed, i.e. the factor sigmas, the
+//! GBP iteration schedule and the draw settings, they are applied to the
+//! running simulation instead, without respawning any robots.
+//!
+//! Files that fail to parse are reported with a toast, and the simulation
+//! keeps running as is.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use bevy::{prelude::*, time::common_conditions::on_real_timer};
+use bevy_notify::{ToastEvent, ToastLevel, ToastOptions};
+use gbp_config::{Config, DrawSection, DrawSetting, GbpSection};
+use struct_iterable::Iterable;
+
+use super::{ResolvedScenario, Simulation, SimulationFileError, SimulationManager};
+use crate::{
+    factorgraph::prelude::FactorGraph,
+    input::DrawSettingsEvent,
+    planner::robot::{GbpScheduleChanged, OfClass},
+};
+
+/// The files of a scenario that are watched
+const FILES: [&str; 5] = [
+    "config.toml",
+    "environment.yaml",
+    "formation.yaml",
+    "tasks.yaml",
+    "timeline.yaml",
+];
+
+pub struct HotReloadPlugin;
+
+impl HotReloadPlugin {
+    /// How long the files have to be left unchanged before they are reloaded,
+    /// as editors often save a file in several steps
+    pub const DEBOUNCE: Duration = Duration::from_millis(500);
+    /// How often the files are checked for changes
+    pub const POLL_EVERY: Duration = Duration::from_millis(250);
+}
+
+impl Plugin for HotReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WatchedFiles>()
+            .add_event::<SimulationFilesChanged>()
+            .add_systems(
+                Update,
+                (
+                    watch_files.run_if(on_real_timer(Self::POLL_EVERY)),
+                    reload_changed_files.run_if(on_event::<SimulationFilesChanged>()),
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// **Bevy** [`Event`] sent when files of the active simulation have changed,
+/// and have not changed since for [`HotReloadPlugin::DEBOUNCE`]
+#[derive(Debug, Event)]
+pub struct SimulationFilesChanged {
+    pub files: Vec<PathBuf>,
+}
+
+/// **Bevy** [`Resource`] with the state of the watched files
+#[derive(Debug, Default, Resource)]
+struct WatchedFiles {
+    /// Directories of the scenarios the files belong to, see
+    /// [`Simulation::dirs`]
+    dirs: Vec<PathBuf>,
+    /// Every one of [`FILES`] in every directory of `dirs`
+    files: Vec<PathBuf>,
+    /// Last modification time of each of `files`
+    modified: Vec<Option<SystemTime>>,
+    /// Files that have changed since they were last reloaded
+    changed: Vec<PathBuf>,
+    /// Elapsed real time when a file last changed
+    last_change: Option<Duration>,
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// **Bevy** system that checks the files of the active simulation, and of the
+/// scenarios it extends, for changes
+fn watch_files(
+    mut evw_simulation_files_changed: EventWriter<SimulationFilesChanged>,
+    mut watched: ResMut<WatchedFiles>,
+    simulation_manager: Res<SimulationManager>,
+    time: Res<Time<Real>>,
+) {
+    let Some(simulation) = simulation_manager.active() else {
+        return;
+    };
+
+    // Start over when another simulation has been loaded, or the scenarios it
+    // extends have changed
+    if watched.dirs != simulation.dirs {
+        let files: Vec<PathBuf> = simulation
+            .dirs
+            .iter()
+            .flat_map(|dir| FILES.map(|file| dir.join(file)))
+            .collect();
+        *watched = WatchedFiles {
+            dirs: simulation.dirs.clone(),
+            modified: files.iter().map(|path| modified_at(path)).collect(),
+            files,
+            ..Default::default()
+        };
+        return;
+    }
+
+    let now = time.elapsed();
+    let WatchedFiles {
+        files,
+        modified,
+        changed,
+        last_change,
+        ..
+    } = &mut *watched;
+    for (path, modified) in files.iter().zip(modified.iter_mut()) {
+        let modified_now = modified_at(path);
+        if modified_now != *modified {
+            *modified = modified_now;
+            if !changed.contains(path) {
+                changed.push(path.clone());
+            }
+            *last_change = Some(now);
+        }
+    }
+
+    if watched
+        .last_change
+        .is_some_and(|at| now.saturating_sub(at) >= HotReloadPlugin::DEBOUNCE)
+    {
+        watched.last_change = None;
+        let files = std::mem::take(&mut watched.changed);
+        info!("simulation files changed: {:?}", files);
+        evw_simulation_files_changed.send(SimulationFilesChanged { files });
+    }
+}
+
+/// Copy the settings that can be changed without respawning any robots from
+/// `from` to `to`
+fn copy_live_settings(from: &Config, to: &mut Config) {
+    to.gbp.sigma_factor_dynamics = from.gbp.sigma_factor_dynamics;
+    to.gbp.sigma_factor_interrobot = from.gbp.sigma_factor_interrobot;
+    to.gbp.sigma_factor_obstacle = from.gbp.sigma_factor_obstacle;
+    to.gbp.sigma_factor_tracking = from.gbp.sigma_factor_tracking;
+    to.gbp.sigma_factor_region = from.gbp.sigma_factor_region;
+    to.gbp.sigma_factor_relative_pose = from.gbp.sigma_factor_relative_pose;
+    to.gbp.priority_scaling = from.gbp.priority_scaling;
+    to.gbp.iteration_schedule = from.gbp.iteration_schedule;
+    to.visualisation.draw = from.visualisation.draw;
+}
+
+fn same_config(a: &Config, b: &Config) -> bool {
+    matches!((toml::to_string(a), toml::to_string(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Whether `current` and `new` only differ in their live settings
+fn only_live_settings_differ(current: &Config, new: &Config) -> bool {
+    let mut new = new.clone();
+    copy_live_settings(current, &mut new);
+    same_config(current, &new)
+}
+
+/// Apply the live settings of `config` to the running simulation, i.e. the
+/// factor strengths of every robot, the GBP iteration schedule, and the draw
+/// settings that differ from `previous_draw`
+pub(crate) fn apply_live_settings(
+    config: &Config,
+    previous_draw: DrawSection,
+    factorgraphs: &mut Query<(&mut FactorGraph, Option<&OfClass>)>,
+    evw_gbp_schedule_changed: &mut EventWriter<GbpScheduleChanged>,
+    evw_draw_settings: &mut EventWriter<DrawSettingsEvent>,
+) {
+    // Robots of a class keep the sigmas of their class, worked out once per class
+    let mut class_gbp: HashMap<&str, Option<GbpSection>> = HashMap::new();
+    for (mut factorgraph, of_class) in factorgraphs {
+        let gbp = of_class.and_then(|OfClass(class)| {
+            class_gbp
+                .entry(class.as_str())
+                .or_insert_with(|| config.for_class(class).map(|config| config.gbp))
+                .as_ref()
+        });
+        factorgraph.change_factor_strengths(gbp.unwrap_or(&config.gbp));
+    }
+    evw_gbp_schedule_changed.send(config.gbp.iteration_schedule.into());
+    evw_draw_settings.send_batch(
+        config
+            .visualisation
+            .draw
+            .iter()
+            .zip(previous_draw.iter())
+            .filter_map(|((name, draw), (_, previous))| {
+                let draw = *draw.downcast_ref::<bool>()?;
+                let previous = *previous.downcast_ref::<bool>()?;
+                let setting = name.parse::<DrawSetting>().ok()?;
+                (draw != previous).then_some(DrawSettingsEvent { setting, draw })
+            }),
+    );
+}
+
+/// **Bevy** system that loads the changed files of the active simulation, and
+/// either applies the live settings or loads the simulation again. If only
+/// `config.toml` has changed, it is parsed on its own first, so the
+/// environment is only parsed and its SDFs only built again when the
+/// simulation has to be loaded again.
+fn reload_changed_files(
+    mut evr_simulation_files_changed: EventReader<SimulationFilesChanged>,
+    mut evw_gbp_schedule_changed: EventWriter<GbpScheduleChanged>,
+    mut evw_draw_settings: EventWriter<DrawSettingsEvent>,
+    mut evw_toast: EventWriter<ToastEvent>,
+    mut simulation_manager: ResMut<SimulationManager>,
+    mut config: ResMut<Config>,
+    mut factorgraphs: Query<(&mut FactorGraph, Option<&OfClass>)>,
+) {
+    let changed: Vec<PathBuf> = evr_simulation_files_changed
+        .read()
+        .flat_map(|event| event.files.iter().cloned())
+        .collect();
+    let (Some(dir), Some(name)) = (
+        simulation_manager.active_dir(),
+        simulation_manager.active_name().map(ToString::to_string),
+    ) else {
+        return;
+    };
+
+    let only_config_changed = changed.iter().all(|path| path.ends_with("config.toml"));
+    if only_config_changed {
+        let new_config = match ResolvedScenario::load_config(&dir) {
+            // A changed `extends` changes the other files as well
+            Ok((new_config, dirs))
+                if simulation_manager
+                    .active()
+                    .is_some_and(|simulation| simulation.dirs == dirs) =>
+            {
+                Some(new_config)
+            }
+            Ok(_) => None,
+            Err(err) => {
+                report_reload_error(&mut evw_toast, &err);
+                return;
+            }
+        };
+
+        if let Some(new_config) = new_config {
+            // e.g. the settings were just saved from the UI
+            if same_config(&config, &new_config) {
+                return;
+            }
+            if only_live_settings_differ(&config, &new_config) {
+                let previous_draw = config.visualisation.draw;
+                copy_live_settings(&new_config, &mut config);
+                apply_live_settings(
+                    &config,
+                    previous_draw,
+                    &mut factorgraphs,
+                    &mut evw_gbp_schedule_changed,
+                    &mut evw_draw_settings,
+                );
+
+                simulation_manager.set_active_config(new_config);
+                info!("applied live settings from config.toml");
+                evw_toast.send(ToastEvent {
+                    caption: "applied live settings from config.toml".into(),
+                    options: ToastOptions {
+                        level: ToastLevel::Success,
+                        show_progress_bar: false,
+                        duration: Some(Duration::from_secs(1)),
+                        ..Default::default()
+                    },
+                });
+                return;
+            }
+        }
+    }
+
+    match Simulation::load(name, &dir) {
+        Ok(simulation) => {
+            info!("loading simulation '{}' again", simulation.name);
+            simulation_manager.replace_active(simulation);
+        }
+        Err(err) => report_reload_error(&mut evw_toast, &err),
+    }
+}
+
+/// Report that the files of the active simulation could not be loaded again
+fn report_reload_error(evw_toast: &mut EventWriter<ToastEvent>, err: &SimulationFileError) {
+    error!("failed to reload simulation: {}", err);
+    evw_toast.send(ToastEvent {
+        caption: err.to_string(),
+        options: ToastOptions {
+            level: ToastLevel::Error,
+            show_progress_bar: false,
+            duration: Some(Duration::from_secs(5)),
+            ..Default::default()
+        },
+    });
+}
@@ -58,7 +58,8 @@ impl FromWorld for CatppuccinTheme {
     }
 }
 
-#[derive(strum_macros::EnumIter, Debug, Clone, Copy)]
+#[derive(strum_macros::EnumIter, strum_macros::EnumString, Debug, Clone, Copy)]
+#[strum(serialize_all = "kebab-case")]
 pub enum DisplayColour {
     Rosewater,
     Flamingo,
@@ -11,7 +11,10 @@ use bevy_egui::egui;
 use gbp_config::Config;
 
 use super::UiState;
-use crate::diagnostic::prelude::RobotDiagnosticsPlugin;
+use crate::{
+    diagnostic::prelude::RobotDiagnosticsPlugin,
+    planner::connectivity::{ConnectivityLog, ConnectivitySample},
+};
 
 pub struct MetricsPlugin;
 
@@ -65,6 +68,7 @@ impl MetricsPlugin {
     fn render(
         mut egui_ctx: bevy_egui::EguiContexts,
         diagnostics: Res<DiagnosticsStore>,
+        connectivity_log: Res<ConnectivityLog>,
         config: Res<Config>,
         mut ui_state: ResMut<UiState>,
         mut current_pos: Local<egui::Pos2>,
@@ -106,6 +110,40 @@ impl MetricsPlugin {
                     }
                 }
 
+                // only measured for robots in lifelong operation
+                for (name, diagnostic_path) in [
+                    ("goals/min", &RobotDiagnosticsPlugin::GOALS_PER_MINUTE),
+                    ("goal latency [s]", &RobotDiagnosticsPlugin::GOAL_LATENCY),
+                ] {
+                    if let Some(value) = diagnostics
+                        .get_measurement(diagnostic_path)
+                        .map(|d| d.value)
+                    {
+                        ui.label(format!("{}: {:.2}", name, value));
+                    }
+                }
+
+                if !connectivity_log.samples().is_empty() {
+                    ui.separator();
+                    ui.label("communication graph");
+                    let samples = connectivity_log.latest(SPARKLINE_SAMPLES);
+                    #[allow(clippy::cast_precision_loss)]
+                    let metrics: [(&str, fn(&ConnectivitySample) -> f64); 5] = [
+                        ("components", |s| s.connected.components as f64),
+                        ("average degree", |s| s.connected.average_degree),
+                        ("algebraic connectivity", |s| {
+                            s.connected.algebraic_connectivity
+                        }),
+                        ("algebraic connectivity in range", |s| {
+                            s.in_range.algebraic_connectivity
+                        }),
+                        ("antennas off", |s| s.antennas_off as f64),
+                    ];
+                    for (name, metric) in metrics {
+                        sparkline(ui, name, samples, metric);
+                    }
+                }
+
                 // ui.label(format!("{}", egui::special_emojis::GITHUB));
 
                 // if ui.color_edit_button_rgb(&mut [0.1, 0.5, 0.6]).clicked() {
@@ -133,3 +171,63 @@ impl MetricsPlugin {
             .unwrap_or_default();
     }
 }
+
+/// Height of the plot of a metric over time
+const SPARKLINE_HEIGHT: f32 = 32.0;
+
+/// Number of the latest samples plotted. Every sample is walked for every
+/// metric in every frame, so the plots are bounded in long runs
+const SPARKLINE_SAMPLES: usize = 256;
+
+/// Plot `metric` of every sample in `samples` over time, below its name and
+/// latest value
+#[allow(clippy::cast_possible_truncation)]
+fn sparkline(
+    ui: &mut egui::Ui,
+    name: &str,
+    samples: &[ConnectivitySample],
+    metric: fn(&ConnectivitySample) -> f64,
+) {
+    let (Some(first), Some(last)) = (samples.first(), samples.last()) else {
+        return;
+    };
+    ui.label(format!("{}: {:.2}", name, metric(last)));
+
+    let (response, painter) = ui.allocate_painter(
+        egui::vec2(ui.available_width(), SPARKLINE_HEIGHT),
+        egui::Sense::hover(),
+    );
+    let rect = response.rect;
+    let stroke = egui::Stroke::new(1.0, ui.visuals().weak_text_color());
+    painter.rect_stroke(rect, 0.0, stroke);
+
+    let (min, max) = samples
+        .iter()
+        .map(metric)
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+            (min.min(value), max.max(value))
+        });
+    // Flat series are drawn in the middle
+    let (min, range) = if max > min {
+        (min, max - min)
+    } else {
+        (min - 1.0, 2.0)
+    };
+    let duration = (last.t - first.t).max(f64::EPSILON);
+
+    let points: Vec<egui::Pos2> = samples
+        .iter()
+        .map(|sample| {
+            let x = (sample.t - first.t) / duration;
+            let y = (metric(sample) - min) / range;
+            egui::pos2(
+                rect.left() + x as f32 * rect.width(),
+                rect.bottom() - y as f32 * rect.height(),
+            )
+        })
+        .collect();
+    painter.add(egui::Shape::line(
+        points,
+        egui::Stroke::new(1.5, ui.visuals().strong_text_color()),
+    ));
+}
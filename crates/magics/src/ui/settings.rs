@@ -25,7 +25,7 @@ use crate::{
         ChangingBinding, DrawSettingsEvent, ExportFactorGraphAsGraphviz, screenshot::TakeScreenshot,
     },
     pause_play::PausePlay,
-    planner::robot::RadioAntenna,
+    planner::robot::{OfClass, RadioAntenna},
     simulation_loader::{SaveSettings, SimulationId, SimulationManager},
     theme::{CatppuccinTheme, CycleTheme, FromCatppuccinColourExt},
 };
@@ -533,6 +533,24 @@ fn ui_settings_panel(
                                 }
                             });
                             ui.end_row();
+
+                            ui.label("Region");
+                            update_float(ui, &mut config.gbp.sigma_factor_region);
+                            custom::float_right(ui, |ui| {
+                                if custom::toggle_ui(ui, &mut config.gbp.factors_enabled.region).clicked() {
+                                    update_enabled_factors(config.gbp.factors_enabled.clone());
+                                }
+                            });
+                            ui.end_row();
+
+                            ui.label("Relative Pose");
+                            update_float(ui, &mut config.gbp.sigma_factor_relative_pose);
+                            custom::float_right(ui, |ui| {
+                                if custom::toggle_ui(ui, &mut config.gbp.factors_enabled.relative_pose).clicked() {
+                                    update_enabled_factors(config.gbp.factors_enabled.clone());
+                                }
+                            });
+                            ui.end_row();
                         });
                         //
                         //custom::grid("factors_enabled_grid", 2).show(ui, |ui| {
@@ -716,8 +734,12 @@ fn ui_settings_panel(
                                 config.robot.communication.radius = comms_radius.try_into().expect("slider range set to [0.1, 100.0]");
                                 // TODO: this should not be done with a query here, but there is not
                                 // much time left.
-                                let mut query = world.query::<&mut RadioAntenna>();
-                                for mut antenna in query.iter_mut(world) {
+                                let mut query = world.query::<(&mut RadioAntenna, Option<&OfClass>)>();
+                                for (mut antenna, of_class) in query.iter_mut(world) {
+                                    // robots of a class with a radius of its own keep it
+                                    if OfClass::of(of_class, &config).is_some_and(|class| class.communication_radius.is_some()) {
+                                        continue;
+                                    }
                                     antenna.radius = comms_radius;
                                 }
                             }
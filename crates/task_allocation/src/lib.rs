@@ -0,0 +1,409 @@
+//! Assign tasks to robots, given the cost of every robot doing every task.
+//!
+//! Every robot is assigned at most one task, and every task at most one
+//! robot. Three allocators are provided:
+//!
+//! - [`GreedyNearest`] repeatedly picks the cheapest remaining pair
+//! - [`Hungarian`] finds an assignment with the lowest total cost
+//! - [`Auction`] lets robots bid for tasks, and finds an assignment within
+//!   `robots * epsilon` of the lowest total cost
+//!
+//! # Examples
+//!
+//! ```
+//! use task_allocation::{Allocator, Assignment, Hungarian};
+//! // costs[robot][task]
+//! let costs = vec![vec![1.0, 2.0], vec![2.0, 100.0]];
+//! let mut assignments = Hungarian.allocate(&costs);
+//! assignments.sort_by_key(|assignment| assignment.robot);
+//! assert_eq!(assignments, vec![
+//!     Assignment { robot: 0, task: 1 },
+//!     Assignment { robot: 1, task: 0 },
+//! ]);
+//! ```
+
+/// Robot number `robot` is assigned task number `task`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Assignment {
+    pub robot: usize,
+    pub task: usize,
+}
+
+/// Strategy for assigning tasks to robots
+pub trait Allocator {
+    /// Assign tasks to robots, where `costs[robot][task]` is the cost of
+    /// `robot` doing `task`, e.g. the distance from the robot to the task.
+    /// Every row of `costs` must have the same length.
+    ///
+    /// As many tasks as possible are assigned, i.e. the number of robots or
+    /// the number of tasks, whichever is lowest.
+    fn allocate(&self, costs: &[Vec<f32>]) -> Vec<Assignment>;
+}
+
+/// Number of tasks in `costs`
+fn tasks(costs: &[Vec<f32>]) -> usize {
+    costs.first().map_or(0, Vec::len)
+}
+
+/// `costs` with robots and tasks swapped
+fn transpose(costs: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    (0..tasks(costs))
+        .map(|task| costs.iter().map(|row| row[task]).collect())
+        .collect()
+}
+
+/// Solve `costs` with more tasks than robots using `solve`, by swapping
+/// robots and tasks if needed
+fn with_fewer_robots_than_tasks(
+    costs: &[Vec<f32>],
+    solve: impl FnOnce(&[Vec<f32>]) -> Vec<Assignment>,
+) -> Vec<Assignment> {
+    if costs.len() <= tasks(costs) {
+        solve(costs)
+    } else {
+        solve(&transpose(costs))
+            .into_iter()
+            .map(|Assignment { robot, task }| Assignment {
+                robot: task,
+                task: robot,
+            })
+            .collect()
+    }
+}
+
+/// Repeatedly assign the robot and task with the lowest cost, of the robots
+/// and tasks that have not been assigned yet
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GreedyNearest;
+
+impl Allocator for GreedyNearest {
+    fn allocate(&self, costs: &[Vec<f32>]) -> Vec<Assignment> {
+        let mut pairs: Vec<(f32, Assignment)> = costs
+            .iter()
+            .enumerate()
+            .flat_map(|(robot, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(move |(task, &cost)| (cost, Assignment { robot, task }))
+            })
+            .collect();
+        pairs.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        let mut robot_assigned = vec![false; costs.len()];
+        let mut task_assigned = vec![false; tasks(costs)];
+        let mut assignments = Vec::with_capacity(robot_assigned.len().min(task_assigned.len()));
+
+        for (_, assignment) in pairs {
+            if robot_assigned[assignment.robot] || task_assigned[assignment.task] {
+                continue;
+            }
+            robot_assigned[assignment.robot] = true;
+            task_assigned[assignment.task] = true;
+            assignments.push(assignment);
+        }
+
+        assignments
+    }
+}
+
+/// Assignment with the lowest total cost, found with the Hungarian algorithm
+/// in `O(robots^2 * tasks)` time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Hungarian;
+
+impl Allocator for Hungarian {
+    fn allocate(&self, costs: &[Vec<f32>]) -> Vec<Assignment> {
+        with_fewer_robots_than_tasks(costs, hungarian)
+    }
+}
+
+/// The Hungarian algorithm with potentials, for `costs` with at most as many
+/// robots as tasks. Robots and tasks are numbered from 1 internally, with 0
+/// being a sentinel.
+#[allow(clippy::many_single_char_names)]
+fn hungarian(costs: &[Vec<f32>]) -> Vec<Assignment> {
+    let n = costs.len();
+    let m = tasks(costs);
+    if n == 0 || m == 0 {
+        return vec![];
+    }
+
+    let cost = |robot: usize, task: usize| f64::from(costs[robot - 1][task - 1]);
+    // Potentials of robots and tasks
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; m + 1];
+    // The robot assigned to each task, 0 if none
+    let mut assigned = vec![0_usize; m + 1];
+    // The previous task on the augmenting path to each task
+    let mut way = vec![0_usize; m + 1];
+
+    for robot in 1..=n {
+        assigned[0] = robot;
+        let mut task = 0;
+        let mut min_slack = vec![f64::INFINITY; m + 1];
+        let mut visited = vec![false; m + 1];
+
+        loop {
+            visited[task] = true;
+            let current = assigned[task];
+            let mut delta = f64::INFINITY;
+            let mut next = 0;
+            for j in 1..=m {
+                if visited[j] {
+                    continue;
+                }
+                let slack = cost(current, j) - u[current] - v[j];
+                if slack < min_slack[j] {
+                    min_slack[j] = slack;
+                    way[j] = task;
+                }
+                if min_slack[j] < delta {
+                    delta = min_slack[j];
+                    next = j;
+                }
+            }
+            for j in 0..=m {
+                if visited[j] {
+                    u[assigned[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_slack[j] -= delta;
+                }
+            }
+            task = next;
+            if assigned[task] == 0 {
+                break;
+            }
+        }
+
+        // Flip the augmenting path
+        while task != 0 {
+            let previous = way[task];
+            assigned[task] = assigned[previous];
+            task = previous;
+        }
+    }
+
+    (1..=m)
+        .filter(|&task| assigned[task] != 0)
+        .map(|task| Assignment {
+            robot: assigned[task] - 1,
+            task: task - 1,
+        })
+        .collect()
+}
+
+/// Robots bid for the tasks that are most valuable to them, with a total cost
+/// within `robots * epsilon` of the lowest possible
+///
+/// A robot raises the price of a task by how much more it is worth to it than
+/// its second best option, plus `epsilon`.
+///
+/// Unlike [`Hungarian`], each robot only needs to know the prices of the
+/// tasks, so the bidding can be distributed among the robots.
+#[derive(Debug, Clone, Copy)]
+pub struct Auction {
+    /// Minimum amount to raise the price of a task by. Lower values give
+    /// cheaper assignments, but more rounds of bidding.
+    pub epsilon: f32,
+}
+
+impl Auction {
+    /// Create a new `Auction` allocator
+    ///
+    /// # Panics
+    ///
+    /// Panics if `epsilon` is not positive and finite
+    #[must_use]
+    pub fn new(epsilon: f32) -> Self {
+        assert!(
+            epsilon.is_finite() && epsilon > 0.0,
+            "epsilon must be positive and finite"
+        );
+        Self { epsilon }
+    }
+}
+
+impl Default for Auction {
+    fn default() -> Self {
+        Self::new(1e-3)
+    }
+}
+
+impl Allocator for Auction {
+    fn allocate(&self, costs: &[Vec<f32>]) -> Vec<Assignment> {
+        let epsilon = f64::from(self.epsilon);
+        with_fewer_robots_than_tasks(costs, |costs| auction(costs, epsilon))
+    }
+}
+
+/// Forward auction for `costs` with at most as many robots as tasks
+fn auction(costs: &[Vec<f32>], epsilon: f64) -> Vec<Assignment> {
+    let n = costs.len();
+    let m = tasks(costs);
+    let mut prices = vec![0.0_f64; m];
+    // The robot that currently holds each task
+    let mut holder: Vec<Option<usize>> = vec![None; m];
+    let mut unassigned: Vec<usize> = (0..n).rev().collect();
+
+    while let Some(robot) = unassigned.pop() {
+        // The value of a task to a robot is how much cheaper it is than
+        // nothing, minus its current price
+        let mut best: Option<(usize, f64)> = None;
+        let mut second_best = f64::NEG_INFINITY;
+        for (task, &cost) in costs[robot].iter().enumerate() {
+            let value = -f64::from(cost) - prices[task];
+            match best {
+                Some((_, best_value)) if value <= best_value => {
+                    second_best = second_best.max(value);
+                }
+                _ => {
+                    if let Some((_, best_value)) = best {
+                        second_best = best_value;
+                    }
+                    best = Some((task, value));
+                }
+            }
+        }
+        let Some((task, best_value)) = best else {
+            continue;
+        };
+
+        let increment = if second_best.is_finite() {
+            best_value - second_best + epsilon
+        } else {
+            epsilon
+        };
+        prices[task] += increment;
+        if let Some(outbid) = holder[task].replace(robot) {
+            unassigned.push(outbid);
+        }
+    }
+
+    holder
+        .into_iter()
+        .enumerate()
+        .filter_map(|(task, robot)| robot.map(|robot| Assignment { robot, task }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn total_cost(costs: &[Vec<f32>], assignments: &[Assignment]) -> f32 {
+        assignments
+            .iter()
+            .map(|assignment| costs[assignment.robot][assignment.task])
+            .sum()
+    }
+
+    /// Check that no robot or task is assigned twice, and that as many tasks
+    /// as possible are assigned
+    fn assert_valid(costs: &[Vec<f32>], assignments: &[Assignment]) {
+        let mut robots: Vec<_> = assignments.iter().map(|it| it.robot).collect();
+        let mut tasks: Vec<_> = assignments.iter().map(|it| it.task).collect();
+        robots.sort_unstable();
+        robots.dedup();
+        tasks.sort_unstable();
+        tasks.dedup();
+        assert_eq!(robots.len(), assignments.len());
+        assert_eq!(tasks.len(), assignments.len());
+        assert_eq!(assignments.len(), costs.len().min(super::tasks(costs)));
+    }
+
+    /// Lowest total cost of assigning every robot a task, by trying every
+    /// permutation. Assumes at most as many robots as tasks.
+    fn brute_force(costs: &[Vec<f32>]) -> f32 {
+        fn go(costs: &[Vec<f32>], robot: usize, used: &mut Vec<bool>) -> f32 {
+            if robot == costs.len() {
+                return 0.0;
+            }
+            let mut best = f32::INFINITY;
+            for task in 0..used.len() {
+                if used[task] {
+                    continue;
+                }
+                used[task] = true;
+                best = best.min(costs[robot][task] + go(costs, robot + 1, used));
+                used[task] = false;
+            }
+            best
+        }
+        go(costs, 0, &mut vec![false; super::tasks(costs)])
+    }
+
+    fn examples() -> Vec<Vec<Vec<f32>>> {
+        vec![
+            vec![
+                vec![4.0, 1.0, 3.0],
+                vec![2.0, 0.0, 5.0],
+                vec![3.0, 2.0, 2.0],
+            ],
+            vec![vec![1.0, 2.0], vec![2.0, 100.0]],
+            vec![vec![7.0, 3.0, 9.0, 1.0], vec![2.0, 8.0, 4.0, 6.0]],
+            vec![
+                vec![10.0, 19.0, 8.0, 15.0],
+                vec![10.0, 18.0, 7.0, 17.0],
+                vec![13.0, 16.0, 9.0, 14.0],
+                vec![12.0, 19.0, 8.0, 18.0],
+            ],
+            vec![vec![5.5]],
+        ]
+    }
+
+    #[test]
+    fn greedy_takes_cheapest_pair_first() {
+        let costs = vec![vec![1.0, 2.0], vec![2.0, 100.0]];
+        let mut assignments = GreedyNearest.allocate(&costs);
+        assignments.sort_by_key(|assignment| assignment.robot);
+        assert_eq!(
+            assignments,
+            vec![
+                Assignment { robot: 0, task: 0 },
+                Assignment { robot: 1, task: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn hungarian_is_optimal() {
+        for costs in examples() {
+            let assignments = Hungarian.allocate(&costs);
+            assert_valid(&costs, &assignments);
+            assert_eq!(total_cost(&costs, &assignments), brute_force(&costs));
+        }
+    }
+
+    #[test]
+    fn auction_is_close_to_optimal() {
+        let auction = Auction::default();
+        for costs in examples() {
+            let assignments = auction.allocate(&costs);
+            assert_valid(&costs, &assignments);
+            #[allow(clippy::cast_precision_loss)]
+            let bound = costs.len() as f32 * auction.epsilon;
+            assert!(total_cost(&costs, &assignments) <= brute_force(&costs) + bound);
+        }
+    }
+
+    #[test]
+    fn more_robots_than_tasks() {
+        let costs = vec![vec![3.0], vec![1.0], vec![2.0]];
+        let expected = vec![Assignment { robot: 1, task: 0 }];
+        assert_eq!(GreedyNearest.allocate(&costs), expected);
+        assert_eq!(Hungarian.allocate(&costs), expected);
+        assert_eq!(Auction::default().allocate(&costs), expected);
+    }
+
+    #[test]
+    fn nothing_to_assign() {
+        let no_tasks = vec![vec![], vec![]];
+        assert_eq!(GreedyNearest.allocate(&[]), vec![]);
+        assert_eq!(Hungarian.allocate(&[]), vec![]);
+        assert_eq!(Hungarian.allocate(&no_tasks), vec![]);
+        assert_eq!(Auction::default().allocate(&no_tasks), vec![]);
+    }
+}